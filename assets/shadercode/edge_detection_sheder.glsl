@@ -1,4 +1,5 @@
 #version 450
+#pragma shader_stage(compute)
 
 // Input texture
 layout(binding = 0) uniform sampler2D inputTexture;
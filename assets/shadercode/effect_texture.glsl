@@ -0,0 +1,26 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Texture coordinates from Bevy's 2D mesh vertex shader
+layout(location = 2) in vec2 uv;
+
+// Outputs colors in RGBA
+layout(location = 0) out vec4 FragColor;
+
+// Values fed by the Bevy plugin every frame
+layout(binding = 0) uniform Effect {
+    float time;
+    float delta;
+    vec2 resolution;
+    vec4 params;
+};
+
+// Input texture
+layout(binding = 1) uniform sampler2D inputTexture;
+
+void main()
+{
+	// Slowly pulse the brightness of the image
+	float pulse = 0.75 + 0.25 * sin(time * 2.0);
+	FragColor = vec4(texture(inputTexture, uv).rgb * pulse, 1.0);
+}
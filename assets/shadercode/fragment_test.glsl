@@ -1,4 +1,5 @@
 #version 450 
+#pragma shader_stage(fragment)
 
 // Outputs colors in RGBA
 out vec4 FragColor;
@@ -0,0 +1,55 @@
+#version 450
+#pragma shader_stage(compute)
+
+// One jump flood step: every pixel looks at the seeds found by the pixels
+// `step` away (and itself) and keeps the nearest one
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+
+// Nearest seed position per pixel, row by row from the top; x < 0 means none yet
+layout(std430, binding = 0) readonly buffer Source { vec2 source[]; };
+layout(std430, binding = 1) writeonly buffer Destination { vec2 destination[]; };
+layout(std140, binding = 2) uniform Step
+{
+	ivec2 size;
+	int step;
+	int padding;
+};
+
+
+void main()
+{
+	ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+	if (pixel.x >= size.x || pixel.y >= size.y)
+	{
+		return;
+	}
+
+	vec2 center = vec2(pixel) + 0.5;
+	vec2 best = vec2(-1.0);
+	float best_d = 3.4e38;
+	for (int y = -1; y <= 1; y++)
+	{
+		for (int x = -1; x <= 1; x++)
+		{
+			ivec2 q = pixel + ivec2(x, y) * step;
+			if (q.x < 0 || q.y < 0 || q.x >= size.x || q.y >= size.y)
+			{
+				continue;
+			}
+			vec2 seed = source[q.y * size.x + q.x];
+			if (seed.x < 0.0)
+			{
+				continue;
+			}
+			vec2 d = seed - center;
+			float dist = dot(d, d);
+			if (dist < best_d)
+			{
+				best_d = dist;
+				best = seed;
+			}
+		}
+	}
+	destination[pixel.y * size.x + pixel.x] = best;
+}
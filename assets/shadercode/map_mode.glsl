@@ -0,0 +1,28 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the color of the province under the fragment
+out vec4 FragColor;
+
+
+// Inputs the map coordinates from the Vertex Shader
+in vec2 TexCoord;
+
+
+// Province ID of every map pixel, r = bits 0-7, g = bits 8-15, b = bits 16-23
+uniform sampler2D provinceIds;
+// One color per province, rows of DATA_WIDTH; alpha 0 leaves the base map visible
+uniform sampler2D provinceColors;
+// Size of the ID map in pixels
+uniform vec2 mapSize;
+
+const int DATA_WIDTH = 1024;
+
+
+void main()
+{
+	ivec2 pixel = clamp(ivec2(floor(TexCoord * mapSize)), ivec2(0), ivec2(mapSize) - 1);
+	vec3 bytes = texelFetch(provinceIds, pixel, 0).rgb * 255.0 + 0.5;
+	uint id = uint(bytes.r) | (uint(bytes.g) << 8) | (uint(bytes.b) << 16);
+	FragColor = texelFetch(provinceColors, ivec2(int(id % uint(DATA_WIDTH)), int(id / uint(DATA_WIDTH))), 0);
+}
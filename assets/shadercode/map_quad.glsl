@@ -0,0 +1,27 @@
+#version 450
+#pragma shader_stage(vertex)
+
+// Corner of the screen quad, only its texture coordinates are used
+layout (location = 0) in vec2 aPos;
+// Map coordinates, v = 0 at the top of the map
+layout (location = 1) in vec2 aTexCoord;
+
+
+// Outputs the map coordinates for the Fragment Shader
+out vec2 TexCoord;
+
+
+// Camera: map pixels to clip space
+uniform mat4 u_viewProj;
+// Horizontal offset of this copy of the map, for maps that wrap around
+uniform float u_mapOffset;
+// Size of the map in pixels
+uniform vec2 mapSize;
+
+
+void main()
+{
+	vec2 pixel = aTexCoord * mapSize + vec2(u_mapOffset, 0.0);
+	gl_Position = u_viewProj * vec4(pixel, 0.0, 1.0);
+	TexCoord = aTexCoord;
+}
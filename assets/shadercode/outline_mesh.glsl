@@ -0,0 +1,14 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the mesh color
+out vec4 FragColor;
+
+
+uniform vec4 meshColor;
+
+
+void main()
+{
+	FragColor = meshColor;
+}
@@ -0,0 +1,19 @@
+#version 450
+#pragma shader_stage(vertex)
+
+// Vertex in map pixels, from the meshes of `shaders outlines`
+layout (location = 0) in vec2 aPos;
+// Province the vertex belongs to
+layout (location = 1) in float aProvince;
+
+
+// Camera: map pixels to clip space
+uniform mat4 u_viewProj;
+// Horizontal offset of this copy of the map, for maps that wrap around
+uniform float u_mapOffset;
+
+
+void main()
+{
+	gl_Position = u_viewProj * vec4(aPos + vec2(u_mapOffset, 0.0), 0.0, 1.0);
+}
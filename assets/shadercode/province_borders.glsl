@@ -0,0 +1,110 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the border color, transparent away from borders
+out vec4 FragColor;
+
+
+// Inputs the map coordinates from the Vertex Shader
+in vec2 TexCoord;
+
+
+// Province ID of every map pixel, r = bits 0-7, g = bits 8-15, b = bits 16-23
+uniform sampler2D provinceIds;
+// One texel per province, rows of DATA_WIDTH: rg = country ID, b = 255 for sea
+uniform sampler2D provinceData;
+// Size of the ID map in pixels
+uniform vec2 mapSize;
+
+// Border widths in map pixels, 0 hides a style
+uniform float provinceWidth;
+uniform float countryWidth;
+uniform float coastWidth;
+uniform vec4 provinceColor;
+uniform vec4 countryColor;
+uniform vec4 coastColor;
+
+const int DATA_WIDTH = 1024;
+// Furthest neighbour looked at, caps the border width at 2 * MAX_RADIUS
+const int MAX_RADIUS = 4;
+const float FAR = 1e6;
+
+
+uint provinceAt(ivec2 pixel)
+{
+	ivec2 clamped = clamp(pixel, ivec2(0), ivec2(mapSize) - 1);
+	vec3 id = texelFetch(provinceIds, clamped, 0).rgb * 255.0 + 0.5;
+	return uint(id.r) | (uint(id.g) << 8) | (uint(id.b) << 16);
+}
+
+vec4 dataOf(uint id)
+{
+	ivec2 texel = ivec2(int(id % uint(DATA_WIDTH)), int(id / uint(DATA_WIDTH)));
+	return texelFetch(provinceData, texel, 0);
+}
+
+// Coverage of a border of the given width at distance d from the edge
+float coverage(float d, float width, float aa)
+{
+	float half_width = width * 0.5;
+	return width > 0.0 ? 1.0 - smoothstep(half_width - aa, half_width + aa, d) : 0.0;
+}
+
+// Draws color over base with straight alpha
+vec4 over(vec4 base, vec4 color, float alpha)
+{
+	float a = color.a * alpha;
+	float out_a = a + base.a * (1.0 - a);
+	vec3 rgb = out_a > 0.0 ? (color.rgb * a + base.rgb * base.a * (1.0 - a)) / out_a : vec3(0.0);
+	return vec4(rgb, out_a);
+}
+
+void main()
+{
+	vec2 p = TexCoord * mapSize;
+	ivec2 center = ivec2(floor(p));
+	uint self_id = provinceAt(center);
+	vec4 self_data = dataOf(self_id);
+	bool self_sea = self_data.b > 0.5;
+
+	// Distance to the closest pixel of another province, per border style
+	float province_d = FAR;
+	float country_d = FAR;
+	float coast_d = FAR;
+	for (int y = -MAX_RADIUS; y <= MAX_RADIUS; y++)
+	{
+		for (int x = -MAX_RADIUS; x <= MAX_RADIUS; x++)
+		{
+			ivec2 q = center + ivec2(x, y);
+			uint other = provinceAt(q);
+			if (other == self_id)
+			{
+				continue;
+			}
+			// Distance from p to the square covered by pixel q
+			float d = length(max(abs(p - (vec2(q) + 0.5)) - 0.5, vec2(0.0)));
+			vec4 other_data = dataOf(other);
+			bool other_sea = other_data.b > 0.5;
+			if (self_sea != other_sea)
+			{
+				coast_d = min(coast_d, d);
+			}
+			else if (!self_sea && other_data.rg != self_data.rg)
+			{
+				country_d = min(country_d, d);
+			}
+			else
+			{
+				province_d = min(province_d, d);
+			}
+		}
+	}
+
+	// Screen-space size of a map pixel, so edges stay one screen pixel soft at any zoom
+	float aa = max(length(fwidth(p)) * 0.5, 1e-4);
+	vec4 color = vec4(0.0);
+	color = over(color, provinceColor, coverage(province_d, provinceWidth, aa));
+	color = over(color, countryColor, coverage(country_d, countryWidth, aa));
+	color = over(color, coastColor, coverage(coast_d, coastWidth, aa));
+	FragColor = color;
+}
@@ -0,0 +1,54 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the border color, transparent away from borders
+out vec4 FragColor;
+
+
+// Inputs the map coordinates from the Vertex Shader
+in vec2 TexCoord;
+
+
+// Distance to the nearest province (r), country (g) and coast (b) border,
+// scaled by 1 / maxDistance; sampled with linear filtering
+uniform sampler2D borderDistance;
+uniform float maxDistance;
+// Size of the map in pixels
+uniform vec2 mapSize;
+
+// Border widths in map pixels, 0 hides a style. Widths below 1 pixel fade
+// out, since the field is sampled at pixel centers.
+uniform float provinceWidth;
+uniform float countryWidth;
+uniform float coastWidth;
+uniform vec4 provinceColor;
+uniform vec4 countryColor;
+uniform vec4 coastColor;
+
+
+// Coverage of a border of the given width at distance d from the edge
+float coverage(float d, float width, float aa)
+{
+	float half_width = width * 0.5;
+	return width > 0.0 ? 1.0 - smoothstep(half_width - aa, half_width + aa, d) : 0.0;
+}
+
+// Draws color over base with straight alpha
+vec4 over(vec4 base, vec4 color, float alpha)
+{
+	float a = color.a * alpha;
+	float out_a = a + base.a * (1.0 - a);
+	vec3 rgb = out_a > 0.0 ? (color.rgb * a + base.rgb * base.a * (1.0 - a)) / out_a : vec3(0.0);
+	return vec4(rgb, out_a);
+}
+
+void main()
+{
+	vec3 d = texture(borderDistance, TexCoord).rgb * maxDistance;
+	float aa = max(length(fwidth(TexCoord * mapSize)) * 0.5, 1e-4);
+	vec4 color = vec4(0.0);
+	color = over(color, provinceColor, coverage(d.r, provinceWidth, aa));
+	color = over(color, countryColor, coverage(d.g, countryWidth, aa));
+	color = over(color, coastColor, coverage(d.b, coastWidth, aa));
+	FragColor = color;
+}
@@ -0,0 +1,72 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the outline color, transparent elsewhere
+layout(location = 0) out vec4 FragColor;
+
+
+// Inputs the mesh texture coordinates, v = 0 at the top of the map
+layout(location = 2) in vec2 uv;
+
+
+layout(binding = 0) uniform Effect
+{
+	float time;
+	float delta;
+	vec2 resolution;
+	// x = hovered province, y = selected province (-1 for none),
+	// z = outline width in map pixels
+	vec4 params;
+};
+// Province ID map, r = bits 0-7, g = bits 8-15, b = bits 16-23
+layout(binding = 1) uniform sampler2D inputTexture;
+
+const int MAX_RADIUS = 4;
+const vec4 HOVER_COLOR = vec4(1.0, 1.0, 1.0, 0.9);
+const vec4 SELECT_COLOR = vec4(1.0, 0.85, 0.2, 1.0);
+
+
+int provinceAt(ivec2 pixel)
+{
+	ivec2 size = textureSize(inputTexture, 0);
+	vec3 id = texelFetch(inputTexture, clamp(pixel, ivec2(0), size - 1), 0).rgb * 255.0 + 0.5;
+	return int(id.r) | (int(id.g) << 8) | (int(id.b) << 16);
+}
+
+void main()
+{
+	vec2 p = uv * vec2(textureSize(inputTexture, 0));
+	// Derivatives must be taken before any non-uniform branch
+	float aa = max(length(fwidth(p)) * 0.5, 1e-4);
+	ivec2 center = ivec2(floor(p));
+	int self_id = provinceAt(center);
+	int hovered = int(params.x);
+	int selected = int(params.y);
+	if (self_id != hovered && self_id != selected)
+	{
+		FragColor = vec4(0.0);
+		return;
+	}
+
+	// Distance to the nearest pixel of another province, the outline is
+	// drawn on the inside of the edge
+	float d = 1e6;
+	for (int y = -MAX_RADIUS; y <= MAX_RADIUS; y++)
+	{
+		for (int x = -MAX_RADIUS; x <= MAX_RADIUS; x++)
+		{
+			ivec2 q = center + ivec2(x, y);
+			if (provinceAt(q) != self_id)
+			{
+				d = min(d, length(max(abs(p - (vec2(q) + 0.5)) - 0.5, vec2(0.0))));
+			}
+		}
+	}
+
+	float width = clamp(params.z, 0.0, float(MAX_RADIUS));
+	float outline = 1.0 - smoothstep(width - aa, width + aa, d);
+	vec4 color = self_id == selected ? SELECT_COLOR : HOVER_COLOR;
+	// Faint fill so the whole province reads as highlighted
+	float fill = self_id == selected ? 0.25 : 0.12;
+	FragColor = vec4(color.rgb, color.a * max(outline, fill));
+}
@@ -0,0 +1,18 @@
+#version 450
+#pragma shader_stage(vertex)
+
+// Coordinates
+layout (location = 0) in vec2 aPos;
+// Texture coordinates; which end of the image v = 0 is depends on the quad
+layout (location = 1) in vec2 aTexCoord;
+
+
+// Outputs the texture coordinates for the Fragment Shader
+out vec2 TexCoord;
+
+
+void main()
+{
+	gl_Position = vec4(aPos, 0.0, 1.0);
+	TexCoord = aTexCoord;
+}
@@ -0,0 +1,32 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Outputs the glyph color with its outline
+out vec4 FragColor;
+
+
+// Inputs the atlas coordinates from the Vertex Shader
+in vec2 TexCoord;
+
+
+// Signed distance field glyphs, 0.5 on the outline and more inside
+uniform sampler2D glyphAtlas;
+uniform vec4 textColor;
+uniform vec4 outlineColor;
+// Outline thickness as a share of the field's spread, 0..1
+uniform float outlineWidth;
+
+
+void main()
+{
+	float d = texture(glyphAtlas, TexCoord).r;
+	// About one screen pixel of the field, for anti-aliasing at any size
+	float aa = max(fwidth(d) * 0.75, 1e-4);
+	float fill = smoothstep(0.5 - aa, 0.5 + aa, d);
+	float edge = 0.5 - outlineWidth * 0.5;
+	float outline = smoothstep(edge - aa, edge + aa, d);
+
+	// Fill over the outline
+	vec4 halo = vec4(outlineColor.rgb, outlineColor.a * outline);
+	FragColor = mix(halo, textColor, fill);
+}
@@ -0,0 +1,24 @@
+#version 450
+#pragma shader_stage(vertex)
+
+// Glyph corner in map pixels
+layout (location = 0) in vec2 aPos;
+// Texture coordinates in the glyph atlas
+layout (location = 1) in vec2 aTexCoord;
+
+
+// Outputs the atlas coordinates for the Fragment Shader
+out vec2 TexCoord;
+
+
+// Camera: map pixels to clip space
+uniform mat4 u_viewProj;
+// Horizontal offset of this copy of the map, for maps that wrap around
+uniform float u_mapOffset;
+
+
+void main()
+{
+	gl_Position = u_viewProj * vec4(aPos + vec2(u_mapOffset, 0.0), 0.0, 1.0);
+	TexCoord = aTexCoord;
+}
@@ -0,0 +1,18 @@
+#version 450
+#pragma shader_stage(fragment)
+
+// Input texture coordinates
+in vec2 TexCoord;
+
+// Output color
+out vec4 FragColor;
+
+// Input texture
+uniform sampler2D inputTexture;
+
+void main() {
+    // White where the pixel is bright, same test as edge_detection_sheder.glsl
+    vec4 color = texture(inputTexture, TexCoord);
+    float edge = color.r + color.g + color.b > 1.5 ? 1.0 : 0.0;
+    FragColor = vec4(vec3(edge), 1.0);
+}
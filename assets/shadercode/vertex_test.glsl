@@ -1,4 +1,5 @@
 #version 450
+#pragma shader_stage(vertex)
 
 // Coordinates
 layout (location = 0) in vec3 aPos;
@@ -10,12 +11,14 @@ layout (location = 1) in vec3 aColor;
 out vec3 color;
 // Controls the scale of the vertices
 uniform float scale;
+// Camera: world to clip space
+uniform mat4 u_viewProj;
 
 
 void main()
 {
 	// Outputs the coordinates of all vertices
-	gl_Position = vec4(aPos.x + aPos.x * scale, aPos.y + aPos.y * scale, aPos.z + aPos.z * scale, 1.0);
+	gl_Position = u_viewProj * vec4(aPos.x + aPos.x * scale, aPos.y + aPos.y * scale, aPos.z + aPos.z * scale, 1.0);
 	// Assigns the colors from the Vertex Data to "color"
 	color = aColor;
 }
\ No newline at end of file
@@ -0,0 +1,17 @@
+// Paints a dot circling the screen, and the mouse while it is pressed, over
+// the previous frame faded a little (iChannel0 is this buffer)
+void mainImage(out vec4 fragColor, in vec2 fragCoord)
+{
+    vec2 uv = fragCoord / iResolution.xy;
+    vec3 previous = texture(iChannel0, uv).rgb * 0.985;
+
+    vec2 center = iResolution.xy * (0.5 + 0.35 * vec2(cos(iTime), sin(iTime * 1.3)));
+    float spot = smoothstep(12.0, 8.0, length(fragCoord - center));
+    vec3 color = 0.5 + 0.5 * cos(iTime + vec3(0.0, 2.0, 4.0));
+    float brush = iMouse.z > 0.0 ? smoothstep(10.0, 6.0, length(fragCoord - iMouse.xy)) : 0.0;
+
+    fragColor = vec4(max(previous, color * spot + vec3(brush)), 1.0);
+    if (iFrame == 0) {
+        fragColor = vec4(0.0);
+    }
+}
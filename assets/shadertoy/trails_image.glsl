@@ -0,0 +1,8 @@
+// Shows the trails of Buffer A over a dimmed texture
+void mainImage(out vec4 fragColor, in vec2 fragCoord)
+{
+    vec2 uv = fragCoord / iResolution.xy;
+    vec3 trails = texture(iChannel0, uv).rgb;
+    vec3 background = texture(iChannel1, uv).rgb * 0.25;
+    fragColor = vec4(background + trails, 1.0);
+}
@@ -0,0 +1,550 @@
+use gl::types::*;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+use crate::shader_pipeline::gl_device::Device;
+use crate::shader_pipeline::texture::Texture;
+use crate::shader_pipeline::EBO::EBO;
+use crate::shader_pipeline::VAO::VAO;
+use crate::shader_pipeline::VBO::VBO;
+use crate::Shader;
+
+pub type AssetId = usize;
+
+// Finds an existing file: absolute paths as they are, relative ones in each
+// of `roots` in turn
+pub fn resolve_path(path: &Path, roots: &[&Path]) -> Result<PathBuf, String> {
+    if path.is_absolute() {
+        return if path.exists() { Ok(path.to_path_buf()) } else { Err(format!("{}: file not found", path.display())) };
+    }
+    roots
+        .iter()
+        .map(|root| root.join(path))
+        .find(|candidate| candidate.exists())
+        .ok_or_else(|| {
+            let roots: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+            format!("{}: file not found (looked in {})", path.display(), roots.join(", "))
+        })
+}
+
+// Shared by every clone of a handle. Dropping the last one queues the asset
+// to be freed on the next collect_garbage, which runs on the GL thread.
+struct Claim {
+    id: AssetId,
+    released: Rc<RefCell<Vec<AssetId>>>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.released.borrow_mut().push(self.id);
+    }
+}
+
+// Keeps an asset loaded; look it up with AssetManager::get
+pub struct Handle<T> {
+    claim: Rc<Claim>,
+    marker: PhantomData<T>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle { claim: self.claim.clone(), marker: PhantomData }
+    }
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> AssetId {
+        self.claim.id
+    }
+}
+
+// Vertex data as stored in a .json mesh file: `attributes` lists the float
+// count of each attribute in layout order, `vertices` interleaves them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MeshData {
+    pub attributes: Vec<u32>,
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+// Indexed triangles in their own VAO
+pub struct Mesh {
+    vao: VAO,
+    vbo: VBO,
+    ebo: EBO,
+    pub index_count: GLsizei,
+    gl: Device,
+}
+
+impl Mesh {
+    pub fn from_data(gl: Device, data: &MeshData) -> Result<Self, String> {
+        let stride: u32 = data.attributes.iter().sum();
+        if stride == 0 || !data.vertices.len().is_multiple_of(stride as usize) {
+            return Err(format!("{} floats don't make whole vertices of {}", data.vertices.len(), stride));
+        }
+        let vertex_count = data.vertices.len() / stride as usize;
+        if let Some(index) = data.indices.iter().find(|i| **i as usize >= vertex_count) {
+            return Err(format!("index {} is past the last of {} vertices", index, vertex_count));
+        }
+
+        let vao = VAO::with_device(gl.clone());
+        vao.bind();
+        let vbo = VBO::with_device(gl.clone(), &data.vertices);
+        let ebo = EBO::with_device(gl.clone(), &data.indices);
+        let float = std::mem::size_of::<f32>();
+        let mut offset = 0;
+        for (layout, components) in data.attributes.iter().enumerate() {
+            vao.link_attrib(&vbo, layout as GLuint, *components, gl::FLOAT, (stride as usize * float) as GLsizei, (offset * float) as *const std::ffi::c_void);
+            offset += *components as usize;
+        }
+        vao.unbind();
+        ebo.unbind();
+        Ok(Mesh { vao, vbo, ebo, index_count: data.indices.len() as GLsizei, gl })
+    }
+
+    // Draws the mesh with whatever program is active
+    pub fn draw(&self) {
+        self.vao.bind();
+        self.gl.draw_elements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, 0);
+        self.vao.unbind();
+    }
+
+    // Labels its VAO and buffers after `name`
+    pub fn label(&self, name: &str) {
+        self.vao.label(&format!("{} vao", name));
+        self.vbo.label(&format!("{} vertices", name));
+        self.ebo.label(&format!("{} indices", name));
+    }
+
+    pub fn delete(&self) {
+        self.vao.delete();
+        self.vbo.delete();
+        self.ebo.delete();
+    }
+}
+
+pub enum Asset {
+    Shader(Shader),
+    // With the size of its image
+    Texture(Texture, (u32, u32)),
+    Mesh(Mesh),
+}
+
+impl Asset {
+    fn label(&self, name: &str) {
+        match self {
+            Asset::Shader(shader) => shader.label(name),
+            Asset::Texture(texture, _) => texture.label(name),
+            Asset::Mesh(mesh) => mesh.label(name),
+        }
+    }
+
+    fn delete(&self) {
+        match self {
+            Asset::Shader(shader) => shader.delete(),
+            Asset::Texture(texture, _) => texture.delete(),
+            Asset::Mesh(mesh) => mesh.delete(),
+        }
+    }
+}
+
+// Types a handle can point to
+pub trait AssetType: Sized {
+    fn from_asset(asset: &Asset) -> Option<&Self>;
+}
+
+impl AssetType for Shader {
+    fn from_asset(asset: &Asset) -> Option<&Self> {
+        match asset {
+            Asset::Shader(shader) => Some(shader),
+            _ => None,
+        }
+    }
+}
+
+impl AssetType for Texture {
+    fn from_asset(asset: &Asset) -> Option<&Self> {
+        match asset {
+            Asset::Texture(texture, _) => Some(texture),
+            _ => None,
+        }
+    }
+}
+
+impl AssetType for Mesh {
+    fn from_asset(asset: &Asset) -> Option<&Self> {
+        match asset {
+            Asset::Mesh(mesh) => Some(mesh),
+            _ => None,
+        }
+    }
+}
+
+// What an asset was loaded from, with resolved paths; equal sources share
+// one asset
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Source {
+    Program { vertex: PathBuf, fragment: PathBuf },
+    Compute(PathBuf),
+    // A Shadertoy mainImage file, see shadertoy::compile_pass
+    ShadertoyPass { common: Option<PathBuf>, code: PathBuf },
+    // Flipped ones have their bottom row first, so v = 0 is the bottom
+    Texture { path: PathBuf, flipped: bool },
+    Mesh(PathBuf),
+}
+
+pub fn read_source(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Shader::from_source only logs its errors, a failed link is turned into
+// an error here so a broken edit doesn't replace a working program
+pub fn check_linked(gl: &Device, shader: Shader, name: &str) -> Result<Shader, String> {
+    if gl.get_program_iv(shader.id, gl::LINK_STATUS) != gl::TRUE as GLint {
+        let log = gl.get_program_info_log(shader.id);
+        shader.delete();
+        return Err(format!("{}: failed to link: {}", name, log));
+    }
+    Ok(shader)
+}
+
+impl Source {
+    fn files(&self) -> Vec<&Path> {
+        match self {
+            Source::Program { vertex, fragment } => vec![vertex, fragment],
+            Source::ShadertoyPass { common, code } => std::iter::once(code).chain(common).map(PathBuf::as_path).collect(),
+            Source::Compute(path) | Source::Texture { path, .. } | Source::Mesh(path) => vec![path],
+        }
+    }
+
+    // Used in errors and as the debug label of the asset
+    fn name(&self) -> String {
+        match self {
+            Source::Program { vertex, fragment } => format!("{} + {}", vertex.display(), fragment.display()),
+            Source::ShadertoyPass { code: path, .. } | Source::Compute(path) | Source::Texture { path, .. } | Source::Mesh(path) => path.display().to_string(),
+        }
+    }
+
+    fn load(&self, gl: &Device) -> Result<Asset, String> {
+        let asset = self.build(gl)?;
+        asset.label(&self.name());
+        Ok(asset)
+    }
+
+    fn build(&self, gl: &Device) -> Result<Asset, String> {
+        match self {
+            Source::Program { vertex, fragment } => {
+                let shader = Shader::from_source(gl.clone(), &read_source(vertex)?, &read_source(fragment)?);
+                Ok(Asset::Shader(check_linked(gl, shader, &self.name())?))
+            }
+            Source::Compute(path) => {
+                let shader = Shader::compute_from_source(gl.clone(), &read_source(path)?);
+                Ok(Asset::Shader(check_linked(gl, shader, &self.name())?))
+            }
+            Source::ShadertoyPass { common, code } => Ok(Asset::Shader(crate::shadertoy::compile_pass(gl, common.as_deref(), code)?)),
+            Source::Texture { path, flipped } => {
+                let image = image::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let image = if *flipped { image.flipv() } else { image };
+                let size = (image.width(), image.height());
+                Ok(Asset::Texture(Texture::from_image(gl.clone(), &image, gl::TEXTURE_2D, gl::TEXTURE0), size))
+            }
+            Source::Mesh(path) => {
+                let data: MeshData = serde_json::from_str(&read_source(path)?).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Ok(Asset::Mesh(Mesh::from_data(gl.clone(), &data).map_err(|e| format!("{}: {}", path.display(), e))?))
+            }
+        }
+    }
+}
+
+struct Slot {
+    // None for assets built in memory, which can't be reloaded
+    source: Option<Source>,
+    asset: Asset,
+    claim: Weak<Claim>,
+    // Modification times of the source files when last loaded
+    loaded: Vec<Option<SystemTime>>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReloadReport {
+    // Only the assets whose own files changed; see AssetManager
+    pub reloaded: Vec<AssetId>,
+    // Assets that failed to reload keep their previous version
+    pub errors: Vec<String>,
+}
+
+// Loads shaders, textures and meshes by logical path, found in the first
+// root that has them. Loading the same files twice gives the same asset;
+// it is freed once every handle to it is gone and collect_garbage runs.
+//
+// Hot reload watches each asset's own files only; dependents are not
+// tracked. Assets don't refer to each other, and passes look theirs up with
+// `get` every frame, so they draw with the new version without being told.
+// Anything computed from an asset's contents, like a compute shader's
+// local size, has to check `ReloadReport::reloaded` itself.
+pub struct AssetManager {
+    gl: Device,
+    roots: Vec<PathBuf>,
+    slots: HashMap<AssetId, Slot>,
+    by_source: HashMap<Source, AssetId>,
+    released: Rc<RefCell<Vec<AssetId>>>,
+    next_id: AssetId,
+}
+
+impl AssetManager {
+    pub fn new(gl: Device, roots: Vec<PathBuf>) -> Self {
+        AssetManager {
+            gl,
+            roots,
+            slots: HashMap::new(),
+            by_source: HashMap::new(),
+            released: Rc::new(RefCell::new(Vec::new())),
+            next_id: 0,
+        }
+    }
+
+    // Full path of a logical one, canonical so that different spellings of
+    // a file share its asset
+    pub fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf, String> {
+        let roots: Vec<&Path> = self.roots.iter().map(PathBuf::as_path).collect();
+        let found = resolve_path(path.as_ref(), &roots)?;
+        Ok(found.canonicalize().unwrap_or(found))
+    }
+
+    pub fn shader(&mut self, vertex: impl AsRef<Path>, fragment: impl AsRef<Path>) -> Result<Handle<Shader>, String> {
+        let source = Source::Program { vertex: self.resolve(vertex)?, fragment: self.resolve(fragment)? };
+        self.load(source)
+    }
+
+    pub fn compute_shader(&mut self, path: impl AsRef<Path>) -> Result<Handle<Shader>, String> {
+        let source = Source::Compute(self.resolve(path)?);
+        self.load(source)
+    }
+
+    // A Shadertoy pass with the common code of its project, if any
+    pub fn shadertoy_pass(&mut self, common: Option<&Path>, code: impl AsRef<Path>) -> Result<Handle<Shader>, String> {
+        let source = Source::ShadertoyPass { common: common.map(|c| self.resolve(c)).transpose()?, code: self.resolve(code)? };
+        self.load(source)
+    }
+
+    pub fn texture(&mut self, path: impl AsRef<Path>) -> Result<Handle<Texture>, String> {
+        let source = Source::Texture { path: self.resolve(path)?, flipped: false };
+        self.load(source)
+    }
+
+    // An image with its rows flipped, for shaders that take v = 0 as the
+    // bottom like Shadertoy does
+    pub fn flipped_texture(&mut self, path: impl AsRef<Path>) -> Result<Handle<Texture>, String> {
+        let source = Source::Texture { path: self.resolve(path)?, flipped: true };
+        self.load(source)
+    }
+
+    pub fn mesh(&mut self, path: impl AsRef<Path>) -> Result<Handle<Mesh>, String> {
+        let source = Source::Mesh(self.resolve(path)?);
+        self.load(source)
+    }
+
+    // A mesh built in code; every call makes a new one
+    pub fn add_mesh(&mut self, data: &MeshData) -> Result<Handle<Mesh>, String> {
+        let mesh = Mesh::from_data(self.gl.clone(), data)?;
+        Ok(self.insert(None, Asset::Mesh(mesh), Vec::new()))
+    }
+
+    fn load<T: AssetType>(&mut self, source: Source) -> Result<Handle<T>, String> {
+        if let Some(id) = self.by_source.get(&source).copied() {
+            let slot = self.slots.get_mut(&id).expect("indexed asset is loaded");
+            if let Some(claim) = slot.claim.upgrade() {
+                return Ok(Handle { claim, marker: PhantomData });
+            }
+            // Released but not collected yet, take it back
+            self.released.borrow_mut().retain(|r| *r != id);
+            let claim = Rc::new(Claim { id, released: self.released.clone() });
+            slot.claim = Rc::downgrade(&claim);
+            return Ok(Handle { claim, marker: PhantomData });
+        }
+
+        let loaded = source.files().iter().map(|f| modified(f)).collect();
+        let asset = source.load(&self.gl)?;
+        Ok(self.insert(Some(source), asset, loaded))
+    }
+
+    fn insert<T: AssetType>(&mut self, source: Option<Source>, asset: Asset, loaded: Vec<Option<SystemTime>>) -> Handle<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(source) = &source {
+            self.by_source.insert(source.clone(), id);
+        }
+        let claim = Rc::new(Claim { id, released: self.released.clone() });
+        self.slots.insert(id, Slot { source, asset, claim: Rc::downgrade(&claim), loaded });
+        Handle { claim, marker: PhantomData }
+    }
+
+    pub fn get<T: AssetType>(&self, handle: &Handle<T>) -> &T {
+        // The handle keeps its asset loaded, and its type follows its source
+        let slot = &self.slots[&handle.id()];
+        T::from_asset(&slot.asset).expect("handle type matches its asset")
+    }
+
+    // Size of the image a texture was loaded from, which reloads can change
+    pub fn texture_size(&self, handle: &Handle<Texture>) -> (u32, u32) {
+        match &self.slots[&handle.id()].asset {
+            Asset::Texture(_, size) => *size,
+            _ => unreachable!("texture handles point to textures"),
+        }
+    }
+
+    // Reloads every asset whose files changed on disk since it was loaded;
+    // meant to be called once per frame
+    pub fn reload_changed(&mut self) -> ReloadReport {
+        let mut report = ReloadReport::default();
+        let mut ids: Vec<AssetId> = self.slots.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let slot = self.slots.get_mut(&id).expect("listed asset is loaded");
+            let Some(source) = &slot.source else { continue };
+            let now: Vec<Option<SystemTime>> = source.files().iter().map(|f| modified(f)).collect();
+            if now == slot.loaded {
+                continue;
+            }
+            // Don't retry a broken file until it changes again
+            slot.loaded = now;
+            match source.load(&self.gl) {
+                Ok(asset) => {
+                    std::mem::replace(&mut slot.asset, asset).delete();
+                    report.reloaded.push(id);
+                }
+                Err(error) => report.errors.push(error),
+            }
+        }
+        report
+    }
+
+    // Frees the assets no handle points to anymore, returns how many
+    pub fn collect_garbage(&mut self) -> usize {
+        let released: Vec<AssetId> = self.released.borrow_mut().drain(..).collect();
+        let mut freed = 0;
+        for id in released {
+            let Some(slot) = self.slots.remove(&id) else { continue };
+            slot.asset.delete();
+            if let Some(source) = &slot.source {
+                self.by_source.remove(source);
+            }
+            freed += 1;
+        }
+        freed
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Makes every file count as changed, instead of waiting for the clock
+    // to tick between writes
+    #[cfg(test)]
+    pub fn forget_load_times(&mut self) {
+        for slot in self.slots.values_mut() {
+            slot.loaded.iter_mut().for_each(|time| *time = None);
+        }
+    }
+
+    // Frees everything, handles still around must not be used afterwards
+    pub fn delete(&mut self) {
+        for (_, slot) in self.slots.drain() {
+            slot.asset.delete();
+        }
+        self.by_source.clear();
+        self.released.borrow_mut().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+    use crate::test_util::TempDir;
+
+    const TRIANGLE: &str = r#"{"attributes": [2], "vertices": [0, 0, 1, 0, 0, 1], "indices": [0, 1, 2]}"#;
+
+    #[test]
+    fn equal_paths_share_one_asset_until_collected() {
+        let dir = TempDir::new("assets-share", &[("v.glsl", "void main() {}"), ("f.glsl", "void main() {}")]);
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut assets = AssetManager::new(device, vec![dir.0.clone()]);
+
+        let first = assets.shader("v.glsl", "f.glsl").unwrap();
+        let second = assets.shader(dir.0.join("v.glsl"), "f.glsl").unwrap();
+        assert_eq!(first.id(), second.id());
+        let program = assets.get(&first).id;
+
+        drop(first);
+        assert_eq!(assets.collect_garbage(), 0);
+        drop(second);
+        assert_eq!(assets.collect_garbage(), 1);
+        assert_eq!(assets.len(), 0);
+        assert!(recording.recorded(&GlCall::DeleteProgram(program)));
+    }
+
+    #[test]
+    fn released_assets_are_taken_back_before_collection() {
+        let dir = TempDir::new("assets-reuse", &[("mesh.json", TRIANGLE)]);
+        let device: Device = RecordingGl::new();
+        let mut assets = AssetManager::new(device, vec![dir.0.clone()]);
+        let id = assets.mesh("mesh.json").unwrap().id();
+        let again = assets.mesh("mesh.json").unwrap();
+        assert_eq!(again.id(), id);
+        assert_eq!(assets.collect_garbage(), 0);
+        assert_eq!(assets.get(&again).index_count, 3);
+    }
+
+    #[test]
+    fn meshes_are_checked_before_upload() {
+        let device: Device = RecordingGl::new();
+        let mut assets = AssetManager::new(device, Vec::new());
+        let ragged = MeshData { attributes: vec![3, 2], vertices: vec![0.0; 7], indices: vec![0] };
+        assert_eq!(assets.add_mesh(&ragged).err(), Some("7 floats don't make whole vertices of 5".to_string()));
+        let past_end = MeshData { attributes: vec![2], vertices: vec![0.0; 4], indices: vec![0, 1, 2] };
+        assert_eq!(assets.add_mesh(&past_end).err(), Some("index 2 is past the last of 2 vertices".to_string()));
+        // Meshes built in code are never shared
+        let quad = MeshData { attributes: vec![2], vertices: vec![0.0; 6], indices: vec![0, 1, 2] };
+        assert_ne!(assets.add_mesh(&quad).unwrap().id(), assets.add_mesh(&quad).unwrap().id());
+    }
+
+    #[test]
+    fn changed_files_reload_and_broken_ones_keep_the_old_version() {
+        let dir = TempDir::new("assets-reload", &[("mesh.json", TRIANGLE)]);
+        let device: Device = RecordingGl::new();
+        let mut assets = AssetManager::new(device, vec![dir.0.clone()]);
+        let mesh = assets.mesh("mesh.json").unwrap();
+        assert_eq!(assets.reload_changed(), ReloadReport::default());
+
+        let path = dir.0.join("mesh.json");
+        fs::write(&path, r#"{"attributes": [2], "vertices": [0, 0, 1, 0, 0, 1, 1, 1], "indices": [0, 1, 2, 1, 3, 2]}"#).unwrap();
+        assets.forget_load_times();
+        assert_eq!(assets.reload_changed().reloaded, vec![mesh.id()]);
+        assert_eq!(assets.get(&mesh).index_count, 6);
+
+        fs::write(&path, "{").unwrap();
+        assets.forget_load_times();
+        let report = assets.reload_changed();
+        assert!(report.reloaded.is_empty() && report.errors.len() == 1);
+        assert_eq!(assets.get(&mesh).index_count, 6);
+    }
+
+    #[test]
+    fn missing_files_name_the_roots_searched() {
+        let device: Device = RecordingGl::new();
+        let mut assets = AssetManager::new(device, vec![PathBuf::from("nowhere"), PathBuf::from("elsewhere")]);
+        let error = assets.texture("t.png").err().unwrap();
+        assert_eq!(error, "t.png: file not found (looked in nowhere, elsewhere)");
+    }
+}
@@ -0,0 +1,232 @@
+use gl::types::*;
+use crate::shader_pipeline::gl_device::Device;
+use crate::shader_pipeline::Shader::Shader;
+use crate::shader_pipeline::VAO::VAO;
+use super::{Binding, BufferId, BufferKind, Pass, ProgramId, RenderBackend, TextureId, VertexLayout};
+
+struct GlProgram {
+    shader: Shader,
+    layout: VertexLayout,
+}
+
+struct GlBuffer {
+    id: GLuint,
+    target: GLenum,
+}
+
+struct GlTexture {
+    id: GLuint,
+    width: u32,
+    height: u32,
+    // Created the first time the texture is rendered to or read back
+    framebuffer: Option<GLuint>,
+}
+
+// Backend on top of the raw OpenGL wrappers. Image rows are flipped on upload
+// and readback so that pixel data is top row first like on the other backends.
+pub struct GlBackend {
+    gl: Device,
+    // A core profile needs some VAO bound to draw; attributes are re-pointed per pass
+    vao: VAO,
+    programs: Vec<GlProgram>,
+    buffers: Vec<GlBuffer>,
+    textures: Vec<GlTexture>,
+    window_size: (u32, u32),
+}
+
+// Reverses the order of the rows of a tightly packed RGBA8 image
+fn flip_rows(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    let mut flipped = Vec::with_capacity(pixels.len());
+    for y in (0..height as usize).rev() {
+        flipped.extend_from_slice(&pixels[y * row..(y + 1) * row]);
+    }
+    flipped
+}
+
+impl GlBackend {
+    pub fn new(gl: Device) -> Self {
+        let vao = VAO::with_device(gl.clone());
+        GlBackend {
+            gl,
+            vao,
+            programs: Vec::new(),
+            buffers: Vec::new(),
+            textures: Vec::new(),
+            window_size: (800, 800),
+        }
+    }
+
+    // Size of the default framebuffer, used as the viewport for window passes
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = (width, height);
+    }
+
+    // Raw GL name of a texture, for code that still talks to GL directly
+    pub fn texture_name(&self, texture: TextureId) -> GLuint {
+        self.textures[texture.0].id
+    }
+
+    // Binds the framebuffer of a texture, creating it on first use
+    fn bind_texture_framebuffer(&mut self, texture: TextureId) -> Result<(), String> {
+        let gl = self.gl.clone();
+        let tex = &mut self.textures[texture.0];
+        match tex.framebuffer {
+            Some(fbo) => gl.bind_framebuffer(gl::FRAMEBUFFER, fbo),
+            None => {
+                let fbo = gl.gen_framebuffer();
+                gl.bind_framebuffer(gl::FRAMEBUFFER, fbo);
+                gl.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, tex.id, 0);
+                let status = gl.check_framebuffer_status(gl::FRAMEBUFFER);
+                if status != gl::FRAMEBUFFER_COMPLETE {
+                    gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+                    gl.delete_framebuffer(fbo);
+                    return Err(format!("framebuffer for texture {} is incomplete (status 0x{:x})", tex.id, status));
+                }
+                tex.framebuffer = Some(fbo);
+            }
+        }
+        Ok(())
+    }
+
+    // Deletes every object owned by the backend
+    pub fn delete(&self) {
+        for program in &self.programs {
+            program.shader.delete();
+        }
+        for buffer in &self.buffers {
+            self.gl.delete_buffer(buffer.id);
+        }
+        for texture in &self.textures {
+            if let Some(fbo) = texture.framebuffer {
+                self.gl.delete_framebuffer(fbo);
+            }
+            self.gl.delete_texture(texture.id);
+        }
+        self.vao.delete();
+    }
+}
+
+impl RenderBackend for GlBackend {
+    fn name(&self) -> String {
+        "OpenGL".to_string()
+    }
+
+    fn create_program(&mut self, vertex_src: &str, fragment_src: &str, layout: &VertexLayout) -> Result<ProgramId, String> {
+        let shader = Shader::from_source(self.gl.clone(), vertex_src, fragment_src);
+        if self.gl.get_program_iv(shader.id, gl::LINK_STATUS) != gl::TRUE as GLint {
+            let log = self.gl.get_program_info_log(shader.id);
+            shader.delete();
+            return Err(format!("program failed to link: {}", log));
+        }
+        self.programs.push(GlProgram { shader, layout: layout.clone() });
+        Ok(ProgramId(self.programs.len() - 1))
+    }
+
+    fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> BufferId {
+        let target = match kind {
+            BufferKind::Vertex => gl::ARRAY_BUFFER,
+            BufferKind::Index => gl::ELEMENT_ARRAY_BUFFER,
+            BufferKind::Uniform => gl::UNIFORM_BUFFER,
+        };
+        let id = self.gl.gen_buffer();
+        self.gl.bind_buffer(target, id);
+        self.gl.buffer_data(target, data, gl::DYNAMIC_DRAW);
+        self.gl.bind_buffer(target, 0);
+        self.buffers.push(GlBuffer { id, target });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    fn update_buffer(&mut self, buffer: BufferId, data: &[u8]) {
+        let buffer = &self.buffers[buffer.0];
+        self.gl.bind_buffer(buffer.target, buffer.id);
+        self.gl.buffer_sub_data(buffer.target, 0, data);
+        self.gl.bind_buffer(buffer.target, 0);
+    }
+
+    fn create_texture(&mut self, width: u32, height: u32, pixels: Option<&[u8]>) -> TextureId {
+        let id = self.gl.gen_texture();
+        self.gl.bind_texture(gl::TEXTURE_2D, id);
+        self.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        self.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        self.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        self.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        let data = pixels.map(|p| flip_rows(p, width, height)).unwrap_or_default();
+        self.gl.tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, &data);
+        self.gl.bind_texture(gl::TEXTURE_2D, 0);
+        self.textures.push(GlTexture { id, width, height, framebuffer: None });
+        TextureId(self.textures.len() - 1)
+    }
+
+    fn texture_size(&self, texture: TextureId) -> (u32, u32) {
+        let tex = &self.textures[texture.0];
+        (tex.width, tex.height)
+    }
+
+    fn run_pass(&mut self, pass: &Pass) -> Result<(), String> {
+        // Select the render target and cover it with the viewport
+        let (width, height) = match pass.target {
+            Some(target) => {
+                self.bind_texture_framebuffer(target)?;
+                self.texture_size(target)
+            }
+            None => {
+                self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+                self.window_size
+            }
+        };
+        self.gl.viewport(0, 0, width as i32, height as i32);
+
+        if let Some([r, g, b, a]) = pass.clear_color {
+            self.gl.clear_color(r, g, b, a);
+            self.gl.clear(gl::COLOR_BUFFER_BIT);
+        }
+
+        let program = &self.programs[pass.program.0];
+        program.shader.activate();
+
+        // Point the attributes of the program at the vertex buffer
+        self.vao.bind();
+        let vertex_buffer = &self.buffers[pass.vertex_buffer.0];
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, vertex_buffer.id);
+        for attrib in &program.layout.attribs {
+            self.gl.vertex_attrib_pointer(attrib.location, attrib.components as GLint, gl::FLOAT, gl::FALSE, program.layout.stride as GLsizei, attrib.offset as usize);
+            self.gl.enable_vertex_attrib_array(attrib.location);
+        }
+        self.gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.buffers[pass.index_buffer.0].id);
+
+        for binding in pass.bindings {
+            match *binding {
+                Binding::Uniform { binding, buffer } => {
+                    self.gl.bind_buffer_base(gl::UNIFORM_BUFFER, binding, self.buffers[buffer.0].id);
+                }
+                Binding::Texture { binding, texture } => {
+                    self.gl.active_texture(gl::TEXTURE0 + binding);
+                    self.gl.bind_texture(gl::TEXTURE_2D, self.textures[texture.0].id);
+                }
+                Binding::Sampler { .. } => {}
+            }
+        }
+
+        self.gl.draw_elements(gl::TRIANGLES, pass.index_count as GLsizei, gl::UNSIGNED_INT, 0);
+
+        // Unbind all to prevent accidental modifications
+        self.vao.unbind();
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, 0);
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        Ok(())
+    }
+
+    fn read_texture(&mut self, texture: TextureId) -> Result<Vec<u8>, String> {
+        let (width, height) = self.texture_size(texture);
+        self.bind_texture_framebuffer(texture)?;
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        self.gl.read_pixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, &mut pixels);
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        Ok(flip_rows(&pixels, width, height))
+    }
+
+    fn v_origin_top(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,164 @@
+use std::str::FromStr;
+
+pub mod gl_backend;
+pub mod wgpu_backend;
+
+pub use gl_backend::GlBackend;
+pub use wgpu_backend::WgpuBackend;
+
+// Handles returned by a backend; they index into the backend's own tables and
+// are only meaningful to the backend that created them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProgramId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureId(pub usize);
+
+// What a buffer is going to be bound as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Vertex,
+    Index,
+    Uniform,
+}
+
+// One float vertex attribute inside an interleaved vertex buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VertexAttrib {
+    pub location: u32,
+    pub components: u32,
+    // Offset from the start of the vertex in bytes
+    pub offset: u32,
+}
+
+// Layout of the vertex buffer a program reads from
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexLayout {
+    // Size of one vertex in bytes
+    pub stride: u32,
+    pub attribs: Vec<VertexAttrib>,
+}
+
+impl VertexLayout {
+    // Layout of the quad in main.rs: position, color and texture coordinates
+    pub fn pos_color_uv() -> Self {
+        let float = std::mem::size_of::<f32>() as u32;
+        VertexLayout {
+            stride: 8 * float,
+            attribs: vec![
+                VertexAttrib { location: 0, components: 3, offset: 0 },
+                VertexAttrib { location: 1, components: 3, offset: 3 * float },
+                VertexAttrib { location: 2, components: 2, offset: 6 * float },
+            ],
+        }
+    }
+}
+
+// A resource made visible to the shaders of a pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    // Uniform block at the given binding point
+    Uniform { binding: u32, buffer: BufferId },
+    // Texture at the given binding; on GL this is also the texture unit
+    Texture { binding: u32, texture: TextureId },
+    // Linear sampler at the given binding; GL samplers are combined with the
+    // texture, so the GL backend ignores these
+    Sampler { binding: u32 },
+}
+
+// One indexed draw into a target
+#[derive(Debug, Clone)]
+pub struct Pass<'a> {
+    pub program: ProgramId,
+    pub vertex_buffer: BufferId,
+    // u32 indices
+    pub index_buffer: BufferId,
+    pub index_count: u32,
+    pub bindings: &'a [Binding],
+    // None draws to the window; headless backends reject this
+    pub target: Option<TextureId>,
+    pub clear_color: Option<[f32; 4]>,
+}
+
+// Everything the scene/effect code needs from a graphics API. Textures are
+// always RGBA8 so that results can be compared between backends.
+pub trait RenderBackend {
+    // Human readable name of the backend and adapter
+    fn name(&self) -> String;
+
+    // Builds a program from GLSL vertex and fragment sources
+    fn create_program(&mut self, vertex_src: &str, fragment_src: &str, layout: &VertexLayout) -> Result<ProgramId, String>;
+
+    fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> BufferId;
+    // Overwrites the start of a buffer; the length must be a multiple of 4
+    fn update_buffer(&mut self, buffer: BufferId, data: &[u8]);
+
+    // Creates an RGBA8 texture, optionally filled with tightly packed pixels
+    fn create_texture(&mut self, width: u32, height: u32, pixels: Option<&[u8]>) -> TextureId;
+    fn texture_size(&self, texture: TextureId) -> (u32, u32);
+
+    fn run_pass(&mut self, pass: &Pass) -> Result<(), String>;
+
+    // Reads a texture back as tightly packed RGBA8 rows, top row first
+    fn read_texture(&mut self, texture: TextureId) -> Result<Vec<u8>, String>;
+
+    // Whether texture coordinate v = 0 samples the top row of the pixels a
+    // texture was created from. GL stores rows bottom up, so there it is the
+    // bottom row and quads reading textures need their v flipped.
+    fn v_origin_top(&self) -> bool;
+}
+
+// Backends that can be chosen at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    // Raw OpenGL through the shader_pipeline wrappers
+    Gl,
+    // wgpu restricted to the given APIs; all of them lets it pick the
+    // platform's preferred one
+    Wgpu(wgpu::Backends),
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gl" | "opengl" => Ok(BackendKind::Gl),
+            "wgpu" => Ok(BackendKind::Wgpu(wgpu::Backends::all())),
+            "vulkan" => Ok(BackendKind::Wgpu(wgpu::Backends::VULKAN)),
+            "metal" => Ok(BackendKind::Wgpu(wgpu::Backends::METAL)),
+            "dx12" => Ok(BackendKind::Wgpu(wgpu::Backends::DX12)),
+            "gles" => Ok(BackendKind::Wgpu(wgpu::Backends::GL)),
+            other => Err(format!("unknown backend '{}', expected gl, wgpu, vulkan, metal, dx12 or gles", other)),
+        }
+    }
+}
+
+// Creates a backend of the given kind. The GL backend needs a current context
+// with the function pointers loaded; the wgpu one runs headless.
+pub fn create_backend(kind: BackendKind) -> Result<Box<dyn RenderBackend>, String> {
+    match kind {
+        BackendKind::Gl => Ok(Box::new(GlBackend::new(crate::shader_pipeline::gl_device::real_device()))),
+        BackendKind::Wgpu(backends) => Ok(Box::new(WgpuBackend::new_headless(backends)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_names_restrict_wgpu_to_that_api() {
+        assert_eq!("gl".parse(), Ok(BackendKind::Gl));
+        assert_eq!("OpenGL".parse(), Ok(BackendKind::Gl));
+        assert_eq!("wgpu".parse(), Ok(BackendKind::Wgpu(wgpu::Backends::all())));
+        assert_eq!("vulkan".parse(), Ok(BackendKind::Wgpu(wgpu::Backends::VULKAN)));
+        assert_eq!("metal".parse(), Ok(BackendKind::Wgpu(wgpu::Backends::METAL)));
+        assert_eq!("DX12".parse(), Ok(BackendKind::Wgpu(wgpu::Backends::DX12)));
+        assert_eq!("gles".parse(), Ok(BackendKind::Wgpu(wgpu::Backends::GL)));
+        assert!("d3d9".parse::<BackendKind>().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use std::borrow::Cow;
+use wgpu::util::DeviceExt;
+use crate::translate;
+use super::{Binding, BufferId, BufferKind, Pass, ProgramId, RenderBackend, TextureId, VertexLayout};
+
+// All textures created by this backend use this format
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+struct WgpuProgram {
+    pipeline: wgpu::RenderPipeline,
+}
+
+struct WgpuTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+
+// Backend on top of wgpu, rendering into offscreen textures. It picks
+// whatever adapter wgpu prefers among the allowed APIs (Vulkan, Metal, DX12,
+// GLES or a software fallback) and never needs a window.
+pub struct WgpuBackend {
+    adapter_info: wgpu::AdapterInfo,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    sampler: wgpu::Sampler,
+    programs: Vec<WgpuProgram>,
+    buffers: Vec<wgpu::Buffer>,
+    textures: Vec<WgpuTexture>,
+}
+
+// Maps a component count onto the matching float vertex format
+fn float_format(components: u32) -> Result<wgpu::VertexFormat, String> {
+    match components {
+        1 => Ok(wgpu::VertexFormat::Float32),
+        2 => Ok(wgpu::VertexFormat::Float32x2),
+        3 => Ok(wgpu::VertexFormat::Float32x3),
+        4 => Ok(wgpu::VertexFormat::Float32x4),
+        n => Err(format!("vertex attributes must have 1 to 4 components, got {}", n)),
+    }
+}
+
+impl WgpuBackend {
+    // Creates a backend on the preferred adapter of the given APIs, without
+    // any surface
+    pub fn new_headless(backends: wgpu::Backends) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor { backends, ..Default::default() });
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or_else(|| format!("no suitable wgpu adapter found for {:?}", backends))?;
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("shaders"),
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+            },
+            None,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("linear"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(WgpuBackend {
+            adapter_info: adapter.get_info(),
+            device,
+            queue,
+            sampler,
+            programs: Vec::new(),
+            buffers: Vec::new(),
+            textures: Vec::new(),
+        })
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    // Translates GLSL through naga so that desktop-style sources are accepted
+    fn shader_module(&self, label: &str, source: &str, stage: naga::ShaderStage) -> Result<wgpu::ShaderModule, String> {
+        let translated = translate::translate(label, source, stage).map_err(|diagnostics| {
+            diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
+        })?;
+        Ok(self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Naga(Cow::Owned(translated.module)),
+        }))
+    }
+}
+
+impl RenderBackend for WgpuBackend {
+    fn name(&self) -> String {
+        format!("wgpu ({:?}, {})", self.adapter_info.backend, self.adapter_info.name)
+    }
+
+    fn create_program(&mut self, vertex_src: &str, fragment_src: &str, layout: &VertexLayout) -> Result<ProgramId, String> {
+        let vertex = self.shader_module("vertex", vertex_src, naga::ShaderStage::Vertex)?;
+        let fragment = self.shader_module("fragment", fragment_src, naga::ShaderStage::Fragment)?;
+
+        // Built before the error scope is pushed, so failing here can't leave
+        // it open
+        let attributes = layout
+            .attribs
+            .iter()
+            .map(|a| {
+                Ok(wgpu::VertexAttribute {
+                    format: float_format(a.components)?,
+                    offset: a.offset as u64,
+                    shader_location: a.location,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Pipeline errors are reported through the error scope instead of the
+        // default panicking handler
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            // Derive the bind group layout from the shaders
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: layout.stride as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &attributes,
+                }],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        if let Some(error) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(format!("program failed to build: {}", error));
+        }
+
+        self.programs.push(WgpuProgram { pipeline });
+        Ok(ProgramId(self.programs.len() - 1))
+    }
+
+    fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> BufferId {
+        let usage = match kind {
+            BufferKind::Vertex => wgpu::BufferUsages::VERTEX,
+            BufferKind::Index => wgpu::BufferUsages::INDEX,
+            BufferKind::Uniform => wgpu::BufferUsages::UNIFORM,
+        };
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: data,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+        });
+        self.buffers.push(buffer);
+        BufferId(self.buffers.len() - 1)
+    }
+
+    fn update_buffer(&mut self, buffer: BufferId, data: &[u8]) {
+        self.queue.write_buffer(&self.buffers[buffer.0], 0, data);
+    }
+
+    fn create_texture(&mut self, width: u32, height: u32, pixels: Option<&[u8]>) -> TextureId {
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        if let Some(pixels) = pixels {
+            self.queue.write_texture(
+                texture.as_image_copy(),
+                pixels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(width * 4),
+                    rows_per_image: Some(height),
+                },
+                size,
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.textures.push(WgpuTexture { texture, view, width, height });
+        TextureId(self.textures.len() - 1)
+    }
+
+    fn texture_size(&self, texture: TextureId) -> (u32, u32) {
+        let tex = &self.textures[texture.0];
+        (tex.width, tex.height)
+    }
+
+    fn run_pass(&mut self, pass: &Pass) -> Result<(), String> {
+        let target = pass.target.ok_or_else(|| "the wgpu backend is headless and cannot draw to a window".to_string())?;
+        let program = &self.programs[pass.program.0];
+
+        let entries: Vec<wgpu::BindGroupEntry> = pass
+            .bindings
+            .iter()
+            .map(|binding| match *binding {
+                Binding::Uniform { binding, buffer } => wgpu::BindGroupEntry {
+                    binding,
+                    resource: self.buffers[buffer.0].as_entire_binding(),
+                },
+                Binding::Texture { binding, texture } => wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(&self.textures[texture.0].view),
+                },
+                Binding::Sampler { binding } => wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            })
+            .collect();
+
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let bind_group = if entries.is_empty() {
+            None
+        } else {
+            Some(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &program.pipeline.get_bind_group_layout(0),
+                entries: &entries,
+            }))
+        };
+
+        let load = match pass.clear_color {
+            Some([r, g, b, a]) => wgpu::LoadOp::Clear(wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: a as f64 }),
+            None => wgpu::LoadOp::Load,
+        };
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.textures[target.0].view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&program.pipeline);
+            if let Some(bind_group) = &bind_group {
+                render_pass.set_bind_group(0, bind_group, &[]);
+            }
+            render_pass.set_vertex_buffer(0, self.buffers[pass.vertex_buffer.0].slice(..));
+            render_pass.set_index_buffer(self.buffers[pass.index_buffer.0].slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..pass.index_count, 0, 0..1);
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(error.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn read_texture(&mut self, texture: TextureId) -> Result<Vec<u8>, String> {
+        let tex = &self.textures[texture.0];
+        let row = tex.width * 4;
+        // Buffer copies need rows aligned to 256 bytes
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = row.div_ceil(align) * align;
+
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded_row * tex.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            tex.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &staging,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(tex.height),
+                },
+            },
+            wgpu::Extent3d { width: tex.width, height: tex.height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+
+        // Strip the row padding
+        let mut pixels = Vec::with_capacity((row * tex.height) as usize);
+        {
+            let mapped = slice.get_mapped_range();
+            for y in 0..tex.height as usize {
+                let start = y * padded_row as usize;
+                pixels.extend_from_slice(&mapped[start..start + row as usize]);
+            }
+        }
+        staging.unmap();
+        Ok(pixels)
+    }
+
+    fn v_origin_top(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::reflect::TypePath;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{AsBindGroup, RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError};
+use bevy::sprite::{Material2d, Material2dKey, Material2dPlugin, MaterialMesh2dBundle};
+use bevy::utils::BoxedFuture;
+use bevy::window::PrimaryWindow;
+use std::fmt;
+use crate::check;
+use crate::translate;
+
+// Bevy binds material resources to group 1; view and mesh use 0 and 2
+const MATERIAL_GROUP: u32 = 1;
+
+// Error of the .glsl loader, with the translation diagnostics joined together
+#[derive(Debug)]
+pub struct GlslLoadError(String);
+
+impl fmt::Display for GlslLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for GlslLoadError {}
+
+// Loads our .glsl effects as Bevy shaders. The stage comes from the
+// `#pragma shader_stage(...)` line, the source goes through the naga
+// translation (so loose uniforms and combined samplers work) and every
+// resource is moved into the material bind group.
+#[derive(Default)]
+pub struct GlslShaderLoader;
+
+impl AssetLoader for GlslShaderLoader {
+    type Asset = Shader;
+    type Settings = ();
+    type Error = GlslLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Shader, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await.map_err(|e| GlslLoadError(e.to_string()))?;
+            let source = String::from_utf8(bytes).map_err(|e| GlslLoadError(e.to_string()))?;
+
+            let path = load_context.path().to_path_buf();
+            let file = path.display().to_string();
+            let stage = check::infer_stage(&path, &source)
+                .ok_or_else(|| GlslLoadError(format!("{}: missing `#pragma shader_stage(...)`", file)))?;
+
+            let mut translated = translate::translate(&file, &source, stage).map_err(|diagnostics| {
+                GlslLoadError(diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))
+            })?;
+            translated.move_to_group(MATERIAL_GROUP);
+            let wgsl = translated.to_wgsl().map_err(GlslLoadError)?;
+            Ok(Shader::from_wgsl(wgsl, file))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["glsl"]
+    }
+}
+
+// Uniform block every effect material receives at binding 0. In GLSL:
+// layout(binding = 0) uniform Effect { float time; float delta; vec2 resolution; vec4 params; };
+#[derive(ShaderType, Clone, Copy, Debug, Default)]
+pub struct EffectUniforms {
+    // Seconds since startup
+    pub time: f32,
+    // Seconds since the previous frame
+    pub delta: f32,
+    // Size of the primary window in physical pixels
+    pub resolution: Vec2,
+    // Free parameters driven by the EffectParams resource
+    pub params: Vec4,
+}
+
+// Game-controlled values copied into `EffectUniforms::params` every frame
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct EffectParams(pub Vec4);
+
+// A 2D material running one of our GLSL fragment shaders on top of Bevy's
+// default mesh vertex shader. The fragment shader reads the mesh UVs as
+// `layout(location = 2) in vec2 uv;`, and the optional input texture as
+// `layout(binding = 1) uniform sampler2D` (its sampler lands on binding 2).
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+#[bind_group_data(GlslMaterialKey)]
+pub struct GlslMaterial {
+    #[uniform(0)]
+    pub uniforms: EffectUniforms,
+    #[texture(1)]
+    #[sampler(2)]
+    pub input: Option<Handle<Image>>,
+    pub shader: Handle<Shader>,
+    // Params of this material only, used instead of EffectParams when set
+    pub params: Option<Vec4>,
+}
+
+impl GlslMaterial {
+    pub fn new(shader: Handle<Shader>, input: Option<Handle<Image>>) -> Self {
+        GlslMaterial { uniforms: EffectUniforms::default(), input, shader, params: None }
+    }
+}
+
+// Pipelines are specialized per fragment shader, since Material2d only
+// knows one shader per type
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct GlslMaterialKey {
+    shader: Handle<Shader>,
+}
+
+impl From<&GlslMaterial> for GlslMaterialKey {
+    fn from(material: &GlslMaterial) -> Self {
+        GlslMaterialKey { shader: material.shader.clone() }
+    }
+}
+
+impl Material2d for GlslMaterial {
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        let fragment = descriptor.fragment.as_mut().unwrap();
+        fragment.shader = key.bind_group_data.shader;
+        // naga names the entry point of GLSL shaders after the GLSL function
+        fragment.entry_point = "main".into();
+        Ok(())
+    }
+}
+
+// Marks a quad that should always cover the primary window, turning its
+// material into a post-process over its input image
+#[derive(Component, Default)]
+pub struct FullscreenEffect;
+
+// Spawns a window-covering quad running `shader` over `input`
+pub fn spawn_fullscreen_effect(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<GlslMaterial>,
+    shader: Handle<Shader>,
+    input: Option<Handle<Image>>,
+) -> Entity {
+    commands
+        .spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))).into(),
+                material: materials.add(GlslMaterial::new(shader, input)),
+                ..default()
+            },
+            FullscreenEffect,
+        ))
+        .id()
+}
+
+// Copies time, window size and EffectParams (unless the material has its
+// own params) into every effect material
+fn update_effect_uniforms(
+    time: Res<Time>,
+    params: Res<EffectParams>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut materials: ResMut<Assets<GlslMaterial>>,
+) {
+    let resolution = windows
+        .get_single()
+        .map(|w| Vec2::new(w.physical_width() as f32, w.physical_height() as f32))
+        .unwrap_or_default();
+    for (_, material) in materials.iter_mut() {
+        material.uniforms = EffectUniforms {
+            time: time.elapsed_seconds(),
+            delta: time.delta_seconds(),
+            resolution,
+            params: material.params.unwrap_or(params.0),
+        };
+    }
+}
+
+// Keeps fullscreen effect quads the size of the window
+fn fit_fullscreen_effects(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut quads: Query<&mut Transform, With<FullscreenEffect>>,
+) {
+    let Ok(window) = windows.get_single() else { return };
+    for mut transform in quads.iter_mut() {
+        transform.scale = Vec3::new(window.width(), window.height(), 1.0);
+    }
+}
+
+// Registers the .glsl loader, the effect material and the systems feeding
+// its uniforms. Hot reload follows the AssetPlugin's file watching.
+pub struct ShaderPipelinePlugin;
+
+impl Plugin for ShaderPipelinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset_loader::<GlslShaderLoader>()
+            .add_plugins(Material2dPlugin::<GlslMaterial>::default())
+            .init_resource::<EffectParams>()
+            .add_systems(Update, (update_effect_uniforms, fit_fullscreen_effects));
+    }
+}
+
+// Opens a Bevy window showing `effect` over `image` (both relative to the
+// assets folder), reloading the shader whenever it changes on disk
+pub fn run_demo(effect: &str, image: Option<&str>) {
+    let effect = effect.to_string();
+    let image = image.map(str::to_string);
+    App::new()
+        .add_plugins((
+            DefaultPlugins.set(AssetPlugin {
+                watch_for_changes_override: Some(true),
+                ..default()
+            }),
+            ShaderPipelinePlugin,
+        ))
+        .add_systems(
+            Startup,
+            move |mut commands: Commands,
+                  asset_server: Res<AssetServer>,
+                  mut meshes: ResMut<Assets<Mesh>>,
+                  mut materials: ResMut<Assets<GlslMaterial>>| {
+                commands.spawn(Camera2dBundle::default());
+                let shader = asset_server.load(effect.clone());
+                let input = image.as_ref().map(|path| asset_server.load(path.clone()));
+                spawn_fullscreen_effect(&mut commands, &mut meshes, &mut materials, shader, input);
+            },
+        )
+        .run();
+}
@@ -0,0 +1,608 @@
+use glfw::{Action, Key, MouseButton, WindowEvent};
+use nalgebra::{Matrix4, Point3, Vector2, Vector3};
+use crate::Shader;
+
+// Orthographic camera over a map, in map pixels with y down. `center` is
+// the map point in the middle of the viewport and `zoom` the number of
+// screen pixels per map pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera2D {
+    pub center: Vector2<f32>,
+    pub zoom: f32,
+    // Size of the viewport in screen pixels
+    pub viewport: Vector2<f32>,
+    pub map_size: Vector2<f32>,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    // World maps repeat horizontally instead of stopping at the edges
+    pub wrap: bool,
+}
+
+// What every shader drawing in map space receives: `u_viewProj` (map pixels
+// to clip space), `u_viewport` (screen pixels) and `u_zoom`. With wrapping the
+// map is drawn once per visible copy, offset by `u_mapOffset` map pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraUniforms {
+    pub view_proj: [f32; 16],
+    pub viewport: [f32; 2],
+    pub zoom: f32,
+    pub map_offsets: Vec<f32>,
+}
+
+impl CameraUniforms {
+    // Uploads the camera to the active program; uniforms the program doesn't
+    // use have location -1, which GL ignores
+    pub fn apply(&self, shader: &Shader) {
+        let gl = shader.device();
+        gl.uniform_matrix_4fv(shader.uniform_location("u_viewProj"), &self.view_proj);
+        gl.uniform_2f(shader.uniform_location("u_viewport"), self.viewport[0], self.viewport[1]);
+        gl.uniform_1f(shader.uniform_location("u_zoom"), self.zoom);
+    }
+
+    // Calls `draw` once per visible copy of the map with `u_mapOffset` set
+    pub fn draw_copies(&self, shader: &Shader, mut draw: impl FnMut()) {
+        let location = shader.uniform_location("u_mapOffset");
+        for offset in &self.map_offsets {
+            shader.device().uniform_1f(location, *offset);
+            draw();
+        }
+    }
+}
+
+impl Camera2D {
+    // Camera showing the whole map, able to zoom out to half of that and in
+    // to 32 screen pixels per map pixel
+    pub fn new(map_size: (u32, u32), viewport: (u32, u32), wrap: bool) -> Self {
+        let mut camera = Camera2D {
+            center: Vector2::new(map_size.0 as f32 * 0.5, map_size.1 as f32 * 0.5),
+            zoom: 1.0,
+            viewport: Vector2::new(viewport.0.max(1) as f32, viewport.1.max(1) as f32),
+            map_size: Vector2::new(map_size.0 as f32, map_size.1 as f32),
+            min_zoom: 0.0,
+            max_zoom: 32.0,
+            wrap,
+        };
+        camera.zoom = camera.fit_zoom();
+        camera.min_zoom = camera.zoom * 0.5;
+        camera.constrain();
+        camera
+    }
+
+    // Zoom at which the whole map fits the viewport
+    pub fn fit_zoom(&self) -> f32 {
+        (self.viewport.x / self.map_size.x).min(self.viewport.y / self.map_size.y)
+    }
+
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        self.viewport = Vector2::new(width.max(1) as f32, height.max(1) as f32);
+        self.constrain();
+    }
+
+    // Map pixels to clip space
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        let half = self.viewport / (2.0 * self.zoom);
+        // Bottom and top are swapped so that map y grows downwards on screen
+        Matrix4::new_orthographic(
+            self.center.x - half.x,
+            self.center.x + half.x,
+            self.center.y + half.y,
+            self.center.y - half.y,
+            -1.0,
+            1.0,
+        )
+    }
+
+    // Map point under a screen position (pixels from the top left), folded
+    // back into the map when wrapping
+    pub fn screen_to_map(&self, screen: Vector2<f32>) -> Vector2<f32> {
+        let mut point = self.center + (screen - self.viewport * 0.5) / self.zoom;
+        if self.wrap {
+            point.x = point.x.rem_euclid(self.map_size.x);
+        }
+        point
+    }
+
+    pub fn map_to_screen(&self, point: Vector2<f32>) -> Vector2<f32> {
+        (point - self.center) * self.zoom + self.viewport * 0.5
+    }
+
+    // Moves the map along with a drag of `delta` screen pixels
+    pub fn pan(&mut self, delta: Vector2<f32>) {
+        self.center -= delta / self.zoom;
+        self.constrain();
+    }
+
+    // Zooms by `factor`, keeping the map point under `screen` in place
+    pub fn zoom_at(&mut self, factor: f32, screen: Vector2<f32>) {
+        let before = self.center + (screen - self.viewport * 0.5) / self.zoom;
+        self.zoom = (self.zoom * factor).clamp(self.min_zoom, self.max_zoom);
+        self.center = before - (screen - self.viewport * 0.5) / self.zoom;
+        self.constrain();
+    }
+
+    // Keeps the zoom within its limits and the map on screen: an axis
+    // smaller than the viewport is centered, a larger one can't be scrolled
+    // past its edges. Wrapping maps scroll freely sideways.
+    fn constrain(&mut self) {
+        self.zoom = self.zoom.clamp(self.min_zoom, self.max_zoom.max(self.min_zoom));
+        let half = self.viewport / (2.0 * self.zoom);
+        let clamp_axis = |center: f32, half: f32, size: f32| {
+            if half * 2.0 >= size {
+                size * 0.5
+            } else {
+                center.clamp(half, size - half)
+            }
+        };
+        self.center.y = clamp_axis(self.center.y, half.y, self.map_size.y);
+        self.center.x = if self.wrap {
+            self.center.x.rem_euclid(self.map_size.x)
+        } else {
+            clamp_axis(self.center.x, half.x, self.map_size.x)
+        };
+    }
+
+    // Horizontal offsets of the copies of the map that are on screen
+    pub fn map_offsets(&self) -> Vec<f32> {
+        if !self.wrap {
+            return vec![0.0];
+        }
+        let half = self.viewport.x / (2.0 * self.zoom);
+        let first = ((self.center.x - half) / self.map_size.x).floor() as i32;
+        let last = ((self.center.x + half) / self.map_size.x).floor() as i32;
+        (first..=last).map(|copy| copy as f32 * self.map_size.x).collect()
+    }
+
+    pub fn uniforms(&self) -> CameraUniforms {
+        let mut view_proj = [0.0; 16];
+        view_proj.copy_from_slice(self.view_proj().as_slice());
+        CameraUniforms { view_proj, viewport: [self.viewport.x, self.viewport.y], zoom: self.zoom, map_offsets: self.map_offsets() }
+    }
+}
+
+// Drives a Camera2D from GLFW events: drag with the left or middle button to
+// pan, scroll to zoom toward the cursor. The window needs cursor position,
+// mouse button and scroll polling enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera2DController {
+    // Zoom factor per scroll notch
+    pub zoom_step: f32,
+    dragging: bool,
+    cursor: Vector2<f32>,
+}
+
+impl Default for Camera2DController {
+    fn default() -> Self {
+        Camera2DController { zoom_step: 1.15, dragging: false, cursor: Vector2::zeros() }
+    }
+}
+
+impl Camera2DController {
+    // Returns whether the camera moved
+    pub fn handle_event(&mut self, camera: &mut Camera2D, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseButton(MouseButton::Button1 | MouseButton::Button3, action, _) => {
+                self.dragging = *action != Action::Release;
+                false
+            }
+            WindowEvent::CursorPos(x, y) => {
+                let cursor = Vector2::new(*x as f32, *y as f32);
+                let delta = cursor - self.cursor;
+                self.cursor = cursor;
+                if self.dragging {
+                    camera.pan(delta);
+                }
+                self.dragging
+            }
+            WindowEvent::Scroll(_, dy) => {
+                camera.zoom_at(self.zoom_step.powf(*dy as f32), self.cursor);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Perspective camera, right handed with y up. Yaw 0 looks down -z, positive
+// yaw turns right and positive pitch looks up; angles are in radians.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera3D {
+    pub position: Point3<f32>,
+    pub yaw: f32,
+    pub pitch: f32,
+    // Vertical field of view
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+    // Size of the viewport in screen pixels, gives the aspect ratio
+    pub viewport: Vector2<f32>,
+}
+
+impl Camera3D {
+    pub fn new(position: Point3<f32>, viewport: (u32, u32)) -> Self {
+        Camera3D {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            fov_y: 60f32.to_radians(),
+            near: 0.05,
+            far: 1000.0,
+            viewport: Vector2::new(viewport.0.max(1) as f32, viewport.1.max(1) as f32),
+        }
+    }
+
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        self.viewport = Vector2::new(width.max(1) as f32, height.max(1) as f32);
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.sin() * self.pitch.cos(), self.pitch.sin(), -self.yaw.cos() * self.pitch.cos())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.cos(), 0.0, self.yaw.sin())
+    }
+
+    // Turns the camera toward a point
+    pub fn look_at(&mut self, target: Point3<f32>) {
+        let direction = target - self.position;
+        if direction.norm() > 0.0 {
+            self.yaw = direction.x.atan2(-direction.z);
+            self.pitch = (direction.y / direction.norm()).clamp(-1.0, 1.0).asin();
+        }
+    }
+
+    pub fn view(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(&self.position, &(self.position + self.forward()), &Vector3::y())
+    }
+
+    pub fn projection(&self) -> Matrix4<f32> {
+        Matrix4::new_perspective(self.viewport.x / self.viewport.y, self.fov_y, self.near, self.far)
+    }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.projection() * self.view()
+    }
+
+    pub fn uniforms(&self) -> CameraUniforms {
+        let mut view_proj = [0.0; 16];
+        view_proj.copy_from_slice(self.view_proj().as_slice());
+        CameraUniforms { view_proj, viewport: [self.viewport.x, self.viewport.y], zoom: 1.0, map_offsets: vec![0.0] }
+    }
+}
+
+// Keeps the pitch short of straight up or down, where look_at_rh breaks down
+const MAX_PITCH: f32 = 1.55;
+
+// Something that moves a Camera3D from GLFW events. `handle_event` sees every
+// event, `update` runs once per frame with the frame time in seconds.
+pub trait CameraController {
+    // Returns whether the camera moved
+    fn handle_event(&mut self, camera: &mut Camera3D, event: &WindowEvent) -> bool;
+    fn update(&mut self, _camera: &mut Camera3D, _dt: f32) {}
+}
+
+// Circles around a target: drag with the left button to rotate, with the
+// right or middle button to move the target, scroll to get closer
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // Radians per pixel dragged
+    pub rotate_speed: f32,
+    // Distance factor per scroll notch
+    pub zoom_step: f32,
+    rotating: bool,
+    panning: bool,
+    cursor: Option<Vector2<f32>>,
+}
+
+impl OrbitController {
+    // Starts from where the camera is, orbiting `target`
+    pub fn new(camera: &mut Camera3D, target: Point3<f32>) -> Self {
+        camera.look_at(target);
+        OrbitController {
+            target,
+            distance: (camera.position - target).norm().max(0.01),
+            min_distance: 0.1,
+            max_distance: 500.0,
+            rotate_speed: 0.005,
+            zoom_step: 1.1,
+            rotating: false,
+            panning: false,
+            cursor: None,
+        }
+    }
+
+    fn place(&self, camera: &mut Camera3D) {
+        camera.position = self.target - camera.forward() * self.distance;
+    }
+}
+
+impl CameraController for OrbitController {
+    fn handle_event(&mut self, camera: &mut Camera3D, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseButton(MouseButton::Button1, action, _) => {
+                self.rotating = *action != Action::Release;
+                false
+            }
+            WindowEvent::MouseButton(MouseButton::Button2 | MouseButton::Button3, action, _) => {
+                self.panning = *action != Action::Release;
+                false
+            }
+            WindowEvent::CursorPos(x, y) => {
+                let cursor = Vector2::new(*x as f32, *y as f32);
+                let delta = self.cursor.map_or(Vector2::zeros(), |last| cursor - last);
+                self.cursor = Some(cursor);
+                if self.rotating {
+                    camera.yaw += delta.x * self.rotate_speed;
+                    camera.pitch = (camera.pitch - delta.y * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+                } else if self.panning {
+                    // Move the target so that the point under the cursor follows it
+                    let scale = 2.0 * self.distance * (camera.fov_y * 0.5).tan() / camera.viewport.y;
+                    let up = camera.right().cross(&camera.forward());
+                    self.target += (-camera.right() * delta.x + up * delta.y) * scale;
+                } else {
+                    return false;
+                }
+                self.place(camera);
+                true
+            }
+            WindowEvent::Scroll(_, dy) => {
+                self.distance = (self.distance * self.zoom_step.powf(-*dy as f32)).clamp(self.min_distance, self.max_distance);
+                self.place(camera);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Free flight: hold the right button and move the mouse to look around,
+// WASD to move, Q/E to go down/up and Left Shift to go faster
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlyController {
+    // Units per second
+    pub speed: f32,
+    pub fast_factor: f32,
+    // Radians per pixel
+    pub look_speed: f32,
+    looking: bool,
+    cursor: Option<Vector2<f32>>,
+    held: Vec<Key>,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        FlyController { speed: 2.0, fast_factor: 4.0, look_speed: 0.003, looking: false, cursor: None, held: Vec::new() }
+    }
+}
+
+impl CameraController for FlyController {
+    fn handle_event(&mut self, camera: &mut Camera3D, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseButton(MouseButton::Button2, action, _) => {
+                self.looking = *action != Action::Release;
+                false
+            }
+            WindowEvent::CursorPos(x, y) => {
+                let cursor = Vector2::new(*x as f32, *y as f32);
+                let delta = self.cursor.map_or(Vector2::zeros(), |last| cursor - last);
+                self.cursor = Some(cursor);
+                if self.looking {
+                    camera.yaw += delta.x * self.look_speed;
+                    camera.pitch = (camera.pitch - delta.y * self.look_speed).clamp(-MAX_PITCH, MAX_PITCH);
+                }
+                self.looking
+            }
+            WindowEvent::Key(key, _, Action::Press, _) => {
+                if !self.held.contains(key) {
+                    self.held.push(*key);
+                }
+                false
+            }
+            WindowEvent::Key(key, _, Action::Release, _) => {
+                self.held.retain(|k| k != key);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn update(&mut self, camera: &mut Camera3D, dt: f32) {
+        let axis = |plus: Key, minus: Key| self.held.contains(&plus) as i32 as f32 - self.held.contains(&minus) as i32 as f32;
+        let movement = camera.forward() * axis(Key::W, Key::S) + camera.right() * axis(Key::D, Key::A) + Vector3::y() * axis(Key::E, Key::Q);
+        if movement.norm() > 0.0 {
+            let fast = if self.held.contains(&Key::LeftShift) { self.fast_factor } else { 1.0 };
+            camera.position += movement.normalize() * self.speed * fast * dt;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Vector2<f32>, b: Vector2<f32>) {
+        assert!((a - b).norm() < 1e-4, "{} != {}", a, b);
+    }
+
+    // A 200x100 map in a 400x200 viewport, fitting at zoom 2
+    fn map_camera(wrap: bool) -> Camera2D {
+        Camera2D::new((200, 100), (400, 200), wrap)
+    }
+
+    #[test]
+    fn new_cameras_fit_the_map() {
+        let camera = map_camera(false);
+        assert_eq!((camera.zoom, camera.min_zoom), (2.0, 1.0));
+        assert_eq!(camera.center, Vector2::new(100.0, 50.0));
+        // A minimized window has a 0x0 framebuffer
+        let camera = Camera2D::new((200, 100), (0, 0), false);
+        assert!(camera.zoom > 0.0 && camera.center.x.is_finite() && camera.center.y.is_finite());
+    }
+
+    #[test]
+    fn screen_and_map_positions_round_trip() {
+        let mut camera = map_camera(false);
+        camera.zoom_at(3.0, Vector2::new(100.0, 60.0));
+        camera.pan(Vector2::new(-30.0, 12.0));
+        for screen in [Vector2::new(0.0, 0.0), Vector2::new(123.0, 45.0), Vector2::new(400.0, 200.0)] {
+            assert_near(camera.map_to_screen(camera.screen_to_map(screen)), screen);
+        }
+        assert_near(camera.screen_to_map(Vector2::new(200.0, 100.0)), camera.center);
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut camera = map_camera(false);
+        let cursor = Vector2::new(300.0, 50.0);
+        let before = camera.screen_to_map(cursor);
+        camera.zoom_at(2.0, cursor);
+        assert_eq!(camera.zoom, 4.0);
+        assert_near(camera.screen_to_map(cursor), before);
+    }
+
+    #[test]
+    fn zoom_and_scrolling_stay_within_limits() {
+        let mut camera = map_camera(false);
+        camera.zoom_at(1000.0, Vector2::new(200.0, 100.0));
+        assert_eq!(camera.zoom, camera.max_zoom);
+
+        // Zoomed out past the map, both axes are centered
+        camera.zoom_at(0.001, Vector2::new(0.0, 0.0));
+        assert_eq!(camera.zoom, camera.min_zoom);
+        assert_eq!(camera.center, Vector2::new(100.0, 50.0));
+
+        // Zoomed in, the map can't be dragged past its edges
+        camera.zoom_at(4.0, Vector2::new(200.0, 100.0));
+        camera.pan(Vector2::new(10000.0, 10000.0));
+        assert_eq!(camera.center, Vector2::new(50.0, 25.0));
+        camera.pan(Vector2::new(-10000.0, -10000.0));
+        assert_eq!(camera.center, Vector2::new(150.0, 75.0));
+    }
+
+    #[test]
+    fn wrapping_maps_draw_every_visible_copy() {
+        assert_eq!(map_camera(false).map_offsets(), vec![0.0]);
+
+        let mut camera = map_camera(true);
+        camera.zoom_at(0.5, Vector2::new(200.0, 100.0));
+        // 400 map pixels wide around x = 100
+        assert_eq!(camera.map_offsets(), vec![-200.0, 0.0, 200.0]);
+
+        camera.zoom_at(4.0, Vector2::new(200.0, 100.0));
+        assert_eq!(camera.map_offsets(), vec![0.0]);
+        // Dragging past the left edge folds the center back into the map
+        camera.pan(Vector2::new(500.0, 0.0));
+        assert_near(camera.center, Vector2::new(175.0, 50.0));
+        assert_eq!(camera.map_offsets(), vec![0.0, 200.0]);
+        assert!(camera.screen_to_map(Vector2::new(400.0, 100.0)).x < 200.0);
+    }
+
+    fn assert_near3(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).norm() < 1e-4, "{} != {}", a, b);
+    }
+
+    fn press(button: MouseButton) -> WindowEvent {
+        WindowEvent::MouseButton(button, Action::Press, glfw::Modifiers::empty())
+    }
+
+    fn key(key: Key, action: Action) -> WindowEvent {
+        WindowEvent::Key(key, 0, action, glfw::Modifiers::empty())
+    }
+
+    #[test]
+    fn looking_at_a_point_points_forward_at_it() {
+        let mut camera = Camera3D::new(Point3::new(1.0, 2.0, 3.0), (800, 600));
+        assert_near3(camera.forward(), -Vector3::z());
+        assert_near3(camera.right(), Vector3::x());
+        for target in [Point3::new(4.0, -1.0, 0.0), Point3::new(-2.0, 5.0, 7.0), Point3::new(1.0, 2.5, -10.0)] {
+            camera.look_at(target);
+            assert_near3(camera.forward(), (target - camera.position).normalize());
+            assert!(camera.right().dot(&camera.forward()).abs() < 1e-5);
+        }
+        // The view matrix puts what is ahead on -z
+        let ahead = camera.view().transform_point(&(camera.position + camera.forward() * 3.0));
+        assert_near3(ahead.coords, Vector3::new(0.0, 0.0, -3.0));
+    }
+
+    #[test]
+    fn pitch_stops_short_of_straight_up_and_down() {
+        let mut camera = Camera3D::new(Point3::origin(), (800, 600));
+        let mut orbit = OrbitController::new(&mut camera, Point3::new(0.0, 0.0, -5.0));
+        orbit.handle_event(&mut camera, &press(MouseButton::Button1));
+        orbit.handle_event(&mut camera, &WindowEvent::CursorPos(0.0, 0.0));
+        orbit.handle_event(&mut camera, &WindowEvent::CursorPos(0.0, -100000.0));
+        assert_eq!(camera.pitch, MAX_PITCH);
+
+        let mut fly = FlyController::default();
+        fly.handle_event(&mut camera, &press(MouseButton::Button2));
+        fly.handle_event(&mut camera, &WindowEvent::CursorPos(0.0, 0.0));
+        assert!(fly.handle_event(&mut camera, &WindowEvent::CursorPos(0.0, 100000.0)));
+        assert_eq!(camera.pitch, -MAX_PITCH);
+    }
+
+    #[test]
+    fn orbiting_keeps_the_distance_to_the_target() {
+        let target = Point3::new(1.0, 0.0, -2.0);
+        let mut camera = Camera3D::new(Point3::new(1.0, 3.0, 2.0), (800, 600));
+        let mut orbit = OrbitController::new(&mut camera, target);
+        assert!((orbit.distance - 5.0).abs() < 1e-5);
+        let check = |orbit: &OrbitController, camera: &Camera3D| {
+            assert!(((camera.position - orbit.target).norm() - orbit.distance).abs() < 1e-4);
+            assert_near3(camera.forward(), (orbit.target - camera.position).normalize());
+        };
+
+        orbit.handle_event(&mut camera, &press(MouseButton::Button1));
+        orbit.handle_event(&mut camera, &WindowEvent::CursorPos(100.0, 100.0));
+        assert!(orbit.handle_event(&mut camera, &WindowEvent::CursorPos(160.0, 70.0)));
+        check(&orbit, &camera);
+        assert_eq!(orbit.target, target);
+        orbit.handle_event(&mut camera, &WindowEvent::MouseButton(MouseButton::Button1, Action::Release, glfw::Modifiers::empty()));
+
+        // Panning moves the target across the view, not toward it
+        let forward = camera.forward();
+        orbit.handle_event(&mut camera, &press(MouseButton::Button2));
+        assert!(orbit.handle_event(&mut camera, &WindowEvent::CursorPos(200.0, 120.0)));
+        check(&orbit, &camera);
+        assert!((orbit.target - target).norm() > 0.0);
+        assert!((orbit.target - target).dot(&forward).abs() < 1e-4);
+
+        assert!(orbit.handle_event(&mut camera, &WindowEvent::Scroll(0.0, 2.0)));
+        assert!((orbit.distance - 5.0 / 1.1f32.powi(2)).abs() < 1e-4);
+        check(&orbit, &camera);
+        orbit.handle_event(&mut camera, &WindowEvent::Scroll(0.0, -1000.0));
+        assert_eq!(orbit.distance, orbit.max_distance);
+        check(&orbit, &camera);
+    }
+
+    #[test]
+    fn flying_moves_along_the_view_scaled_by_the_frame_time() {
+        let mut camera = Camera3D::new(Point3::origin(), (800, 600));
+        camera.look_at(Point3::new(1.0, 0.0, -1.0));
+        let mut fly = FlyController::default();
+        fly.update(&mut camera, 0.5);
+        assert_eq!(camera.position, Point3::origin());
+
+        fly.handle_event(&mut camera, &key(Key::W, Action::Press));
+        fly.update(&mut camera, 0.5);
+        assert_near3(camera.position.coords, camera.forward() * fly.speed * 0.5);
+
+        // Diagonals are no faster, and Shift multiplies the speed
+        let start = camera.position;
+        fly.handle_event(&mut camera, &key(Key::D, Action::Press));
+        fly.handle_event(&mut camera, &key(Key::LeftShift, Action::Press));
+        fly.update(&mut camera, 0.25);
+        let expected = (camera.forward() + camera.right()).normalize() * fly.speed * fly.fast_factor * 0.25;
+        assert_near3(camera.position - start, expected);
+
+        for released in [Key::W, Key::D, Key::LeftShift] {
+            fly.handle_event(&mut camera, &key(released, Action::Release));
+        }
+        let start = camera.position;
+        fly.update(&mut camera, 1.0);
+        assert_eq!(camera.position, start);
+    }
+}
@@ -0,0 +1,313 @@
+use naga::{Binding, ShaderStage, TypeInner};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::translate::{self, Diagnostic, Translated};
+
+// Name of the optional file listing which shaders are linked together
+pub const PROGRAMS_FILE: &str = "programs.json";
+
+// File extensions that are picked up as shaders
+const SHADER_EXTENSIONS: &[&str] = &["glsl", "vert", "frag", "comp", "vs", "fs", "cs"];
+
+// One vertex/fragment pair from programs.json, paths relative to the directory
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgramPair {
+    pub name: String,
+    pub vertex: String,
+    pub fragment: String,
+}
+
+// Infers the stage from the extension, or for .glsl files from a
+// `#pragma shader_stage(vertex|fragment|compute)` line
+pub fn infer_stage(path: &Path, source: &str) -> Option<ShaderStage> {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "vert" | "vs" => return Some(ShaderStage::Vertex),
+        "frag" | "fs" => return Some(ShaderStage::Fragment),
+        "comp" | "cs" => return Some(ShaderStage::Compute),
+        _ => {}
+    }
+    let pragma = Regex::new(r"(?m)^\s*#\s*pragma\s+shader_stage\s*\(\s*(\w+)\s*\)").unwrap();
+    match pragma.captures(source)?.get(1)?.as_str() {
+        "vertex" => Some(ShaderStage::Vertex),
+        "fragment" => Some(ShaderStage::Fragment),
+        "compute" => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
+
+// A stage input or output with a location
+struct Varying {
+    name: String,
+    location: u32,
+    ty: TypeInner,
+}
+
+// Location-bound values of an entry point, either its arguments or its result
+fn varyings(translated: &Translated, outputs: bool) -> Vec<Varying> {
+    let module = &translated.module;
+    let mut found = Vec::new();
+    let mut push = |name: &Option<String>, binding: &Option<Binding>, ty: naga::Handle<naga::Type>| {
+        if let Some(Binding::Location { location, .. }) = binding {
+            found.push(Varying {
+                name: name.clone().unwrap_or_default(),
+                location: *location,
+                ty: module.types[ty].inner.clone(),
+            });
+        }
+    };
+    // Struct members carry the bindings when there are several values
+    let mut visit = |name: &Option<String>, binding: &Option<Binding>, ty: naga::Handle<naga::Type>| {
+        match &module.types[ty].inner {
+            TypeInner::Struct { members, .. } if binding.is_none() => {
+                for member in members {
+                    push(&member.name, &member.binding, member.ty);
+                }
+            }
+            _ => push(name, binding, ty),
+        }
+    };
+    for entry_point in &module.entry_points {
+        if outputs {
+            if let Some(result) = &entry_point.function.result {
+                visit(&None, &result.binding, result.ty);
+            }
+        } else {
+            for argument in &entry_point.function.arguments {
+                visit(&argument.name, &argument.binding, argument.ty);
+            }
+        }
+    }
+    found
+}
+
+// GLSL spelling of a varying type, for messages
+fn glsl_type_name(ty: &TypeInner) -> String {
+    use naga::{ScalarKind, VectorSize};
+    let size = |s: &VectorSize| *s as u8;
+    match ty {
+        TypeInner::Scalar { kind: ScalarKind::Float, .. } => "float".to_string(),
+        TypeInner::Scalar { kind: ScalarKind::Sint, .. } => "int".to_string(),
+        TypeInner::Scalar { kind: ScalarKind::Uint, .. } => "uint".to_string(),
+        TypeInner::Scalar { kind: ScalarKind::Bool, .. } => "bool".to_string(),
+        TypeInner::Vector { size: s, kind, .. } => {
+            let prefix = match kind {
+                ScalarKind::Float => "",
+                ScalarKind::Sint => "i",
+                ScalarKind::Uint => "u",
+                ScalarKind::Bool => "b",
+            };
+            format!("{}vec{}", prefix, size(s))
+        }
+        TypeInner::Matrix { columns, rows, .. } => format!("mat{}x{}", size(columns), size(rows)),
+        other => format!("{:?}", other),
+    }
+}
+
+// Line of the `in ... name;` declaration, for pointing diagnostics at it
+fn declaration_line(source: &str, name: &str) -> u32 {
+    let decl = Regex::new(&format!(r"\bin\s+\w+\s+{}\s*;", regex::escape(name))).unwrap();
+    source
+        .lines()
+        .position(|line| decl.is_match(line))
+        .map_or(0, |i| i as u32 + 1)
+}
+
+// Names of the `in` or `out` variables declared with `layout(location = N)`.
+// Translation gives the others a location too, so only the source tells.
+fn explicit_locations(source: &str, direction: &str) -> HashSet<String> {
+    let decl = Regex::new(&format!(
+        r"layout\s*\([^)]*\blocation\s*=\s*\d+[^)]*\)\s*(?:(?:flat|smooth|noperspective)\s+)?{}\s+\w+\s+(\w+)\s*;",
+        direction
+    ))
+    .unwrap();
+    source.lines().filter_map(|line| decl.captures(line)).map(|caps| caps[1].to_string()).collect()
+}
+
+// Checks that every fragment input is written by the vertex stage. Like GL,
+// an input and an output both declaring a location are matched by it, any
+// others by name.
+pub fn check_interface(vertex: &Translated, vertex_source: &str, fragment: &Translated, fragment_file: &str, fragment_source: &str) -> Vec<Diagnostic> {
+    let outputs = varyings(vertex, true);
+    let explicit_outputs = explicit_locations(vertex_source, "out");
+    let explicit_inputs = explicit_locations(fragment_source, "in");
+    let mut diagnostics = Vec::new();
+    for input in varyings(fragment, false) {
+        let line = declaration_line(fragment_source, &input.name);
+        let diagnostic = |message: String| Diagnostic { file: fragment_file.to_string(), line, column: if line == 0 { 0 } else { 1 }, message };
+        let input_explicit = explicit_inputs.contains(&input.name);
+        let by_location = outputs
+            .iter()
+            .find(|o| input_explicit && explicit_outputs.contains(&o.name) && o.location == input.location);
+        let output = by_location.or_else(|| {
+            outputs
+                .iter()
+                .find(|o| o.name == input.name && !(input_explicit && explicit_outputs.contains(&o.name)))
+        });
+        match output {
+            None => diagnostics.push(diagnostic(format!(
+                "fragment input '{}' (location {}) is not written by the vertex shader",
+                input.name, input.location
+            ))),
+            Some(output) if output.ty != input.ty => diagnostics.push(diagnostic(format!(
+                "fragment input '{}' is {} but vertex output '{}' is {}",
+                input.name,
+                glsl_type_name(&input.ty),
+                output.name,
+                glsl_type_name(&output.ty)
+            ))),
+            Some(_) => {}
+        }
+    }
+    diagnostics
+}
+
+// Shaders of a directory, sorted so the output is stable
+fn shader_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| SHADER_EXTENSIONS.contains(&e))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+// Loads programs.json from the directory, if there is one
+fn program_pairs(dir: &Path) -> Result<Vec<ProgramPair>, String> {
+    let path = dir.join(PROGRAMS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Validates every shader in `dir` and the interfaces of the declared programs.
+// Prints diagnostics to stderr and returns the process exit code.
+pub fn run(dir: &Path) -> i32 {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut translated: HashMap<String, (Translated, String)> = HashMap::new();
+
+    let files = match shader_files(dir) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return 2;
+        }
+    };
+
+    for path in &files {
+        let file = path.display().to_string();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                diagnostics.push(Diagnostic { file, line: 0, column: 0, message: e.to_string() });
+                continue;
+            }
+        };
+        let stage = match infer_stage(path, &source) {
+            Some(stage) => stage,
+            None => {
+                diagnostics.push(Diagnostic {
+                    file,
+                    line: 0,
+                    column: 0,
+                    message: "cannot infer the shader stage; use a .vert/.frag/.comp extension or add `#pragma shader_stage(...)`".to_string(),
+                });
+                continue;
+            }
+        };
+        match translate::translate(&file, &source, stage) {
+            Ok(t) => {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                translated.insert(name, (t, source));
+            }
+            Err(errors) => diagnostics.extend(errors),
+        }
+    }
+
+    let pairs = match program_pairs(dir) {
+        Ok(pairs) => pairs,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return 2;
+        }
+    };
+    for pair in &pairs {
+        let lookup = |name: &str, stage: ShaderStage| match translated.get(name) {
+            Some((t, source)) if t.stage == stage => Ok((t, source)),
+            Some(_) => Err(format!("program '{}': {} is not a {:?} shader", pair.name, name, stage)),
+            None => Err(format!("program '{}': {} is missing or failed to validate", pair.name, name)),
+        };
+        match (lookup(&pair.vertex, ShaderStage::Vertex), lookup(&pair.fragment, ShaderStage::Fragment)) {
+            (Ok((vertex, vertex_source)), Ok((fragment, fragment_source))) => {
+                let fragment_file = dir.join(&pair.fragment).display().to_string();
+                diagnostics.extend(check_interface(vertex, vertex_source, fragment, &fragment_file, fragment_source));
+            }
+            (vertex, fragment) => {
+                for message in [vertex.err(), fragment.err()].into_iter().flatten() {
+                    diagnostics.push(Diagnostic { file: dir.join(PROGRAMS_FILE).display().to_string(), line: 0, column: 0, message });
+                }
+            }
+        }
+    }
+
+    for diagnostic in &diagnostics {
+        eprintln!("error: {}", diagnostic);
+    }
+    println!(
+        "checked {} shaders and {} programs: {} errors",
+        files.len(),
+        pairs.len(),
+        diagnostics.len()
+    );
+    if diagnostics.is_empty() { 0 } else { 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(vertex_source: &str, fragment_source: &str) -> Vec<String> {
+        let vertex = translate::translate("v.glsl", vertex_source, ShaderStage::Vertex).unwrap_or_else(|d| panic!("{:?}", d));
+        let fragment = translate::translate("f.glsl", fragment_source, ShaderStage::Fragment).unwrap_or_else(|d| panic!("{:?}", d));
+        check_interface(&vertex, vertex_source, &fragment, "f.glsl", fragment_source).into_iter().map(|d| d.message).collect()
+    }
+
+    const OUT: &str = "out vec4 color;\nvoid main() { color = vec4(uv, 0.0, 1.0); }\n";
+
+    #[test]
+    fn explicit_locations_match_by_location_whatever_the_names() {
+        let vertex = "layout(location = 0) out vec2 texCoord;\nvoid main() { gl_Position = vec4(0.0); texCoord = vec2(0.0); }\n";
+        let fragment = format!("layout(location = 0) in vec2 uv;\n{}", OUT);
+        assert!(interface(vertex, &fragment).is_empty());
+    }
+
+    #[test]
+    fn explicit_locations_dont_fall_back_to_names() {
+        let vertex = "layout(location = 1) out vec2 uv;\nvoid main() { gl_Position = vec4(0.0); uv = vec2(0.0); }\n";
+        let fragment = format!("layout(location = 0) in vec2 uv;\n{}", OUT);
+        assert_eq!(interface(vertex, &fragment), vec!["fragment input 'uv' (location 0) is not written by the vertex shader"]);
+    }
+
+    #[test]
+    fn implicit_locations_match_by_name() {
+        let vertex = "out vec3 normal;\nout vec2 uv;\nvoid main() { gl_Position = vec4(0.0); normal = vec3(0.0); uv = vec2(0.0); }\n";
+        let fragment = format!("in vec2 uv;\n{}", OUT);
+        assert!(interface(vertex, &fragment).is_empty());
+    }
+
+    #[test]
+    fn one_sided_location_matches_by_name() {
+        let vertex = "out vec2 other;\nlayout(location = 3) out vec3 uv;\nvoid main() { gl_Position = vec4(0.0); other = vec2(0.0); uv = vec3(0.0); }\n";
+        let fragment = format!("in vec2 uv;\n{}", OUT);
+        assert_eq!(interface(vertex, &fragment), vec!["fragment input 'uv' is vec2 but vertex output 'uv' is vec3"]);
+    }
+}
@@ -0,0 +1,502 @@
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use crate::assets::resolve_path;
+use crate::backend::BackendKind;
+
+// The command line: a subcommand, or the playground's options when none is
+// given
+#[derive(Parser, Debug)]
+#[command(name = "shaders", about = "Draws a quad with the given shaders", args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub playground: PlaygroundArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Validates the shaders offline, without a GL context
+    Check(CheckArgs),
+    /// Runs an effect through the Bevy plugin
+    Bevy(BevyArgs),
+    /// Writes the province borders of an image as outlines.svg and
+    /// outlines.geojson, and their triangles as fill_mesh.json and border_mesh.json
+    Outlines(OutlinesArgs),
+    /// Writes the border distance image of a province map, flooding on the GPU when one is found
+    Sdf(SdfArgs),
+    /// Places a label on every province and draws them over the map
+    Labels(LabelsArgs),
+    /// Reports mistakes in a province image and writes an annotated copy of it
+    MapLint(MapLintArgs),
+    /// Compares a definition file with the colors of its image
+    Definitions(DefinitionsArgs),
+    /// Opens a window to pan and zoom around a province map
+    View(ViewArgs),
+    /// Shows a province map with hover and selection
+    Pick(PickArgs),
+    /// Runs a Shadertoy style shader; Space pauses, Backspace restarts, F12
+    /// saves a screenshot, F9 records a video, F3 shows timings, F4 traces
+    Shadertoy(ShadertoyArgs),
+    /// Runs images through fragment shader passes and saves the results
+    Process(ProcessArgs),
+    /// Draws a fragment shader over the whole image on a chosen render backend
+    Render(RenderArgs),
+}
+
+// Options of `shaders check`
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// Folder of shaders, with programs.json listing the programs to link
+    #[arg(default_value = "assets/shadercode")]
+    pub dir: PathBuf,
+}
+
+// Options of `shaders bevy`; paths are relative to the assets folder
+#[derive(Args, Debug)]
+pub struct BevyArgs {
+    /// Effect fragment shader
+    #[arg(default_value = "shadercode/effect_texture.glsl")]
+    pub effect: String,
+    /// Image the effect is drawn over
+    #[arg(default_value = "sample_texture.jpg")]
+    pub image: String,
+}
+
+// Options of `shaders outlines`
+#[derive(Args, Debug)]
+pub struct OutlinesArgs {
+    /// Province image
+    pub image: String,
+    /// Folder the files are written to
+    pub out_dir: PathBuf,
+    /// Simplification tolerance in pixels, 0 only merges collinear edges
+    #[arg(default_value_t = 0.5)]
+    pub tolerance: f32,
+}
+
+// Options of `shaders sdf`
+#[derive(Args, Debug)]
+pub struct SdfArgs {
+    /// Province image
+    pub image: String,
+    /// Distance image written
+    pub out: String,
+    /// Distance in pixels that maps to 255
+    #[arg(default_value_t = 8.0)]
+    pub max_distance: f32,
+    /// Classes of the provinces, see province_borders::parse_classes; the
+    /// country and coast channels hold no borders without it
+    pub classes: Option<String>,
+}
+
+// Options of `shaders labels`
+#[derive(Args, Debug)]
+pub struct LabelsArgs {
+    /// Province image
+    pub image: String,
+    /// TTF or OTF font
+    pub font: String,
+    /// Image written, the map with its labels
+    pub out: String,
+    /// Definition file naming the provinces, "Province <id>" without it
+    pub definitions: Option<String>,
+}
+
+// Options of `shaders map-lint`
+#[derive(Args, Debug)]
+pub struct MapLintArgs {
+    /// Province image
+    pub image: String,
+    /// Annotated copy written
+    #[arg(default_value = "map_lint.png")]
+    pub overlay: String,
+    /// Definition file checked against the image
+    pub definitions: Option<String>,
+}
+
+// Options of `shaders definitions`
+#[derive(Args, Debug)]
+pub struct DefinitionsArgs {
+    /// Province image
+    pub image: String,
+    /// Definition file
+    pub definition: String,
+    /// Completed copy of the definition file, with the missing colors added
+    pub out: Option<String>,
+}
+
+// Options of `shaders view`
+#[derive(Args, Debug)]
+pub struct ViewArgs {
+    /// Province image
+    pub image: String,
+    /// Repeats the map horizontally, for maps that wrap around
+    #[arg(long)]
+    pub wrap: bool,
+    /// TTF or OTF font; given one, province names are drawn, L toggles them
+    #[arg(long)]
+    pub font: Option<String>,
+    /// Definition file naming the provinces, "Province <id>" without it
+    #[arg(long)]
+    pub definitions: Option<String>,
+    /// Colors the provinces by a column of the definition file, counting
+    /// from 0 for the id and 4 for the name; with a font its values are
+    /// named too. C switches back to the province colors
+    #[arg(long, requires = "definitions")]
+    pub column: Option<usize>,
+}
+
+// Options of `shaders pick`
+#[derive(Args, Debug)]
+pub struct PickArgs {
+    /// Province image, relative to the assets folder
+    #[arg(default_value = "Provinces_2600_100_3600_1000.png")]
+    pub image: String,
+}
+
+// Options of the shader playground, the default command. Relative paths are
+// looked up in the working directory first, then in the assets root.
+#[derive(Args, Debug)]
+pub struct PlaygroundArgs {
+    /// Vertex shader
+    #[arg(long)]
+    pub vertex: Option<PathBuf>,
+    /// Fragment shader
+    #[arg(long)]
+    pub fragment: Option<PathBuf>,
+    /// Compute shader run before every frame, reading `inputTexture` and
+    /// writing `outputImage`, which the fragment shader sees as `computeOutput`
+    #[arg(long)]
+    pub compute: Option<PathBuf>,
+    /// Input texture, bound as `inputTexture0`, `inputTexture1`, ... in order;
+    /// the first one is also `inputTexture`
+    #[arg(long = "texture")]
+    pub textures: Vec<PathBuf>,
+    /// JSON mesh drawn instead of the quad, with `attributes` (float count
+    /// of each location), `vertices` and `indices`, like `shaders outlines` writes
+    #[arg(long)]
+    pub mesh: Option<PathBuf>,
+    /// Window or output width in pixels
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Window or output height in pixels
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Renders without showing a window and saves the last frame here, or
+    /// every frame at 60 fps when it is a .mp4, .webm or .gif file
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Frames to render before exiting, 1 by default with --output
+    #[arg(long)]
+    pub frames: Option<u32>,
+    /// JSON file with any of the options above; command line options win
+    #[arg(long)]
+    pub pipeline: Option<PathBuf>,
+    /// Folder relative paths fall back to
+    #[arg(long, default_value = "assets")]
+    pub assets: PathBuf,
+}
+
+// Options of `shaders shadertoy`
+#[derive(Args, Debug)]
+pub struct ShadertoyArgs {
+    /// A file with mainImage, or a project .json with buffers
+    pub path: PathBuf,
+    /// Inputs of a lone mainImage file: image files or buffers A to D
+    pub channels: Vec<String>,
+    /// Renders this many frames at a fixed timestep into --out, without a window
+    #[arg(long)]
+    pub frames: Option<u32>,
+    /// Frames per second of recorded time
+    #[arg(long, default_value_t = 60.0)]
+    pub fps: f32,
+    /// Folder recorded frames are written to, or a .mp4, .webm or .gif file
+    #[arg(long, default_value = "frames")]
+    pub out: PathBuf,
+    /// Image format of recorded frames, png or exr
+    #[arg(long, default_value = "png")]
+    pub format: String,
+    /// Writes a Chrome trace of the frames recorded with --frames to this file
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+    /// Format of recordings started with F9: mp4, webm or gif
+    #[arg(long, default_value = "mp4")]
+    pub video: String,
+    /// WIDTHxHEIGHT of the window or the recording
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+}
+
+// Options of `shaders process`
+#[derive(Args, Debug)]
+pub struct ProcessArgs {
+    /// Images, or folders whose images are all processed
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Folder results are written to, with the name and format of their input
+    #[arg(long)]
+    pub out: PathBuf,
+    /// Fragment shader drawn over the image with screen_quad.glsl, reading it
+    /// as `inputTexture`; repeat to chain passes
+    #[arg(long = "pass")]
+    pub passes: Vec<PathBuf>,
+    /// JSON file listing passes, `{"passes": [...]}`, run before any --pass
+    #[arg(long)]
+    pub pipeline: Option<PathBuf>,
+    /// gpu runs the shaders, cpu their reference versions in Rust
+    #[arg(long, value_enum, default_value_t = Backend::Gpu)]
+    pub backend: Backend,
+    /// Images processed at once by the CPU backend, all cores by default
+    #[arg(long)]
+    pub jobs: Option<usize>,
+    /// Folder relative shader paths fall back to
+    #[arg(long, default_value = "assets/shadercode")]
+    pub shaders: PathBuf,
+}
+
+// Options of `shaders render`
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    /// Fragment shader, drawn with screen_quad.glsl
+    pub fragment: PathBuf,
+    /// Image written, in the format of its extension
+    #[arg(long)]
+    pub out: PathBuf,
+    /// Image bound to the shader's textures in binding order; on gl, shaders
+    /// reading more than one need `layout(binding = N)` on each
+    #[arg(long = "texture")]
+    pub textures: Vec<PathBuf>,
+    /// WIDTHxHEIGHT of the image, by default the first texture's size or 512x512
+    #[arg(long, value_parser = parse_size)]
+    pub size: Option<(u32, u32)>,
+    /// gl, or wgpu on its preferred API; vulkan, metal, dx12 and gles make
+    /// wgpu use that one
+    #[arg(long, default_value = "wgpu")]
+    pub backend: BackendKind,
+    /// Folder relative shader paths fall back to
+    #[arg(long, default_value = "assets/shadercode")]
+    pub shaders: PathBuf,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Gpu,
+    Cpu,
+}
+
+// Contents of a `shaders process --pipeline` file; relative paths are looked
+// up next to the file first
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessConfig {
+    pub passes: Vec<PathBuf>,
+}
+
+impl ProcessArgs {
+    // Passes of the pipeline file then the command line, resolved
+    pub fn passes(&self) -> Result<Vec<PathBuf>, String> {
+        let mut passes = Vec::new();
+        if let Some(pipeline) = &self.pipeline {
+            let text = std::fs::read_to_string(pipeline).map_err(|e| format!("{}: {}", pipeline.display(), e))?;
+            let config: ProcessConfig = serde_json::from_str(&text).map_err(|e| format!("{}: {}", pipeline.display(), e))?;
+            let config_dir = pipeline.parent().unwrap_or(Path::new("."));
+            for pass in &config.passes {
+                passes.push(resolve_path(pass, &[config_dir, Path::new("."), self.shaders.as_path()])?);
+            }
+        }
+        for pass in &self.passes {
+            passes.push(resolve_path(pass, &[Path::new("."), self.shaders.as_path()])?);
+        }
+        if passes.is_empty() {
+            return Err("no passes, give --pass or --pipeline".to_string());
+        }
+        Ok(passes)
+    }
+}
+
+pub fn parse_size(text: &str) -> Result<(u32, u32), String> {
+    let (width, height) = text.split_once('x').ok_or_else(|| format!("'{}' is not WIDTHxHEIGHT", text))?;
+    let parse = |n: &str| n.trim().parse::<u32>().ok().filter(|n| *n > 0).ok_or_else(|| format!("'{}' is not a size in pixels", n));
+    Ok((parse(width)?, parse(height)?))
+}
+
+// Contents of a --pipeline file. Its relative paths are looked up next to
+// the file first.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub vertex: Option<PathBuf>,
+    pub fragment: Option<PathBuf>,
+    pub compute: Option<PathBuf>,
+    #[serde(default)]
+    pub textures: Vec<PathBuf>,
+    pub mesh: Option<PathBuf>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub output: Option<PathBuf>,
+    pub frames: Option<u32>,
+}
+
+impl PipelineConfig {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// Everything the playground needs, with input paths resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaygroundOptions {
+    pub vertex: PathBuf,
+    pub fragment: PathBuf,
+    pub compute: Option<PathBuf>,
+    pub textures: Vec<PathBuf>,
+    pub mesh: Option<PathBuf>,
+    pub width: u32,
+    pub height: u32,
+    pub output: Option<PathBuf>,
+    // None runs until the window is closed
+    pub frames: Option<u32>,
+    // Root relative paths fall back to
+    pub assets: PathBuf,
+}
+
+impl PlaygroundArgs {
+    // Merges the command line with the pipeline file and the defaults
+    pub fn options(&self) -> Result<PlaygroundOptions, String> {
+        let config = self.pipeline.as_deref().map(PipelineConfig::read).transpose()?.unwrap_or_default();
+        let config_dir = self.pipeline.as_deref().and_then(Path::parent).unwrap_or(Path::new("."));
+        let cli_roots = [Path::new("."), self.assets.as_path()];
+        let config_roots = [config_dir, Path::new("."), self.assets.as_path()];
+        let cli = |path: &Path| resolve_path(path, &cli_roots);
+        let from_config = |path: &Path| resolve_path(path, &config_roots);
+
+        let pick = |arg: &Option<PathBuf>, configured: &Option<PathBuf>, default: &str| match (arg, configured) {
+            (Some(path), _) => cli(path),
+            (None, Some(path)) => from_config(path),
+            (None, None) => cli(Path::new(default)),
+        };
+        let vertex = pick(&self.vertex, &config.vertex, "shadercode/vertex_test.glsl")?;
+        let fragment = pick(&self.fragment, &config.fragment, "shadercode/fragment_test.glsl")?;
+        let optional = |arg: &Option<PathBuf>, configured: &Option<PathBuf>| match (arg, configured) {
+            (Some(path), _) => cli(path).map(Some),
+            (None, Some(path)) => from_config(path).map(Some),
+            (None, None) => Ok(None),
+        };
+        let compute = optional(&self.compute, &config.compute)?;
+        let mesh = optional(&self.mesh, &config.mesh)?;
+        let textures = if self.textures.is_empty() {
+            config.textures.iter().map(|t| from_config(t)).collect::<Result<_, _>>()?
+        } else {
+            self.textures.iter().map(|t| cli(t)).collect::<Result<_, _>>()?
+        };
+        // Outputs are written relative to the working directory
+        let output = self.output.clone().or(config.output);
+        let frames = self.frames.or(config.frames).or(output.as_ref().map(|_| 1));
+        Ok(PlaygroundOptions {
+            vertex,
+            fragment,
+            compute,
+            textures,
+            mesh,
+            width: self.width.or(config.width).unwrap_or(800),
+            height: self.height.or(config.height).unwrap_or(800),
+            output,
+            frames,
+            assets: self.assets.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("shaders").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn command_line_is_consistent() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn sizes_are_width_x_height() {
+        assert_eq!(parse_size("640x480"), Ok((640, 480)));
+        assert_eq!(parse_size(" 64 x 32 "), Ok((64, 32)));
+        assert_eq!(parse_size("640"), Err("'640' is not WIDTHxHEIGHT".to_string()));
+        assert_eq!(parse_size("0x480"), Err("'0' is not a size in pixels".to_string()));
+        assert!(parse_size("640x-1").is_err());
+    }
+
+    #[test]
+    fn no_subcommand_runs_the_playground() {
+        let cli = parse(&["--width", "320", "--texture", "a.png", "--texture", "b.png"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.playground.width, Some(320));
+        assert_eq!(cli.playground.textures, vec![PathBuf::from("a.png"), PathBuf::from("b.png")]);
+    }
+
+    #[test]
+    fn subcommands_take_typed_arguments() {
+        let Some(Command::Sdf(sdf)) = parse(&["sdf", "map.png", "out.png"]).command else { panic!("sdf") };
+        assert_eq!((sdf.max_distance, sdf.classes), (8.0, None));
+        let Some(Command::Outlines(outlines)) = parse(&["outlines", "map.png", "out", "2"]).command else { panic!("outlines") };
+        assert_eq!((outlines.out_dir, outlines.tolerance), (PathBuf::from("out"), 2.0));
+        let Some(Command::MapLint(lint)) = parse(&["map-lint", "map.png"]).command else { panic!("map-lint") };
+        assert_eq!(lint.overlay, "map_lint.png");
+        let Some(Command::View(view)) = parse(&["view", "map.png", "--wrap"]).command else { panic!("view") };
+        assert!(view.wrap);
+        assert_eq!((view.font, view.definitions, view.column), (None, None, None));
+        let Some(Command::View(view)) = parse(&["view", "map.png", "--font", "f.ttf", "--definitions", "definition.csv"]).command else { panic!("view") };
+        assert_eq!((view.font.as_deref(), view.definitions.as_deref()), (Some("f.ttf"), Some("definition.csv")));
+        let Some(Command::View(view)) = parse(&["view", "map.png", "--definitions", "definition.csv", "--column", "5"]).command else { panic!("view") };
+        assert_eq!(view.column, Some(5));
+        assert!(Cli::try_parse_from(["shaders", "view", "map.png", "--column", "5"]).is_err());
+        let Some(Command::Render(render)) = parse(&["render", "f.glsl", "--out", "o.png", "--size", "8x4"]).command else { panic!("render") };
+        assert_eq!(render.size, Some((8, 4)));
+        assert!(Cli::try_parse_from(["shaders", "outlines", "map.png", "out", "wide"]).is_err());
+        assert!(Cli::try_parse_from(["shaders", "--width", "3", "check"]).is_err());
+    }
+
+    #[test]
+    fn playground_defaults_come_from_the_assets_root() {
+        let assets = TempDir::new("cli-defaults", &[("shadercode/vertex_test.glsl", ""), ("shadercode/fragment_test.glsl", "")]);
+        let options = parse(&["--assets", assets.0.to_str().unwrap()]).playground.options().unwrap();
+        assert_eq!(options.vertex, assets.0.join("shadercode/vertex_test.glsl"));
+        assert_eq!(options.fragment, assets.0.join("shadercode/fragment_test.glsl"));
+        assert_eq!((options.width, options.height, options.frames, options.compute, options.mesh), (800, 800, None, None, None));
+        assert_eq!(options.assets, assets.0);
+    }
+
+    #[test]
+    fn command_line_wins_over_the_pipeline_file() {
+        let assets = TempDir::new("cli-pipeline", &[("shadercode/vertex_test.glsl", ""), ("shadercode/fragment_test.glsl", ""), ("cli.glsl", ""), ("pipes/next.glsl", ""), ("pipes/image.png", "")]);
+        let pipeline = assets.0.join("pipes/pipeline.json");
+        std::fs::write(&pipeline, r#"{"fragment": "next.glsl", "textures": ["image.png"], "mesh": "image.png", "width": 64, "height": 32, "output": "out.png"}"#).unwrap();
+        let root = assets.0.to_str().unwrap();
+        let options = parse(&["--assets", root, "--pipeline", pipeline.to_str().unwrap(), "--width", "128"]).playground.options().unwrap();
+        assert_eq!(options.fragment, assets.0.join("pipes/next.glsl"));
+        assert_eq!(options.textures, vec![assets.0.join("pipes/image.png")]);
+        assert_eq!(options.mesh, Some(assets.0.join("pipes/image.png")));
+        assert_eq!((options.width, options.height), (128, 32));
+        // An output renders one frame unless told otherwise
+        assert_eq!((options.output, options.frames), (Some(PathBuf::from("out.png")), Some(1)));
+
+        let options = parse(&["--assets", root, "--pipeline", pipeline.to_str().unwrap(), "--fragment", "cli.glsl", "--frames", "5"]).playground.options().unwrap();
+        assert_eq!(options.fragment, assets.0.join("cli.glsl"));
+        assert_eq!(options.frames, Some(5));
+    }
+
+    #[test]
+    fn missing_playground_files_are_errors() {
+        let assets = TempDir::new("cli-missing", &[("shadercode/vertex_test.glsl", "")]);
+        let error = parse(&["--assets", assets.0.to_str().unwrap()]).playground.options().unwrap_err();
+        assert!(error.starts_with("shadercode/fragment_test.glsl: file not found"), "{}", error);
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::fs;
+use crate::province_map::{ProvinceId, ProvinceMap};
+
+// One line of a definition file: `id;r;g;b;name;...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    // The game's province number, unrelated to ProvinceId
+    pub id: u32,
+    pub color: [u8; 3],
+    // As written, surrounding spaces included, so unchanged lines are
+    // written back the same; `names` trims it
+    pub name: String,
+    // Any further columns, kept as they are
+    pub extra: Vec<String>,
+}
+
+impl Definition {
+    // The entry a line holds, None when it doesn't start with `id;red;green;blue`
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(';').collect();
+        let channel = |i: usize| fields.get(i).and_then(|f| f.trim().parse::<u8>().ok());
+        Some(Definition {
+            id: fields[0].trim().parse().ok()?,
+            color: [channel(1)?, channel(2)?, channel(3)?],
+            name: fields.get(4).map(|n| n.to_string()).unwrap_or_default(),
+            extra: fields.iter().skip(5).map(|f| f.to_string()).collect(),
+        })
+    }
+
+    // Column `index` of the line as written back: 0 is the id, 1 to 3 the
+    // color, 4 the name and the extra columns follow
+    pub fn field(&self, index: usize) -> Option<String> {
+        match index {
+            0 => Some(self.id.to_string()),
+            1..=3 => Some(self.color[index - 1].to_string()),
+            4 => Some(self.name.clone()),
+            _ => self.extra.get(index - 5).cloned(),
+        }
+    }
+}
+
+// The definition.csv that goes with a province image. Games write these in
+// Windows-1252 as often as UTF-8; the encoding, byte order mark, line endings
+// and comments found are kept for writing back.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Definitions {
+    // First line when it is a header rather than a province
+    pub header: Option<String>,
+    pub entries: Vec<Definition>,
+    // Line each entry was read from, indexed like `entries`; written back as
+    // it is while the entry still matches it, so spacing and zero padding stay
+    pub lines: Vec<String>,
+    // Blank and `#` lines, after how many entries they came
+    pub comments: Vec<(usize, String)>,
+    pub latin1: bool,
+    pub bom: bool,
+    pub crlf: bool,
+}
+
+const BOM: &str = "\u{feff}";
+
+// How a definition file and the colors of an image line up
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Reconciliation {
+    // Entry of every province, indexed by ProvinceId
+    pub by_province: Vec<Option<usize>>,
+    // Colors of the image with no entry
+    pub missing: Vec<ProvinceId>,
+    // Entries whose color is not in the image
+    pub extra: Vec<usize>,
+    // Entries sharing a color, the first of each group is the one used
+    pub duplicate_colors: Vec<Vec<usize>>,
+    // Entries sharing an id
+    pub duplicate_ids: Vec<Vec<usize>>,
+}
+
+impl Reconciliation {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.duplicate_colors.is_empty() && self.duplicate_ids.is_empty()
+    }
+}
+
+// Groups of indices sharing a key, in order of first appearance
+fn duplicates<K: std::hash::Hash + Eq>(keys: impl Iterator<Item = K>) -> Vec<Vec<usize>> {
+    let mut groups: HashMap<K, Vec<usize>> = HashMap::new();
+    for (i, key) in keys.enumerate() {
+        groups.entry(key).or_default().push(i);
+    }
+    let mut result: Vec<Vec<usize>> = groups.into_values().filter(|g| g.len() > 1).collect();
+    result.sort_by_key(|g| g[0]);
+    result
+}
+
+impl Definitions {
+    // Parses semicolon separated lines. Blank lines and `#` comments are
+    // kept aside, as is a first line that doesn't start with a number.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut definitions = Definitions { bom: text.starts_with(BOM), crlf: text.contains("\r\n"), ..Default::default() };
+        for (number, line) in text.trim_start_matches(BOM).lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                definitions.comments.push((definitions.entries.len(), line.to_string()));
+                continue;
+            }
+            let numbered = line.split(';').next().is_some_and(|id| id.trim().parse::<u32>().is_ok());
+            if !numbered && definitions.header.is_none() && definitions.entries.is_empty() {
+                definitions.header = Some(line.to_string());
+                continue;
+            }
+            let Some(entry) = Definition::parse(line) else {
+                return Err(format!("line {}: expected `id;red;green;blue;name`, found `{}`", number + 1, line));
+            };
+            definitions.entries.push(entry);
+            definitions.lines.push(line.to_string());
+        }
+        Ok(definitions)
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        // Decoded separately so a mark in front of Latin-1 text is still found
+        let (bom, bytes) = match bytes.strip_prefix(BOM.as_bytes()) {
+            Some(rest) => (true, rest.to_vec()),
+            None => (false, bytes),
+        };
+        let (text, latin1) = match String::from_utf8(bytes) {
+            Ok(text) => (text, false),
+            // Windows-1252 and Latin-1 agree on the letters used in names
+            Err(e) => (e.into_bytes().iter().map(|b| *b as char).collect(), true),
+        };
+        let mut definitions = Self::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        definitions.latin1 = latin1;
+        definitions.bom = bom;
+        Ok(definitions)
+    }
+
+    // The file without its byte order mark, which `write` adds back
+    pub fn to_text(&self) -> String {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        let mut text = String::new();
+        if let Some(header) = &self.header {
+            text.push_str(header);
+            text.push_str(newline);
+        }
+        let mut comments = self.comments.iter().peekable();
+        for i in 0..=self.entries.len() {
+            while let Some((_, comment)) = comments.next_if(|(before, _)| *before <= i) {
+                text.push_str(comment);
+                text.push_str(newline);
+            }
+            let Some(entry) = self.entries.get(i) else { break };
+            match self.lines.get(i).filter(|line| Definition::parse(line).as_ref() == Some(entry)) {
+                Some(line) => text.push_str(line),
+                None => {
+                    let [r, g, b] = entry.color;
+                    text.push_str(&format!("{};{};{};{};{}", entry.id, r, g, b, entry.name));
+                    for field in &entry.extra {
+                        text.push(';');
+                        text.push_str(field);
+                    }
+                }
+            }
+            text.push_str(newline);
+        }
+        text
+    }
+
+    // Writes in the encoding the file was read with; characters Latin-1
+    // can't hold become `?`
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let text = self.to_text();
+        let mut bytes = if self.bom { BOM.as_bytes().to_vec() } else { Vec::new() };
+        if self.latin1 {
+            bytes.extend(text.chars().map(|c| if (c as u32) < 256 { c as u8 } else { b'?' }));
+        } else {
+            bytes.extend(text.into_bytes());
+        }
+        fs::write(path, bytes).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Matches the entries to the provinces of `map` by color
+    pub fn reconcile(&self, map: &ProvinceMap) -> Reconciliation {
+        let duplicate_colors = duplicates(self.entries.iter().map(|e| e.color));
+        let duplicate_ids = duplicates(self.entries.iter().map(|e| e.id));
+        let mut by_color: HashMap<[u8; 3], usize> = HashMap::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            by_color.entry(entry.color).or_insert(i);
+        }
+        let by_province: Vec<Option<usize>> = map.provinces.iter().map(|p| by_color.get(&p.color).copied()).collect();
+        let missing = map.provinces.iter().filter(|p| by_province[p.id as usize].is_none()).map(|p| p.id).collect();
+        let in_image: std::collections::HashSet<[u8; 3]> = map.provinces.iter().map(|p| p.color).collect();
+        let extra = (0..self.entries.len()).filter(|i| !in_image.contains(&self.entries[*i].color)).collect();
+        Reconciliation { by_province, missing, extra, duplicate_colors, duplicate_ids }
+    }
+
+    // Adds an entry for every missing province, numbered after the highest
+    // id, and returns how many were added
+    pub fn add_missing(&mut self, map: &ProvinceMap, reconciliation: &Reconciliation) -> usize {
+        let first_id = self.entries.iter().map(|e| e.id + 1).max().unwrap_or(1);
+        // Fill the extra columns like the other lines do, games expect a trailing `x`
+        let columns = self.entries.first().map_or(0, |e| e.extra.len());
+        for (id, province) in (first_id..).zip(&reconciliation.missing) {
+            let color = map.provinces[*province as usize].color;
+            self.entries.push(Definition { id, color, name: String::new(), extra: vec!["x".to_string(); columns] });
+        }
+        reconciliation.missing.len()
+    }
+
+    // Entry of every province of `map`, indexed by ProvinceId
+    pub fn for_provinces<'a>(&'a self, reconciliation: &Reconciliation) -> Vec<Option<&'a Definition>> {
+        reconciliation.by_province.iter().map(|i| i.map(|i| &self.entries[i])).collect()
+    }
+
+    // Column `index` (see `Definition::field`) of every province of `map`,
+    // trimmed; None when undefined or empty. Feeds `MapMode::categories`
+    // and `labels::grouped_regions`.
+    pub fn column(&self, map: &ProvinceMap, index: usize) -> Vec<Option<String>> {
+        let reconciliation = self.reconcile(map);
+        self.for_provinces(&reconciliation)
+            .into_iter()
+            .map(|d| d.and_then(|d| d.field(index)).map(|v| v.trim().to_string()).filter(|v| !v.is_empty()))
+            .collect()
+    }
+
+    // Name of every province of `map`, None when undefined or unnamed
+    pub fn names(&self, map: &ProvinceMap) -> Vec<Option<String>> {
+        self.column(map, 4)
+    }
+}
+
+// `shaders definitions`: prints how a definition file matches an image and,
+// given an output path, writes the file back with the missing colors added.
+// Returns the exit code (0 clean, 1 mismatches found, 2 failed to run).
+pub fn run(image_path: &str, definition_path: &str, out_path: Option<&str>) -> i32 {
+    let result = (|| {
+        let map = ProvinceMap::from_path(image_path)?;
+        let mut definitions = Definitions::read(definition_path)?;
+        let reconciliation = definitions.reconcile(&map);
+        let hex = |c: [u8; 3]| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        for province in &reconciliation.missing {
+            let p = &map.provinces[*province as usize];
+            println!("{}: {} at {},{} has no definition", definition_path, hex(p.color), p.bbox.min_x, p.bbox.min_y);
+        }
+        for i in &reconciliation.extra {
+            let e = &definitions.entries[*i];
+            println!("{}: {} {} ({}) is not in the image", definition_path, e.id, e.name.trim(), hex(e.color));
+        }
+        for group in &reconciliation.duplicate_colors {
+            let ids: Vec<String> = group.iter().map(|i| definitions.entries[*i].id.to_string()).collect();
+            println!("{}: {} share the color {}", definition_path, ids.join(", "), hex(definitions.entries[group[0]].color));
+        }
+        for group in &reconciliation.duplicate_ids {
+            println!("{}: id {} is used {} times", definition_path, definitions.entries[group[0]].id, group.len());
+        }
+        println!(
+            "{} provinces, {} definitions: {} missing, {} extra, {} duplicate colors, {} duplicate ids",
+            map.provinces.len(),
+            definitions.entries.len(),
+            reconciliation.missing.len(),
+            reconciliation.extra.len(),
+            reconciliation.duplicate_colors.len(),
+            reconciliation.duplicate_ids.len()
+        );
+        if let Some(out_path) = out_path {
+            let added = definitions.add_missing(&map, &reconciliation);
+            definitions.write(out_path)?;
+            println!("wrote {} with {} new entries", out_path, added);
+        }
+        Ok::<_, String>(reconciliation.is_clean())
+    })();
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn parses_header_entries_and_extra_columns() {
+        let definitions = Definitions::parse("province;red;green;blue;x;x\n1;255;0;0;Stockholm;x\n2; 0;128;0 ;;x;y\n").unwrap();
+        assert_eq!(definitions.header.as_deref(), Some("province;red;green;blue;x;x"));
+        assert_eq!(definitions.entries.len(), 2);
+        assert_eq!(definitions.entries[0], Definition { id: 1, color: [255, 0, 0], name: "Stockholm".to_string(), extra: vec!["x".to_string()] });
+        assert_eq!(definitions.entries[1].color, [0, 128, 0]);
+        assert_eq!(definitions.entries[1].extra, vec!["x", "y"]);
+    }
+
+    #[test]
+    fn bad_lines_are_errors_with_their_number() {
+        let error = Definitions::parse("1;255;0;0;A\n\n2;300;0;0;B\n").unwrap_err();
+        assert_eq!(error, "line 3: expected `id;red;green;blue;name`, found `2;300;0;0;B`");
+    }
+
+    #[test]
+    fn unchanged_files_are_written_back_the_same() {
+        let text = "\u{feff}province;red;green;blue;x;x\r\n# Sweden\r\n1;255;0;0; Stockholm ;x\r\n\r\n2;0;128;0;Uppsala;x\r\n# end\r\n";
+        let definitions = Definitions::parse(text).unwrap();
+        assert!(definitions.bom && definitions.crlf);
+        assert_eq!(definitions.entries[0].name, " Stockholm ");
+        assert_eq!(format!("{}{}", BOM, definitions.to_text()), text);
+    }
+
+    #[test]
+    fn new_entries_go_after_the_comments_that_end_the_file() {
+        let mut definitions = Definitions::parse("1;255;0;0;A;x\n# end\n").unwrap();
+        definitions.entries.push(Definition { id: 2, color: [0, 0, 255], name: String::new(), extra: vec!["x".to_string()] });
+        assert_eq!(definitions.to_text(), "1;255;0;0;A;x\n# end\n2;0;0;255;;x\n");
+    }
+
+    #[test]
+    fn lines_are_kept_as_written_until_their_entry_changes() {
+        let text = "007;255;0;0;A;x\n2; 0;128;0 ;;x;y\n3;0;0;255;C;x\n";
+        let mut definitions = Definitions::parse(text).unwrap();
+        assert_eq!(definitions.to_text(), text);
+        definitions.entries[1].name = "B".to_string();
+        definitions.entries.remove(2);
+        assert_eq!(definitions.to_text(), "007;255;0;0;A;x\n2;0;128;0;B;x;y\n");
+    }
+
+    // Red, green and blue provinces, ProvinceIds 0 to 2
+    fn three_colors() -> ProvinceMap {
+        ProvinceMap::from_image(&image::RgbImage::from_fn(3, 1, |x, _| match x {
+            0 => image::Rgb([255, 0, 0]),
+            1 => image::Rgb([0, 255, 0]),
+            _ => image::Rgb([0, 0, 255]),
+        }))
+    }
+
+    #[test]
+    fn reconciling_finds_missing_extra_and_duplicate_entries() {
+        let map = three_colors();
+        let definitions = Definitions::parse("1;255;0;0;A;x\n1;0;255;0;B;x\n5;255;0;0;C;x\n9;9;9;9;D;x\n").unwrap();
+        let reconciliation = definitions.reconcile(&map);
+        // The first of the two reds is used
+        assert_eq!(reconciliation.by_province, vec![Some(0), Some(1), None]);
+        assert_eq!(reconciliation.missing, vec![2]);
+        assert_eq!(reconciliation.extra, vec![3]);
+        assert_eq!(reconciliation.duplicate_colors, vec![vec![0, 2]]);
+        assert_eq!(reconciliation.duplicate_ids, vec![vec![0, 1]]);
+        assert!(!reconciliation.is_clean());
+
+        let clean = Definitions::parse("1;255;0;0;A\n2;0;255;0;B\n3;0;0;255;C\n").unwrap();
+        assert!(clean.reconcile(&map).is_clean());
+    }
+
+    #[test]
+    fn missing_provinces_are_numbered_after_the_highest_id() {
+        let map = three_colors();
+        let mut definitions = Definitions::parse("4;0;255;0;B;x;y\n2;255;0;0;A;x;y\n").unwrap();
+        let reconciliation = definitions.reconcile(&map);
+        assert_eq!(definitions.add_missing(&map, &reconciliation), 1);
+        assert_eq!(definitions.to_text(), "4;0;255;0;B;x;y\n2;255;0;0;A;x;y\n5;0;0;255;;x;x\n");
+        assert!(definitions.reconcile(&map).is_clean());
+
+        let mut empty = Definitions::default();
+        let reconciliation = empty.reconcile(&map);
+        assert_eq!(empty.add_missing(&map, &reconciliation), 3);
+        assert_eq!(empty.entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(empty.entries.iter().all(|e| e.extra.is_empty()));
+    }
+
+    #[test]
+    fn columns_are_read_per_province() {
+        let map = three_colors();
+        let definitions = Definitions::parse("1;255;0;0; Svealand ;SWE;x\n2;0;255;0;Skane;DAN;x\n3;0;0;255;Gotland;;x\n").unwrap();
+        assert_eq!(definitions.column(&map, 5), vec![Some("SWE".to_string()), Some("DAN".to_string()), None]);
+        assert_eq!(definitions.names(&map)[0].as_deref(), Some("Svealand"));
+        assert_eq!(definitions.column(&map, 0), vec![Some("1".to_string()), Some("2".to_string()), Some("3".to_string())]);
+        assert_eq!(definitions.column(&map, 9), vec![None, None, None]);
+    }
+
+    #[test]
+    fn files_keep_their_mark_and_encoding() {
+        let dir = TempDir::new("definitions-encoding", &[]);
+        let (from, to) = (dir.0.join("in.csv"), dir.0.join("out.csv"));
+        let mut bytes = BOM.as_bytes().to_vec();
+        bytes.extend(b"1;255;0;0;G\xf6teborg;x\n");
+        fs::write(&from, &bytes).unwrap();
+        let definitions = Definitions::read(from.to_str().unwrap()).unwrap();
+        assert!(definitions.latin1 && definitions.bom);
+        assert_eq!(definitions.entries[0].name, "G\u{f6}teborg");
+        definitions.write(to.to_str().unwrap()).unwrap();
+        assert_eq!(fs::read(&to).unwrap(), bytes);
+    }
+}
@@ -0,0 +1,337 @@
+use image::{Rgba, RgbaImage};
+use std::fs;
+use wgpu::util::DeviceExt;
+use crate::province_borders::ProvinceClass;
+use crate::province_map::{ProvinceId, ProvinceMap};
+use crate::translate;
+use crate::backend::WgpuBackend;
+use crate::cli::SdfArgs;
+use crate::province_borders::read_classes;
+
+// Compute shader doing one jump flood step, relative to the shader directory
+pub const JUMP_FLOOD_SHADER: &str = "jump_flood.glsl";
+
+// Marks a pixel that has not found a seed yet
+const NO_SEED: [f32; 2] = [-1.0, -1.0];
+
+// Must match local_size in jump_flood.glsl
+const WORKGROUP_SIZE: u32 = 8;
+
+// Distance in pixels from every pixel center to the nearest border, row by
+// row from the top. Region fields are signed, negative inside the region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceField {
+    pub width: u32,
+    pub height: u32,
+    pub distances: Vec<f32>,
+}
+
+// Seed of every pixel touching a border: the middle of its first edge shared
+// with a pixel on the other side. `differs` tells if two neighbouring
+// provinces are separated by the kind of border wanted.
+pub fn border_seeds(map: &ProvinceMap, differs: impl Fn(ProvinceId, ProvinceId) -> bool) -> Vec<[f32; 2]> {
+    let (w, h) = (map.width as i64, map.height as i64);
+    let mut seeds = vec![NO_SEED; map.ids.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let id = map.ids[(y * w + x) as usize];
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                    continue;
+                }
+                if differs(id, map.ids[(ny * w + nx) as usize]) {
+                    seeds[(y * w + x) as usize] = [x as f32 + 0.5 + dx as f32 * 0.5, y as f32 + 0.5 + dy as f32 * 0.5];
+                    break;
+                }
+            }
+        }
+    }
+    seeds
+}
+
+// Step lengths of the flood: half the size down to 1, then 1 again (JFA+1),
+// which fixes most of the errors plain jump flooding leaves
+fn jump_steps(width: u32, height: u32) -> Vec<u32> {
+    let mut steps = Vec::new();
+    let mut step = width.max(height).next_power_of_two() / 2;
+    while step >= 1 {
+        steps.push(step);
+        step /= 2;
+    }
+    steps.push(1);
+    steps
+}
+
+// Jump flood on the CPU, same steps and tie breaking as jump_flood.glsl.
+// Returns the nearest seed of every pixel.
+pub fn jump_flood_cpu(width: u32, height: u32, seeds: &[[f32; 2]]) -> Vec<[f32; 2]> {
+    let (w, h) = (width as i64, height as i64);
+    let mut source = seeds.to_vec();
+    let mut destination = vec![NO_SEED; seeds.len()];
+    for step in jump_steps(width, height) {
+        let step = step as i64;
+        for y in 0..h {
+            for x in 0..w {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let mut best = NO_SEED;
+                let mut best_d = f32::MAX;
+                for oy in -1..=1 {
+                    for ox in -1..=1 {
+                        let (qx, qy) = (x + ox * step, y + oy * step);
+                        if qx < 0 || qy < 0 || qx >= w || qy >= h {
+                            continue;
+                        }
+                        let seed = source[(qy * w + qx) as usize];
+                        if seed[0] < 0.0 {
+                            continue;
+                        }
+                        let d = (seed[0] - center[0]).powi(2) + (seed[1] - center[1]).powi(2);
+                        if d < best_d {
+                            best_d = d;
+                            best = seed;
+                        }
+                    }
+                }
+                destination[(y * w + x) as usize] = best;
+            }
+        }
+        std::mem::swap(&mut source, &mut destination);
+    }
+    source
+}
+
+fn to_bytes(points: &[[f32; 2]]) -> Vec<u8> {
+    points.iter().flat_map(|p| p.iter().flat_map(|v| v.to_ne_bytes())).collect()
+}
+
+// Jump flood as a wgpu compute shader, ping-ponging between two storage buffers
+pub struct JumpFloodGpu {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl JumpFloodGpu {
+    // Builds the pipeline from jump_flood.glsl in `shader_dir`
+    pub fn new(device: &wgpu::Device, shader_dir: &str) -> Result<Self, String> {
+        let path = format!("{}/{}", shader_dir, JUMP_FLOOD_SHADER);
+        let source = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+        let translated = translate::translate(&path, &source, naga::ShaderStage::Compute).map_err(|diagnostics| {
+            diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
+        })?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(JUMP_FLOOD_SHADER),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(translated.module)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("jump flood"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+        });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(format!("jump flood pipeline failed to build: {}", error));
+        }
+        Ok(JumpFloodGpu { pipeline })
+    }
+
+    // Returns the nearest seed of every pixel, like `jump_flood_cpu`
+    pub fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, seeds: &[[f32; 2]]) -> Result<Vec<[f32; 2]>, String> {
+        let size = (seeds.len() * 8) as u64;
+        // Both scopes cover the buffers too, which fail on maps larger than
+        // the device allows; errors outside a scope panic instead of reaching
+        // the CPU fallback
+        device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let buffers = [
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some("jump flood a"), contents: &to_bytes(seeds), usage: storage }),
+            device.create_buffer(&wgpu::BufferDescriptor { label: Some("jump flood b"), size, usage: storage, mapped_at_creation: false }),
+        ];
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("jump flood readback"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let layout = self.pipeline.get_bind_group_layout(0);
+        let steps = jump_steps(width, height);
+        // One uniform buffer and bind group per step, all recorded in one submission
+        let bind_groups: Vec<wgpu::BindGroup> = steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let params: Vec<u8> = [width as i32, height as i32, *step as i32, 0].iter().flat_map(|v| v.to_ne_bytes()).collect();
+                let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("jump flood step"),
+                    contents: &params,
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 0, resource: buffers[i % 2].as_entire_binding() },
+                        wgpu::BindGroupEntry { binding: 1, resource: buffers[(i + 1) % 2].as_entire_binding() },
+                        wgpu::BindGroupEntry { binding: 2, resource: uniform.as_entire_binding() },
+                    ],
+                })
+            })
+            .collect();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("jump flood") });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("jump flood") });
+            pass.set_pipeline(&self.pipeline);
+            for bind_group in &bind_groups {
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+            }
+        }
+        encoder.copy_buffer_to_buffer(&buffers[steps.len() % 2], 0, &staging, 0, size);
+        let commands = encoder.finish();
+        let validation = pollster::block_on(device.pop_error_scope());
+        let out_of_memory = pollster::block_on(device.pop_error_scope());
+        if let Some(error) = validation.or(out_of_memory) {
+            return Err(error.to_string());
+        }
+        queue.submit(Some(commands));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+
+        let nearest = {
+            let mapped = slice.get_mapped_range();
+            mapped
+                .chunks_exact(8)
+                .map(|c| [f32::from_ne_bytes([c[0], c[1], c[2], c[3]]), f32::from_ne_bytes([c[4], c[5], c[6], c[7]])])
+                .collect()
+        };
+        staging.unmap();
+        Ok(nearest)
+    }
+}
+
+// GPU context to run the flood on; None runs it on the CPU
+pub type Gpu<'a> = Option<(&'a JumpFloodGpu, &'a wgpu::Device, &'a wgpu::Queue)>;
+
+// Nearest seeds on the GPU when given one, falling back to the CPU when the
+// GPU run fails
+fn nearest_seeds(width: u32, height: u32, seeds: &[[f32; 2]], gpu: Gpu) -> Vec<[f32; 2]> {
+    if let Some((flood, device, queue)) = gpu {
+        match flood.run(device, queue, width, height, seeds) {
+            Ok(nearest) => return nearest,
+            Err(error) => log::warn!("jump flood on the GPU failed, using the CPU: {}", error),
+        }
+    }
+    jump_flood_cpu(width, height, seeds)
+}
+
+impl DistanceField {
+    // Distance to the nearest border between provinces for which `differs`
+    // holds; f32::INFINITY everywhere when there is no such border
+    pub fn borders(map: &ProvinceMap, differs: impl Fn(ProvinceId, ProvinceId) -> bool, gpu: Gpu) -> Self {
+        let seeds = border_seeds(map, differs);
+        let nearest = nearest_seeds(map.width, map.height, &seeds, gpu);
+        let distances = nearest
+            .iter()
+            .enumerate()
+            .map(|(i, seed)| {
+                if seed[0] < 0.0 {
+                    return f32::INFINITY;
+                }
+                let (x, y) = ((i as u32 % map.width) as f32 + 0.5, (i as u32 / map.width) as f32 + 0.5);
+                ((seed[0] - x).powi(2) + (seed[1] - y).powi(2)).sqrt()
+            })
+            .collect();
+        DistanceField { width: map.width, height: map.height, distances }
+    }
+
+    // Signed distance to the edge of the region made of the provinces for
+    // which `inside` holds, negative inside
+    pub fn region(map: &ProvinceMap, inside: impl Fn(ProvinceId) -> bool, gpu: Gpu) -> Self {
+        let mut field = Self::borders(map, |a, b| inside(a) != inside(b), gpu);
+        for (distance, id) in field.distances.iter_mut().zip(&map.ids) {
+            if inside(*id) {
+                *distance = -*distance;
+            }
+        }
+        field
+    }
+
+    pub fn distance_at(&self, x: u32, y: u32) -> f32 {
+        self.distances[(y * self.width + x) as usize]
+    }
+}
+
+// Packs the province, country and coast border distances of province_borders
+// into one texture (r, g, b), each as distance / max_distance in 0..255.
+// Read it with linear filtering for smooth outlines at any zoom.
+pub fn border_distance_image(map: &ProvinceMap, classes: &[ProvinceClass], max_distance: f32, gpu: Gpu) -> RgbaImage {
+    let class = |id: ProvinceId| classes.get(id as usize).copied().unwrap_or_default();
+    let any = DistanceField::borders(map, |a, b| a != b, gpu);
+    let country = DistanceField::borders(map, |a, b| {
+        let (a, b) = (class(a), class(b));
+        !a.is_sea && !b.is_sea && a.country != b.country
+    }, gpu);
+    let coast = DistanceField::borders(map, |a, b| class(a).is_sea != class(b).is_sea, gpu);
+
+    let encode = |d: f32| ((d / max_distance).clamp(0.0, 1.0) * 255.0).round() as u8;
+    RgbaImage::from_fn(map.width, map.height, |x, y| {
+        Rgba([encode(any.distance_at(x, y)), encode(country.distance_at(x, y)), encode(coast.distance_at(x, y)), 255])
+    })
+}
+
+// `shaders sdf`: writes the border distance image of a province image,
+// flooding on the GPU when one is available
+pub fn run(args: &SdfArgs) -> Result<(), String> {
+    let map = ProvinceMap::from_path(&args.image)?;
+    let classes = match &args.classes {
+        Some(path) => read_classes(path, &map)?,
+        None => Vec::new(),
+    };
+    let backend = WgpuBackend::new_headless(wgpu::Backends::all()).ok();
+    let flood = backend.as_ref().and_then(|b| JumpFloodGpu::new(b.device(), "assets/shadercode").ok());
+    let gpu = backend.as_ref().zip(flood.as_ref()).map(|(b, flood)| (flood, b.device(), b.queue()));
+    let distances = border_distance_image(&map, &classes, args.max_distance, gpu);
+    distances.save(&args.out).map_err(|e| format!("{}: {}", args.out, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::province_borders::parse_classes;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn distance_image_has_a_channel_per_kind_of_border() {
+        // Two land provinces of different countries, then sea
+        let map = ProvinceMap::from_image(&RgbImage::from_fn(6, 1, |x, _| match x / 2 {
+            0 => Rgb([255, 0, 0]),
+            1 => Rgb([0, 255, 0]),
+            _ => Rgb([0, 0, 255]),
+        }));
+        let classes = parse_classes(r#"[{"color": [255, 0, 0], "country": 1}, {"color": [0, 255, 0], "country": 2}, {"color": [0, 0, 255], "sea": true}]"#, &map).unwrap();
+        let image = border_distance_image(&map, &classes, 4.0, None);
+        let channel = |c: usize| (0..6).map(|x| image.get_pixel(x, 0)[c]).collect::<Vec<_>>();
+        // Pixel centers sit half a pixel from the nearest edge
+        let near = (0.5f32 / 4.0 * 255.0).round() as u8;
+        assert_eq!(channel(0)[1..5], [near; 4]);
+        assert_eq!(channel(1)[1..3], [near; 2]);
+        assert_eq!(channel(2)[3..5], [near; 2]);
+        assert!(channel(1)[4] > near && channel(2)[1] > near);
+    }
+
+    #[test]
+    fn cpu_flood_finds_the_nearest_seed() {
+        let nearest = jump_flood_cpu(5, 1, &[[0.5, 0.5], NO_SEED, NO_SEED, NO_SEED, [4.5, 0.5]]);
+        assert_eq!(nearest.iter().map(|p| p[0]).collect::<Vec<_>>(), [0.5, 0.5, 0.5, 4.5, 4.5]);
+    }
+}
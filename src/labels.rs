@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+use crate::distance_field::DistanceField;
+use crate::province_map::{ProvinceId, ProvinceMap};
+use crate::text::{default_charset, draw_path_texts, GlyphAtlas, PathText, TextStyle};
+use crate::cli::LabelsArgs;
+use crate::definitions::Definitions;
+
+// Something to name on the map: a province, or several (a country)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelRegion {
+    pub text: String,
+    pub provinces: Vec<ProvinceId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacementSettings {
+    // Font sizes in map pixels; labels that don't fit at min_size are dropped
+    pub min_size: f32,
+    pub max_size: f32,
+    // Extra room left along the path, as a share of the text width
+    pub padding: f32,
+    // Largest bend, as the curve's height over the middle of the region
+    // relative to the region's length
+    pub max_bend: f32,
+    // Pixels looked at per region, bigger regions are sampled more sparsely
+    pub max_samples: usize,
+}
+
+impl Default for PlacementSettings {
+    fn default() -> Self {
+        PlacementSettings { min_size: 4.0, max_size: 120.0, padding: 0.15, max_bend: 0.15, max_samples: 20000 }
+    }
+}
+
+// A point of the region with its distance to the region's edge
+struct Sample {
+    x: f32,
+    y: f32,
+    clearance: f32,
+}
+
+// Solves a 3x3 system by Gaussian elimination, None when it is singular
+fn solve3(mut m: [[f64; 4]; 3]) -> Option<[f64; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        m.swap(col, pivot);
+        for row in 0..3 {
+            if row != col {
+                let f = m[row][col] / m[col][col];
+                let pivot_row = m[col];
+                for (value, pivot_value) in m[row].iter_mut().zip(pivot_row).skip(col) {
+                    *value -= f * pivot_value;
+                }
+            }
+        }
+    }
+    Some([m[0][3] / m[0][0], m[1][3] / m[1][1], m[2][3] / m[2][2]])
+}
+
+// Fits the text of `region` on a curve along the middle of the region, as
+// large as the region allows. `field` holds the distance to the borders
+// between regions of this kind (province borders for province names,
+// country borders for country names). Returns None when it doesn't fit.
+pub fn place_label(map: &ProvinceMap, field: &DistanceField, atlas: &GlyphAtlas, region: &LabelRegion, settings: &PlacementSettings) -> Option<PathText> {
+    fit_label(map, field, |size| atlas.text_width(&region.text, size), region, settings)
+}
+
+// `place_label` with the text measured by `text_width`, the width of the
+// region's text at a font size
+fn fit_label(map: &ProvinceMap, field: &DistanceField, text_width: impl Fn(f32) -> f32, region: &LabelRegion, settings: &PlacementSettings) -> Option<PathText> {
+    let members: HashSet<ProvinceId> = region.provinces.iter().copied().collect();
+    let provinces: Vec<_> = region.provinces.iter().filter_map(|id| map.provinces.get(*id as usize)).collect();
+    let min_x = provinces.iter().map(|p| p.bbox.min_x).min()?;
+    let min_y = provinces.iter().map(|p| p.bbox.min_y).min()?;
+    let max_x = provinces.iter().map(|p| p.bbox.max_x).max()?;
+    let max_y = provinces.iter().map(|p| p.bbox.max_y).max()?;
+    let area: u64 = provinces.iter().map(|p| p.area).sum();
+    let inside = |x: f32, y: f32| {
+        let (px, py) = (x.floor(), y.floor());
+        px >= 0.0 && py >= 0.0 && map.province_at(px as u32, py as u32).is_some_and(|id| members.contains(&id))
+    };
+    let clearance = |x: f32, y: f32| field.distance_at((x.floor() as u32).min(map.width - 1), (y.floor() as u32).min(map.height - 1));
+
+    // Pixels of the region, on a grid coarse enough to stay under max_samples
+    let stride = ((area as f64 / settings.max_samples as f64).sqrt().ceil() as u32).max(1);
+    let mut samples = Vec::new();
+    for y in (min_y..=max_y).step_by(stride as usize) {
+        for x in (min_x..=max_x).step_by(stride as usize) {
+            if map.province_at(x, y).is_some_and(|id| members.contains(&id)) {
+                samples.push(Sample { x: x as f32 + 0.5, y: y as f32 + 0.5, clearance: field.distance_at(x, y) });
+            }
+        }
+    }
+    if samples.is_empty() {
+        return None;
+    }
+
+    // Main axis of the region, pointing right (or up when vertical) so the text reads well
+    let n = samples.len() as f32;
+    let (mx, my) = (samples.iter().map(|s| s.x).sum::<f32>() / n, samples.iter().map(|s| s.y).sum::<f32>() / n);
+    let (mut cxx, mut cyy, mut cxy) = (0.0, 0.0, 0.0);
+    for s in &samples {
+        cxx += (s.x - mx) * (s.x - mx);
+        cyy += (s.y - my) * (s.y - my);
+        cxy += (s.x - mx) * (s.y - my);
+    }
+    let angle = 0.5 * (2.0 * cxy).atan2(cxx - cyy);
+    let mut u = [angle.cos(), angle.sin()];
+    if u[0] < -1e-3 || (u[0].abs() <= 1e-3 && u[1] > 0.0) {
+        u = [-u[0], -u[1]];
+    }
+    let v = [-u[1], u[0]];
+    let to_local = |x: f32, y: f32| ((x - mx) * u[0] + (y - my) * u[1], (x - mx) * v[0] + (y - my) * v[1]);
+    let to_map = |t: f32, n: f32| [mx + u[0] * t + v[0] * n, my + u[1] * t + v[1] * n];
+
+    // Medial axis: fit n = a t^2 + b t + c through the ridge of the distance
+    // field, the samples farthest from the edge, weighting the deepest most
+    let deepest = samples.iter().map(|s| s.clearance).fold(0.0, f32::max);
+    let (mut t_min, mut t_max) = (f32::MAX, f32::MIN);
+    let mut normal = [[0.0f64; 4]; 3];
+    for s in &samples {
+        let (t, n) = to_local(s.x, s.y);
+        t_min = t_min.min(t);
+        t_max = t_max.max(t);
+        if s.clearance < deepest * 0.5 {
+            continue;
+        }
+        let w = (s.clearance as f64).powi(2);
+        let basis = [(t as f64).powi(2), t as f64, 1.0];
+        for row in 0..3 {
+            for col in 0..3 {
+                normal[row][col] += w * basis[row] * basis[col];
+            }
+            normal[row][3] += w * basis[row] * n as f64;
+        }
+    }
+    let [mut a, b, c] = solve3(normal).or_else(|| {
+        // Ridge along a single line across the axis, keep the curve straight
+        let (sum_w, sum_n) = (normal[2][2], normal[2][3]);
+        (sum_w > 0.0).then(|| [0.0, 0.0, sum_n / sum_w])
+    })?;
+    let half_span = ((t_max - t_min) * 0.5).max(1.0) as f64;
+    let bend_limit = settings.max_bend as f64 * 2.0 / half_span;
+    a = a.clamp(-bend_limit, bend_limit);
+    let curve_n = |t: f32| (a * (t as f64).powi(2) + b * t as f64 + c) as f32;
+
+    // Walk the curve one pixel at a time, noting the clearance at each step
+    let step = 1.0;
+    let mut path: Vec<([f32; 2], f32, f32)> = Vec::new(); // point, arc length, clearance
+    let mut t = t_min;
+    let mut length = 0.0;
+    while t <= t_max {
+        let p = to_map(t, curve_n(t));
+        if let Some((last, _, _)) = path.last() {
+            length += ((p[0] - last[0]).powi(2) + (p[1] - last[1]).powi(2)).sqrt();
+        }
+        let room = if inside(p[0], p[1]) { clearance(p[0], p[1]) } else { 0.0 };
+        path.push((p, length, room));
+        t += step;
+    }
+
+    // Longest stretch of the curve staying `needed` away from the edge
+    let longest_run = |needed: f32| {
+        let mut best: Option<(usize, usize)> = None;
+        let mut start = None;
+        for i in 0..=path.len() {
+            let ok = i < path.len() && path[i].2 >= needed;
+            match (ok, start) {
+                (true, None) => start = Some(i),
+                (false, Some(s)) => {
+                    let len = path[i - 1].1 - path[s].1;
+                    if best.is_none_or(|(bs, be)| len > path[be].1 - path[bs].1) {
+                        best = Some((s, i - 1));
+                    }
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        best
+    };
+    // The capitals take about 0.7 of the font size, centered on the curve
+    let fits = |size: f32| {
+        let needed_length = text_width(size) * (1.0 + settings.padding);
+        longest_run(size * 0.4).filter(|(s, e)| path[*e].1 - path[*s].1 >= needed_length)
+    };
+
+    fits(settings.min_size)?;
+    let (mut low, mut high) = (settings.min_size, settings.max_size);
+    for _ in 0..16 {
+        let mid = (low + high) * 0.5;
+        if fits(mid).is_some() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let size = low;
+    let (start, end) = fits(size)?;
+
+    // Keep just the part of the run the text covers, centered in it
+    let width = text_width(size);
+    let middle = (path[start].1 + path[end].1) * 0.5;
+    let (from, to) = (middle - width * 0.5, middle + width * 0.5);
+    let points: Vec<[f32; 2]> = path[start..=end]
+        .iter()
+        .filter(|(_, s, _)| *s >= from - step && *s <= to + step)
+        .map(|(p, _, _)| *p)
+        .collect();
+    (points.len() >= 2).then(|| PathText { text: region.text.clone(), path: points, size })
+}
+
+// A region per named province; `names` is indexed by ProvinceId, as given
+// by `Definitions::names`
+pub fn province_regions(names: &[Option<String>]) -> Vec<LabelRegion> {
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(id, name)| Some(LabelRegion { text: name.clone()?, provinces: vec![id as ProvinceId] }))
+        .collect()
+}
+
+// A region per value of `values`, indexed by ProvinceId, holding the
+// provinces that have it, such as the owner column of a definition file.
+// Regions are in order of their first province.
+pub fn grouped_regions(values: &[Option<String>]) -> Vec<LabelRegion> {
+    let mut regions: Vec<LabelRegion> = Vec::new();
+    let mut by_value: HashMap<&str, usize> = HashMap::new();
+    for (id, value) in values.iter().enumerate() {
+        let Some(value) = value else { continue };
+        let region = *by_value.entry(value).or_insert_with(|| {
+            regions.push(LabelRegion { text: value.clone(), provinces: Vec::new() });
+            regions.len() - 1
+        });
+        regions[region].provinces.push(id as ProvinceId);
+    }
+    regions
+}
+
+// Places every region, dropping those that don't fit
+pub fn place_labels(map: &ProvinceMap, field: &DistanceField, atlas: &GlyphAtlas, regions: &[LabelRegion], settings: &PlacementSettings) -> Vec<PathText> {
+    regions.iter().filter_map(|region| place_label(map, field, atlas, region, settings)).collect()
+}
+
+// Name of every province from a definition file, "Province <id>" without one
+pub fn province_names(map: &ProvinceMap, definitions: Option<&Definitions>) -> Vec<Option<String>> {
+    match definitions {
+        Some(definitions) => definitions.names(map),
+        None => map.provinces.iter().map(|p| Some(format!("Province {}", p.id))).collect(),
+    }
+}
+
+// `shaders labels`: places the province names on the map image and saves
+// it, naming provinces "Province <id>" without a definition file
+pub fn run(args: &LabelsArgs) -> Result<(), String> {
+    let map = ProvinceMap::from_path(&args.image)?;
+    let atlas = GlyphAtlas::from_file(&args.font, 48.0, &default_charset())?;
+    let field = DistanceField::borders(&map, |a, b| a != b, None);
+    let definitions = args.definitions.as_deref().map(Definitions::read).transpose()?;
+    let regions = province_regions(&province_names(&map, definitions.as_ref()));
+    let placed = place_labels(&map, &field, &atlas, &regions, &PlacementSettings::default());
+    println!("{} of {} labels placed", placed.len(), regions.len());
+    let mut canvas = image::open(&args.image).map_err(|e| format!("{}: {}", args.image, e))?.to_rgba8();
+    draw_path_texts(&mut canvas, &atlas, &placed, &TextStyle::default());
+    canvas.save(&args.out).map_err(|e| format!("{}: {}", args.out, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Sea around a long, thin ellipse tilted by `angle`
+    fn ellipse(angle: f32) -> ProvinceMap {
+        let (cos, sin) = (angle.cos(), angle.sin());
+        ProvinceMap::from_image(&RgbImage::from_fn(80, 80, |x, y| {
+            let (dx, dy) = (x as f32 + 0.5 - 40.0, y as f32 + 0.5 - 40.0);
+            let (t, n) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+            if (t / 30.0).powi(2) + (n / 6.0).powi(2) <= 1.0 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn labels_follow_the_long_axis_inside_the_region() {
+        let angle = 30f32.to_radians();
+        let map = ellipse(angle);
+        let field = DistanceField::borders(&map, |a, b| a != b, None);
+        let region = LabelRegion { text: "Ellipse".to_string(), provinces: vec![1] };
+        let settings = PlacementSettings::default();
+        let label = fit_label(&map, &field, |size| size * 3.0, &region, &settings).unwrap();
+
+        // As large as the ellipse's 6 pixel half width allows
+        assert!(label.size > 8.0 && label.size < 15.0, "{}", label.size);
+        let (first, last) = (label.path[0], label.path[label.path.len() - 1]);
+        let (dx, dy) = (last[0] - first[0], last[1] - first[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        // Reads left to right along the axis, and covers the text
+        assert!((dx * angle.cos() + dy * angle.sin()) / length > 0.98, "{:?}", label.path);
+        assert!(length >= label.size * 3.0 - 2.0);
+        for [x, y] in &label.path {
+            let (x, y) = (x.floor() as u32, y.floor() as u32);
+            assert_eq!(map.province_at(x, y), Some(1));
+            assert!(field.distance_at(x, y) >= label.size * 0.4);
+        }
+    }
+
+    #[test]
+    fn labels_too_long_for_the_region_are_dropped() {
+        let map = ellipse(0.0);
+        let field = DistanceField::borders(&map, |a, b| a != b, None);
+        let region = LabelRegion { text: "Ellipse".to_string(), provinces: vec![1] };
+        assert!(fit_label(&map, &field, |size| size * 50.0, &region, &PlacementSettings::default()).is_none());
+    }
+
+    #[test]
+    fn provinces_sharing_a_value_form_one_region() {
+        let values = [Some("SWE".to_string()), None, Some("DAN".to_string()), Some("SWE".to_string())];
+        let regions = grouped_regions(&values);
+        assert_eq!(regions, vec![
+            LabelRegion { text: "SWE".to_string(), provinces: vec![0, 3] },
+            LabelRegion { text: "DAN".to_string(), provinces: vec![2] },
+        ]);
+        assert!(grouped_regions(&[None, None]).is_empty());
+    }
+
+    #[test]
+    fn solves_systems_and_rejects_singular_ones() {
+        let [x, y, z] = solve3([[0.0, 1.0, 0.0, 2.0], [2.0, 0.0, 0.0, 2.0], [0.0, 1.0, 1.0, 5.0]]).unwrap();
+        assert!((x - 1.0).abs() < 1e-9 && (y - 2.0).abs() < 1e-9 && (z - 3.0).abs() < 1e-9);
+        assert!(solve3([[1.0, 2.0, 3.0, 1.0], [2.0, 4.0, 6.0, 2.0], [0.0, 0.0, 1.0, 1.0]]).is_none());
+    }
+}
@@ -0,0 +1,29 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+// Prints log records to stderr as `LEVEL target: message`
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{} {}: {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// Installs the logger. SHADERS_LOG sets the most verbose level shown (off,
+// error, warn, info, debug or trace), warn by default.
+pub fn init() {
+    let level = std::env::var("SHADERS_LOG").ok().and_then(|level| level.parse().ok()).unwrap_or(LevelFilter::Warn);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
@@ -12,136 +12,85 @@ use std::fs;
 use gl::types::*;
 use glfw::{Action, Context, Key};
 use glfw::fail_on_errors;
+use clap::Parser;
 use crate::image::GenericImageView;
 
 mod shader_pipeline;
+mod backend;
+mod translate;
+mod check;
+mod bevy_plugin;
+mod province_map;
+mod province_borders;
+mod map_modes;
+mod province_picking;
+mod province_outlines;
+mod distance_field;
+mod text;
+mod labels;
+mod map_lint;
+mod definitions;
+mod camera;
+mod map_view;
+mod surface;
+mod assets;
+mod cli;
+mod playground;
+mod shadertoy;
+mod process;
+mod logger;
+mod render;
+#[cfg(test)]
+mod test_util;
 
 use shader_pipeline::VAO::VAO;
 use shader_pipeline::VBO::VBO;
 use shader_pipeline::EBO::EBO;
 use shader_pipeline::Shader::Shader;
 
-fn main() {
-    // Vertices coordinates
-        let vertices: [GLfloat; 32] = [
-//             COORDINATES    /     COLORS        /   TexCoord   //
-            -0.5, -0.5, 0.0,     1.0, 0.0, 0.0,     0.0, 0.0,  // Lower left corner
-            -0.5, 0.5, 0.0,     0.0, 1.0, 0.0,     0.0, 1.0,  // Lower right corner
-            0.5, 0.5, 0.0,     0.0, 0.0, 1.0,     1.0, 1.0,  // Upper corner
-            0.5, -0.5, 0.0,     1.0, 1.0, 1.0,     1.0, 0.0  // Inner left
-        ];
-
-    // Indices for vertices order
-    let indices: [GLuint; 6] = [
-        0, 2, 1, // Upper triangle
-	    0, 3, 2 // Lower triangle
-    ];
-
-    // Initialize GLFW
-    let mut glfw = glfw::init(glfw::fail_on_errors!()).unwrap();
-
-    // Specify OpenGL version and profile
-    glfw.window_hint(glfw::WindowHint::ContextVersion(3, 3));
-    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
-
-    // Create a GLFW window
-    let (mut window, events) = glfw
-        .create_window(800, 800, "OpenGL Playground", glfw::WindowMode::Windowed)
-        .expect("Failed to create GLFW window");
-
-    // Make window's context current
-    window.make_current();
-    gl::load_with(|s| window.get_proc_address(s) as *const _);
-
-    // Specify the viewport
-    unsafe {
-        gl::Viewport(0, 0, 800, 800);
+// Ends the process with `code` when a command failed
+fn exit_on_error(result: Result<(), String>, code: i32) {
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        std::process::exit(code);
     }
+}
 
-    // Shader source code
-    let vertex_file = "C:\\Users\\pasca\\My Game\\shaders\\assets\\shadercode\\vertex_test.glsl";
-    let fragment_file = "C:\\Users\\pasca\\My Game\\shaders\\assets\\shadercode\\fragment_test.glsl";
-
-    // Generate Shader object using shaders default.vert and default.frag
-    // Check if Shader creation was successful
-    match Shader::new(vertex_file, fragment_file) {
-        Ok(shader_program) => {
-            // Shader creation successful
-
-            // Generate Vertex Array Object and bind it
-            let vao = VAO::new();
-            vao.bind();
-
-            // Generate Vertex Buffer Object and link it to verticies
-            let vbo = VBO::new(&vertices);
-
-            // Generate Element Buffer Object and link it to indices
-            let ebo = EBO::new(&indices);
-
-            // Links VBO attributes such as coordinates and colors to VAO
-            vao.link_attrib(&vbo, 0, 3, gl::FLOAT, 6  * std::mem::size_of::<f32>() as GLsizei, std::ptr::null());
-            vao.link_attrib(&vbo, 1, 3, gl::FLOAT, 6 * std::mem::size_of::<f32>() as GLsizei, (3 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
-
-
-            // Unbind all to prevent accidental modifications
-            vao.unbind();
-            vbo.unbind();
-            ebo.unbind();
-
-            
-            // Get id of Uniform called "scale"                
-            let uni_id: i32 = unsafe {
-                gl::GetUniformLocation(shader_program.id,  CString::new("scale").unwrap().as_ptr())
-            };
-
-            // Loop until the user closes the window
-            while !window.should_close() {
-                unsafe {
-                    // Specify the color of the background
-                    gl::ClearColor(0.07f32, 0.13f32, 0.17f32, 1.0f32);
-                    // Clean the back buffer and assign the new color
-                    gl::Clear(gl::COLOR_BUFFER_BIT);
-                    // Tell OpenGL which shader program to use 
-                    shader_program.activate();
-                    // Assigns a value to the uniform; NOTE: Must always be done after activating the Shader Program
-                    gl::Uniform1f(uni_id, 0.5);
-                    // Bind the vao so OpenGL knows to use it
-                    vao.bind();
-                    // Draw the triangles using GL_TRIANGLES primitive
-                    gl::DrawElements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null());
-                    // Swap front and back buffers
-                    window.swap_buffers();
-                }
-
-                // Poll for and process events
-                glfw.poll_events();
-                for (_, event) in glfw::flush_messages(&events) {
-                    println!("{:?}", event);
-                    match event {
-                        glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-                            window.set_should_close(true)
-                        },
-                        _ => {},
-                    }
-                }
-
-                // Render the quad with the shader
-                // render_quad(program, texture_id, vao);
-            }
-        
-            // Delete all the objects 
-            vao.delete();
-            vbo.delete();
-            ebo.delete();
-            shader_program.delete();
-        }
+fn main() {
+    let cli = cli::Cli::parse();
 
-        Err(error) => {
-            // Shader creation failed, handle or log the error
-            eprintln!("Error creating shader program: {}", error);
+    // The Bevy demo installs its own logger
+    if !matches!(cli.command, Some(cli::Command::Bevy(_))) {
+        logger::init();
+    }
 
-            // Optionally, you might want to exit the program or take appropriate action
+    let cache_dir = std::path::Path::new("target/province_cache");
+    match cli.command {
+        // Anything but a subcommand runs the playground, see `shaders --help`
+        None => {
+            let options = cli.playground.options().unwrap_or_else(|error| {
+                eprintln!("error: {}", error);
+                std::process::exit(2);
+            });
+            exit_on_error(playground::run(&options), 1);
         }
+        Some(cli::Command::Check(args)) => std::process::exit(check::run(&args.dir)),
+        // Paths are relative to the assets folder
+        Some(cli::Command::Bevy(args)) => bevy_plugin::run_demo(&args.effect, Some(&args.image)),
+        Some(cli::Command::Outlines(args)) => exit_on_error(province_outlines::run(&args), 2),
+        Some(cli::Command::Sdf(args)) => exit_on_error(distance_field::run(&args), 2),
+        Some(cli::Command::Labels(args)) => exit_on_error(labels::run(&args), 2),
+        Some(cli::Command::MapLint(args)) => std::process::exit(map_lint::run(&args.image, &args.overlay, args.definitions.as_deref())),
+        Some(cli::Command::Definitions(args)) => std::process::exit(definitions::run(&args.image, &args.definition, args.out.as_deref())),
+        Some(cli::Command::View(args)) => exit_on_error(map_view::run(&args, "assets/shadercode", cache_dir), 2),
+        Some(cli::Command::Pick(args)) => exit_on_error(province_picking::run_demo("assets", &args.image, cache_dir), 2),
+        Some(cli::Command::Shadertoy(args)) => exit_on_error(shadertoy::run(&args), 2),
+        // Exits with 1 when some images failed
+        Some(cli::Command::Process(args)) => match process::run(&args) {
+            Ok(0) => {}
+            Ok(_) => std::process::exit(1),
+            Err(error) => exit_on_error(Err(error), 2),
+        },
+        Some(cli::Command::Render(args)) => exit_on_error(render::run(&args), 2),
     }
 }
-
@@ -0,0 +1,299 @@
+use image::{Rgb, RgbImage};
+use std::collections::HashSet;
+use std::fmt;
+use crate::definitions::Definitions;
+use crate::province_map::{ProvinceId, ProvinceMap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LintSettings {
+    // Pieces of a province this small or smaller are stray pixels
+    pub stray_size: u64,
+    // Colors closer than this (euclidean, in 0..255 units) are near duplicates
+    pub similar_distance: f32,
+}
+
+impl Default for LintSettings {
+    fn default() -> Self {
+        LintSettings { stray_size: 2, similar_distance: 3.0 }
+    }
+}
+
+// A 4-connected piece of a province
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub pixels: Vec<[u32; 2]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    // A few pixels cut off from the rest of their province, or a whole province that small
+    StrayPixels { province: ProvinceId, color: [u8; 3], part: Part },
+    // A province in several pieces big enough to be on purpose; the largest piece is left out
+    Disconnected { province: ProvinceId, color: [u8; 3], parts: Vec<Part> },
+    // Two provinces whose colors are easy to confuse
+    SimilarColors { a: (ProvinceId, [u8; 3]), b: (ProvinceId, [u8; 3]), distance: f32 },
+    // A color of the image with no line in the definition file
+    MissingDefinition { province: ProvinceId, color: [u8; 3], pixel: [u32; 2] },
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::StrayPixels { color, part, .. } => {
+                let [x, y] = part.pixels[0];
+                write!(f, "{},{}: {} stray pixel(s) of {}", x, y, part.pixels.len(), hex(*color))
+            }
+            Issue::Disconnected { color, parts, .. } => {
+                let pieces: Vec<String> = parts.iter().map(|p| format!("{},{} ({} px)", p.pixels[0][0], p.pixels[0][1], p.pixels.len())).collect();
+                write!(f, "{} is in {} pieces, apart from the main one: {}", hex(*color), parts.len() + 1, pieces.join(", "))
+            }
+            Issue::SimilarColors { a, b, distance } => {
+                write!(f, "{} and {} are nearly the same color (distance {:.1})", hex(a.1), hex(b.1), distance)
+            }
+            Issue::MissingDefinition { color, pixel, .. } => {
+                write!(f, "{},{}: {} is not in the definition file", pixel[0], pixel[1], hex(*color))
+            }
+        }
+    }
+}
+
+// Splits every province into its 4-connected pieces
+fn parts(map: &ProvinceMap) -> Vec<Vec<Part>> {
+    let (w, h) = (map.width as usize, map.height as usize);
+    let mut seen = vec![false; w * h];
+    let mut parts: Vec<Vec<Part>> = vec![Vec::new(); map.provinces.len()];
+    let mut stack = Vec::new();
+    for start in 0..w * h {
+        if seen[start] {
+            continue;
+        }
+        let id = map.ids[start];
+        seen[start] = true;
+        stack.push(start);
+        let mut pixels = Vec::new();
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            pixels.push([x as u32, y as u32]);
+            let mut visit = |j: usize| {
+                if !seen[j] && map.ids[j] == id {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            };
+            if x > 0 {
+                visit(i - 1);
+            }
+            if x + 1 < w {
+                visit(i + 1);
+            }
+            if y > 0 {
+                visit(i - w);
+            }
+            if y + 1 < h {
+                visit(i + w);
+            }
+        }
+        // Report pieces from their top left pixel
+        pixels.sort_by_key(|p| (p[1], p[0]));
+        parts[id as usize].push(Part { pixels });
+    }
+    parts
+}
+
+// Checks a province map, and its colors against `definitions` when given
+pub fn lint(map: &ProvinceMap, definitions: Option<&HashSet<[u8; 3]>>, settings: &LintSettings) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for (province, mut pieces) in parts(map).into_iter().enumerate() {
+        let province = province as ProvinceId;
+        let color = map.provinces[province as usize].color;
+        pieces.sort_by_key(|p| std::cmp::Reverse(p.pixels.len()));
+        // Keep the main piece unless the whole province is stray
+        if map.provinces[province as usize].area > settings.stray_size {
+            pieces.remove(0);
+        }
+        let (stray, apart): (Vec<Part>, Vec<Part>) = pieces.into_iter().partition(|p| p.pixels.len() as u64 <= settings.stray_size);
+        issues.extend(stray.into_iter().map(|part| Issue::StrayPixels { province, color, part }));
+        if !apart.is_empty() {
+            issues.push(Issue::Disconnected { province, color, parts: apart });
+        }
+    }
+
+    let provinces = &map.provinces;
+    for (i, a) in provinces.iter().enumerate() {
+        for b in &provinces[i + 1..] {
+            let distance = (0..3).map(|c| (a.color[c] as f32 - b.color[c] as f32).powi(2)).sum::<f32>().sqrt();
+            if distance < settings.similar_distance {
+                issues.push(Issue::SimilarColors { a: (a.id, a.color), b: (b.id, b.color), distance });
+            }
+        }
+    }
+
+    if let Some(definitions) = definitions {
+        for province in provinces {
+            if !definitions.contains(&province.color) {
+                // First pixel met when scanning, the bbox's top row holds it
+                let y = province.bbox.min_y;
+                let x = (province.bbox.min_x..=province.bbox.max_x).find(|x| map.province_at(*x, y) == Some(province.id)).unwrap_or(province.bbox.min_x);
+                issues.push(Issue::MissingDefinition { province: province.id, color: province.color, pixel: [x, y] });
+            }
+        }
+    }
+    issues
+}
+
+// The map dimmed to gray with the issues painted on top: stray pixels red,
+// detached pieces orange, near duplicate colors magenta and undefined colors
+// blue. Small marks get a ring so they can be found on a large map.
+pub fn overlay(map: &ProvinceMap, image: &RgbImage, issues: &[Issue]) -> RgbImage {
+    let mut out = RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        let gray = ((r as u32 * 3 + g as u32 * 6 + b as u32) / 10 / 3 + 40) as u8;
+        Rgb([gray, gray, gray])
+    });
+    let paint_province = |out: &mut RgbImage, province: ProvinceId, color: Rgb<u8>| {
+        let bbox = map.provinces[province as usize].bbox;
+        for y in bbox.min_y..=bbox.max_y {
+            for x in bbox.min_x..=bbox.max_x {
+                if map.province_at(x, y) == Some(province) {
+                    out.put_pixel(x, y, color);
+                }
+            }
+        }
+    };
+    let ring = |out: &mut RgbImage, center: [u32; 2], color: Rgb<u8>| {
+        const RADIUS: f32 = 8.0;
+        for step in 0..64 {
+            let angle = step as f32 / 64.0 * std::f32::consts::TAU;
+            let x = center[0] as f32 + 0.5 + angle.cos() * RADIUS;
+            let y = center[1] as f32 + 0.5 + angle.sin() * RADIUS;
+            if x >= 0.0 && y >= 0.0 && (x as u32) < out.width() && (y as u32) < out.height() {
+                out.put_pixel(x as u32, y as u32, color);
+            }
+        }
+    };
+
+    for issue in issues {
+        match issue {
+            Issue::StrayPixels { part, .. } => {
+                let red = Rgb([255, 0, 0]);
+                for [x, y] in &part.pixels {
+                    out.put_pixel(*x, *y, red);
+                }
+                ring(&mut out, part.pixels[0], red);
+            }
+            Issue::Disconnected { parts, .. } => {
+                let orange = Rgb([255, 160, 0]);
+                for part in parts {
+                    for [x, y] in &part.pixels {
+                        out.put_pixel(*x, *y, orange);
+                    }
+                    if part.pixels.len() < 16 {
+                        ring(&mut out, part.pixels[0], orange);
+                    }
+                }
+            }
+            Issue::SimilarColors { a, b, .. } => {
+                paint_province(&mut out, a.0, Rgb([255, 0, 255]));
+                paint_province(&mut out, b.0, Rgb([200, 0, 200]));
+            }
+            Issue::MissingDefinition { province, pixel, .. } => {
+                paint_province(&mut out, *province, Rgb([0, 90, 255]));
+                if map.provinces[*province as usize].area < 16 {
+                    ring(&mut out, *pixel, Rgb([0, 90, 255]));
+                }
+            }
+        }
+    }
+    out
+}
+
+// `shaders map-lint`: prints the issues of an image and writes the overlay,
+// returns the exit code (0 clean, 1 issues found, 2 failed to run)
+pub fn run(image_path: &str, overlay_path: &str, definition_path: Option<&str>) -> i32 {
+    let result = (|| {
+        let image = image::open(image_path).map_err(|e| format!("{}: {}", image_path, e))?.to_rgb8();
+        let map = ProvinceMap::from_image(&image);
+        let definitions = definition_path.map(Definitions::read).transpose()?;
+        let definitions = definitions.map(|d| d.entries.iter().map(|e| e.color).collect::<HashSet<_>>());
+        let issues = lint(&map, definitions.as_ref(), &LintSettings::default());
+        for issue in &issues {
+            println!("{}: {}", image_path, issue);
+        }
+        println!("checked {} provinces: {} issues", map.provinces.len(), issues.len());
+        overlay(&map, &image, &issues).save(overlay_path).map_err(|e| format!("{}: {}", overlay_path, e))?;
+        Ok::<_, String>(issues.len())
+    })();
+    match result {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            2
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 3] = [255, 0, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    // One row of pixels, one color per character
+    fn row(colors: &str, palette: &[(char, [u8; 3])]) -> ProvinceMap {
+        let colors: Vec<char> = colors.chars().collect();
+        ProvinceMap::from_image(&RgbImage::from_fn(colors.len() as u32, 1, |x, _| {
+            Rgb(palette.iter().find(|(c, _)| *c == colors[x as usize]).unwrap().1)
+        }))
+    }
+
+    fn part(xs: &[u32]) -> Part {
+        Part { pixels: xs.iter().map(|x| [*x, 0]).collect() }
+    }
+
+    #[test]
+    fn the_largest_piece_is_not_stray_unless_the_whole_province_is() {
+        let map = row("RRBRB", &[('R', RED), ('B', BLUE)]);
+        assert_eq!(
+            lint(&map, None, &LintSettings::default()),
+            vec![
+                Issue::StrayPixels { province: 0, color: RED, part: part(&[3]) },
+                Issue::StrayPixels { province: 1, color: BLUE, part: part(&[2]) },
+                Issue::StrayPixels { province: 1, color: BLUE, part: part(&[4]) },
+            ]
+        );
+    }
+
+    #[test]
+    fn large_detached_pieces_are_disconnected() {
+        let map = row("RRRBBBRRRR", &[('R', RED), ('B', BLUE)]);
+        let issues = lint(&map, None, &LintSettings::default());
+        assert_eq!(issues, vec![Issue::Disconnected { province: 0, color: RED, parts: vec![part(&[0, 1, 2])] }]);
+        assert_eq!(issues[0].to_string(), "#ff0000 is in 2 pieces, apart from the main one: 0,0 (3 px)");
+    }
+
+    #[test]
+    fn near_identical_colors_are_reported() {
+        let (a, b) = ([10, 10, 10], [11, 12, 10]);
+        let map = row("AAABBB", &[('A', a), ('B', b)]);
+        assert_eq!(lint(&map, None, &LintSettings::default()), vec![Issue::SimilarColors { a: (0, a), b: (1, b), distance: 5f32.sqrt() }]);
+        assert!(lint(&map, None, &LintSettings { similar_distance: 2.0, ..LintSettings::default() }).is_empty());
+    }
+
+    #[test]
+    fn colors_missing_from_the_definitions_point_at_their_first_pixel() {
+        // Blue reaches further left on the second row than on the first
+        let image = RgbImage::from_fn(6, 2, |x, y| Rgb(if x >= 3 - y { BLUE } else { RED }));
+        let map = ProvinceMap::from_image(&image);
+        let definitions: HashSet<[u8; 3]> = [RED].into_iter().collect();
+        let issues = lint(&map, Some(&definitions), &LintSettings::default());
+        assert_eq!(issues, vec![Issue::MissingDefinition { province: 1, color: BLUE, pixel: [3, 0] }]);
+        assert!(lint(&map, None, &LintSettings::default()).is_empty());
+    }
+}
@@ -0,0 +1,328 @@
+use gl::types::*;
+use std::fs;
+use crate::province_borders::DATA_WIDTH;
+use crate::camera::CameraUniforms;
+use crate::province_map::{fnv1a, ProvinceMap};
+use crate::shader_pipeline::debug::DebugGroup;
+use crate::shader_pipeline::gl_device::{blended, Device};
+use crate::shader_pipeline::screen_quad::ScreenQuad;
+use crate::shader_pipeline::texture::Texture;
+use crate::Shader;
+
+// Straight RGBA; alpha 0 leaves the base map visible under a province
+pub type Color = [u8; 4];
+
+pub const TRANSPARENT: Color = [0, 0, 0, 0];
+
+// Piecewise linear color ramp over [0, 1]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    // Sorted by position
+    stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    // Stops may come in any order; an empty list gives a transparent gradient
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Gradient { stops }
+    }
+
+    pub fn two(from: Color, to: Color) -> Self {
+        Self::new(vec![(0.0, from), (1.0, to)])
+    }
+
+    // Dark blue through yellow to red, for densities like population
+    pub fn heat() -> Self {
+        Self::new(vec![
+            (0.0, [20, 30, 90, 255]),
+            (0.5, [240, 220, 60, 255]),
+            (1.0, [200, 30, 20, 255]),
+        ])
+    }
+
+    // Color at `t`, clamped to the first and last stop. NaN, which infinite
+    // bounds of a scalar mode give, is transparent like a missing value.
+    pub fn sample(&self, t: f32) -> Color {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) if !t.is_nan() => (first, last),
+            _ => return TRANSPARENT,
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+        let i = self.stops.iter().position(|(p, _)| *p > t).unwrap();
+        let ((p0, c0), (p1, c1)) = (self.stops[i - 1], self.stops[i]);
+        let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 0.0 };
+        let mut color = [0u8; 4];
+        for c in 0..4 {
+            color[c] = (c0[c] as f32 + (c1[c] as f32 - c0[c] as f32) * f).round() as u8;
+        }
+        color
+    }
+}
+
+// How provinces are colored, indexed by ProvinceId. Provinces past the end
+// of the table are left transparent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapMode {
+    // Nothing drawn, the base map shows through
+    Off,
+    // A color per province, e.g. the owner's color in a political mode
+    Colors(Vec<Color>),
+    // A value per province mapped from [min, max] onto a gradient; NaN
+    // values are left transparent
+    Scalar { values: Vec<f32>, min: f32, max: f32, gradient: Gradient },
+}
+
+impl MapMode {
+    // Scalar mode spanning the range of the values
+    pub fn scalar(values: Vec<f32>, gradient: Gradient) -> Self {
+        let finite = values.iter().copied().filter(|v| v.is_finite());
+        let min = finite.clone().fold(f32::INFINITY, f32::min);
+        let max = finite.fold(f32::NEG_INFINITY, f32::max);
+        let (min, max) = if min <= max { (min, max) } else { (0.0, 1.0) };
+        MapMode::Scalar { values, min, max, gradient }
+    }
+
+    // A color per distinct value, e.g. the region or terrain column of the
+    // definition file; provinces without a value are left transparent. The
+    // same value always gets the same color.
+    pub fn categories(values: &[Option<String>]) -> Self {
+        let color = |value: &str| {
+            let hash = fnv1a(value.as_bytes());
+            let hue = (hash % 360) as f32;
+            // Mid saturation and lightness keep borders and labels readable
+            let (s, l) = (0.45 + ((hash >> 16) % 20) as f32 / 100.0, 0.55 + ((hash >> 24) % 15) as f32 / 100.0);
+            let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+            let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+            let (r, g, b) = match (hue / 60.0) as u32 {
+                0 => (c, x, 0.0),
+                1 => (x, c, 0.0),
+                2 => (0.0, c, x),
+                3 => (0.0, x, c),
+                4 => (x, 0.0, c),
+                _ => (c, 0.0, x),
+            };
+            let m = l - c / 2.0;
+            let channel = |v: f32| ((v + m) * 255.0).round() as u8;
+            [channel(r), channel(g), channel(b), 255]
+        };
+        MapMode::Colors(values.iter().map(|v| v.as_deref().map_or(TRANSPARENT, color)).collect())
+    }
+
+    // Color of every province, `count` entries
+    pub fn colors(&self, count: usize) -> Vec<Color> {
+        match self {
+            MapMode::Off => vec![TRANSPARENT; count],
+            MapMode::Colors(colors) => (0..count).map(|i| colors.get(i).copied().unwrap_or(TRANSPARENT)).collect(),
+            MapMode::Scalar { values, min, max, gradient } => (0..count)
+                .map(|i| match values.get(i) {
+                    Some(v) if !v.is_nan() => {
+                        let t = if max > min { (v - min) / (max - min) } else { 0.0 };
+                        gradient.sample(t)
+                    }
+                    _ => TRANSPARENT,
+                })
+                .collect(),
+        }
+    }
+}
+
+// Recolors every province of the ID map through a palette texture holding
+// one color per province. The ID map and the base image are never touched;
+// changing modes only rewrites the palette entries that differ.
+pub struct MapModePass {
+    shader: Shader,
+    quad: ScreenQuad,
+    ids: Texture,
+    palette: Texture,
+    // CPU copy of the palette, to find changed entries
+    colors: Vec<Color>,
+    map_size: (u32, u32),
+}
+
+impl MapModePass {
+    // Builds the pass from map_quad.glsl and map_mode.glsl in `shader_dir`,
+    // starting with every province transparent
+    pub fn new(gl: Device, shader_dir: &str, map: &ProvinceMap) -> Result<Self, String> {
+        let read = |name: &str| {
+            let path = format!("{}/{}", shader_dir, name);
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))
+        };
+        let shader = Shader::from_source(gl.clone(), &read("map_quad.glsl")?, &read("map_mode.glsl")?);
+        let quad = ScreenQuad::new(gl.clone());
+
+        let id_image = image::DynamicImage::ImageRgb8(map.id_image()).to_rgba8();
+        let ids = Texture::from_rgba(gl.clone(), map.width, map.height, id_image.as_raw(), gl::NEAREST, gl::TEXTURE0);
+
+        let count = map.provinces.len();
+        let rows = (count.max(1) as u32).div_ceil(DATA_WIDTH);
+        let blank = vec![0u8; (DATA_WIDTH * rows * 4) as usize];
+        let palette = Texture::from_rgba(gl, DATA_WIDTH, rows, &blank, gl::NEAREST, gl::TEXTURE1);
+
+        Ok(MapModePass { shader, quad, ids, palette, colors: vec![TRANSPARENT; count], map_size: (map.width, map.height) })
+    }
+
+    // Switches to another mode, see `set_colors`
+    pub fn set_mode(&mut self, mode: &MapMode) -> usize {
+        let colors = mode.colors(self.colors.len());
+        self.set_colors(&colors)
+    }
+
+    // Uploads the entries that differ from the current palette, one
+    // sub-image per run of consecutive changed entries within a palette row.
+    // Returns the number of entries uploaded.
+    pub fn set_colors(&mut self, colors: &[Color]) -> usize {
+        let count = self.colors.len();
+        let mut uploaded = 0;
+        let mut id = 0;
+        while id < count {
+            let new = colors.get(id).copied().unwrap_or(TRANSPARENT);
+            if new == self.colors[id] {
+                id += 1;
+                continue;
+            }
+            // Extend the run while entries change and stay on the same row
+            let start = id;
+            let row_end = ((start / DATA_WIDTH as usize) + 1) * DATA_WIDTH as usize;
+            let mut bytes: Vec<u8> = Vec::new();
+            while id < count.min(row_end) {
+                let new = colors.get(id).copied().unwrap_or(TRANSPARENT);
+                if new == self.colors[id] {
+                    break;
+                }
+                self.colors[id] = new;
+                bytes.extend_from_slice(&new);
+                id += 1;
+            }
+            let (x, y) = (start as u32 % DATA_WIDTH, start as u32 / DATA_WIDTH);
+            self.palette.update_rgba(x, y, (id - start) as u32, 1, &bytes);
+            uploaded += id - start;
+        }
+        uploaded
+    }
+
+    // Current color of every province
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    // Blends the recolored provinces over whatever is in the bound framebuffer,
+    // where the camera puts the map
+    pub fn draw(&self, camera: &CameraUniforms) {
+        let gl = self.shader.device();
+        let _group = DebugGroup::new(gl, "map mode");
+        self.shader.activate();
+        camera.apply(&self.shader);
+        gl.uniform_2f(self.shader.uniform_location("mapSize"), self.map_size.0 as f32, self.map_size.1 as f32);
+        self.ids.tex_unit(&self.shader, "provinceIds", 0);
+        self.palette.tex_unit(&self.shader, "provinceColors", 1);
+        gl.active_texture(gl::TEXTURE0);
+        self.ids.bind();
+        gl.active_texture(gl::TEXTURE1);
+        self.palette.bind();
+
+        blended(gl, || camera.draw_copies(&self.shader, || self.quad.draw()));
+        gl.active_texture(gl::TEXTURE0);
+    }
+
+    pub fn delete(&self) {
+        self.shader.delete();
+        self.quad.delete();
+        self.ids.delete();
+        self.palette.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+    use image::{Rgb, RgbImage};
+
+    const BLACK: Color = [0, 0, 0, 255];
+    const WHITE: Color = [255, 255, 255, 255];
+
+    #[test]
+    fn gradient_interpolates_between_stops() {
+        let gradient = Gradient::new(vec![(1.0, WHITE), (0.0, BLACK)]);
+        assert_eq!(gradient.sample(0.5), [128, 128, 128, 255]);
+        assert_eq!(gradient.sample(0.25), [64, 64, 64, 255]);
+    }
+
+    #[test]
+    fn gradient_clamps_outside_its_stops() {
+        let gradient = Gradient::two(BLACK, WHITE);
+        assert_eq!(gradient.sample(-3.0), BLACK);
+        assert_eq!(gradient.sample(f32::NEG_INFINITY), BLACK);
+        assert_eq!(gradient.sample(7.0), WHITE);
+        assert_eq!(gradient.sample(f32::INFINITY), WHITE);
+    }
+
+    #[test]
+    fn gradient_is_transparent_for_nan_and_without_stops() {
+        assert_eq!(Gradient::two(BLACK, WHITE).sample(f32::NAN), TRANSPARENT);
+        assert_eq!(Gradient::new(Vec::new()).sample(0.5), TRANSPARENT);
+    }
+
+    #[test]
+    fn scalar_mode_with_infinite_bounds_doesnt_panic() {
+        let mode = MapMode::Scalar { values: vec![1.0, f32::NAN], min: f32::NEG_INFINITY, max: f32::INFINITY, gradient: Gradient::heat() };
+        assert_eq!(mode.colors(3), vec![TRANSPARENT; 3]);
+    }
+
+    #[test]
+    fn scalar_mode_spans_the_finite_values() {
+        let mode = MapMode::scalar(vec![2.0, f32::INFINITY, 4.0, f32::NAN], Gradient::two(BLACK, WHITE));
+        assert_eq!(mode.colors(5), vec![BLACK, WHITE, WHITE, TRANSPARENT, TRANSPARENT]);
+    }
+
+    #[test]
+    fn categories_give_equal_values_equal_colors() {
+        let values = [Some("north".to_string()), None, Some("south".to_string()), Some("north".to_string())];
+        let MapMode::Colors(colors) = MapMode::categories(&values) else { panic!("categories are colors") };
+        assert_eq!(colors[0], colors[3]);
+        assert_ne!(colors[0], colors[2]);
+        assert_eq!(colors[1], TRANSPARENT);
+        assert!(colors.iter().filter(|c| **c != TRANSPARENT).all(|c| c[3] == 255));
+    }
+
+    fn palette_upload(x: i32, y: i32, width: i32) -> GlCall {
+        GlCall::TexSubImage2D { target: gl::TEXTURE_2D, level: 0, x, y, width, height: 1, format: gl::RGBA, data_type: gl::UNSIGNED_BYTE, len: width as usize * 4 }
+    }
+
+    #[test]
+    fn only_changed_palette_entries_are_uploaded() {
+        // A province per pixel, enough for a second palette row
+        let count = DATA_WIDTH + 6;
+        let map = ProvinceMap::from_image(&RgbImage::from_fn(count, 1, |x, _| Rgb([(x & 0xff) as u8, (x >> 8) as u8, 0])));
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut pass = MapModePass::new(device, "assets/shadercode", &map).unwrap();
+        let uploads = || recording.calls().into_iter().filter(|c| matches!(c, GlCall::TexSubImage2D { .. })).collect::<Vec<_>>();
+
+        recording.clear();
+        assert_eq!(pass.set_colors(&vec![WHITE; count as usize]), count as usize);
+        assert_eq!(uploads(), vec![palette_upload(0, 0, DATA_WIDTH as i32), palette_upload(0, 1, 6)]);
+
+        // Runs of changes are split where they cross into the next row
+        recording.clear();
+        let mut colors = vec![WHITE; count as usize];
+        let last = DATA_WIDTH as usize - 1;
+        for id in [5, 6, last - 1, last, last + 1, last + 2] {
+            colors[id] = BLACK;
+        }
+        assert_eq!(pass.set_colors(&colors), 6);
+        assert_eq!(uploads(), vec![palette_upload(5, 0, 2), palette_upload(DATA_WIDTH as i32 - 2, 0, 2), palette_upload(0, 1, 2)]);
+        assert_eq!(pass.colors(), colors.as_slice());
+
+        recording.clear();
+        assert_eq!(pass.set_colors(&colors), 0);
+        assert!(uploads().is_empty());
+        pass.delete();
+    }
+}
@@ -0,0 +1,213 @@
+use glfw::{Action, Context, Key};
+use std::path::{Path, PathBuf};
+use crate::assets::{AssetManager, Handle, Mesh};
+use crate::camera::{Camera2D, Camera2DController, CameraUniforms};
+use crate::cli::ViewArgs;
+use crate::distance_field::DistanceField;
+use crate::definitions::Definitions;
+use crate::labels::{grouped_regions, place_labels, province_names, province_regions, LabelRegion, PlacementSettings};
+use crate::map_modes::{MapMode, MapModePass};
+use crate::distance_field::border_distance_image;
+use crate::province_borders::{BorderPass, BorderSettings};
+use crate::province_map::ProvinceMap;
+use crate::province_outlines::Outlines;
+use crate::shader_pipeline::capture;
+use crate::shader_pipeline::debug;
+use crate::shader_pipeline::gl_device::{blended, real_device, Device};
+use crate::surface::Surface;
+use crate::text::{default_charset, GlyphAtlas, LabelLayer, TextStyle};
+use crate::Shader;
+
+// Borders drawn as the triangles of the traced outlines instead of by the
+// border shader, built the first time they are shown
+struct MeshBorders {
+    shader: Handle<Shader>,
+    mesh: Handle<Mesh>,
+}
+
+impl MeshBorders {
+    fn new(assets: &mut AssetManager, map: &ProvinceMap) -> Result<Self, String> {
+        let shader = assets.shader("outline_mesh_vertex.glsl", "outline_mesh.glsl")?;
+        let mesh = assets.add_mesh(&Outlines::trace(map, 0.5).border_mesh(1.0).to_mesh_data())?;
+        Ok(MeshBorders { shader, mesh })
+    }
+
+    fn draw(&self, assets: &AssetManager, settings: &BorderSettings, uniforms: &CameraUniforms) {
+        let shader = assets.get(&self.shader);
+        let gl = shader.device();
+        shader.activate();
+        uniforms.apply(shader);
+        let [r, g, b, a] = settings.province.color;
+        gl.uniform_4f(shader.uniform_location("meshColor"), r, g, b, a);
+        blended(gl, || uniforms.draw_copies(shader, || assets.get(&self.mesh).draw()));
+    }
+}
+
+// Names along their regions. Placed once in map pixels; the layer hides the
+// ones too small or too large on screen at the current zoom. `field` holds
+// the distance to the borders between regions.
+fn label_layer(gl: &Device, shader_dir: &str, map: &ProvinceMap, atlas: &GlyphAtlas, regions: &[LabelRegion], field: &DistanceField, style: TextStyle) -> Result<LabelLayer, String> {
+    let placed = place_labels(map, field, atlas, regions, &PlacementSettings::default());
+    println!("{} of {} labels placed", placed.len(), regions.len());
+    let mut layer = LabelLayer::new(gl.clone(), shader_dir, atlas, style)?;
+    layer.set_texts(atlas, &placed);
+    Ok(layer)
+}
+
+// Names of the column's values hide once zoomed in far enough to read the
+// province names, which are smaller
+const COLUMN_LABEL_STYLE: TextStyle = TextStyle {
+    color: [0.3, 0.05, 0.05, 0.85],
+    outline_color: [1.0, 0.97, 0.9, 0.6],
+    outline_width: 0.4,
+    min_screen_size: 10.0,
+    max_screen_size: 60.0,
+};
+
+// Distance covered by the distance field of the D borders, in map pixels
+const SDF_MAX_DISTANCE: f32 = 8.0;
+
+// Which way the borders are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BorderKind {
+    IdMap,
+    DistanceField,
+    Mesh,
+}
+
+// Opens a window showing a province map in its own colors with borders.
+// Drag to pan, scroll to zoom, W toggles wrapping, Home shows the whole map,
+// M swaps the border shader for the meshes of `shaders outlines`, D for the
+// distance field shader, C swaps a definition column for the province colors,
+// L toggles the names when a font is given, F12 saves a screenshot.
+pub fn run(args: &ViewArgs, shader_dir: &str, cache_dir: &Path) -> Result<(), String> {
+    let map = ProvinceMap::load_or_build(&args.image, cache_dir)?;
+
+    let mut glfw = debug::init_glfw()?;
+    let (mut window, events) = debug::create_window(&mut glfw, (3, 3), (800, 800), "Map viewer", true)?;
+    window.set_key_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_scroll_polling(true);
+    Surface::enable_polling(&mut window);
+
+    let gl = real_device();
+    let definitions = args.definitions.as_deref().map(Definitions::read).transpose()?;
+    let column = args.column.zip(definitions.as_ref()).map(|(index, definitions)| definitions.column(&map, index));
+    let province_colors = MapMode::Colors(map.provinces.iter().map(|p| [p.color[0], p.color[1], p.color[2], 255]).collect());
+    let column_colors = column.as_deref().map(MapMode::categories);
+    let mut show_column = column_colors.is_some();
+    let mut map_mode = MapModePass::new(gl.clone(), shader_dir, &map)?;
+    map_mode.set_mode(column_colors.as_ref().unwrap_or(&province_colors));
+    let borders = BorderPass::new(gl.clone(), shader_dir, &map, &[])?;
+    let settings = BorderSettings::default();
+    let mut assets = AssetManager::new(gl.clone(), vec![PathBuf::from(shader_dir)]);
+    let mut mesh_borders: Option<MeshBorders> = None;
+    let mut sdf_borders: Option<BorderPass> = None;
+    let mut shown = BorderKind::IdMap;
+    let mut labels: Vec<LabelLayer> = Vec::new();
+    if let Some(font) = &args.font {
+        let atlas = GlyphAtlas::from_file(font, 48.0, &default_charset())?;
+        let field = DistanceField::borders(&map, |a, b| a != b, None);
+        let regions = province_regions(&province_names(&map, definitions.as_ref()));
+        labels.push(label_layer(&gl, shader_dir, &map, &atlas, &regions, &field, TextStyle::default())?);
+        if let Some(column) = &column {
+            let field = DistanceField::borders(&map, |a, b| column[a as usize] != column[b as usize], None);
+            labels.push(label_layer(&gl, shader_dir, &map, &atlas, &grouped_regions(column), &field, COLUMN_LABEL_STYLE)?);
+        }
+    }
+    let mut show_labels = true;
+
+    let mut surface = Surface::from_window(&window);
+    surface.apply_viewport(&gl);
+    let mut camera = Camera2D::new((map.width, map.height), surface.framebuffer, args.wrap);
+    let mut controller = Camera2DController::default();
+    let mut take_screenshot = false;
+
+    while !window.should_close() {
+        gl.clear_color(0.07, 0.13, 0.17, 1.0);
+        gl.clear(gl::COLOR_BUFFER_BIT);
+        let uniforms = camera.uniforms();
+        map_mode.draw(&uniforms);
+        match (shown, &mesh_borders, &sdf_borders) {
+            (BorderKind::Mesh, Some(mesh_borders), _) => mesh_borders.draw(&assets, &settings, &uniforms),
+            (BorderKind::DistanceField, _, Some(sdf_borders)) => sdf_borders.draw(&settings, &uniforms),
+            _ => borders.draw(&settings, &uniforms),
+        }
+        if show_labels {
+            for layer in &labels {
+                layer.draw(&uniforms);
+            }
+        }
+        // F12 saves what was just drawn, before the buffers swap; a minimized
+        // window has nothing to save until it is restored
+        if take_screenshot && !surface.is_empty() {
+            take_screenshot = false;
+            match capture::screenshot(&gl, surface.framebuffer) {
+                Ok(path) => println!("saved {}", path.display()),
+                Err(error) => eprintln!("error: {}", error),
+            }
+        }
+        window.swap_buffers();
+
+        glfw.poll_events();
+        for (_, event) in glfw::flush_messages(&events) {
+            if surface.handle_event(&event) {
+                surface.apply_viewport(&gl);
+                camera.set_viewport(surface.framebuffer.0, surface.framebuffer.1);
+            }
+            let event = surface.cursor_to_pixels(event);
+            controller.handle_event(&mut camera, &event);
+            match event {
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
+                glfw::WindowEvent::Key(Key::F12, _, Action::Press, _) => take_screenshot = true,
+                glfw::WindowEvent::Key(Key::W, _, Action::Press, _) => {
+                    camera = Camera2D { wrap: !camera.wrap, ..camera };
+                    camera.pan(nalgebra::Vector2::zeros());
+                }
+                glfw::WindowEvent::Key(Key::M, _, Action::Press, _) => {
+                    if mesh_borders.is_none() {
+                        match MeshBorders::new(&mut assets, &map) {
+                            Ok(built) => mesh_borders = Some(built),
+                            Err(error) => eprintln!("error: {}", error),
+                        }
+                    }
+                    shown = if shown != BorderKind::Mesh && mesh_borders.is_some() { BorderKind::Mesh } else { BorderKind::IdMap };
+                }
+                glfw::WindowEvent::Key(Key::D, _, Action::Press, _) => {
+                    if sdf_borders.is_none() {
+                        let distances = border_distance_image(&map, &[], SDF_MAX_DISTANCE, None);
+                        match BorderPass::from_distance_field(gl.clone(), shader_dir, &distances, SDF_MAX_DISTANCE) {
+                            Ok(built) => sdf_borders = Some(built),
+                            Err(error) => eprintln!("error: {}", error),
+                        }
+                    }
+                    shown = if shown != BorderKind::DistanceField && sdf_borders.is_some() { BorderKind::DistanceField } else { BorderKind::IdMap };
+                }
+                glfw::WindowEvent::Key(Key::L, _, Action::Press, _) => show_labels = !show_labels,
+                glfw::WindowEvent::Key(Key::C, _, Action::Press, _) => {
+                    if let Some(column_colors) = &column_colors {
+                        show_column = !show_column;
+                        map_mode.set_mode(if show_column { column_colors } else { &province_colors });
+                    }
+                }
+                glfw::WindowEvent::Key(Key::Home, _, Action::Press, _) => {
+                    camera = Camera2D::new((map.width, map.height), (camera.viewport.x as u32, camera.viewport.y as u32), camera.wrap);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    map_mode.delete();
+    borders.delete();
+    if let Some(sdf_borders) = &sdf_borders {
+        sdf_borders.delete();
+    }
+    for layer in &labels {
+        layer.delete();
+    }
+    drop(mesh_borders);
+    assets.delete();
+    Ok(())
+}
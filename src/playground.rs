@@ -0,0 +1,283 @@
+use gl::types::*;
+use glfw::{Action, Context, Key};
+use image::GenericImageView;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+use crate::assets::{AssetManager, MeshData};
+use crate::camera;
+use crate::cli::PlaygroundOptions;
+use crate::shader_pipeline::capture;
+use crate::shader_pipeline::debug;
+use crate::shader_pipeline::gl_device::real_device;
+use crate::shader_pipeline::profiler::{Profiler, ProfilerOverlay};
+use crate::shader_pipeline::render_target::RenderTarget;
+use crate::shader_pipeline::texture::Texture;
+use crate::shader_pipeline::video::{VideoFormat, VideoRecorder};
+use crate::surface::Surface;
+use crate::Shader;
+
+// Frame rate of video output, which is also the headless timestep
+const VIDEO_FPS: f32 = 60.0;
+
+fn read_source(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Work group size declared by a compute shader, 1 for missing dimensions
+fn local_size(source: &str) -> (u32, u32) {
+    let size = |axis: &str| {
+        let pattern = Regex::new(&format!(r"local_size_{}\s*=\s*(\d+)", axis)).unwrap();
+        pattern.captures(source).and_then(|c| c[1].parse().ok()).unwrap_or(1u32)
+    };
+    (size("x"), size("y"))
+}
+
+// Draws a colored quad, or the --mesh, with the given shaders, orbiting around it with the
+// mouse (Tab switches to flying), F12 saves a screenshot. With an output
+// path the window stays hidden and the last frame is saved instead, or
+// every frame when the output is a video.
+pub fn run(options: &PlaygroundOptions) -> Result<(), String> {
+    // Vertices coordinates
+    let vertices: [GLfloat; 32] = [
+        //  COORDINATES    /     COLORS        /   TexCoord   //
+        -0.5, -0.5, 0.0,     1.0, 0.0, 0.0,     0.0, 0.0,  // Lower left corner
+        -0.5,  0.5, 0.0,     0.0, 1.0, 0.0,     0.0, 1.0,  // Upper left corner
+         0.5,  0.5, 0.0,     0.0, 0.0, 1.0,     1.0, 1.0,  // Upper right corner
+         0.5, -0.5, 0.0,     1.0, 1.0, 1.0,     1.0, 0.0,  // Lower right corner
+    ];
+
+    // Indices for vertices order
+    let indices: [GLuint; 6] = [
+        0, 2, 1, // Upper triangle
+        0, 3, 2, // Lower triangle
+    ];
+
+    // Initialize GLFW; compute shaders need GL 4.3
+    let mut glfw = debug::init_glfw()?;
+    let version = if options.compute.is_some() { (4, 3) } else { (3, 3) };
+    let headless = options.output.is_some();
+
+    // Create a GLFW window with its context current
+    let (mut window, events) = debug::create_window(&mut glfw, version, (options.width, options.height), "OpenGL Playground", !headless)?;
+    window.set_key_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_scroll_polling(true);
+    Surface::enable_polling(&mut window);
+    let gl = real_device();
+
+    // Headless frames go to a target of exactly the requested size, windows
+    // draw at their framebuffer size, which differs on HiDPI displays
+    let mut surface = if headless {
+        let size = (options.width, options.height);
+        Surface { framebuffer: size, window: size, content_scale: (1.0, 1.0) }
+    } else {
+        Surface::from_window(&window)
+    };
+    // .exr output keeps values outside 0..1
+    let format = match options.output.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("exr") => gl::RGBA32F,
+        _ => gl::RGBA8,
+    };
+    let target = if headless { Some(RenderTarget::with_format(gl.clone(), options.width, options.height, format)?) } else { None };
+    let mut video = match &options.output {
+        Some(path) if VideoFormat::from_path(path).is_some() => Some(VideoRecorder::new(path, options.width, options.height, VIDEO_FPS)?),
+        _ => None,
+    };
+    surface.apply_viewport(&gl);
+
+    // Camera orbiting the quad; Tab switches between orbiting and flying
+    let mut camera = camera::Camera3D::new(nalgebra::Point3::new(0.0, 0.0, 2.0), surface.framebuffer);
+    let mut controller: Box<dyn camera::CameraController> =
+        Box::new(camera::OrbitController::new(&mut camera, nalgebra::Point3::origin()));
+    let mut orbiting = true;
+    let mut last_time = glfw.get_time();
+
+    // Options hold resolved paths already; the roots are those of
+    // PlaygroundArgs::options, for anything loaded by logical path.
+    // Edits to loaded files reload while running.
+    let mut assets = AssetManager::new(gl.clone(), vec![".".into(), options.assets.clone()]);
+    let program = assets.shader(&options.vertex, &options.fragment)?;
+
+    // Coordinates, colors and texture coordinates go to locations 0, 1 and 2
+    let quad = match &options.mesh {
+        Some(path) => assets.mesh(path)?,
+        None => {
+            let quad = assets.add_mesh(&MeshData { attributes: vec![3, 3, 2], vertices: vertices.to_vec(), indices: indices.to_vec() })?;
+            assets.get(&quad).label("playground quad");
+            quad
+        }
+    };
+
+    // Input textures take the first units, the compute output the next one
+    let textures = options.textures.iter().map(|path| assets.texture(path)).collect::<Result<Vec<_>, _>>()?;
+    let mut compute = match &options.compute {
+        Some(path) => {
+            // As big as the first input, or the output when there is none
+            let size = match options.textures.first() {
+                Some(input) => image::image_dimensions(input).map_err(|e| format!("{}: {}", input.display(), e))?,
+                None => (options.width, options.height),
+            };
+            let unit = textures.len() as GLuint;
+            let output = Texture::from_rgba(gl.clone(), size.0, size.1, &[], gl::LINEAR, gl::TEXTURE0 + unit);
+            output.label("computeOutput");
+            Some((assets.compute_shader(path)?, output, size, local_size(&read_source(path)?)))
+        }
+        None => None,
+    };
+
+    // F3 shows frame timings, F4 starts and stops a trace
+    let mut profiler = Profiler::new(gl.clone());
+    let overlay = ProfilerOverlay::new(gl.clone())?;
+    let mut show_profiler = false;
+
+    let mut frame = 0;
+    let mut take_screenshot = false;
+    let mut result = Ok(());
+    while !window.should_close() && options.frames.is_none_or(|frames| frame < frames) {
+        let report = assets.reload_changed();
+        for error in &report.errors {
+            eprintln!("error: {}", error);
+        }
+        assets.collect_garbage();
+        if let (Some((handle, _, _, local)), Some(path)) = (&mut compute, &options.compute) {
+            if let (true, Ok(source)) = (report.reloaded.contains(&handle.id()), read_source(path)) {
+                *local = local_size(&source);
+            }
+        }
+        let shader_program = assets.get(&program);
+        profiler.begin_frame();
+
+        if let Some((handle, output, size, local)) = &compute {
+            profiler.begin("compute");
+            let program = assets.get(handle);
+            program.activate();
+            if let Some(input) = textures.first() {
+                gl.active_texture(gl::TEXTURE0);
+                assets.get(input).bind();
+                gl.uniform_1i(program.uniform_location("inputTexture"), 0);
+            }
+            gl.bind_image_texture(1, output.id, gl::WRITE_ONLY, gl::RGBA8);
+            gl.dispatch_compute(size.0.div_ceil(local.0), size.1.div_ceil(local.1), 1);
+            gl.memory_barrier(gl::TEXTURE_FETCH_BARRIER_BIT | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT);
+            profiler.end();
+        }
+
+        profiler.begin("quad");
+        if let Some(target) = &target {
+            target.bind();
+        }
+        // Specify the color of the background
+        gl.clear_color(0.07, 0.13, 0.17, 1.0);
+        // Clean the back buffer and assign the new color
+        gl.clear(gl::COLOR_BUFFER_BIT);
+        // Tell OpenGL which shader program to use
+        shader_program.activate();
+        // Assigns a value to the uniform; NOTE: Must always be done after activating the Shader Program
+        gl.uniform_1f(shader_program.uniform_location("scale"), 0.5);
+        // Uploads u_viewProj for the current camera
+        camera.uniforms().apply(shader_program);
+        surface.apply_resolution(shader_program);
+        for (unit, texture) in textures.iter().map(|t| assets.get(t)).enumerate() {
+            gl.active_texture(gl::TEXTURE0 + unit as GLuint);
+            texture.bind();
+            texture.tex_unit(shader_program, &format!("inputTexture{}", unit), unit as GLuint);
+        }
+        if let Some(texture) = textures.first().map(|t| assets.get(t)) {
+            texture.tex_unit(shader_program, "inputTexture", 0);
+        }
+        if let Some((_, output, _, _)) = &compute {
+            let unit = textures.len() as GLuint;
+            gl.active_texture(gl::TEXTURE0 + unit);
+            output.bind();
+            output.tex_unit(shader_program, "computeOutput", unit);
+        }
+        // Draw the triangles using GL_TRIANGLES primitive
+        assets.get(&quad).draw();
+        profiler.end();
+        if let Some(target) = &target {
+            if let Some(video) = &mut video {
+                if let Err(error) = video.capture(&gl, target.framebuffer) {
+                    result = Err(error);
+                    break;
+                }
+            }
+            target.unbind();
+        }
+        // F12 saves what was just drawn, before the buffers swap; a minimized
+        // window has nothing to save until it is restored
+        if take_screenshot && !surface.is_empty() {
+            take_screenshot = false;
+            match capture::screenshot(&gl, surface.framebuffer) {
+                Ok(path) => println!("saved {}", path.display()),
+                Err(error) => eprintln!("error: {}", error),
+            }
+        }
+        if show_profiler {
+            overlay.draw(&profiler, surface.framebuffer, surface.content_scale.0);
+        }
+        profiler.end_frame();
+        // Swap front and back buffers
+        window.swap_buffers();
+        frame += 1;
+
+        // Poll for and process events
+        glfw.poll_events();
+        for (_, event) in glfw::flush_messages(&events) {
+            if !headless && surface.handle_event(&event) {
+                surface.apply_viewport(&gl);
+                camera.set_viewport(surface.framebuffer.0, surface.framebuffer.1);
+            }
+            let event = surface.cursor_to_pixels(event);
+            controller.handle_event(&mut camera, &event);
+            match event {
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
+                glfw::WindowEvent::Key(Key::F12, _, Action::Press, _) => take_screenshot = true,
+                glfw::WindowEvent::Key(Key::F3, _, Action::Press, _) => show_profiler = !show_profiler,
+                glfw::WindowEvent::Key(Key::F4, _, Action::Press, _) if profiler.is_tracing() => {
+                    match profiler.save_trace() {
+                        Ok((path, frames)) => println!("saved {} frames to {}", frames, path.display()),
+                        Err(error) => eprintln!("error: {}", error),
+                    }
+                }
+                glfw::WindowEvent::Key(Key::F4, _, Action::Press, _) => {
+                    profiler.start_trace();
+                    println!("tracing, F4 again to save");
+                }
+                glfw::WindowEvent::Key(Key::Tab, _, Action::Press, _) => {
+                    orbiting = !orbiting;
+                    controller = if orbiting {
+                        Box::new(camera::OrbitController::new(&mut camera, nalgebra::Point3::origin()))
+                    } else {
+                        Box::new(camera::FlyController::default())
+                    };
+                }
+                _ => {}
+            }
+        }
+        // Headless frames step by a fixed time so videos play back at real speed
+        let now = glfw.get_time();
+        let elapsed = if headless { 1.0 / VIDEO_FPS } else { (now - last_time) as f32 };
+        controller.update(&mut camera, elapsed);
+        last_time = now;
+    }
+
+    result = match (video, &target, &options.output) {
+        (Some(video), _, _) => result.and(video.finish()),
+        (None, Some(target), Some(path)) if result.is_ok() => target.save(path),
+        _ => result,
+    };
+
+    // Delete all the objects
+    if let Some((_, output, _, _)) = &compute {
+        output.delete();
+    }
+    assets.delete();
+    profiler.delete();
+    overlay.delete();
+    if let Some(target) = &target {
+        target.delete();
+    }
+    result
+}
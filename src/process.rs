@@ -0,0 +1,373 @@
+use image::{ColorType, DynamicImage, ImageFormat, Rgba, Rgba32FImage};
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::assets::{check_linked, read_source, resolve_path};
+use crate::cli::{Backend, ProcessArgs};
+use crate::shader_pipeline::capture;
+use crate::shader_pipeline::debug::{self, DebugGroup};
+use crate::shader_pipeline::gl_device::{real_device, Device};
+use crate::shader_pipeline::render_target::RenderTarget;
+use crate::shader_pipeline::screen_quad::ScreenQuad;
+use crate::shader_pipeline::texture::Texture;
+use crate::Shader;
+
+// Files given as they are, folders contributing the images they hold, sorted
+pub fn collect_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut images = Vec::new();
+    for input in inputs {
+        if !input.is_dir() {
+            images.push(input.clone());
+            continue;
+        }
+        let entries = fs::read_dir(input).map_err(|e| format!("{}: {}", input.display(), e))?;
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+            .collect();
+        found.sort();
+        images.extend(found);
+    }
+    Ok(images)
+}
+
+// Loads an image as float RGBA, so 16 bit and float images keep their
+// precision, along with what it takes to save the result the same way
+fn load(path: &Path) -> Result<(Rgba32FImage, ColorType, ImageFormat), String> {
+    let format = ImageFormat::from_path(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let image = image::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok((image.to_rgba32f(), image.color(), format))
+}
+
+// Converts back to the input's color type; 8 and 16 bit ones clamp to 0..1
+fn save(image: Rgba32FImage, color: ColorType, format: ImageFormat, path: &Path) -> Result<(), String> {
+    let image = DynamicImage::ImageRgba32F(image);
+    let converted = match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(image.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(image.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        ColorType::Rgba32F => image,
+        _ => DynamicImage::ImageRgba8(image.to_rgba8()),
+    };
+    converted.save_with_format(path, format).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Runs one image through `apply` and saves it in `out_dir` under its own name
+fn process_image(input: &Path, out_dir: &Path, apply: impl Fn(Rgba32FImage) -> Result<Rgba32FImage, String>) -> Result<PathBuf, String> {
+    let (image, color, format) = load(input)?;
+    let size = image.dimensions();
+    let result = apply(image)?;
+    debug_assert_eq!(result.dimensions(), size);
+    let output = out_dir.join(input.file_name().ok_or_else(|| format!("{}: not a file", input.display()))?);
+    save(result, color, format, &output)?;
+    Ok(output)
+}
+
+// Reference version of a pass on the CPU, same math as its shader
+type CpuPass = fn(&Rgba32FImage) -> Rgba32FImage;
+
+// Shaders the CPU backend knows, as files in the `shaders` folder; a copy
+// elsewhere may have been edited, so the same name isn't enough
+fn cpu_pass(path: &Path, shaders: &Path) -> Option<CpuPass> {
+    let path = path.canonicalize().ok()?;
+    let known: [(&str, CpuPass); 2] = [("simple_sobel_shader.glsl", sobel), ("threshold.glsl", threshold)];
+    known
+        .into_iter()
+        .find(|(name, _)| shaders.join(name).canonicalize().is_ok_and(|known| known == path))
+        .map(|(_, pass)| pass)
+}
+
+// Texel `dx` right and `dy` up from (x, y), which counts rows from the top;
+// clamped to the edges like CLAMP_TO_EDGE
+fn texel(image: &Rgba32FImage, x: u32, y: u32, dx: i64, dy: i64) -> [f32; 4] {
+    let x = (x as i64 + dx).clamp(0, image.width() as i64 - 1) as u32;
+    let y = (y as i64 - dy).clamp(0, image.height() as i64 - 1) as u32;
+    image.get_pixel(x, y).0
+}
+
+// simple_sobel_shader.glsl: kernel[i + 1][j + 1] weighs the texel i right
+// and j up, the matrices are written column by column like GLSL's mat3
+fn sobel(image: &Rgba32FImage) -> Rgba32FImage {
+    let sobel_x = [[-1.0, 0.0, 1.0], [-2.0, 0.0, 2.0], [-1.0, 0.0, 1.0]];
+    let sobel_y = [[-1.0, -2.0, -1.0], [0.0, 0.0, 0.0], [1.0, 2.0, 1.0]];
+    Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let mut gradient_x = [0.0f32; 3];
+        let mut gradient_y = [0.0f32; 3];
+        for i in -1..=1i64 {
+            for j in -1..=1i64 {
+                let sample = texel(image, x, y, i, j);
+                let (wx, wy) = (sobel_x[(i + 1) as usize][(j + 1) as usize], sobel_y[(i + 1) as usize][(j + 1) as usize]);
+                for c in 0..3 {
+                    gradient_x[c] += sample[c] * wx;
+                    gradient_y[c] += sample[c] * wy;
+                }
+            }
+        }
+        let magnitude = |c: usize| (gradient_x[c] * gradient_x[c] + gradient_y[c] * gradient_y[c]).sqrt();
+        Rgba([magnitude(0), magnitude(1), magnitude(2), 1.0])
+    })
+}
+
+// threshold.glsl: white where r + g + b > 1.5
+fn threshold(image: &Rgba32FImage) -> Rgba32FImage {
+    Rgba32FImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, _] = image.get_pixel(x, y).0;
+        let edge = if r + g + b > 1.5 { 1.0 } else { 0.0 };
+        Rgba([edge, edge, edge, 1.0])
+    })
+}
+
+// The passes compiled against screen_quad.glsl. Images go through float
+// targets, so values outside 0..1 survive from one pass to the next.
+struct GpuPipeline {
+    // File name of each pass, naming its debug group, and its program
+    passes: Vec<(String, Shader)>,
+    quad: ScreenQuad,
+    gl: Device,
+}
+
+impl GpuPipeline {
+    fn new(gl: Device, vertex: &Path, passes: &[PathBuf]) -> Result<Self, String> {
+        let vertex_code = read_source(vertex)?;
+        let mut shaders = Vec::new();
+        for pass in passes {
+            let shader = Shader::from_source(gl.clone(), &vertex_code, &read_source(pass)?);
+            match check_linked(&gl, shader, &pass.display().to_string()) {
+                Ok(shader) => {
+                    let name = pass.file_name().map_or_else(|| pass.display().to_string(), |n| n.to_string_lossy().into_owned());
+                    shader.label(&name);
+                    shaders.push((name, shader));
+                }
+                Err(error) => {
+                    shaders.iter().for_each(|(_, shader)| shader.delete());
+                    return Err(error);
+                }
+            }
+        }
+        // Render targets store rows bottom first, so the quad must too
+        let quad = ScreenQuad::bottom_up(gl.clone());
+        Ok(GpuPipeline { passes: shaders, quad, gl })
+    }
+
+    // Draws every pass over the whole image, ping-ponging between two
+    // targets of its size
+    fn run(&self, image: &Rgba32FImage) -> Result<Rgba32FImage, String> {
+        let gl = &self.gl;
+        let (width, height) = image.dimensions();
+        let flipped = image::imageops::flip_vertical(image);
+        let input = Texture::from_rgba_f32(gl.clone(), width, height, flipped.as_raw(), gl::LINEAR, gl::TEXTURE0);
+        input.label("process input");
+        let target = || RenderTarget::with_format(gl.clone(), width, height, gl::RGBA32F);
+        let targets = target().and_then(|first| match target() {
+            Ok(second) => Ok([first, second]),
+            Err(error) => {
+                first.delete();
+                Err(error)
+            }
+        });
+        let targets = match targets {
+            Ok(targets) => targets,
+            Err(error) => {
+                input.delete();
+                return Err(error);
+            }
+        };
+        targets[0].label("process target 0");
+        targets[1].label("process target 1");
+        for (i, (name, shader)) in self.passes.iter().enumerate() {
+            let _group = DebugGroup::new(gl, name);
+            targets[i % 2].bind();
+            shader.activate();
+            gl.active_texture(gl::TEXTURE0);
+            if i == 0 {
+                input.bind();
+            } else {
+                targets[(i + 1) % 2].texture.bind();
+            }
+            gl.uniform_1i(shader.uniform_location("inputTexture"), 0);
+            self.quad.draw();
+            targets[i % 2].unbind();
+        }
+        let last = &targets[(self.passes.len() - 1) % 2];
+        let result = capture::read_rgba_f32(gl, last.framebuffer, width, height);
+        input.delete();
+        targets.iter().for_each(RenderTarget::delete);
+        Ok(result)
+    }
+
+    fn delete(&self) {
+        self.passes.iter().for_each(|(_, shader)| shader.delete());
+        self.quad.delete();
+    }
+}
+
+// `shaders process`: runs every input image through the passes and saves
+// the results in --out. Returns how many images failed, each one reported.
+pub fn run(args: &ProcessArgs) -> Result<usize, String> {
+    let passes = args.passes()?;
+    let inputs = collect_inputs(&args.inputs)?;
+    if inputs.is_empty() {
+        return Err("no images found in the inputs".to_string());
+    }
+    // Results are named after their input, so names must not repeat
+    let mut names = HashSet::new();
+    if let Some(input) = inputs.iter().find(|input| !names.insert(input.file_name().map(|n| n.to_owned()))) {
+        return Err(format!("{}: another input has the same file name", input.display()));
+    }
+    fs::create_dir_all(&args.out).map_err(|e| format!("{}: {}", args.out.display(), e))?;
+
+    let results: Vec<Result<PathBuf, String>> = match args.backend {
+        Backend::Cpu => {
+            let cpu_passes = passes
+                .iter()
+                .map(|pass| cpu_pass(pass, &args.shaders).ok_or_else(|| format!("{}: no CPU version of this shader, use --backend gpu", pass.display())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(args.jobs.unwrap_or(0))
+                .build()
+                .map_err(|e| e.to_string())?;
+            pool.install(|| {
+                inputs
+                    .par_iter()
+                    .map(|input| process_image(input, &args.out, |image| Ok(cpu_passes.iter().fold(image, |image, pass| pass(&image)))))
+                    .collect()
+            })
+        }
+        Backend::Gpu => {
+            let mut glfw = debug::init_glfw()?;
+            // The hidden window only provides the context
+            let (_window, _events) = debug::create_window(&mut glfw, (3, 3), (64, 64), "shaders process", false)?;
+            let vertex = resolve_path(Path::new("screen_quad.glsl"), &[Path::new("."), args.shaders.as_path()])?;
+            let pipeline = GpuPipeline::new(real_device(), &vertex, &passes)?;
+            let results = inputs.iter().map(|input| process_image(input, &args.out, |image| pipeline.run(&image))).collect();
+            pipeline.delete();
+            results
+        }
+    };
+
+    let mut failed = 0;
+    for error in results.iter().filter_map(|result| result.as_ref().err()) {
+        eprintln!("error: {}", error);
+        failed += 1;
+    }
+    println!("processed {} of {} images into {}", inputs.len() - failed, inputs.len(), args.out.display());
+    Ok(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gl::types::GLuint;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+    use crate::test_util::TempDir;
+
+    fn image(width: u32, rgb: impl Fn(u32) -> f32) -> Rgba32FImage {
+        Rgba32FImage::from_fn(width, 3, |x, _| Rgba([rgb(x), rgb(x), rgb(x), 1.0]))
+    }
+
+    fn rows(image: &Rgba32FImage) -> Vec<Vec<f32>> {
+        (0..image.height()).map(|y| (0..image.width()).map(|x| image.get_pixel(x, y)[0]).collect()).collect()
+    }
+
+    #[test]
+    fn sobel_finds_vertical_edges_and_clamps_at_the_border() {
+        let step = sobel(&image(4, |x| if x >= 2 { 1.0 } else { 0.0 }));
+        assert_eq!(rows(&step), vec![vec![0.0, 4.0, 4.0, 0.0]; 3]);
+        assert!(step.pixels().all(|p| p[3] == 1.0 && p[0] == p[1] && p[1] == p[2]));
+        // Outside the image repeats the edge, so the first column sees the
+        // step next to it and nothing at the border itself
+        let edge = sobel(&image(3, |x| if x == 0 { 1.0 } else { 0.0 }));
+        assert_eq!(rows(&edge), vec![vec![4.0, 4.0, 0.0]; 3]);
+        // Flat images have no edges, even at the border
+        assert!(sobel(&image(3, |_| 0.7)).pixels().all(|p| p[0] < 1e-6 && p[3] == 1.0));
+    }
+
+    #[test]
+    fn threshold_is_white_above_half_brightness() {
+        let sums = Rgba32FImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([0.5, 0.5, 0.5, 0.2]),
+            1 => Rgba([0.6, 0.5, 0.5, 0.2]),
+            _ => Rgba([1.0, 1.0, 0.0, 0.2]),
+        });
+        let result = threshold(&sums);
+        assert_eq!(result.pixels().map(|p| p.0).collect::<Vec<_>>(), vec![[0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0], [1.0, 1.0, 1.0, 1.0]]);
+    }
+
+    #[test]
+    fn results_keep_the_color_type_of_their_input() {
+        let dir = TempDir::new("process-save", &[]);
+        let image = image(2, |x| x as f32 * 0.5);
+        for (name, color, format) in [("l8.png", ColorType::L8, ImageFormat::Png), ("rgb16.png", ColorType::Rgb16, ImageFormat::Png), ("rgba32f.exr", ColorType::Rgba32F, ImageFormat::OpenExr)] {
+            let path = dir.0.join(name);
+            save(image.clone(), color, format, &path).unwrap();
+            let (loaded, loaded_color, loaded_format) = load(&path).unwrap();
+            assert_eq!((loaded_color, loaded_format), (color, format), "{}", name);
+            assert_eq!(loaded.dimensions(), (2, 3));
+            assert!((loaded.get_pixel(1, 0)[0] - 0.5).abs() < 0.01, "{}", name);
+        }
+    }
+
+    #[test]
+    fn folders_contribute_their_images_sorted() {
+        let dir = TempDir::new("process-inputs", &[("in/b.png", ""), ("in/a.jpg", ""), ("in/notes.txt", ""), ("in/nested/c.png", ""), ("given.txt", "")]);
+        let inputs = collect_inputs(&[dir.0.join("in"), dir.0.join("given.txt")]).unwrap();
+        assert_eq!(inputs, vec![dir.0.join("in/a.jpg"), dir.0.join("in/b.png"), dir.0.join("given.txt")]);
+        assert_eq!(collect_inputs(&[dir.0.join("missing")]).unwrap(), vec![dir.0.join("missing")]);
+    }
+
+    // Names made and deleted of textures and framebuffers
+    fn objects(recording: &RecordingGl) -> [(Vec<GLuint>, Vec<GLuint>); 2] {
+        let mut objects: [(Vec<GLuint>, Vec<GLuint>); 2] = Default::default();
+        for call in recording.calls() {
+            match call {
+                GlCall::GenTexture(id) => objects[0].0.push(id),
+                GlCall::DeleteTexture(id) => objects[0].1.push(id),
+                GlCall::GenFramebuffer(id) => objects[1].0.push(id),
+                GlCall::DeleteFramebuffer(id) => objects[1].1.push(id),
+                _ => {}
+            }
+        }
+        objects.iter_mut().for_each(|(_, deleted)| deleted.sort());
+        objects
+    }
+
+    #[test]
+    fn runs_delete_their_textures_and_targets_even_when_failing() {
+        let dir = TempDir::new("process-gpu", &[("screen_quad.glsl", "void main() {}"), ("a.glsl", "void main() {}"), ("b.glsl", "void main() {}")]);
+        let recording = RecordingGl::new();
+        let pipeline = GpuPipeline::new(recording.clone(), &dir.0.join("screen_quad.glsl"), &[dir.0.join("a.glsl"), dir.0.join("b.glsl")]).unwrap();
+        let input = image(4, |_| 0.0);
+
+        recording.clear();
+        assert_eq!(pipeline.run(&input).unwrap().dimensions(), (4, 3));
+        for (made, deleted) in objects(&recording) {
+            assert_eq!(made, deleted);
+        }
+
+        // The second target fails after the input and the first were made
+        recording.clear();
+        recording.complete_framebuffers.set(Some(1));
+        assert!(pipeline.run(&input).unwrap_err().contains("incomplete"));
+        let [textures, framebuffers] = objects(&recording);
+        assert_eq!(textures.0.len(), 3);
+        assert_eq!(textures.0, textures.1);
+        assert_eq!(framebuffers.0, framebuffers.1);
+        pipeline.delete();
+    }
+
+    #[test]
+    fn cpu_passes_are_only_the_shaders_in_the_shaders_folder() {
+        let dir = TempDir::new("process-cpu-passes", &[("shaders/threshold.glsl", ""), ("elsewhere/threshold.glsl", "")]);
+        let (shaders, elsewhere) = (dir.0.join("shaders"), dir.0.join("elsewhere"));
+        assert!(cpu_pass(&shaders.join("threshold.glsl"), &shaders).is_some());
+        assert!(cpu_pass(&shaders.join("../shaders/threshold.glsl"), &shaders).is_some());
+        assert!(cpu_pass(&elsewhere.join("threshold.glsl"), &shaders).is_none());
+        assert!(cpu_pass(&shaders.join("simple_sobel_shader.glsl"), &shaders).is_none());
+    }
+}
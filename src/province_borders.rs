@@ -0,0 +1,272 @@
+use gl::types::*;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use crate::camera::CameraUniforms;
+use crate::province_map::{ProvinceId, ProvinceMap};
+use crate::shader_pipeline::debug::DebugGroup;
+use crate::shader_pipeline::gl_device::{blended, Device};
+use crate::shader_pipeline::screen_quad::ScreenQuad;
+use crate::shader_pipeline::texture::Texture;
+use crate::Shader;
+
+// Width of the per-province data texture; must match DATA_WIDTH in province_borders.glsl
+pub const DATA_WIDTH: u32 = 1024;
+
+// Border widths are limited by the neighbourhood the shader searches (MAX_RADIUS)
+pub const MAX_BORDER_WIDTH: f32 = 8.0;
+
+// Look of one kind of border
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BorderStyle {
+    // Total width in map pixels, centered on the edge between the provinces;
+    // 0 hides the style
+    pub width: f32,
+    // Straight (not premultiplied) RGBA
+    pub color: [f32; 4],
+}
+
+// Styles of the three kinds of border. Where they meet, coast is drawn over
+// country, which is drawn over province.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BorderSettings {
+    // Between provinces of the same country, and between sea provinces
+    pub province: BorderStyle,
+    // Between land provinces of different countries
+    pub country: BorderStyle,
+    // Between land and sea
+    pub coast: BorderStyle,
+}
+
+impl Default for BorderSettings {
+    fn default() -> Self {
+        BorderSettings {
+            province: BorderStyle { width: 1.0, color: [0.0, 0.0, 0.0, 0.35] },
+            country: BorderStyle { width: 2.5, color: [0.05, 0.05, 0.05, 0.9] },
+            coast: BorderStyle { width: 2.0, color: [0.1, 0.2, 0.35, 0.8] },
+        }
+    }
+}
+
+// What the border shader needs to know about a province
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProvinceClass {
+    // Owner; only compared for equality, at most 16 bits are used
+    pub country: u32,
+    pub is_sea: bool,
+}
+
+// One entry of a classes file, which names provinces by their image color
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ClassEntry {
+    color: [u8; 3],
+    #[serde(default)]
+    country: u32,
+    #[serde(default)]
+    sea: bool,
+}
+
+// Classes of every province, indexed by ProvinceId, from a JSON list of
+// `{"color": [r, g, b], "country": 3, "sea": false}`. Provinces left out are
+// land of country 0; colors not on the map are an error.
+pub fn parse_classes(text: &str, map: &ProvinceMap) -> Result<Vec<ProvinceClass>, String> {
+    let entries: Vec<ClassEntry> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    let by_color: HashMap<[u8; 3], ProvinceId> = map.provinces.iter().map(|p| (p.color, p.id)).collect();
+    let mut classes = vec![ProvinceClass::default(); map.provinces.len()];
+    for entry in entries {
+        let [r, g, b] = entry.color;
+        let id = by_color.get(&entry.color).ok_or_else(|| format!("no province has the color {} {} {}", r, g, b))?;
+        classes[*id as usize] = ProvinceClass { country: entry.country, is_sea: entry.sea };
+    }
+    Ok(classes)
+}
+
+pub fn read_classes(path: &str, map: &ProvinceMap) -> Result<Vec<ProvinceClass>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    parse_classes(&text, map).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Packs the classes into the data texture read by province_borders.glsl:
+// province `id` sits at (id % DATA_WIDTH, id / DATA_WIDTH), rg = country,
+// b = 255 for sea. Provinces missing from `classes` count as land of country 0.
+pub fn province_data_image(map: &ProvinceMap, classes: &[ProvinceClass]) -> RgbaImage {
+    let count = map.provinces.len().max(1) as u32;
+    let mut image = RgbaImage::new(DATA_WIDTH, count.div_ceil(DATA_WIDTH));
+    for province in &map.provinces {
+        let class = classes.get(province.id as usize).copied().unwrap_or_default();
+        let (x, y) = (province.id % DATA_WIDTH, province.id / DATA_WIDTH);
+        image.put_pixel(x, y, Rgba([
+            (class.country & 0xff) as u8,
+            ((class.country >> 8) & 0xff) as u8,
+            if class.is_sea { 255 } else { 0 },
+            255,
+        ]));
+    }
+    image
+}
+
+// Where the border shader gets its distances from
+enum BorderSource {
+    // Neighbour search in the ID map (province_borders.glsl); exact, but
+    // every pixel reads the whole neighbourhood and widths are capped
+    IdMap { ids: Texture, data: Texture },
+    // Precomputed distances (province_borders_sdf.glsl, see
+    // distance_field::border_distance_image); one filtered read per pixel
+    DistanceField { distances: Texture, max_distance: f32 },
+}
+
+// Draws province, country and coast borders over the map, straight from the province ID map or its distance field, so they stay
+// sharp at any zoom
+pub struct BorderPass {
+    shader: Shader,
+    quad: ScreenQuad,
+    source: BorderSource,
+    map_size: (u32, u32),
+}
+
+// Reads map_quad.glsl and the given fragment shader from `shader_dir`
+fn build_shader(gl: Device, shader_dir: &str, fragment: &str) -> Result<Shader, String> {
+    let read = |name: &str| {
+        let path = format!("{}/{}", shader_dir, name);
+        fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))
+    };
+    Ok(Shader::from_source(gl, &read("map_quad.glsl")?, &read(fragment)?))
+}
+
+impl BorderPass {
+    // Builds the pass from map_quad.glsl and province_borders.glsl in `shader_dir`
+    pub fn new(gl: Device, shader_dir: &str, map: &ProvinceMap, classes: &[ProvinceClass]) -> Result<Self, String> {
+        let shader = build_shader(gl.clone(), shader_dir, "province_borders.glsl")?;
+        let quad = ScreenQuad::new(gl.clone());
+
+        let id_image = image::DynamicImage::ImageRgb8(map.id_image()).to_rgba8();
+        let ids = Texture::from_rgba(gl.clone(), map.width, map.height, id_image.as_raw(), gl::NEAREST, gl::TEXTURE0);
+        let data_image = province_data_image(map, classes);
+        let data = Texture::from_rgba(gl, data_image.width(), data_image.height(), data_image.as_raw(), gl::NEAREST, gl::TEXTURE1);
+
+        Ok(BorderPass { shader, quad, source: BorderSource::IdMap { ids, data }, map_size: (map.width, map.height) })
+    }
+
+    // Builds the pass from map_quad.glsl and province_borders_sdf.glsl in
+    // `shader_dir`, reading a distance image made with the same `max_distance`
+    pub fn from_distance_field(gl: Device, shader_dir: &str, distances: &RgbaImage, max_distance: f32) -> Result<Self, String> {
+        let shader = build_shader(gl.clone(), shader_dir, "province_borders_sdf.glsl")?;
+        let quad = ScreenQuad::new(gl.clone());
+        let (width, height) = distances.dimensions();
+        let distances = Texture::from_rgba(gl, width, height, distances.as_raw(), gl::LINEAR, gl::TEXTURE0);
+        Ok(BorderPass { shader, quad, source: BorderSource::DistanceField { distances, max_distance }, map_size: (width, height) })
+    }
+
+    // Replaces the classes, e.g. after provinces change owner. A distance
+    // field pass needs a new distance image instead, see `set_distances`.
+    pub fn set_classes(&mut self, map: &ProvinceMap, classes: &[ProvinceClass]) {
+        if let BorderSource::IdMap { data, .. } = &mut self.source {
+            let data_image = province_data_image(map, classes);
+            data.delete();
+            *data = Texture::from_rgba(
+                self.shader.device().clone(),
+                data_image.width(),
+                data_image.height(),
+                data_image.as_raw(),
+                gl::NEAREST,
+                gl::TEXTURE1,
+            );
+        }
+    }
+
+    // Replaces the distance image of a distance field pass
+    pub fn set_distances(&mut self, image: &RgbaImage) {
+        if let BorderSource::DistanceField { distances, .. } = &mut self.source {
+            distances.update_rgba(0, 0, image.width(), image.height(), image.as_raw());
+        }
+    }
+
+    fn set_style(&self, width_uniform: &str, color_uniform: &str, style: &BorderStyle) {
+        let gl = self.shader.device();
+        let max_width = match self.source {
+            BorderSource::IdMap { .. } => MAX_BORDER_WIDTH,
+            BorderSource::DistanceField { max_distance, .. } => max_distance * 2.0,
+        };
+        gl.uniform_1f(self.shader.uniform_location(width_uniform), style.width.clamp(0.0, max_width));
+        let [r, g, b, a] = style.color;
+        gl.uniform_4f(self.shader.uniform_location(color_uniform), r, g, b, a);
+    }
+
+    // Blends the borders over whatever is in the bound framebuffer
+    pub fn draw(&self, settings: &BorderSettings, camera: &CameraUniforms) {
+        let gl = self.shader.device();
+        let _group = DebugGroup::new(gl, "province borders");
+        self.shader.activate();
+        camera.apply(&self.shader);
+        gl.uniform_2f(self.shader.uniform_location("mapSize"), self.map_size.0 as f32, self.map_size.1 as f32);
+        self.set_style("provinceWidth", "provinceColor", &settings.province);
+        self.set_style("countryWidth", "countryColor", &settings.country);
+        self.set_style("coastWidth", "coastColor", &settings.coast);
+
+        match &self.source {
+            BorderSource::IdMap { ids, data } => {
+                ids.tex_unit(&self.shader, "provinceIds", 0);
+                data.tex_unit(&self.shader, "provinceData", 1);
+                gl.active_texture(gl::TEXTURE0);
+                ids.bind();
+                gl.active_texture(gl::TEXTURE1);
+                data.bind();
+            }
+            BorderSource::DistanceField { distances, max_distance } => {
+                distances.tex_unit(&self.shader, "borderDistance", 0);
+                gl.uniform_1f(self.shader.uniform_location("maxDistance"), *max_distance);
+                gl.active_texture(gl::TEXTURE0);
+                distances.bind();
+            }
+        }
+
+        blended(gl, || camera.draw_copies(&self.shader, || self.quad.draw()));
+        gl.active_texture(gl::TEXTURE0);
+    }
+
+    pub fn delete(&self) {
+        self.shader.delete();
+        self.quad.delete();
+        match &self.source {
+            BorderSource::IdMap { ids, data } => {
+                ids.delete();
+                data.delete();
+            }
+            BorderSource::DistanceField { distances, .. } => distances.delete(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn three_stripes() -> ProvinceMap {
+        ProvinceMap::from_image(&RgbImage::from_fn(6, 2, |x, _| match x / 2 {
+            0 => Rgb([255, 0, 0]),
+            1 => Rgb([0, 255, 0]),
+            _ => Rgb([0, 0, 255]),
+        }))
+    }
+
+    #[test]
+    fn classes_are_found_by_color_and_default_to_land() {
+        let map = three_stripes();
+        let classes = parse_classes(r#"[{"color": [0, 0, 255], "sea": true}, {"color": [0, 255, 0], "country": 7}]"#, &map).unwrap();
+        let id = |color: [u8; 3]| map.provinces.iter().find(|p| p.color == color).unwrap().id as usize;
+        assert_eq!(classes.len(), 3);
+        assert_eq!(classes[id([255, 0, 0])], ProvinceClass::default());
+        assert_eq!(classes[id([0, 255, 0])], ProvinceClass { country: 7, is_sea: false });
+        assert_eq!(classes[id([0, 0, 255])], ProvinceClass { country: 0, is_sea: true });
+    }
+
+    #[test]
+    fn classes_of_colors_missing_from_the_map_are_an_error() {
+        let error = parse_classes(r#"[{"color": [1, 2, 3]}]"#, &three_stripes()).unwrap_err();
+        assert_eq!(error, "no province has the color 1 2 3");
+        assert!(parse_classes(r#"[{"color": [255, 0, 0], "owner": 1}]"#, &three_stripes()).is_err());
+    }
+}
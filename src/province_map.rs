@@ -0,0 +1,347 @@
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Index of a province in `ProvinceMap::provinces`
+pub type ProvinceId = u32;
+
+// File names used by `ProvinceMap::save` / `load`
+pub const TABLE_FILE: &str = "provinces.json";
+pub const ID_MAP_FILE: &str = "province_ids.png";
+
+// Pixel rectangle, max is inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32,
+    pub max_y: u32,
+}
+
+impl BoundingBox {
+    pub fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+// One uniquely colored region of the map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Province {
+    pub id: ProvinceId,
+    pub color: [u8; 3],
+    // Number of pixels
+    pub area: u64,
+    pub bbox: BoundingBox,
+    // Mean pixel position, measured at pixel centers
+    pub centroid: [f32; 2],
+}
+
+// Two provinces that touch, `a < b`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Adjacency {
+    pub a: ProvinceId,
+    pub b: ProvinceId,
+    // Number of pixel edges the two provinces share
+    pub border_length: u32,
+}
+
+// Province table, adjacency graph and per-pixel ID map built from a
+// province image where every province has a unique RGB color
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProvinceMap {
+    pub width: u32,
+    pub height: u32,
+    // Hash of the source image bytes, used to tell if a saved map is stale
+    pub source_hash: u64,
+    pub provinces: Vec<Province>,
+    // Sorted by (a, b)
+    pub adjacency: Vec<Adjacency>,
+    // Province of every pixel, row by row from the top; saved as a PNG
+    #[serde(skip)]
+    pub ids: Vec<ProvinceId>,
+}
+
+// 64-bit FNV-1a; stable across runs and Rust versions, unlike DefaultHasher
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+impl ProvinceMap {
+    // Scans an image, numbering provinces in the order their color is first
+    // met (row by row from the top left), so IDs are stable for a given image
+    pub fn from_image(image: &RgbImage) -> Self {
+        let (width, height) = image.dimensions();
+        let mut by_color: HashMap<[u8; 3], ProvinceId> = HashMap::new();
+        let mut provinces: Vec<Province> = Vec::new();
+        // Running sums for the centroids
+        let mut sums: Vec<(u64, u64)> = Vec::new();
+        let mut ids = Vec::with_capacity((width * height) as usize);
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let color = pixel.0;
+            let id = *by_color.entry(color).or_insert_with(|| {
+                provinces.push(Province {
+                    id: provinces.len() as ProvinceId,
+                    color,
+                    area: 0,
+                    bbox: BoundingBox { min_x: x, min_y: y, max_x: x, max_y: y },
+                    centroid: [0.0, 0.0],
+                });
+                sums.push((0, 0));
+                (provinces.len() - 1) as ProvinceId
+            });
+            let province = &mut provinces[id as usize];
+            province.area += 1;
+            province.bbox.min_x = province.bbox.min_x.min(x);
+            province.bbox.min_y = province.bbox.min_y.min(y);
+            province.bbox.max_x = province.bbox.max_x.max(x);
+            province.bbox.max_y = province.bbox.max_y.max(y);
+            sums[id as usize].0 += x as u64;
+            sums[id as usize].1 += y as u64;
+            ids.push(id);
+        }
+
+        for (province, (sx, sy)) in provinces.iter_mut().zip(sums) {
+            let area = province.area as f64;
+            province.centroid = [(sx as f64 / area + 0.5) as f32, (sy as f64 / area + 0.5) as f32];
+        }
+
+        let adjacency = Self::build_adjacency(&ids, width, height);
+        ProvinceMap {
+            width,
+            height,
+            source_hash: fnv1a(image.as_raw()),
+            provinces,
+            adjacency,
+            ids,
+        }
+    }
+
+    // Counts the pixel edges between differing provinces, looking right and down
+    fn build_adjacency(ids: &[ProvinceId], width: u32, height: u32) -> Vec<Adjacency> {
+        let mut borders: BTreeMap<(ProvinceId, ProvinceId), u32> = BTreeMap::new();
+        let (w, h) = (width as usize, height as usize);
+        let mut count = |p: ProvinceId, q: ProvinceId| {
+            if p != q {
+                *borders.entry((p.min(q), p.max(q))).or_insert(0) += 1;
+            }
+        };
+        for y in 0..h {
+            for x in 0..w {
+                let id = ids[y * w + x];
+                if x + 1 < w {
+                    count(id, ids[y * w + x + 1]);
+                }
+                if y + 1 < h {
+                    count(id, ids[(y + 1) * w + x]);
+                }
+            }
+        }
+        borders
+            .into_iter()
+            .map(|((a, b), border_length)| Adjacency { a, b, border_length })
+            .collect()
+    }
+
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self::from_image(&image.to_rgb8()))
+    }
+
+    // Province under a pixel, None outside the map
+    pub fn province_at(&self, x: u32, y: u32) -> Option<ProvinceId> {
+        if x < self.width && y < self.height {
+            Some(self.ids[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    // Neighbours of a province with the length of the shared border
+    pub fn neighbours(&self, id: ProvinceId) -> impl Iterator<Item = (ProvinceId, u32)> + '_ {
+        self.adjacency.iter().filter_map(move |adj| {
+            if adj.a == id {
+                Some((adj.b, adj.border_length))
+            } else if adj.b == id {
+                Some((adj.a, adj.border_length))
+            } else {
+                None
+            }
+        })
+    }
+
+    // The ID map as an image, with the ID spread over the channels
+    // (r = bits 0-7, g = bits 8-15, b = bits 16-23) so shaders can decode it
+    pub fn id_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            let id = self.ids[(y * self.width + x) as usize];
+            Rgb([(id & 0xff) as u8, ((id >> 8) & 0xff) as u8, ((id >> 16) & 0xff) as u8])
+        })
+    }
+
+    // Writes the table and adjacency as JSON and the ID map as a PNG
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let table = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let table_path = dir.join(TABLE_FILE);
+        fs::write(&table_path, table).map_err(|e| format!("{}: {}", table_path.display(), e))?;
+        let ids_path = dir.join(ID_MAP_FILE);
+        self.id_image().save(&ids_path).map_err(|e| format!("{}: {}", ids_path.display(), e))
+    }
+
+    // Reads back what `save` wrote
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let table_path = dir.join(TABLE_FILE);
+        let table = fs::read_to_string(&table_path).map_err(|e| format!("{}: {}", table_path.display(), e))?;
+        let mut map: ProvinceMap = serde_json::from_str(&table).map_err(|e| format!("{}: {}", table_path.display(), e))?;
+
+        let ids_path = dir.join(ID_MAP_FILE);
+        let ids = image::open(&ids_path).map_err(|e| format!("{}: {}", ids_path.display(), e))?.to_rgb8();
+        if ids.dimensions() != (map.width, map.height) {
+            return Err(format!("{} does not match the size in {}", ids_path.display(), table_path.display()));
+        }
+        map.ids = ids
+            .pixels()
+            .map(|p| p.0[0] as u32 | (p.0[1] as u32) << 8 | (p.0[2] as u32) << 16)
+            .collect();
+        // A stale or corrupted ID map would only panic later, on lookups
+        if let Some(id) = map.ids.iter().find(|id| **id as usize >= map.provinces.len()) {
+            return Err(format!("{}: province {} is not in {}", ids_path.display(), id, table_path.display()));
+        }
+        Ok(map)
+    }
+
+    // Loads the saved map from `cache_dir` if it was built from the same
+    // image, otherwise scans the image and saves the result there
+    pub fn load_or_build(image_path: &str, cache_dir: &Path) -> Result<Self, String> {
+        let image = image::open(image_path).map_err(|e| format!("{}: {}", image_path, e))?.to_rgb8();
+        let hash = fnv1a(image.as_raw());
+        if let Ok(map) = Self::load(cache_dir) {
+            if map.source_hash == hash {
+                return Ok(map);
+            }
+        }
+        let map = Self::from_image(&image);
+        map.save(cache_dir)?;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    // Red and green on top, blue and green below:
+    //   R R G
+    //   B B G
+    fn three_provinces() -> RgbImage {
+        RgbImage::from_fn(3, 2, |x, y| match (x, y) {
+            (2, _) => Rgb([0, 255, 0]),
+            (_, 0) => Rgb([255, 0, 0]),
+            _ => Rgb([0, 0, 255]),
+        })
+    }
+
+    #[test]
+    fn provinces_are_numbered_in_scan_order() {
+        let map = ProvinceMap::from_image(&three_provinces());
+        assert_eq!(map.ids, vec![0, 0, 1, 2, 2, 1]);
+        let colors: Vec<[u8; 3]> = map.provinces.iter().map(|p| p.color).collect();
+        assert_eq!(colors, vec![[255, 0, 0], [0, 255, 0], [0, 0, 255]]);
+        assert_eq!(map.province_at(2, 1), Some(1));
+        assert_eq!(map.province_at(3, 0), None);
+    }
+
+    #[test]
+    fn provinces_have_area_bounds_and_centroid() {
+        let map = ProvinceMap::from_image(&three_provinces());
+        let green = &map.provinces[1];
+        assert_eq!(green.area, 2);
+        assert_eq!(green.bbox, BoundingBox { min_x: 2, min_y: 0, max_x: 2, max_y: 1 });
+        assert_eq!((green.bbox.width(), green.bbox.height()), (1, 2));
+        assert_eq!(green.centroid, [2.5, 1.0]);
+        assert_eq!(map.provinces[0].centroid, [1.0, 0.5]);
+    }
+
+    #[test]
+    fn adjacency_counts_shared_pixel_edges() {
+        let map = ProvinceMap::from_image(&three_provinces());
+        assert_eq!(
+            map.adjacency,
+            vec![
+                Adjacency { a: 0, b: 1, border_length: 1 },
+                Adjacency { a: 0, b: 2, border_length: 2 },
+                Adjacency { a: 1, b: 2, border_length: 1 },
+            ]
+        );
+        assert_eq!(map.neighbours(2).collect::<Vec<_>>(), vec![(0, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn id_image_spreads_ids_over_the_channels() {
+        let mut map = ProvinceMap::from_image(&three_provinces());
+        map.ids[0] = 0x030201;
+        let image = map.id_image();
+        assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3]);
+        assert_eq!(image.get_pixel(2, 1).0, [1, 0, 0]);
+    }
+
+    #[test]
+    fn saved_maps_load_back_the_same() {
+        let dir = TempDir::new("province-map-round-trip", &[]);
+        let map = ProvinceMap::from_image(&three_provinces());
+        map.save(&dir.0).unwrap();
+        let loaded = ProvinceMap::load(&dir.0).unwrap();
+        assert_eq!((loaded.width, loaded.height, loaded.source_hash), (map.width, map.height, map.source_hash));
+        assert_eq!(loaded.provinces, map.provinces);
+        assert_eq!(loaded.adjacency, map.adjacency);
+        assert_eq!(loaded.ids, map.ids);
+    }
+
+    #[test]
+    fn ids_missing_from_the_table_fail_to_load() {
+        let dir = TempDir::new("province-map-bad-ids", &[]);
+        let map = ProvinceMap::from_image(&three_provinces());
+        map.save(&dir.0).unwrap();
+        RgbImage::from_pixel(3, 2, Rgb([3, 0, 0])).save(dir.0.join(ID_MAP_FILE)).unwrap();
+        assert!(ProvinceMap::load(&dir.0).unwrap_err().contains("province 3 is not in"));
+    }
+
+    #[test]
+    fn stale_or_broken_caches_are_rebuilt() {
+        let dir = TempDir::new("province-map-cache", &[]);
+        let (image_path, cache) = (dir.0.join("provinces.png"), dir.0.join("cache"));
+        let image_str = image_path.to_str().unwrap();
+        three_provinces().save(&image_path).unwrap();
+        let built = ProvinceMap::load_or_build(image_str, &cache).unwrap();
+
+        // A cache of the same image is used as it is
+        let mut cached = built.clone();
+        cached.provinces[0].color = [9, 9, 9];
+        cached.save(&cache).unwrap();
+        assert_eq!(ProvinceMap::load_or_build(image_str, &cache).unwrap().provinces[0].color, [9, 9, 9]);
+
+        // An edited image no longer matches the cache's hash
+        RgbImage::from_pixel(3, 2, Rgb([255, 255, 0])).save(&image_path).unwrap();
+        let rebuilt = ProvinceMap::load_or_build(image_str, &cache).unwrap();
+        assert_ne!(rebuilt.source_hash, built.source_hash);
+        assert_eq!(rebuilt.provinces.len(), 1);
+        assert_eq!(ProvinceMap::load(&cache).unwrap().source_hash, rebuilt.source_hash);
+
+        // So does a cache whose ID map names unknown provinces
+        RgbImage::from_pixel(3, 2, Rgb([7, 0, 0])).save(cache.join(ID_MAP_FILE)).unwrap();
+        assert_eq!(ProvinceMap::load_or_build(image_str, &cache).unwrap().ids, vec![0; 6]);
+    }
+}
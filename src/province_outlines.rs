@@ -0,0 +1,780 @@
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use crate::assets::MeshData;
+use crate::province_map::{ProvinceId, ProvinceMap};
+use std::fs;
+use crate::cli::OutlinesArgs;
+
+// Position on the pixel corner grid, (0, 0) is the top left corner of the
+// map and y grows downwards
+pub type Point = [f32; 2];
+
+// One stretch of boundary between two regions, running from junction to
+// junction (or around a closed loop, in which case the first and last point
+// are equal). Every stretch is stored and simplified once, so both provinces
+// it separates get exactly the same edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Border {
+    // Province on the left and right when walking the points as seen on
+    // screen; None is outside the map
+    pub left: Option<ProvinceId>,
+    pub right: Option<ProvinceId>,
+    pub points: Vec<Point>,
+}
+
+// Closed ring without the repeated first point, plus the rings of its holes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    // Clockwise on screen
+    pub exterior: Vec<Point>,
+    // Counter-clockwise on screen
+    pub holes: Vec<Vec<Point>>,
+}
+
+// Outline of one province; several polygons when it is split in parts
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvinceOutline {
+    pub id: ProvinceId,
+    pub polygons: Vec<Polygon>,
+}
+
+// Triangles covering the provinces, in map pixel coordinates
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutlineMesh {
+    pub positions: Vec<Point>,
+    // Province of every vertex, so one mesh can be recolored per province
+    pub provinces: Vec<ProvinceId>,
+    pub indices: Vec<u32>,
+}
+
+impl OutlineMesh {
+    // Two floats of position then the province as a float (exact below
+    // 2^24), the layout outline_mesh_vertex.glsl reads
+    pub fn to_mesh_data(&self) -> MeshData {
+        let vertices = self.positions.iter().zip(&self.provinces).flat_map(|(p, id)| [p[0], p[1], *id as f32]).collect();
+        MeshData { attributes: vec![2, 1], vertices, indices: self.indices.clone() }
+    }
+}
+
+// Vector version of a province map
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outlines {
+    pub width: u32,
+    pub height: u32,
+    pub borders: Vec<Border>,
+    // Indexed by ProvinceId
+    pub provinces: Vec<ProvinceOutline>,
+}
+
+// Boundary stretch on the corner grid, before simplification
+struct RawChain {
+    corners: Vec<u32>,
+    left: Option<ProvinceId>,
+    right: Option<ProvinceId>,
+}
+
+// Distance from p to the segment a-b
+fn segment_distance(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 { (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+    let (x, y) = (a[0] + t * dx - p[0], a[1] + t * dy - p[1]);
+    (x * x + y * y).sqrt()
+}
+
+// Douglas-Peucker, keeping both end points
+fn simplify_open(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let mut farthest = (0.0, 0);
+        for i in first + 1..last {
+            let d = segment_distance(points[i], points[first], points[last]);
+            if d > farthest.0 {
+                farthest = (d, i);
+            }
+        }
+        // Collinear points always go, even with a zero tolerance
+        if farthest.0 > tolerance.max(1e-6) {
+            keep[farthest.1] = true;
+            stack.push((first, farthest.1));
+            stack.push((farthest.1, last));
+        }
+    }
+    points.iter().zip(keep).filter(|(_, k)| *k).map(|(p, _)| *p).collect()
+}
+
+// Simplifies a boundary stretch; closed loops are split at their point
+// farthest from the start so they keep at least a triangle
+fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 4 || points[0] != points[points.len() - 1] {
+        return simplify_open(points, tolerance);
+    }
+    let start = points[0];
+    let distance = |p: &Point| (p[0] - start[0]).powi(2) + (p[1] - start[1]).powi(2);
+    let split = (1..points.len() - 1).max_by(|a, b| distance(&points[*a]).total_cmp(&distance(&points[*b]))).unwrap();
+    let mut first = simplify_open(&points[..=split], tolerance);
+    let second = simplify_open(&points[split..], tolerance);
+    first.pop();
+    first.extend(second);
+    if first.len() < 4 {
+        // Both halves collapsed to straight lines; keep the loop's extremes
+        let quarter = points.len() / 4;
+        return vec![start, points[quarter.max(1)], points[split], points[(split + quarter).min(points.len() - 2)], start]
+            .into_iter()
+            .fold(Vec::new(), |mut acc: Vec<Point>, p| {
+                if acc.last() != Some(&p) {
+                    acc.push(p);
+                }
+                acc
+            });
+    }
+    first
+}
+
+// Twice the signed area, positive for rings that run clockwise on screen
+fn ring_area(ring: &[Point]) -> f32 {
+    let mut sum = 0.0;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+        sum += a[0] * b[1] - b[0] * a[1];
+    }
+    sum
+}
+
+fn point_in_ring(p: Point, ring: &[Point]) -> bool {
+    let mut inside = false;
+    let mut j = ring.len() - 1;
+    for i in 0..ring.len() {
+        let (a, b) = (ring[i], ring[j]);
+        if (a[1] > p[1]) != (b[1] > p[1]) && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0] {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+impl Outlines {
+    // Traces the boundaries of every province of the map. Pixel edges between
+    // different provinces are joined into stretches between junctions (corners
+    // where three or more regions meet), which are then simplified with the
+    // given tolerance in pixels; 0 only merges collinear edges.
+    pub fn trace(map: &ProvinceMap, tolerance: f32) -> Self {
+        let (w, h) = (map.width as i64, map.height as i64);
+        let corner = |x: i64, y: i64| (y * (w + 1) + x) as u32;
+        let corner_xy = |c: u32| ((c as i64 % (w + 1)), (c as i64 / (w + 1)));
+        let region = |x: i64, y: i64| {
+            if x >= 0 && y >= 0 && x < w && y < h {
+                Some(map.ids[(y * w + x) as usize])
+            } else {
+                None
+            }
+        };
+
+        // Marching squares over the pixel corners: every corner gets the
+        // boundary edges leaving it
+        let mut edges: Vec<Vec<u32>> = vec![Vec::new(); ((w + 1) * (h + 1)) as usize];
+        let mut add = |a: u32, b: u32| {
+            edges[a as usize].push(b);
+            edges[b as usize].push(a);
+        };
+        for y in 0..=h {
+            for x in 0..w {
+                if region(x, y - 1) != region(x, y) {
+                    add(corner(x, y), corner(x + 1, y));
+                }
+            }
+        }
+        for y in 0..h {
+            for x in 0..=w {
+                if region(x - 1, y) != region(x, y) {
+                    add(corner(x, y), corner(x, y + 1));
+                }
+            }
+        }
+
+        // Regions on each side of the first edge of a stretch
+        let sides = |a: u32, b: u32| {
+            let ((ax, ay), (bx, by)) = (corner_xy(a), corner_xy(b));
+            let (dx, dy) = (bx - ax, by - ay);
+            // Pixels half a step to the right (on screen) of the edge's middle,
+            // and half a step to the left
+            let right = region(ax + (dx - dy).div_euclid(2), ay + (dy + dx).div_euclid(2));
+            let left = region(ax + (dx + dy).div_euclid(2), ay + (dy - dx).div_euclid(2));
+            (left, right)
+        };
+
+        // Walk from every junction along each of its edges to the next junction
+        let mut visited: HashSet<(u32, u32)> = HashSet::new();
+        let key = |a: u32, b: u32| (a.min(b), a.max(b));
+        let mut chains: Vec<RawChain> = Vec::new();
+        let mut walk = |start: u32, first: u32, visited: &mut HashSet<(u32, u32)>| {
+            let mut corners = vec![start, first];
+            visited.insert(key(start, first));
+            let (mut prev, mut cur) = (start, first);
+            while edges[cur as usize].len() == 2 && cur != start {
+                let next = if edges[cur as usize][0] == prev { edges[cur as usize][1] } else { edges[cur as usize][0] };
+                visited.insert(key(cur, next));
+                corners.push(next);
+                prev = cur;
+                cur = next;
+            }
+            let (left, right) = sides(start, first);
+            chains.push(RawChain { corners, left, right });
+        };
+        for c in 0..edges.len() as u32 {
+            if edges[c as usize].len() > 2 {
+                for &next in &edges[c as usize].clone() {
+                    if !visited.contains(&key(c, next)) {
+                        walk(c, next, &mut visited);
+                    }
+                }
+            }
+        }
+        // What is left are closed loops without junctions, like islands
+        for c in 0..edges.len() as u32 {
+            if let Some(&next) = edges[c as usize].first() {
+                if !visited.contains(&key(c, next)) {
+                    walk(c, next, &mut visited);
+                }
+            }
+        }
+
+        let to_point = |c: u32| {
+            let (x, y) = corner_xy(c);
+            [x as f32, y as f32]
+        };
+        let borders: Vec<Border> = chains
+            .iter()
+            .map(|chain| {
+                let points: Vec<Point> = chain.corners.iter().map(|c| to_point(*c)).collect();
+                Border { left: chain.left, right: chain.right, points: simplify(&points, tolerance) }
+            })
+            .collect();
+
+        let provinces = Self::assemble(map, &chains, &borders, &corner_xy);
+        Outlines { width: map.width, height: map.height, borders, provinces }
+    }
+
+    // Joins the borders of each province into rings with the province on the
+    // right, and sorts them into exteriors and holes
+    fn assemble(map: &ProvinceMap, chains: &[RawChain], borders: &[Border], corner_xy: &dyn Fn(u32) -> (i64, i64)) -> Vec<ProvinceOutline> {
+        // (chain, reversed) per province, oriented so the province is on the right
+        let mut sides: Vec<Vec<(usize, bool)>> = vec![Vec::new(); map.provinces.len()];
+        for (i, chain) in chains.iter().enumerate() {
+            if let Some(right) = chain.right {
+                sides[right as usize].push((i, false));
+            }
+            if let Some(left) = chain.left {
+                sides[left as usize].push((i, true));
+            }
+        }
+        let ends = |(chain, reversed): (usize, bool)| {
+            let corners = &chains[chain].corners;
+            if reversed {
+                (corners[corners.len() - 1], corners[corners.len() - 2], corners[0], corners[1])
+            } else {
+                (corners[0], corners[1], corners[corners.len() - 1], corners[corners.len() - 2])
+            }
+        };
+        let direction = |a: u32, b: u32| {
+            let ((ax, ay), (bx, by)) = (corner_xy(a), corner_xy(b));
+            (bx - ax, by - ay)
+        };
+
+        sides
+            .iter()
+            .enumerate()
+            .map(|(id, oriented)| {
+                let mut starts: HashMap<u32, Vec<usize>> = HashMap::new();
+                for (i, o) in oriented.iter().enumerate() {
+                    starts.entry(ends(*o).0).or_default().push(i);
+                }
+                let mut used = vec![false; oriented.len()];
+                let mut rings: Vec<Vec<Point>> = Vec::new();
+                for first in 0..oriented.len() {
+                    if used[first] {
+                        continue;
+                    }
+                    let ring_start = ends(oriented[first]).0;
+                    let mut ring: Vec<Point> = Vec::new();
+                    let mut current = first;
+                    loop {
+                        used[current] = true;
+                        let (chain, reversed) = oriented[current];
+                        let mut points = borders[chain].points.clone();
+                        if reversed {
+                            points.reverse();
+                        }
+                        points.pop();
+                        ring.extend(points);
+
+                        let (_, _, end, before_end) = ends(oriented[current]);
+                        if end == ring_start {
+                            break;
+                        }
+                        // Where a province touches itself diagonally, turn as
+                        // far right as possible so each part gets its own ring
+                        let (dx, dy) = direction(before_end, end);
+                        let next = starts.get(&end).and_then(|candidates| {
+                            candidates.iter().copied().filter(|c| !used[*c]).min_by_key(|c| {
+                                let (_, second, _, _) = ends(oriented[*c]);
+                                let out = direction(end, second);
+                                if out == (-dy, dx) { 0 } else if out == (dx, dy) { 1 } else { 2 }
+                            })
+                        });
+                        match next {
+                            Some(next) => current = next,
+                            None => break,
+                        }
+                    }
+                    if ring.len() >= 3 && ring_area(&ring).abs() > 0.0 {
+                        rings.push(ring);
+                    }
+                }
+
+                let (exteriors, holes): (Vec<_>, Vec<_>) = rings.into_iter().partition(|r| ring_area(r) > 0.0);
+                let mut polygons: Vec<Polygon> = exteriors.into_iter().map(|exterior| Polygon { exterior, holes: Vec::new() }).collect();
+                for hole in holes {
+                    // Test the middle of an edge, hole vertices may touch the exterior
+                    let probe = [(hole[0][0] + hole[1][0]) * 0.5, (hole[0][1] + hole[1][1]) * 0.5];
+                    let owner = polygons.iter().position(|p| point_in_ring(probe, &p.exterior)).or(if polygons.is_empty() { None } else { Some(0) });
+                    if let Some(owner) = owner {
+                        polygons[owner].holes.push(hole);
+                    }
+                }
+                ProvinceOutline { id: id as ProvinceId, polygons }
+            })
+            .collect()
+    }
+
+    // SVG with every province filled in its map color, and the borders on top
+    pub fn to_svg(&self, map: &ProvinceMap, border_width: f32) -> String {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+            self.width, self.height
+        );
+        let ring_path = |d: &mut String, ring: &[Point]| {
+            for (i, p) in ring.iter().enumerate() {
+                let _ = write!(d, "{}{} {} ", if i == 0 { "M" } else { "L" }, p[0], p[1]);
+            }
+            d.push_str("Z ");
+        };
+        let _ = writeln!(svg, r#"<g stroke="none" fill-rule="evenodd">"#);
+        for outline in &self.provinces {
+            let mut d = String::new();
+            for polygon in &outline.polygons {
+                ring_path(&mut d, &polygon.exterior);
+                for hole in &polygon.holes {
+                    ring_path(&mut d, hole);
+                }
+            }
+            let [r, g, b] = map.provinces[outline.id as usize].color;
+            let _ = writeln!(svg, r##"<path data-id="{}" fill="#{:02x}{:02x}{:02x}" d="{}"/>"##, outline.id, r, g, b, d.trim_end());
+        }
+        let _ = writeln!(svg, "</g>");
+        let _ = writeln!(svg, r#"<g fill="none" stroke="black" stroke-width="{}" stroke-linejoin="round">"#, border_width);
+        for border in self.borders.iter().filter(|b| b.left.is_some() && b.right.is_some()) {
+            let points: Vec<String> = border.points.iter().map(|p| format!("{},{}", p[0], p[1])).collect();
+            let _ = writeln!(svg, r#"<polyline points="{}"/>"#, points.join(" "));
+        }
+        let _ = writeln!(svg, "</g>");
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // GeoJSON FeatureCollection with a (Multi)Polygon feature per province.
+    // Coordinates are map pixels with y flipped to point up, and rings follow
+    // the right-hand rule (exteriors counter-clockwise).
+    pub fn to_geojson(&self, map: &ProvinceMap) -> Value {
+        let height = self.height as f32;
+        let ring_json = |ring: &[Point], reverse: bool| {
+            let mut coords: Vec<Value> = ring.iter().map(|p| json!([p[0], height - p[1]])).collect();
+            // Flipping y keeps the on-screen orientation, so reverse to turn
+            // clockwise exteriors counter-clockwise
+            if reverse {
+                coords.reverse();
+            }
+            coords.push(coords[0].clone());
+            Value::Array(coords)
+        };
+        let features: Vec<Value> = self
+            .provinces
+            .iter()
+            .filter(|outline| !outline.polygons.is_empty())
+            .map(|outline| {
+                let polygons: Vec<Value> = outline
+                    .polygons
+                    .iter()
+                    .map(|polygon| {
+                        let mut rings = vec![ring_json(&polygon.exterior, true)];
+                        rings.extend(polygon.holes.iter().map(|hole| ring_json(hole, true)));
+                        Value::Array(rings)
+                    })
+                    .collect();
+                let geometry = if polygons.len() == 1 {
+                    json!({ "type": "Polygon", "coordinates": polygons[0] })
+                } else {
+                    json!({ "type": "MultiPolygon", "coordinates": polygons })
+                };
+                let province = &map.provinces[outline.id as usize];
+                json!({
+                    "type": "Feature",
+                    "id": outline.id,
+                    "properties": { "id": outline.id, "color": province.color, "area": province.area },
+                    "geometry": geometry,
+                })
+            })
+            .collect();
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+
+    // Triangulates every province polygon by ear clipping, holes included.
+    // Quadratic to cubic in the points of a polygon, fine for traced
+    // provinces of a few hundred points after simplification; trace with a
+    // tolerance above 0 before meshing large unsimplified regions.
+    pub fn fill_mesh(&self) -> OutlineMesh {
+        let mut mesh = OutlineMesh::default();
+        for outline in &self.provinces {
+            for polygon in &outline.polygons {
+                let ring = bridge_holes(polygon);
+                let base = mesh.positions.len() as u32;
+                mesh.indices.extend(ear_clip(&ring).into_iter().map(|i| base + i));
+                mesh.provinces.extend(std::iter::repeat_n(outline.id, ring.len()));
+                mesh.positions.extend(ring);
+            }
+        }
+        mesh
+    }
+
+    // Quads of the given width along every border between two provinces,
+    // for drawing borders as geometry instead of in the shader
+    pub fn border_mesh(&self, width: f32) -> OutlineMesh {
+        let mut mesh = OutlineMesh::default();
+        let half = width * 0.5;
+        for border in self.borders.iter().filter(|b| b.left.is_some() && b.right.is_some()) {
+            for segment in border.points.windows(2) {
+                let (a, b) = (segment[0], segment[1]);
+                let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                let length = (dx * dx + dy * dy).sqrt();
+                if length == 0.0 {
+                    continue;
+                }
+                // Extend along the segment too, so consecutive quads overlap at joints
+                let (ux, uy) = (dx / length * half, dy / length * half);
+                let (nx, ny) = (-uy, ux);
+                let base = mesh.positions.len() as u32;
+                mesh.positions.extend([
+                    [a[0] - ux + nx, a[1] - uy + ny],
+                    [b[0] + ux + nx, b[1] + uy + ny],
+                    [b[0] + ux - nx, b[1] + uy - ny],
+                    [a[0] - ux - nx, a[1] - uy - ny],
+                ]);
+                mesh.provinces.extend([border.left.unwrap(); 4]);
+                mesh.indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+        }
+        mesh
+    }
+}
+
+fn cross(a: Point, b: Point, c: Point) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn segments_cross(a: Point, b: Point, c: Point, d: Point) -> bool {
+    cross(a, b, c) * cross(a, b, d) < 0.0 && cross(c, d, a) * cross(c, d, b) < 0.0
+}
+
+// Whether a segment leaving corner v towards t starts inside the polygon,
+// whose interior lies to the positive side of prev-v-next (exteriors run
+// clockwise on screen and holes the other way, so this holds for both)
+fn locally_inside(prev: Point, v: Point, next: Point, t: Point) -> bool {
+    if cross(prev, v, next) >= 0.0 {
+        cross(prev, v, t) > 0.0 && cross(v, next, t) > 0.0
+    } else {
+        cross(prev, v, t) > 0.0 || cross(v, next, t) > 0.0
+    }
+}
+
+// Merges the holes into the exterior through zero-width bridges, giving one
+// ring that ear clipping can handle
+fn bridge_holes(polygon: &Polygon) -> Vec<Point> {
+    let mut ring = polygon.exterior.clone();
+    let mut holes: Vec<&Vec<Point>> = polygon.holes.iter().collect();
+    // Rightmost holes first, like earcut, so bridges rarely need to cross others
+    holes.sort_by(|a, b| {
+        let max_x = |r: &Vec<Point>| r.iter().map(|p| p[0]).fold(f32::MIN, f32::max);
+        max_x(b).total_cmp(&max_x(a))
+    });
+    for (index, hole) in holes.iter().enumerate() {
+        let m = (0..hole.len()).max_by(|a, b| hole[*a][0].total_cmp(&hole[*b][0])).unwrap();
+        let from = hole[m];
+        let around = |r: &[Point], i: usize| (r[(i + r.len() - 1) % r.len()], r[i], r[(i + 1) % r.len()]);
+        let (hole_prev, _, hole_next) = around(hole, m);
+        let reachable = |v: usize| {
+            let to = ring[v];
+            let (prev, _, next) = around(&ring, v);
+            if to == from {
+                // The hole touches the ring here, bridge without a gap
+                return true;
+            }
+            if !locally_inside(prev, to, next, from) || !locally_inside(hole_prev, from, hole_next, to) {
+                return false;
+            }
+            let edges = ring.iter().zip(ring.iter().cycle().skip(1));
+            let hole_edges = holes[index..].iter().flat_map(|h| h.iter().zip(h.iter().cycle().skip(1)));
+            !edges.chain(hole_edges).any(|(a, b)| segments_cross(from, to, *a, *b))
+        };
+        // Closest ring vertex that can be reached without crossing anything
+        let mut candidates: Vec<usize> = (0..ring.len()).collect();
+        let distance = |i: &usize| (ring[*i][0] - from[0]).powi(2) + (ring[*i][1] - from[1]).powi(2);
+        candidates.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        let v = candidates.iter().copied().find(|i| reachable(*i)).unwrap_or(candidates[0]);
+
+        let mut bridged = Vec::with_capacity(ring.len() + hole.len() + 2);
+        bridged.extend_from_slice(&ring[..=v]);
+        bridged.extend(hole[m..].iter().chain(hole[..=m].iter()).copied());
+        bridged.extend_from_slice(&ring[v..]);
+        ring = bridged;
+    }
+    ring
+}
+
+// Ear clipping of a clockwise (on screen) ring; returns indices into it.
+// Every ear test scans the whole ring and a round can miss n times, so this
+// is O(n^2) on typical rings and O(n^3) at worst.
+fn ear_clip(ring: &[Point]) -> Vec<u32> {
+    let mut remaining: Vec<usize> = (0..ring.len()).collect();
+    let mut triangles = Vec::with_capacity(ring.len().saturating_sub(2) * 3);
+    let mut i = 0;
+    let mut misses = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let at = |k: usize| ring[remaining[k % n]];
+        let (a, b, c) = (at(i + n - 1), at(i), at(i + 1));
+        let turn = cross(a, b, c);
+        // Only reflex corners can poke into an ear, like in earcut; convex
+        // ones on its edges (bridge ends, straight runs) do not count
+        let empty = turn > 0.0
+            && (0..n).all(|k| {
+                let p = at(k);
+                if p == a || p == b || p == c || cross(at(k + n - 1), p, at(k + 1)) > 0.0 {
+                    return true;
+                }
+                !(cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0)
+            });
+        // Straight corners and spikes go without a triangle. After a full
+        // round without ears (simplification can make the ring
+        // self-intersect) clip anyway, so triangulation always finishes.
+        if turn == 0.0 || empty || misses > n {
+            if turn != 0.0 {
+                triangles.extend([remaining[(i + n - 1) % n] as u32, remaining[i % n] as u32, remaining[(i + 1) % n] as u32]);
+            }
+            remaining.remove(i % n);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+        i %= remaining.len();
+    }
+    if remaining.len() == 3 && cross(ring[remaining[0]], ring[remaining[1]], ring[remaining[2]]) != 0.0 {
+        triangles.extend(remaining.iter().map(|i| *i as u32));
+    }
+    triangles
+}
+
+// `shaders outlines`: traces an image and writes the outlines as SVG and
+// GeoJSON, plus the fill and border meshes as mesh JSON, into `out_dir`
+pub fn run(args: &OutlinesArgs) -> Result<(), String> {
+    let map = ProvinceMap::from_path(&args.image)?;
+    let outlines = Outlines::trace(&map, args.tolerance);
+    fs::create_dir_all(&args.out_dir).map_err(|e| format!("{}: {}", args.out_dir.display(), e))?;
+    let write = |name: &str, text: String| {
+        let path = args.out_dir.join(name);
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+    write("outlines.svg", outlines.to_svg(&map, 0.5))?;
+    write("outlines.geojson", outlines.to_geojson(&map).to_string())?;
+    let mesh_json = |mesh: OutlineMesh| serde_json::to_string(&mesh.to_mesh_data()).map_err(|e| e.to_string());
+    write("fill_mesh.json", mesh_json(outlines.fill_mesh())?)?;
+    write("border_mesh.json", mesh_json(outlines.border_mesh(1.0))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Left half red, right half blue, with a green island inside the red
+    fn two_provinces_and_an_island() -> ProvinceMap {
+        let image = RgbImage::from_fn(8, 6, |x, y| match (x, y) {
+            (1..=2, 2..=3) => Rgb([0, 255, 0]),
+            (0..=3, _) => Rgb([255, 0, 0]),
+            _ => Rgb([0, 0, 255]),
+        });
+        ProvinceMap::from_image(&image)
+    }
+
+    // Red on the left, blue over green on the right; the three meet at (3, 2)
+    fn three_provinces() -> ProvinceMap {
+        let image = RgbImage::from_fn(6, 4, |x, y| match (x, y) {
+            (0..=2, _) => Rgb([255, 0, 0]),
+            (_, 0..=1) => Rgb([0, 0, 255]),
+            _ => Rgb([0, 255, 0]),
+        });
+        ProvinceMap::from_image(&image)
+    }
+
+    // Shoelace sum with y pointing up, positive for counter-clockwise rings
+    fn geojson_area(ring: &[Value]) -> f64 {
+        ring.windows(2).map(|w| w[0][0].as_f64().unwrap() * w[1][1].as_f64().unwrap() - w[1][0].as_f64().unwrap() * w[0][1].as_f64().unwrap()).sum()
+    }
+
+    fn area(mesh: &OutlineMesh, province: ProvinceId) -> f32 {
+        mesh.indices
+            .chunks(3)
+            .filter(|t| mesh.provinces[t[0] as usize] == province)
+            .map(|t| cross(mesh.positions[t[0] as usize], mesh.positions[t[1] as usize], mesh.positions[t[2] as usize]).abs() / 2.0)
+            .sum()
+    }
+
+    #[test]
+    fn shared_borders_are_stored_once_and_split_at_junctions() {
+        let map = three_provinces();
+        let outlines = Outlines::trace(&map, 0.0);
+        let inner: Vec<&Border> = outlines.borders.iter().filter(|b| b.left.is_some() && b.right.is_some()).collect();
+        let mut pairs: Vec<(ProvinceId, ProvinceId)> = inner.iter().map(|b| (b.left.unwrap().min(b.right.unwrap()), b.left.unwrap().max(b.right.unwrap()))).collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (1, 2)]);
+        for border in &inner {
+            // Straight from the junction to the map's edge
+            assert_eq!(border.points.len(), 2, "{:?}", border);
+            assert!(border.points.contains(&[3.0, 2.0]), "{:?}", border);
+            // The pixel to the right on screen belongs to `right`
+            let ([ax, ay], [bx, by]) = (border.points[0], border.points[1]);
+            let length = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+            let (dx, dy) = ((bx - ax) / length, (by - ay) / length);
+            let (x, y) = ((ax + bx) * 0.5 - dy * 0.5, (ay + by) * 0.5 + dx * 0.5);
+            assert_eq!(map.province_at(x.floor() as u32, y.floor() as u32), border.right, "{:?}", border);
+        }
+        // The map's edge splits at the three corners where the inner borders reach it
+        assert_eq!(outlines.borders.len(), 6);
+        for outline in &outlines.provinces {
+            assert_eq!(outline.polygons.len(), 1);
+            assert!(outline.polygons[0].holes.is_empty());
+            assert!(ring_area(&outline.polygons[0].exterior) > 0.0);
+        }
+    }
+
+    #[test]
+    fn islands_are_closed_loops_and_holes_of_what_surrounds_them() {
+        let map = two_provinces_and_an_island();
+        let outlines = Outlines::trace(&map, 0.0);
+        let island = outlines.borders.iter().find(|b| b.left == Some(2) || b.right == Some(2)).unwrap();
+        assert_eq!(island.points.len(), 5);
+        assert_eq!(island.points[0], island.points[4]);
+        assert_eq!((island.left.unwrap().min(island.right.unwrap()), island.left.unwrap().max(island.right.unwrap())), (0, 2));
+
+        let red = &outlines.provinces[0];
+        assert_eq!(red.polygons.len(), 1);
+        assert_eq!(red.polygons[0].holes.len(), 1);
+        assert!(ring_area(&red.polygons[0].exterior) > 0.0);
+        assert!(ring_area(&red.polygons[0].holes[0]) < 0.0);
+        assert_eq!(ring_area(&red.polygons[0].holes[0]), -ring_area(&outlines.provinces[2].polygons[0].exterior));
+    }
+
+    #[test]
+    fn simplification_stays_within_the_tolerance() {
+        let stairs: Vec<Point> = (0..9).map(|i| [((i + 1) / 2) as f32, (i / 2) as f32]).collect();
+        assert_eq!(simplify(&stairs, 1.0), vec![[0.0, 0.0], [4.0, 4.0]]);
+        let kept = simplify(&stairs, 0.5);
+        assert!(kept.len() > 2 && kept.len() < stairs.len());
+        for point in &stairs {
+            let nearest = kept.windows(2).map(|w| segment_distance(*point, w[0], w[1])).fold(f32::MAX, f32::min);
+            assert!(nearest <= 0.5, "{:?}", point);
+        }
+        // Zero tolerance only drops collinear points
+        assert_eq!(simplify(&[[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 1.0]], 0.0), vec![[0.0, 0.0], [2.0, 0.0], [2.0, 1.0]]);
+        // Loops keep at least a triangle
+        let square = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 1.0], [2.0, 2.0], [1.0, 2.0], [0.0, 2.0], [0.0, 1.0], [0.0, 0.0]];
+        let loop_kept = simplify(&square, 10.0);
+        assert!(loop_kept.len() >= 4 && loop_kept[0] == loop_kept[loop_kept.len() - 1], "{:?}", loop_kept);
+    }
+
+    #[test]
+    fn svg_fills_provinces_and_strokes_inner_borders() {
+        let map = two_provinces_and_an_island();
+        let svg = Outlines::trace(&map, 0.0).to_svg(&map, 0.5);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="6" viewBox="0 0 8 6">"#));
+        let red = svg.lines().find(|l| l.contains(r##"fill="#ff0000""##)).unwrap();
+        // Exterior and hole
+        assert_eq!(red.matches('M').count(), 2);
+        assert!(svg.contains(r##"fill="#00ff00""##) && svg.contains(r##"fill="#0000ff""##));
+        // The red/blue line and the island's ring
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(r#"stroke-width="0.5""#));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn geojson_flips_y_and_follows_the_right_hand_rule() {
+        let map = three_provinces();
+        let geojson = Outlines::trace(&map, 0.0).to_geojson(&map);
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 3);
+        let blue = &features[1];
+        assert_eq!(blue["properties"]["color"], json!([0, 0, 255]));
+        assert_eq!(blue["geometry"]["type"], "Polygon");
+        let ring = blue["geometry"]["coordinates"][0].as_array().unwrap();
+        assert_eq!(ring.first(), ring.last());
+        // Blue is the top of the map, so the top of the y-up coordinates
+        assert!(ring.iter().all(|p| p[1].as_f64().unwrap() >= 2.0 && p[0].as_f64().unwrap() >= 3.0), "{:?}", ring);
+        assert_eq!(geojson_area(ring), 2.0 * 6.0);
+
+        let map = two_provinces_and_an_island();
+        let geojson = Outlines::trace(&map, 0.0).to_geojson(&map);
+        let red = &geojson["features"][0]["geometry"]["coordinates"];
+        assert!(geojson_area(red[0].as_array().unwrap()) > 0.0);
+        assert_eq!(geojson_area(red[1].as_array().unwrap()), -2.0 * 4.0);
+    }
+
+    #[test]
+    fn fill_mesh_covers_every_province_around_its_holes() {
+        let map = two_provinces_and_an_island();
+        let mesh = Outlines::trace(&map, 0.0).fill_mesh();
+        assert_eq!(mesh.positions.len(), mesh.provinces.len());
+        for province in &map.provinces {
+            assert_eq!(area(&mesh, province.id), province.area as f32);
+        }
+    }
+
+    #[test]
+    fn border_mesh_only_follows_borders_between_provinces() {
+        let mesh = Outlines::trace(&two_provinces_and_an_island(), 0.0).border_mesh(1.0);
+        // The red/blue line and the island's ring, each stretched half a
+        // pixel past its ends on both sides
+        let total: f32 = mesh.indices.chunks(3).map(|t| cross(mesh.positions[t[0] as usize], mesh.positions[t[1] as usize], mesh.positions[t[2] as usize]).abs() / 2.0).sum();
+        assert!(total >= 6.0 + 8.0, "{}", total);
+        assert!(mesh.positions.iter().all(|p| p[1] >= -0.5 && p[1] <= 6.5));
+    }
+
+    #[test]
+    fn mesh_data_interleaves_position_and_province() {
+        let mesh = OutlineMesh { positions: vec![[0.0, 0.0], [4.0, 0.0], [0.0, 2.0]], provinces: vec![3, 3, 3], indices: vec![0, 1, 2] };
+        let data = mesh.to_mesh_data();
+        assert_eq!(data.attributes, vec![2, 1]);
+        assert_eq!(data.vertices, vec![0.0, 0.0, 3.0, 4.0, 0.0, 3.0, 0.0, 2.0, 3.0]);
+        assert_eq!(data.indices, vec![0, 1, 2]);
+    }
+}
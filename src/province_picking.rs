@@ -0,0 +1,256 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use bevy::window::PrimaryWindow;
+use std::path::Path;
+use std::sync::Arc;
+use crate::bevy_plugin::GlslMaterial;
+use crate::province_map::{ProvinceId, ProvinceMap};
+
+// Fragment shader drawing the hovered and selected outlines, relative to the assets folder
+pub const HIGHLIGHT_SHADER: &str = "shadercode/province_highlight.glsl";
+
+// A quad showing a province map. Its transform scales a unit quad to the
+// map's size in world units; picking works at any translation and scale.
+#[derive(Component, Clone)]
+pub struct ProvinceMapQuad {
+    pub map: Arc<ProvinceMap>,
+}
+
+impl ProvinceMapQuad {
+    // Map pixel under a world position, None off the map
+    pub fn pixel_at(&self, transform: &GlobalTransform, world: Vec2) -> Option<(u32, u32)> {
+        // Unit quad space, (-0.5, -0.5) bottom left to (0.5, 0.5) top right
+        let local = transform.affine().inverse().transform_point3(world.extend(0.0));
+        let x = (local.x + 0.5) * self.map.width as f32;
+        let y = (0.5 - local.y) * self.map.height as f32;
+        if x < 0.0 || y < 0.0 || x >= self.map.width as f32 || y >= self.map.height as f32 {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+}
+
+// Hovered and selected province, shared by every map quad
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProvinceSelection {
+    pub hovered: Option<(Entity, ProvinceId)>,
+    pub selected: Option<(Entity, ProvinceId)>,
+}
+
+// Sent when the province under the cursor changes; None when the cursor
+// left every map
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ProvinceHovered {
+    pub hovered: Option<(Entity, ProvinceId)>,
+}
+
+// Sent when a mouse button is pressed over a province
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ProvinceClicked {
+    pub map: Entity,
+    pub province: ProvinceId,
+    pub button: MouseButton,
+}
+
+// Outline drawn over a map quad, child of the quad
+#[derive(Component)]
+pub struct ProvinceHighlight {
+    // Width of the outline in map pixels, at most 4
+    pub width: f32,
+}
+
+// The ID map as a GPU image, sampled without filtering so IDs stay exact
+pub fn id_map_image(map: &ProvinceMap) -> Image {
+    let rgba = image::DynamicImage::ImageRgb8(map.id_image()).to_rgba8();
+    let mut image = Image::new(
+        Extent3d { width: map.width, height: map.height, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        rgba.into_raw(),
+        TextureFormat::Rgba8Unorm,
+    );
+    image.sampler = ImageSampler::nearest();
+    image
+}
+
+// Spawns a pickable quad showing `image` (the province image itself or any
+// map mode rendering of it), with a highlight outline on top
+pub fn spawn_province_map(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    images: &mut Assets<Image>,
+    materials: &mut Assets<GlslMaterial>,
+    asset_server: &AssetServer,
+    map: Arc<ProvinceMap>,
+    image: Handle<Image>,
+) -> Entity {
+    let quad = meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE)));
+    let ids = images.add(id_map_image(&map));
+    let mut highlight = GlslMaterial::new(asset_server.load(HIGHLIGHT_SHADER), Some(ids));
+    highlight.params = Some(Vec4::new(-1.0, -1.0, 2.0, 0.0));
+    let size = Vec3::new(map.width as f32, map.height as f32, 1.0);
+
+    commands
+        .spawn((
+            SpriteBundle {
+                texture: image,
+                sprite: Sprite { custom_size: Some(Vec2::ONE), ..default() },
+                transform: Transform::from_scale(size),
+                ..default()
+            },
+            ProvinceMapQuad { map },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                bevy::sprite::MaterialMesh2dBundle {
+                    mesh: quad.into(),
+                    material: materials.add(highlight),
+                    // Just above the map, in the parent's unit quad space
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    ..default()
+                },
+                ProvinceHighlight { width: 2.0 },
+            ));
+        })
+        .id()
+}
+
+// Finds the province under the cursor and sends hover and click events
+fn pick_provinces(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    maps: Query<(Entity, &ProvinceMapQuad, &GlobalTransform)>,
+    buttons: Res<Input<MouseButton>>,
+    mut selection: ResMut<ProvinceSelection>,
+    mut hovered_events: EventWriter<ProvinceHovered>,
+    mut clicked_events: EventWriter<ProvinceClicked>,
+) {
+    let cursor = windows.get_single().ok().and_then(|w| w.cursor_position());
+    let mut hovered = None;
+    if let Some(cursor) = cursor {
+        for (camera, camera_transform) in cameras.iter().filter(|(c, _)| c.is_active) {
+            let Some(world) = camera.viewport_to_world_2d(camera_transform, cursor) else { continue };
+            // Topmost map wins where quads overlap
+            hovered = maps
+                .iter()
+                .filter_map(|(entity, quad, transform)| {
+                    let (x, y) = quad.pixel_at(transform, world)?;
+                    Some((transform.translation().z, entity, quad.map.province_at(x, y)?))
+                })
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, entity, province)| (entity, province));
+            if hovered.is_some() {
+                break;
+            }
+        }
+    }
+
+    if hovered != selection.hovered {
+        selection.hovered = hovered;
+        hovered_events.send(ProvinceHovered { hovered });
+    }
+    if let Some((map, province)) = hovered {
+        for button in buttons.get_just_pressed() {
+            clicked_events.send(ProvinceClicked { map, province, button: *button });
+            if *button == MouseButton::Left {
+                selection.selected = Some((map, province));
+            }
+        }
+    } else if buttons.just_pressed(MouseButton::Left) {
+        // Clicking off the maps clears the selection
+        selection.selected = None;
+    }
+}
+
+// Passes the hovered and selected IDs of each map to its outline material
+fn update_highlights(
+    selection: Res<ProvinceSelection>,
+    highlights: Query<(&Parent, &ProvinceHighlight, &Handle<GlslMaterial>)>,
+    mut materials: ResMut<Assets<GlslMaterial>>,
+) {
+    if !selection.is_changed() {
+        return;
+    }
+    let id_on = |pick: Option<(Entity, ProvinceId)>, map: Entity| match pick {
+        Some((entity, province)) if entity == map => province as f32,
+        _ => -1.0,
+    };
+    for (parent, highlight, handle) in highlights.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.params = Some(Vec4::new(
+                id_on(selection.hovered, parent.get()),
+                id_on(selection.selected, parent.get()),
+                highlight.width,
+                0.0,
+            ));
+        }
+    }
+}
+
+// Picking for province map quads; needs ShaderPipelinePlugin for the outlines
+pub struct ProvincePickingPlugin;
+
+impl Plugin for ProvincePickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProvinceSelection>()
+            .add_event::<ProvinceHovered>()
+            .add_event::<ProvinceClicked>()
+            .add_systems(Update, (pick_provinces, update_highlights).chain());
+    }
+}
+
+// Prints hover and click events, for the demo
+fn log_province_events(
+    mut hovered: EventReader<ProvinceHovered>,
+    mut clicked: EventReader<ProvinceClicked>,
+    maps: Query<&ProvinceMapQuad>,
+) {
+    for event in hovered.read() {
+        if let Some((_, province)) = event.hovered {
+            println!("hovered province {}", province);
+        }
+    }
+    for event in clicked.read() {
+        let Ok(quad) = maps.get(event.map) else { continue };
+        let province = &quad.map.provinces[event.province as usize];
+        println!(
+            "{:?} click on province {} (color {:?}, area {}, {} neighbours)",
+            event.button,
+            province.id,
+            province.color,
+            province.area,
+            quad.map.neighbours(province.id).count()
+        );
+    }
+}
+
+// Opens a Bevy window showing the province image `image` (relative to the
+// assets folder) with hover and selection outlines
+pub fn run_demo(assets: &str, image: &str, cache_dir: &Path) -> Result<(), String> {
+    let map = Arc::new(ProvinceMap::load_or_build(&format!("{}/{}", assets, image), cache_dir)?);
+    let image = image.to_string();
+    App::new()
+        .add_plugins((
+            DefaultPlugins.set(AssetPlugin {
+                watch_for_changes_override: Some(true),
+                ..default()
+            }),
+            crate::bevy_plugin::ShaderPipelinePlugin,
+            ProvincePickingPlugin,
+        ))
+        .add_systems(
+            Startup,
+            move |mut commands: Commands,
+                  asset_server: Res<AssetServer>,
+                  mut meshes: ResMut<Assets<Mesh>>,
+                  mut images: ResMut<Assets<Image>>,
+                  mut materials: ResMut<Assets<GlslMaterial>>| {
+                commands.spawn(Camera2dBundle::default());
+                let texture = asset_server.load(image.clone());
+                spawn_province_map(&mut commands, &mut meshes, &mut images, &mut materials, &asset_server, map.clone(), texture);
+            },
+        )
+        .add_systems(Update, log_province_events)
+        .run();
+    Ok(())
+}
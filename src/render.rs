@@ -0,0 +1,94 @@
+use image::RgbaImage;
+use std::path::Path;
+use crate::assets::{read_source, resolve_path};
+use crate::backend::{self, BackendKind, Binding, BufferKind, Pass, VertexAttrib, VertexLayout};
+use crate::cli::RenderArgs;
+use crate::shader_pipeline::debug;
+use crate::translate::{self, BindingKind};
+
+fn as_bytes<T: Copy, const N: usize>(values: [T; N], to_bytes: fn(T) -> [u8; 4]) -> Vec<u8> {
+    values.into_iter().flat_map(to_bytes).collect()
+}
+
+// Texture and sampler bindings of a fragment shader, sorted. Loose uniforms
+// and blocks are refused since nothing here would fill them.
+fn texture_bindings(path: &Path, source: &str) -> Result<(Vec<u32>, Vec<u32>), String> {
+    let translated = translate::translate(&path.display().to_string(), source, naga::ShaderStage::Fragment)
+        .map_err(|diagnostics| diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))?;
+    let (mut textures, mut samplers) = (Vec::new(), Vec::new());
+    for info in &translated.bindings {
+        match info.kind {
+            BindingKind::Texture => textures.push(info.binding),
+            BindingKind::Sampler => samplers.push(info.binding),
+            _ => return Err(format!("{}: `{}` needs a value, only textures can be given", path.display(), info.name)),
+        }
+    }
+    Ok((textures, samplers))
+}
+
+// `shaders render`: draws the fragment shader over a quad covering the image,
+// on the backend picked with --backend, and saves the result
+pub fn run(args: &RenderArgs) -> Result<(), String> {
+    let roots = [Path::new("."), args.shaders.as_path()];
+    let fragment_path = resolve_path(&args.fragment, &roots)?;
+    let vertex_path = resolve_path(Path::new("screen_quad.glsl"), &roots)?;
+    let fragment = read_source(&fragment_path)?;
+    let vertex = read_source(&vertex_path)?;
+    let images = args
+        .textures
+        .iter()
+        .map(|path| image::open(path).map(|image| image.to_rgba8()).map_err(|e| format!("{}: {}", path.display(), e)))
+        .collect::<Result<Vec<RgbaImage>, _>>()?;
+    let (width, height) = args.size.or(images.first().map(RgbaImage::dimensions)).unwrap_or((512, 512));
+    let (texture_slots, sampler_slots) = texture_bindings(&fragment_path, &fragment)?;
+    if texture_slots.len() != images.len() {
+        return Err(format!("{}: reads {} textures, {} given", fragment_path.display(), texture_slots.len(), images.len()));
+    }
+
+    // The GL backend draws with the context of a hidden window, kept open
+    // until the image is read back
+    let _context = if args.backend == BackendKind::Gl {
+        let mut glfw = debug::init_glfw()?;
+        let (window, events) = debug::create_window(&mut glfw, (3, 3), (64, 64), "shaders render", false)?;
+        Some((glfw, window, events))
+    } else {
+        None
+    };
+    let mut backend = backend::create_backend(args.backend)?;
+    println!("rendering on {}", backend.name());
+
+    let program = backend.create_program(&vertex, &fragment, &VertexLayout {
+        stride: 4 * std::mem::size_of::<f32>() as u32,
+        attribs: vec![
+            VertexAttrib { location: 0, components: 2, offset: 0 },
+            VertexAttrib { location: 1, components: 2, offset: 2 * std::mem::size_of::<f32>() as u32 },
+        ],
+    })?;
+    // Same quad as ScreenQuad, its v matching how the backend stores rows
+    let (top, bottom) = if backend.v_origin_top() { (0.0, 1.0) } else { (1.0, 0.0) };
+    let vertices = [-1.0, -1.0, 0.0, bottom, -1.0, 1.0, 0.0, top, 1.0, 1.0, 1.0, top, 1.0, -1.0, 1.0, bottom];
+    let vertex_buffer = backend.create_buffer(BufferKind::Vertex, &as_bytes(vertices, f32::to_ne_bytes));
+    let index_buffer = backend.create_buffer(BufferKind::Index, &as_bytes([0, 2, 1, 0, 3, 2], u32::to_ne_bytes));
+
+    let mut bindings: Vec<Binding> = sampler_slots.iter().map(|&binding| Binding::Sampler { binding }).collect();
+    for (&binding, image) in texture_slots.iter().zip(&images) {
+        let texture = backend.create_texture(image.width(), image.height(), Some(image.as_raw()));
+        bindings.push(Binding::Texture { binding, texture });
+    }
+    let target = backend.create_texture(width, height, None);
+    backend.run_pass(&Pass {
+        program,
+        vertex_buffer,
+        index_buffer,
+        index_count: 6,
+        bindings: &bindings,
+        target: Some(target),
+        clear_color: Some([0.0, 0.0, 0.0, 1.0]),
+    })?;
+
+    let pixels = backend.read_texture(target)?;
+    let image = RgbaImage::from_raw(width, height, pixels).ok_or("read back fewer pixels than the image holds")?;
+    image.save(&args.out).map_err(|e| format!("{}: {}", args.out.display(), e))?;
+    println!("wrote {}", args.out.display());
+    Ok(())
+}
@@ -1,44 +1,48 @@
 use gl::types::*;
 use std::ffi::c_void;
+use super::gl_device::{real_device, Device};
 
 pub struct EBO {
     // Reference id of Elements Buffer Object
     pub id: GLuint,
+    // Device the calls are issued on
+    gl: Device,
 }
 
 impl EBO {
     // Constructor that generates a Elements Buffer Object and links it to indices
     pub fn new(indicies: &[GLuint]) -> Self {
-        let mut ebo = EBO { id: 0 };
-        unsafe {
-            gl::GenBuffers(1, &mut ebo.id);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo.id);
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, 
-                (indicies.len() * std::mem::size_of::<GLuint>()) as isize,
-                indicies.as_ptr() as *const std::ffi::c_void, 
-                gl::STATIC_DRAW);
-        }
-        ebo
+        Self::with_device(real_device(), indicies)
+    }
+
+    // Same as new, but issues its calls on the given device
+    pub fn with_device(gl: Device, indicies: &[GLuint]) -> Self {
+        let id = gl.gen_buffer();
+        gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, id);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(indicies.as_ptr() as *const u8, std::mem::size_of_val(indicies))
+        };
+        gl.buffer_data(gl::ELEMENT_ARRAY_BUFFER, bytes, gl::STATIC_DRAW);
+        EBO { id, gl }
     }
 
     // Bind the EBO
     pub fn bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
-        }
+        self.gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
     }
 
     // Unbind the EBO
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
-        }
+        self.gl.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+    }
+
+    // Names the EBO in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::BUFFER, self.id, name);
     }
 
     // Deletes the EBO
     pub fn delete(&self) {
-        unsafe {
-            gl::DeleteBuffers(1, &self.id);
-        }
+        self.gl.delete_buffer(self.id);
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,7 @@ use std::ffi::{CString};
 use std::ptr;
 use std::fs;
 use std::io;
+use super::gl_device::{real_device, Device, GlDevice};
 
 // Read file contents into a String
 fn read_file_contents(filename: &str) -> Result<String, io::Error> {
@@ -10,61 +11,30 @@ fn read_file_contents(filename: &str) -> Result<String, io::Error> {
 }
 
 // Check shader compilation errors
-fn check_shader_compile_errors(shader: GLuint, shader_type: &str) {
-    let mut success = gl::FALSE as GLint;
-    unsafe {
-        /*
-        When calling C functions that populate a buffer with a null-terminated string, 
-        Rust needs to provide a buffer with enough space for the string content plus the null terminator '\0'.
-        */
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1); // subtract 1 to skip the trailing null character
-        
-        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-
-        if success != gl::TRUE as i32 {
-            gl::GetShaderInfoLog(
-                shader,
-                512,
-                ptr::null_mut(),
-                info_log.as_mut_ptr() as *mut GLchar
-            );
-
-            let error_message = String::from_utf8_lossy(&info_log);
-            println!(
-                "ERROR::SHADER_COMPILATION_ERROR of type: {}\n{}",
-                shader_type, error_message
-            );
-        }
+fn check_shader_compile_errors(gl: &dyn GlDevice, shader: GLuint, shader_type: &str) {
+    let success = gl.get_shader_iv(shader, gl::COMPILE_STATUS);
+
+    if success != gl::TRUE as i32 {
+        let error_message = gl.get_shader_info_log(shader);
+        log::error!(target: "gl", "{} shader failed to compile:\n{}", shader_type, error_message.trim_end());
     }
 }
 
 // Check shader program linking errors
-fn check_program_link_errors(program: GLuint) {
-    let mut success = gl::FALSE as GLint;
-    unsafe {
-        let mut info_log = Vec::with_capacity(512);
-        info_log.set_len(512 - 1); // subtract 1 to skip the trailing null character
-
-        gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
-
-        if success != gl::TRUE as GLint {
-            gl::GetProgramInfoLog(
-                program,
-                512,
-                ptr::null_mut(),
-                info_log.as_mut_ptr() as *mut GLchar
-            );
-
-            let error_message = String::from_utf8_lossy(&info_log);
-            println!("ERROR::PROGRAM_LINKING_ERROR\n{}", error_message);
-        }
+fn check_program_link_errors(gl: &dyn GlDevice, program: GLuint) {
+    let success = gl.get_program_iv(program, gl::LINK_STATUS);
+
+    if success != gl::TRUE as GLint {
+        let error_message = gl.get_program_info_log(program);
+        log::error!(target: "gl", "program failed to link:\n{}", error_message.trim_end());
     }
 }
 
 pub struct Shader {
     // Reference id of the Shader Program
     pub id: GLuint,
+    // Device the calls are issued on
+    gl: Device,
 }
 
 impl Shader {
@@ -74,67 +44,84 @@ impl Shader {
         let vertex_code = read_file_contents(vertex_file).map_err(|e| e.to_string())?;
         let fragment_code = read_file_contents(fragment_file).map_err(|e| e.to_string())?;
 
-        // Convert the shader source strings into CString
-        let vertex_source = CString::new(vertex_code).expect("CString conversion failed");
-        let fragment_source = CString::new(fragment_code).expect("CString conversion failed");
+        Ok(Self::from_source(real_device(), &vertex_code, &fragment_code))
+    }
 
+    // Builds the Shader Program from in-memory sources on the given device
+    pub fn from_source(gl: Device, vertex_code: &str, fragment_code: &str) -> Self {
         // Create Vertex Shader Object and get its reference
-        let vertex_shader = unsafe {
-            let shader = gl::CreateShader(gl::VERTEX_SHADER);
-            // Attach Vertex Shader source to the Vertex Shader Object
-            gl::ShaderSource(shader, 1, &vertex_source.as_ptr(), std::ptr::null());
-            // Compile the Vertex Shader into machine code
-            gl::CompileShader(shader);
-            // Check for compilation errors
-            check_shader_compile_errors(shader, "VERTEX");
-            shader
-        };
+        let vertex_shader = gl.create_shader(gl::VERTEX_SHADER);
+        // Attach Vertex Shader source to the Vertex Shader Object
+        gl.shader_source(vertex_shader, vertex_code);
+        // Compile the Vertex Shader into machine code
+        gl.compile_shader(vertex_shader);
+        // Check for compilation errors
+        check_shader_compile_errors(gl.as_ref(), vertex_shader, "vertex");
 
         // Create Fragment Shader Object and get its reference
-        let fragment_shader = unsafe {
-            let shader = gl::CreateShader(gl::FRAGMENT_SHADER);
-            // Attach Fragment Shader source to the Fragment Shader Object
-            gl::ShaderSource(shader, 1, &fragment_source.as_ptr(), std::ptr::null());
-            // Compile the Fragment Shader into machine code
-            gl::CompileShader(shader);
-            // Check for compilation errors
-            check_shader_compile_errors(shader, "FRAGMENT");
-            shader
-        };
+        let fragment_shader = gl.create_shader(gl::FRAGMENT_SHADER);
+        // Attach Fragment Shader source to the Fragment Shader Object
+        gl.shader_source(fragment_shader, fragment_code);
+        // Compile the Fragment Shader into machine code
+        gl.compile_shader(fragment_shader);
+        // Check for compilation errors
+        check_shader_compile_errors(gl.as_ref(), fragment_shader, "fragment");
 
         // Create Shader Program Object and get its reference
-        let program_id = unsafe {
-            let program = gl::CreateProgram();
-            // Attach the Vertex and Fragment Shaders to the Shader Program
-            gl::AttachShader(program, vertex_shader);
-            gl::AttachShader(program, fragment_shader);
-            // Link all shaders into a Shader Program
-            gl::LinkProgram(program);
-            // Check for linking errors
-            check_program_link_errors(program);
-            program
-        };
+        let program_id = gl.create_program();
+        // Attach the Vertex and Fragment Shaders to the Shader Program
+        gl.attach_shader(program_id, vertex_shader);
+        gl.attach_shader(program_id, fragment_shader);
+        // Link all shaders into a Shader Program
+        gl.link_program(program_id);
+        // Check for linking errors
+        check_program_link_errors(gl.as_ref(), program_id);
 
         // Delete the now useless Vertex and Fragment Shader Objects
-        unsafe {
-            gl::DeleteShader(vertex_shader);
-            gl::DeleteShader(fragment_shader);
-        }
+        gl.delete_shader(vertex_shader);
+        gl.delete_shader(fragment_shader);
+
+        Self { id: program_id, gl }
+    }
+
+    // Builds a compute program from in-memory source; needs a GL 4.3 context
+    pub fn compute_from_source(gl: Device, compute_code: &str) -> Self {
+        let compute_shader = gl.create_shader(gl::COMPUTE_SHADER);
+        gl.shader_source(compute_shader, compute_code);
+        gl.compile_shader(compute_shader);
+        check_shader_compile_errors(gl.as_ref(), compute_shader, "compute");
+
+        let program_id = gl.create_program();
+        gl.attach_shader(program_id, compute_shader);
+        gl.link_program(program_id);
+        check_program_link_errors(gl.as_ref(), program_id);
+        gl.delete_shader(compute_shader);
 
-        Ok(Self { id: program_id })
+        Self { id: program_id, gl }
+    }
+
+    // Device the program was created on
+    pub fn device(&self) -> &Device {
+        &self.gl
+    }
+
+    // Get the location of a uniform in the Shader Program
+    pub fn uniform_location(&self, name: &str) -> GLint {
+        self.gl.get_uniform_location(self.id, name)
     }
 
     // Activates Shader Program
     pub fn activate(&self) {
-        unsafe {
-            gl::UseProgram(self.id);
-        }
+        self.gl.use_program(self.id);
+    }
+
+    // Names the program in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::PROGRAM, self.id, name);
     }
 
     // Deletes Shader Program 
     pub fn delete(&self) {
-        unsafe {
-            gl::DeleteProgram(self.id);
-        }
+        self.gl.delete_program(self.id);
     }
-}
\ No newline at end of file
+}
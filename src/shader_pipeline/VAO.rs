@@ -1,50 +1,52 @@
 use gl::types::*;
 use crate::VBO;
 use std::ffi::c_void;
+use super::gl_device::{real_device, Device};
 
 pub struct VAO {
     // id reference for the Vertex Array Object
     pub id: GLuint,
+    // Device the calls are issued on
+    gl: Device,
 }
 
 impl VAO {
     // Constructor
     pub fn new() -> Self {
-        let mut vao = VAO { id: 0 };
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao.id);
-        }
-        vao
+        Self::with_device(real_device())
+    }
+
+    // Constructor that issues its calls on the given device
+    pub fn with_device(gl: Device) -> Self {
+        let id = gl.gen_vertex_array();
+        VAO { id, gl }
     }
 
     // Bind the VAO
     pub fn bind(&self) {
-        unsafe{
-            gl::BindVertexArray(self.id);
-        }
+        self.gl.bind_vertex_array(self.id);
     }
 
     // Unbind the VAO
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindVertexArray(0);
-        }
+        self.gl.bind_vertex_array(0);
+    }
+
+    // Names the VAO in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::VERTEX_ARRAY, self.id, name);
     }
 
     // Deletes the VAO
     pub fn delete(&self) {
-        unsafe {
-            gl::DeleteVertexArrays(1, &self.id);
-        }
+        self.gl.delete_vertex_array(self.id);
     }
 
     // Links a VBO to the VAO using a certain layout
     pub fn link_attrib(&self, vbo: &VBO, layout: GLuint, num_components: GLuint, vbo_type: GLenum, stride: GLsizei, offset: *const std::ffi::c_void) {
         vbo.bind();
-        unsafe{
-            gl::VertexAttribPointer(layout, num_components as i32, vbo_type, gl::FALSE, stride, offset); 
-            gl::EnableVertexAttribArray(layout);
-        }
+        self.gl.vertex_attrib_pointer(layout, num_components as i32, vbo_type, gl::FALSE, stride, offset as usize);
+        self.gl.enable_vertex_attrib_array(layout);
         vbo.unbind();
     }
 }
@@ -1,44 +1,48 @@
 use gl::types::*;
 use std::ffi::c_void;
+use super::gl_device::{real_device, Device};
 
 pub struct VBO {
     // Reference id for the Vertex Buffer Object
     pub id: GLuint,
+    // Device the calls are issued on
+    gl: Device,
 }
 
 impl VBO {
     // Constructor that generates a Vertex Buffer Object and links it to vertices
     pub fn new(vertices: &[GLfloat]) -> Self {
-        let mut vbo = VBO { id: 0 };
-        unsafe {
-            gl::GenBuffers(1, &mut vbo.id);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo.id);
-            gl::BufferData(gl::ARRAY_BUFFER, 
-                (vertices.len() * std::mem::size_of::<GLfloat>()) as isize, 
-                vertices.as_ptr() as *const std::ffi::c_void, 
-                gl::STATIC_DRAW);
-        }
-        vbo
+        Self::with_device(real_device(), vertices)
+    }
+
+    // Same as new, but issues its calls on the given device
+    pub fn with_device(gl: Device, vertices: &[GLfloat]) -> Self {
+        let id = gl.gen_buffer();
+        gl.bind_buffer(gl::ARRAY_BUFFER, id);
+        let bytes = unsafe {
+            std::slice::from_raw_parts(vertices.as_ptr() as *const u8, std::mem::size_of_val(vertices))
+        };
+        gl.buffer_data(gl::ARRAY_BUFFER, bytes, gl::STATIC_DRAW);
+        VBO { id, gl }
     }
 
     // Binds the VBO
     pub fn bind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
-        }
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, self.id);
     }
 
     // Unbinds the VBO
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        }
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    // Names the VBO in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::BUFFER, self.id, name);
     }
 
     // Deletes the VBO
     pub fn delete(&self) {
-        unsafe {
-            gl::DeleteBuffers(1, &self.id);
-        }
+        self.gl.delete_buffer(self.id);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,155 @@
+use gl::types::*;
+use image::{Rgba32FImage, RgbaImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use super::gl_device::Device;
+
+// GL returns the bottom row first, images start at the top. Empty rows
+// (a 0-wide framebuffer) have nothing to flip.
+fn flip_rows<T: Copy>(pixels: &[T], row: usize) -> Vec<T> {
+    if row == 0 {
+        return Vec::new();
+    }
+    pixels.chunks_exact(row).rev().flatten().copied().collect()
+}
+
+// Reads a framebuffer (0 for the window's back buffer, before swapping)
+// into RGBA8. Rows are packed tightly whatever the width.
+pub fn read_rgba(gl: &Device, framebuffer: GLuint, width: u32, height: u32) -> RgbaImage {
+    let row = width as usize * 4;
+    let mut pixels = vec![0u8; row * height as usize];
+    gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+    let alignment = gl.get_integer(gl::PACK_ALIGNMENT);
+    gl.pixel_store_i(gl::PACK_ALIGNMENT, 1);
+    gl.read_pixels(0, 0, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, &mut pixels);
+    gl.pixel_store_i(gl::PACK_ALIGNMENT, alignment);
+    gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+    RgbaImage::from_raw(width, height, flip_rows(&pixels, row)).expect("pixels sized for the image")
+}
+
+// Same as read_rgba keeping float values, for targets like gl::RGBA32F
+pub fn read_rgba_f32(gl: &Device, framebuffer: GLuint, width: u32, height: u32) -> Rgba32FImage {
+    let row = width as usize * 4;
+    let mut pixels = vec![0f32; row * height as usize];
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(pixels.as_mut_ptr() as *mut u8, std::mem::size_of_val(pixels.as_slice()))
+    };
+    gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+    gl.read_pixels(0, 0, width as i32, height as i32, gl::RGBA, gl::FLOAT, bytes);
+    gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+    Rgba32FImage::from_raw(width, height, flip_rows(&pixels, row)).expect("pixels sized for the image")
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("exr"))
+}
+
+// Saves a framebuffer, in the format of the path's extension: .exr keeps
+// float values, anything else is written from RGBA8
+pub fn save(gl: &Device, framebuffer: GLuint, width: u32, height: u32, path: &Path) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("{}: cannot save an empty {}x{} framebuffer", path.display(), width, height));
+    }
+    let result = if is_exr(path) {
+        read_rgba_f32(gl, framebuffer, width, height).save(path)
+    } else {
+        read_rgba(gl, framebuffer, width, height).save(path)
+    };
+    result.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// First `<prefix>_0001.<extension>` style path not taken yet in `dir`
+pub fn next_free_path(dir: &Path, prefix: &str, extension: &str) -> PathBuf {
+    (1..)
+        .map(|n| dir.join(format!("{}_{:04}.{}", prefix, n, extension)))
+        .find(|path| !path.exists())
+        .expect("some number is free")
+}
+
+// Saves the window's back buffer as the next screenshots/screenshot_NNNN.png;
+// call it after drawing and before swapping buffers
+pub fn screenshot(gl: &Device, size: (u32, u32)) -> Result<PathBuf, String> {
+    if size.0 == 0 || size.1 == 0 {
+        return Err(format!("cannot take a screenshot of an empty {}x{} window", size.0, size.1));
+    }
+    let dir = Path::new("screenshots");
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = next_free_path(dir, "screenshot", "png");
+    save(gl, 0, size.0, size.1, &path)?;
+    Ok(path)
+}
+
+// Numbered frames in a folder, frame_00000.png and up, ready for
+// `ffmpeg -framerate 60 -i frame_%05d.png`
+pub struct FrameSequence {
+    pub dir: PathBuf,
+    pub extension: String,
+    pub next: u32,
+}
+
+impl FrameSequence {
+    pub fn new(dir: impl Into<PathBuf>, extension: &str) -> Result<Self, String> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        Ok(FrameSequence { dir, extension: extension.to_string(), next: 0 })
+    }
+
+    pub fn save_frame(&mut self, gl: &Device, framebuffer: GLuint, width: u32, height: u32) -> Result<PathBuf, String> {
+        let path = self.dir.join(format!("frame_{:05}.{}", self.next, self.extension));
+        save(gl, framebuffer, width, height, &path)?;
+        self.next += 1;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn rows_are_flipped_top_to_bottom() {
+        assert_eq!(flip_rows(&[1, 2, 3, 4, 5, 6], 2), vec![5, 6, 3, 4, 1, 2]);
+        assert_eq!(flip_rows::<u8>(&[], 0), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn reads_are_byte_aligned_and_restore_the_alignment() {
+        let recording = RecordingGl::new();
+        let gl: Device = recording.clone();
+        // Set before the read, to be restored after it
+        gl.pixel_store_i(gl::PACK_ALIGNMENT, 2);
+        let image = read_rgba(&gl, 7, 3, 2);
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_eq!(
+            recording.calls()[1..],
+            vec![
+                GlCall::BindFramebuffer { target: gl::FRAMEBUFFER, id: 7 },
+                GlCall::PixelStorei { pname: gl::PACK_ALIGNMENT, param: 1 },
+                GlCall::ReadPixels { x: 0, y: 0, width: 3, height: 2, format: gl::RGBA, data_type: gl::UNSIGNED_BYTE },
+                GlCall::PixelStorei { pname: gl::PACK_ALIGNMENT, param: 2 },
+                GlCall::BindFramebuffer { target: gl::FRAMEBUFFER, id: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_framebuffers_are_not_saved() {
+        let recording = RecordingGl::new();
+        let gl: Device = recording.clone();
+        let dir = TempDir::new("capture-empty", &[]);
+        let path = dir.0.join("empty.png");
+        assert!(save(&gl, 0, 0, 4, &path).unwrap_err().contains("empty 0x4 framebuffer"));
+        assert!(screenshot(&gl, (4, 0)).is_err());
+        assert!(recording.calls().is_empty());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn free_paths_count_past_taken_ones() {
+        let dir = TempDir::new("capture-numbers", &[("shot_0001.png", ""), ("shot_0002.png", ""), ("shot_0004.png", "")]);
+        assert_eq!(next_free_path(&dir.0, "shot", "png"), dir.0.join("shot_0003.png"));
+        assert_eq!(next_free_path(&dir.0, "other", "exr"), dir.0.join("other_0001.exr"));
+    }
+}
@@ -0,0 +1,191 @@
+use gl::types::*;
+use glfw::Context;
+use std::ffi::{c_void, CStr};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::gl_device::Device;
+
+// Set once the debug callback is installed; labels and groups are skipped
+// without it
+static DEBUG_OUTPUT: AtomicBool = AtomicBool::new(false);
+// Set when every call has to poll glGetError instead
+static CHECK_ERRORS: AtomicBool = AtomicBool::new(false);
+
+// Runs a raw OpenGL call, then logs any error it raised along with the call's
+// text. Only polls glGetError on contexts without KHR_debug, where `enable`
+// turned the checks on; the debug callback reports errors otherwise.
+macro_rules! gl_check {
+    ($call:expr) => {{
+        let result = $call;
+        $crate::shader_pipeline::debug::check_errors(stringify!($call), file!(), line!());
+        result
+    }};
+}
+pub(crate) use gl_check;
+
+pub fn debug_output_enabled() -> bool {
+    DEBUG_OUTPUT.load(Ordering::Relaxed)
+}
+
+// Called by gl_check! after each call
+pub fn check_errors(call: &str, file: &str, line: u32) {
+    if !CHECK_ERRORS.load(Ordering::Relaxed) {
+        return;
+    }
+    loop {
+        let error = unsafe { gl::GetError() };
+        if error == gl::NO_ERROR {
+            break;
+        }
+        log::error!(target: "gl", "{} at {}:{}: {}", error_name(error), file, line, call);
+    }
+}
+
+fn error_name(error: GLenum) -> &'static str {
+    match error {
+        gl::INVALID_ENUM => "GL_INVALID_ENUM",
+        gl::INVALID_VALUE => "GL_INVALID_VALUE",
+        gl::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        gl::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        gl::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        gl::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        gl::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        _ => "unknown GL error",
+    }
+}
+
+fn source_name(source: GLenum) -> &'static str {
+    match source {
+        gl::DEBUG_SOURCE_API => "api",
+        gl::DEBUG_SOURCE_WINDOW_SYSTEM => "window system",
+        gl::DEBUG_SOURCE_SHADER_COMPILER => "shader compiler",
+        gl::DEBUG_SOURCE_THIRD_PARTY => "third party",
+        gl::DEBUG_SOURCE_APPLICATION => "application",
+        _ => "other",
+    }
+}
+
+fn type_name(message_type: GLenum) -> &'static str {
+    match message_type {
+        gl::DEBUG_TYPE_ERROR => "error",
+        gl::DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+        gl::DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+        gl::DEBUG_TYPE_PORTABILITY => "portability",
+        gl::DEBUG_TYPE_PERFORMANCE => "performance",
+        gl::DEBUG_TYPE_MARKER => "marker",
+        _ => "other",
+    }
+}
+
+// Errors are always logged as errors, other messages by their severity
+fn message_level(message_type: GLenum, severity: GLenum) -> log::Level {
+    match (message_type, severity) {
+        (gl::DEBUG_TYPE_ERROR, _) | (_, gl::DEBUG_SEVERITY_HIGH) => log::Level::Error,
+        (_, gl::DEBUG_SEVERITY_MEDIUM) => log::Level::Warn,
+        (_, gl::DEBUG_SEVERITY_LOW) => log::Level::Info,
+        _ => log::Level::Debug,
+    }
+}
+
+extern "system" fn debug_message(source: GLenum, message_type: GLenum, id: GLuint, severity: GLenum, length: GLsizei, message: *const GLchar, _user_param: *mut c_void) {
+    let text = if length >= 0 {
+        let bytes = unsafe { std::slice::from_raw_parts(message as *const u8, length as usize) };
+        String::from_utf8_lossy(bytes)
+    } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy()
+    };
+    log::log!(target: "gl", message_level(message_type, severity), "{} {} {}: {}", source_name(source), type_name(message_type), id, text.trim_end());
+}
+
+// KHR_debug is core from GL 4.3, and an extension on older contexts
+fn has_khr_debug() -> bool {
+    if !gl::DebugMessageCallback::is_loaded() {
+        return false;
+    }
+    let (mut major, mut minor, mut count) = (0, 0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+    (major, minor) >= (4, 3)
+        || (0..count as GLuint).any(|i| {
+            let name = unsafe { gl::GetStringi(gl::EXTENSIONS, i) };
+            !name.is_null() && unsafe { CStr::from_ptr(name as *const GLchar) }.to_bytes() == b"GL_KHR_debug"
+        })
+}
+
+// Sends the current context's debug messages to the `log` crate under the
+// "gl" target. Messages below log's max level are not even generated, so set
+// the level first. Without KHR_debug, debug builds poll glGetError after
+// every call instead. Returns whether the callback is installed.
+pub fn enable() -> bool {
+    if !has_khr_debug() {
+        CHECK_ERRORS.store(cfg!(debug_assertions), Ordering::Relaxed);
+        log::info!(target: "gl", "KHR_debug is not available, checking glGetError after calls in debug builds");
+        return false;
+    }
+    let severities = [
+        (gl::DEBUG_SEVERITY_HIGH, log::Level::Error),
+        (gl::DEBUG_SEVERITY_MEDIUM, log::Level::Warn),
+        (gl::DEBUG_SEVERITY_LOW, log::Level::Info),
+        (gl::DEBUG_SEVERITY_NOTIFICATION, log::Level::Debug),
+    ];
+    unsafe {
+        gl::Enable(gl::DEBUG_OUTPUT);
+        // Report messages from inside the call that caused them
+        gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        gl::DebugMessageCallback(Some(debug_message), ptr::null());
+        for (severity, level) in severities {
+            let enabled = if level <= log::max_level() { gl::TRUE } else { gl::FALSE };
+            gl::DebugMessageControl(gl::DONT_CARE, gl::DONT_CARE, severity, 0, ptr::null(), enabled);
+        }
+        // Our own groups would echo back on every push and pop
+        gl::DebugMessageControl(gl::DONT_CARE, gl::DEBUG_TYPE_PUSH_GROUP, gl::DONT_CARE, 0, ptr::null(), gl::FALSE);
+        gl::DebugMessageControl(gl::DONT_CARE, gl::DEBUG_TYPE_POP_GROUP, gl::DONT_CARE, 0, ptr::null(), gl::FALSE);
+    }
+    CHECK_ERRORS.store(false, Ordering::Relaxed);
+    DEBUG_OUTPUT.store(true, Ordering::Relaxed);
+    true
+}
+
+pub fn init_glfw() -> Result<glfw::Glfw, String> {
+    glfw::init(glfw::fail_on_errors).map_err(|e| format!("GLFW: {:?}", e))
+}
+
+// Opens a window with a core profile context of `version`, makes it current,
+// loads the GL functions and turns on debug output. Polling is left to the
+// caller.
+pub fn create_window(glfw: &mut glfw::Glfw, version: (u32, u32), size: (u32, u32), title: &str, visible: bool) -> Result<(glfw::PWindow, glfw::GlfwReceiver<(f64, glfw::WindowEvent)>), String> {
+    glfw.window_hint(glfw::WindowHint::ContextVersion(version.0, version.1));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    // Debug contexts report more problems, at some cost
+    glfw.window_hint(glfw::WindowHint::OpenGlDebugContext(cfg!(debug_assertions)));
+    glfw.window_hint(glfw::WindowHint::Visible(visible));
+    let (mut window, events) = glfw
+        .create_window(size.0, size.1, title, glfw::WindowMode::Windowed)
+        .ok_or("failed to create the GLFW window")?;
+    window.make_current();
+    gl::load_with(|s| window.get_proc_address(s) as *const _);
+    enable();
+    Ok((window, events))
+}
+
+// Names the calls made while it lives in debuggers like RenderDoc; the group
+// closes when it is dropped
+pub struct DebugGroup {
+    gl: Device,
+}
+
+impl DebugGroup {
+    pub fn new(gl: &Device, name: &str) -> Self {
+        gl.push_debug_group(name);
+        DebugGroup { gl: gl.clone() }
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        self.gl.pop_debug_group();
+    }
+}
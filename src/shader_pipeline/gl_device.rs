@@ -0,0 +1,1197 @@
+use gl::types::*;
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::ptr;
+use std::rc::Rc;
+use super::debug::{debug_output_enabled, gl_check};
+
+// Thin layer over the raw OpenGL calls used by the wrappers, so they can run
+// against a real context or against a recorder in unit tests. Methods mirror
+// the GL signatures, argument counts included.
+#[allow(clippy::too_many_arguments)]
+pub trait GlDevice {
+    // Vertex arrays
+    fn gen_vertex_array(&self) -> GLuint;
+    fn bind_vertex_array(&self, id: GLuint);
+    fn delete_vertex_array(&self, id: GLuint);
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, attrib_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize);
+    fn enable_vertex_attrib_array(&self, index: GLuint);
+
+    // Buffers
+    fn gen_buffer(&self) -> GLuint;
+    fn bind_buffer(&self, target: GLenum, id: GLuint);
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum);
+    fn delete_buffer(&self, id: GLuint);
+
+    // Textures
+    fn gen_texture(&self) -> GLuint;
+    fn active_texture(&self, unit: GLenum);
+    fn bind_texture(&self, target: GLenum, id: GLuint);
+    fn tex_parameter_i(&self, target: GLenum, pname: GLenum, param: GLint);
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]);
+    fn tex_sub_image_2d(&self, target: GLenum, level: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]);
+    fn generate_mipmap(&self, target: GLenum);
+    fn delete_texture(&self, id: GLuint);
+
+    // Shaders and programs
+    fn create_shader(&self, shader_type: GLenum) -> GLuint;
+    fn shader_source(&self, shader: GLuint, source: &str);
+    fn compile_shader(&self, shader: GLuint);
+    fn get_shader_iv(&self, shader: GLuint, pname: GLenum) -> GLint;
+    fn get_shader_info_log(&self, shader: GLuint) -> String;
+    fn delete_shader(&self, shader: GLuint);
+    fn create_program(&self) -> GLuint;
+    fn attach_shader(&self, program: GLuint, shader: GLuint);
+    fn link_program(&self, program: GLuint);
+    fn get_program_iv(&self, program: GLuint, pname: GLenum) -> GLint;
+    fn get_program_info_log(&self, program: GLuint) -> String;
+    fn use_program(&self, program: GLuint);
+    fn delete_program(&self, program: GLuint);
+
+    // Uniforms
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint;
+    fn uniform_1i(&self, location: GLint, value: GLint);
+    fn uniform_1f(&self, location: GLint, value: GLfloat);
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat);
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat);
+    fn uniform_4f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat);
+    // Column-major, like nalgebra stores its matrices
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[GLfloat; 16]);
+    fn bind_buffer_base(&self, target: GLenum, index: GLuint, buffer: GLuint);
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]);
+
+    // Framebuffers and drawing
+    fn gen_framebuffer(&self) -> GLuint;
+    fn bind_framebuffer(&self, target: GLenum, id: GLuint);
+    fn framebuffer_texture_2d(&self, target: GLenum, attachment: GLenum, tex_target: GLenum, texture: GLuint, level: GLint);
+    fn check_framebuffer_status(&self, target: GLenum) -> GLenum;
+    fn delete_framebuffer(&self, id: GLuint);
+    fn viewport(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei);
+    fn clear_color(&self, r: GLfloat, g: GLfloat, b: GLfloat, a: GLfloat);
+    fn clear(&self, mask: GLbitfield);
+    fn draw_elements(&self, mode: GLenum, count: GLsizei, index_type: GLenum, offset: usize);
+    fn read_pixels(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &mut [u8]);
+    fn pixel_store_i(&self, pname: GLenum, param: GLint);
+    fn get_integer(&self, pname: GLenum) -> GLint;
+    fn enable(&self, cap: GLenum);
+    fn disable(&self, cap: GLenum);
+    fn is_enabled(&self, cap: GLenum) -> bool;
+    fn blend_func(&self, src: GLenum, dst: GLenum);
+
+    // Compute (GL 4.3)
+    fn dispatch_compute(&self, x: GLuint, y: GLuint, z: GLuint);
+    fn bind_image_texture(&self, unit: GLuint, texture: GLuint, access: GLenum, format: GLenum);
+    fn memory_barrier(&self, barriers: GLbitfield);
+
+    // Queries
+    fn gen_query(&self) -> GLuint;
+    fn delete_query(&self, id: GLuint);
+    // Records the GPU time once the commands before it are done (gl::TIMESTAMP)
+    fn query_counter(&self, id: GLuint);
+    fn get_query_object_u64(&self, id: GLuint, pname: GLenum) -> u64;
+
+    // Debugging (KHR_debug), no-ops when debug output is off. `identifier`
+    // is the kind of object: gl::BUFFER, gl::TEXTURE, gl::PROGRAM, ...
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str);
+    fn push_debug_group(&self, message: &str);
+    fn pop_debug_group(&self);
+}
+
+// Shared handle to a device, held by every wrapper object
+pub type Device = Rc<dyn GlDevice>;
+
+// Device backed by the loaded OpenGL function pointers. Calls go through
+// gl_check!, so contexts without KHR_debug still report their errors.
+pub struct RealGl;
+
+// Returns the device that forwards to the current OpenGL context
+pub fn real_device() -> Device {
+    Rc::new(RealGl)
+}
+
+// Runs `draw` with straight alpha blending, leaving GL_BLEND as it found
+// it so passes drawn over each other don't undo one another's state
+pub fn blended(gl: &Device, draw: impl FnOnce()) {
+    let blending = gl.is_enabled(gl::BLEND);
+    gl.enable(gl::BLEND);
+    gl.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+    draw();
+    if !blending {
+        gl.disable(gl::BLEND);
+    }
+}
+
+impl GlDevice for RealGl {
+    fn gen_vertex_array(&self) -> GLuint {
+        let mut id = 0;
+        unsafe {
+            gl_check!(gl::GenVertexArrays(1, &mut id));
+        }
+        id
+    }
+
+    fn bind_vertex_array(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::BindVertexArray(id));
+        }
+    }
+
+    fn delete_vertex_array(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteVertexArrays(1, &id));
+        }
+    }
+
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, attrib_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize) {
+        unsafe {
+            gl_check!(gl::VertexAttribPointer(index, size, attrib_type, normalized, stride, offset as *const std::ffi::c_void));
+        }
+    }
+
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        unsafe {
+            gl_check!(gl::EnableVertexAttribArray(index));
+        }
+    }
+
+    fn gen_buffer(&self) -> GLuint {
+        let mut id = 0;
+        unsafe {
+            gl_check!(gl::GenBuffers(1, &mut id));
+        }
+        id
+    }
+
+    fn bind_buffer(&self, target: GLenum, id: GLuint) {
+        unsafe {
+            gl_check!(gl::BindBuffer(target, id));
+        }
+    }
+
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        unsafe {
+            gl_check!(gl::BufferData(target, data.len() as GLsizeiptr, data.as_ptr() as *const std::ffi::c_void, usage));
+        }
+    }
+
+    fn delete_buffer(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteBuffers(1, &id));
+        }
+    }
+
+    fn gen_texture(&self) -> GLuint {
+        let mut id = 0;
+        unsafe {
+            gl_check!(gl::GenTextures(1, &mut id));
+        }
+        id
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        unsafe {
+            gl_check!(gl::ActiveTexture(unit));
+        }
+    }
+
+    fn bind_texture(&self, target: GLenum, id: GLuint) {
+        unsafe {
+            gl_check!(gl::BindTexture(target, id));
+        }
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, pname: GLenum, param: GLint) {
+        unsafe {
+            gl_check!(gl::TexParameteri(target, pname, param));
+        }
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]) {
+        // An empty slice allocates storage without uploading anything
+        let pixels = if data.is_empty() { ptr::null() } else { data.as_ptr() as *const std::ffi::c_void };
+        unsafe {
+            gl_check!(gl::TexImage2D(target, level, internal_format, width, height, 0, format, data_type, pixels));
+        }
+    }
+
+    fn tex_sub_image_2d(&self, target: GLenum, level: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]) {
+        unsafe {
+            gl_check!(gl::TexSubImage2D(target, level, x, y, width, height, format, data_type, data.as_ptr() as *const std::ffi::c_void));
+        }
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        unsafe {
+            gl_check!(gl::GenerateMipmap(target));
+        }
+    }
+
+    fn delete_texture(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteTextures(1, &id));
+        }
+    }
+
+    fn create_shader(&self, shader_type: GLenum) -> GLuint {
+        unsafe { gl_check!(gl::CreateShader(shader_type)) }
+    }
+
+    fn shader_source(&self, shader: GLuint, source: &str) {
+        let c_source = CString::new(source).expect("CString conversion failed");
+        unsafe {
+            gl_check!(gl::ShaderSource(shader, 1, &c_source.as_ptr(), ptr::null()));
+        }
+    }
+
+    fn compile_shader(&self, shader: GLuint) {
+        unsafe {
+            gl_check!(gl::CompileShader(shader));
+        }
+    }
+
+    fn get_shader_iv(&self, shader: GLuint, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl_check!(gl::GetShaderiv(shader, pname, &mut value));
+        }
+        value
+    }
+
+    fn get_shader_info_log(&self, shader: GLuint) -> String {
+        let len = self.get_shader_iv(shader, gl::INFO_LOG_LENGTH);
+        if len <= 0 {
+            return String::new();
+        }
+        let mut buffer: Vec<u8> = vec![0; len as usize];
+        unsafe {
+            gl_check!(gl::GetShaderInfoLog(shader, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut GLchar));
+        }
+        // Drop the trailing null character
+        buffer.pop();
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    fn delete_shader(&self, shader: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteShader(shader));
+        }
+    }
+
+    fn create_program(&self) -> GLuint {
+        unsafe { gl_check!(gl::CreateProgram()) }
+    }
+
+    fn attach_shader(&self, program: GLuint, shader: GLuint) {
+        unsafe {
+            gl_check!(gl::AttachShader(program, shader));
+        }
+    }
+
+    fn link_program(&self, program: GLuint) {
+        unsafe {
+            gl_check!(gl::LinkProgram(program));
+        }
+    }
+
+    fn get_program_iv(&self, program: GLuint, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl_check!(gl::GetProgramiv(program, pname, &mut value));
+        }
+        value
+    }
+
+    fn get_program_info_log(&self, program: GLuint) -> String {
+        let len = self.get_program_iv(program, gl::INFO_LOG_LENGTH);
+        if len <= 0 {
+            return String::new();
+        }
+        let mut buffer: Vec<u8> = vec![0; len as usize];
+        unsafe {
+            gl_check!(gl::GetProgramInfoLog(program, len, ptr::null_mut(), buffer.as_mut_ptr() as *mut GLchar));
+        }
+        // Drop the trailing null character
+        buffer.pop();
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    fn use_program(&self, program: GLuint) {
+        unsafe {
+            gl_check!(gl::UseProgram(program));
+        }
+    }
+
+    fn delete_program(&self, program: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteProgram(program));
+        }
+    }
+
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let c_name = CString::new(name).expect("CString conversion failed");
+        unsafe { gl_check!(gl::GetUniformLocation(program, c_name.as_ptr())) }
+    }
+
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        unsafe {
+            gl_check!(gl::Uniform1i(location, value));
+        }
+    }
+
+    fn uniform_1f(&self, location: GLint, value: GLfloat) {
+        unsafe {
+            gl_check!(gl::Uniform1f(location, value));
+        }
+    }
+
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat) {
+        unsafe {
+            gl_check!(gl::Uniform2f(location, x, y));
+        }
+    }
+
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat) {
+        unsafe {
+            gl_check!(gl::Uniform3f(location, x, y, z));
+        }
+    }
+
+    fn uniform_4f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat) {
+        unsafe {
+            gl_check!(gl::Uniform4f(location, x, y, z, w));
+        }
+    }
+
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[GLfloat; 16]) {
+        unsafe {
+            gl_check!(gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr()));
+        }
+    }
+
+    fn bind_buffer_base(&self, target: GLenum, index: GLuint, buffer: GLuint) {
+        unsafe {
+            gl_check!(gl::BindBufferBase(target, index, buffer));
+        }
+    }
+
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        unsafe {
+            gl_check!(gl::BufferSubData(target, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const std::ffi::c_void));
+        }
+    }
+
+    fn gen_framebuffer(&self) -> GLuint {
+        let mut id = 0;
+        unsafe {
+            gl_check!(gl::GenFramebuffers(1, &mut id));
+        }
+        id
+    }
+
+    fn bind_framebuffer(&self, target: GLenum, id: GLuint) {
+        unsafe {
+            gl_check!(gl::BindFramebuffer(target, id));
+        }
+    }
+
+    fn framebuffer_texture_2d(&self, target: GLenum, attachment: GLenum, tex_target: GLenum, texture: GLuint, level: GLint) {
+        unsafe {
+            gl_check!(gl::FramebufferTexture2D(target, attachment, tex_target, texture, level));
+        }
+    }
+
+    fn check_framebuffer_status(&self, target: GLenum) -> GLenum {
+        unsafe { gl_check!(gl::CheckFramebufferStatus(target)) }
+    }
+
+    fn delete_framebuffer(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteFramebuffers(1, &id));
+        }
+    }
+
+    fn viewport(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        unsafe {
+            gl_check!(gl::Viewport(x, y, width, height));
+        }
+    }
+
+    fn clear_color(&self, r: GLfloat, g: GLfloat, b: GLfloat, a: GLfloat) {
+        unsafe {
+            gl_check!(gl::ClearColor(r, g, b, a));
+        }
+    }
+
+    fn clear(&self, mask: GLbitfield) {
+        unsafe {
+            gl_check!(gl::Clear(mask));
+        }
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: GLsizei, index_type: GLenum, offset: usize) {
+        unsafe {
+            gl_check!(gl::DrawElements(mode, count, index_type, offset as *const std::ffi::c_void));
+        }
+    }
+
+    fn read_pixels(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &mut [u8]) {
+        unsafe {
+            gl_check!(gl::ReadPixels(x, y, width, height, format, data_type, data.as_mut_ptr() as *mut std::ffi::c_void));
+        }
+    }
+
+    fn pixel_store_i(&self, pname: GLenum, param: GLint) {
+        unsafe {
+            gl_check!(gl::PixelStorei(pname, param));
+        }
+    }
+
+    fn get_integer(&self, pname: GLenum) -> GLint {
+        let mut value = 0;
+        unsafe {
+            gl_check!(gl::GetIntegerv(pname, &mut value));
+        }
+        value
+    }
+
+    fn enable(&self, cap: GLenum) {
+        unsafe {
+            gl_check!(gl::Enable(cap));
+        }
+    }
+
+    fn disable(&self, cap: GLenum) {
+        unsafe {
+            gl_check!(gl::Disable(cap));
+        }
+    }
+
+    fn is_enabled(&self, cap: GLenum) -> bool {
+        unsafe { gl_check!(gl::IsEnabled(cap)) == gl::TRUE }
+    }
+
+    fn blend_func(&self, src: GLenum, dst: GLenum) {
+        unsafe {
+            gl_check!(gl::BlendFunc(src, dst));
+        }
+    }
+
+    fn dispatch_compute(&self, x: GLuint, y: GLuint, z: GLuint) {
+        unsafe {
+            gl_check!(gl::DispatchCompute(x, y, z));
+        }
+    }
+
+    fn bind_image_texture(&self, unit: GLuint, texture: GLuint, access: GLenum, format: GLenum) {
+        unsafe {
+            gl_check!(gl::BindImageTexture(unit, texture, 0, gl::FALSE, 0, access, format));
+        }
+    }
+
+    fn memory_barrier(&self, barriers: GLbitfield) {
+        unsafe {
+            gl_check!(gl::MemoryBarrier(barriers));
+        }
+    }
+
+    fn gen_query(&self) -> GLuint {
+        let mut id = 0;
+        unsafe {
+            gl_check!(gl::GenQueries(1, &mut id));
+        }
+        id
+    }
+
+    fn delete_query(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::DeleteQueries(1, &id));
+        }
+    }
+
+    fn query_counter(&self, id: GLuint) {
+        unsafe {
+            gl_check!(gl::QueryCounter(id, gl::TIMESTAMP));
+        }
+    }
+
+    fn get_query_object_u64(&self, id: GLuint, pname: GLenum) -> u64 {
+        let mut value = 0;
+        unsafe {
+            gl_check!(gl::GetQueryObjectui64v(id, pname, &mut value));
+        }
+        value
+    }
+
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str) {
+        if debug_output_enabled() {
+            unsafe {
+                gl_check!(gl::ObjectLabel(identifier, name, label.len() as GLsizei, label.as_ptr() as *const GLchar));
+            }
+        }
+    }
+
+    fn push_debug_group(&self, message: &str) {
+        if debug_output_enabled() {
+            unsafe {
+                gl_check!(gl::PushDebugGroup(gl::DEBUG_SOURCE_APPLICATION, 0, message.len() as GLsizei, message.as_ptr() as *const GLchar));
+            }
+        }
+    }
+
+    fn pop_debug_group(&self) {
+        if debug_output_enabled() {
+            unsafe {
+                gl_check!(gl::PopDebugGroup());
+            }
+        }
+    }
+}
+
+// One recorded call with its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlCall {
+    GenVertexArray(GLuint),
+    BindVertexArray(GLuint),
+    DeleteVertexArray(GLuint),
+    VertexAttribPointer { index: GLuint, size: GLint, attrib_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize },
+    EnableVertexAttribArray(GLuint),
+    GenBuffer(GLuint),
+    BindBuffer { target: GLenum, id: GLuint },
+    BufferData { target: GLenum, data: Vec<u8>, usage: GLenum },
+    DeleteBuffer(GLuint),
+    GenTexture(GLuint),
+    ActiveTexture(GLenum),
+    BindTexture { target: GLenum, id: GLuint },
+    TexParameterI { target: GLenum, pname: GLenum, param: GLint },
+    TexImage2D { target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, len: usize },
+    TexSubImage2D { target: GLenum, level: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, len: usize },
+    GenerateMipmap(GLenum),
+    DeleteTexture(GLuint),
+    CreateShader { shader_type: GLenum, id: GLuint },
+    ShaderSource { shader: GLuint, source: String },
+    CompileShader(GLuint),
+    DeleteShader(GLuint),
+    CreateProgram(GLuint),
+    AttachShader { program: GLuint, shader: GLuint },
+    LinkProgram(GLuint),
+    UseProgram(GLuint),
+    DeleteProgram(GLuint),
+    GetUniformLocation { program: GLuint, name: String },
+    Uniform1i { location: GLint, value: GLint },
+    Uniform1f { location: GLint, value: GLfloat },
+    Uniform2f { location: GLint, value: [GLfloat; 2] },
+    Uniform3f { location: GLint, value: [GLfloat; 3] },
+    Uniform4f { location: GLint, value: [GLfloat; 4] },
+    UniformMatrix4fv { location: GLint, value: [GLfloat; 16] },
+    BindBufferBase { target: GLenum, index: GLuint, buffer: GLuint },
+    BufferSubData { target: GLenum, offset: usize, data: Vec<u8> },
+    GenFramebuffer(GLuint),
+    BindFramebuffer { target: GLenum, id: GLuint },
+    FramebufferTexture2D { target: GLenum, attachment: GLenum, tex_target: GLenum, texture: GLuint, level: GLint },
+    DeleteFramebuffer(GLuint),
+    Viewport { x: GLint, y: GLint, width: GLsizei, height: GLsizei },
+    ClearColor([GLfloat; 4]),
+    Clear(GLbitfield),
+    DrawElements { mode: GLenum, count: GLsizei, index_type: GLenum, offset: usize },
+    ReadPixels { x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum },
+    PixelStorei { pname: GLenum, param: GLint },
+    Enable(GLenum),
+    Disable(GLenum),
+    BlendFunc { src: GLenum, dst: GLenum },
+    DispatchCompute { x: GLuint, y: GLuint, z: GLuint },
+    BindImageTexture { unit: GLuint, texture: GLuint, access: GLenum, format: GLenum },
+    MemoryBarrier(GLbitfield),
+    GenQuery(GLuint),
+    DeleteQuery(GLuint),
+    QueryCounter(GLuint),
+    ObjectLabel { identifier: GLenum, name: GLuint, label: String },
+    PushDebugGroup(String),
+    PopDebugGroup,
+}
+
+// Device that never touches OpenGL; it hands out sequential object names and
+// logs every call so tests can assert on what a wrapper did
+#[derive(Default)]
+pub struct RecordingGl {
+    calls: RefCell<Vec<GlCall>>,
+    next_id: Cell<GLuint>,
+    // When set, compile and link status queries report failure with this log
+    pub fail_with: RefCell<Option<String>>,
+    // When set, framebuffers are complete this many more times, then incomplete
+    pub complete_framebuffers: Cell<Option<usize>>,
+}
+
+impl RecordingGl {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self::default())
+    }
+
+    // Snapshot of the calls recorded so far
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.calls.borrow().clone()
+    }
+
+    // Forgets the recorded calls, e.g. after setting up a fixture
+    pub fn clear(&self) {
+        self.calls.borrow_mut().clear();
+    }
+
+    // Returns true if the given call was recorded at least once
+    pub fn recorded(&self, call: &GlCall) -> bool {
+        self.calls.borrow().iter().any(|c| c == call)
+    }
+
+    fn record(&self, call: GlCall) {
+        self.calls.borrow_mut().push(call);
+    }
+
+    fn next_name(&self) -> GLuint {
+        // Object name 0 is reserved by OpenGL, so start at 1
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        id
+    }
+
+    fn status(&self) -> GLint {
+        if self.fail_with.borrow().is_some() { gl::FALSE as GLint } else { gl::TRUE as GLint }
+    }
+}
+
+impl GlDevice for RecordingGl {
+    fn gen_vertex_array(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::GenVertexArray(id));
+        id
+    }
+
+    fn bind_vertex_array(&self, id: GLuint) {
+        self.record(GlCall::BindVertexArray(id));
+    }
+
+    fn delete_vertex_array(&self, id: GLuint) {
+        self.record(GlCall::DeleteVertexArray(id));
+    }
+
+    fn vertex_attrib_pointer(&self, index: GLuint, size: GLint, attrib_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize) {
+        self.record(GlCall::VertexAttribPointer { index, size, attrib_type, normalized, stride, offset });
+    }
+
+    fn enable_vertex_attrib_array(&self, index: GLuint) {
+        self.record(GlCall::EnableVertexAttribArray(index));
+    }
+
+    fn gen_buffer(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::GenBuffer(id));
+        id
+    }
+
+    fn bind_buffer(&self, target: GLenum, id: GLuint) {
+        self.record(GlCall::BindBuffer { target, id });
+    }
+
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        self.record(GlCall::BufferData { target, data: data.to_vec(), usage });
+    }
+
+    fn delete_buffer(&self, id: GLuint) {
+        self.record(GlCall::DeleteBuffer(id));
+    }
+
+    fn gen_texture(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::GenTexture(id));
+        id
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        self.record(GlCall::ActiveTexture(unit));
+    }
+
+    fn bind_texture(&self, target: GLenum, id: GLuint) {
+        self.record(GlCall::BindTexture { target, id });
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, pname: GLenum, param: GLint) {
+        self.record(GlCall::TexParameterI { target, pname, param });
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: GLint, internal_format: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]) {
+        self.record(GlCall::TexImage2D { target, level, internal_format, width, height, format, data_type, len: data.len() });
+    }
+
+    fn tex_sub_image_2d(&self, target: GLenum, level: GLint, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &[u8]) {
+        self.record(GlCall::TexSubImage2D { target, level, x, y, width, height, format, data_type, len: data.len() });
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        self.record(GlCall::GenerateMipmap(target));
+    }
+
+    fn delete_texture(&self, id: GLuint) {
+        self.record(GlCall::DeleteTexture(id));
+    }
+
+    fn create_shader(&self, shader_type: GLenum) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::CreateShader { shader_type, id });
+        id
+    }
+
+    fn shader_source(&self, shader: GLuint, source: &str) {
+        self.record(GlCall::ShaderSource { shader, source: source.to_string() });
+    }
+
+    fn compile_shader(&self, shader: GLuint) {
+        self.record(GlCall::CompileShader(shader));
+    }
+
+    fn get_shader_iv(&self, _shader: GLuint, pname: GLenum) -> GLint {
+        match pname {
+            gl::COMPILE_STATUS => self.status(),
+            _ => 0,
+        }
+    }
+
+    fn get_shader_info_log(&self, _shader: GLuint) -> String {
+        self.fail_with.borrow().clone().unwrap_or_default()
+    }
+
+    fn delete_shader(&self, shader: GLuint) {
+        self.record(GlCall::DeleteShader(shader));
+    }
+
+    fn create_program(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::CreateProgram(id));
+        id
+    }
+
+    fn attach_shader(&self, program: GLuint, shader: GLuint) {
+        self.record(GlCall::AttachShader { program, shader });
+    }
+
+    fn link_program(&self, program: GLuint) {
+        self.record(GlCall::LinkProgram(program));
+    }
+
+    fn get_program_iv(&self, _program: GLuint, pname: GLenum) -> GLint {
+        match pname {
+            gl::LINK_STATUS => self.status(),
+            _ => 0,
+        }
+    }
+
+    fn get_program_info_log(&self, _program: GLuint) -> String {
+        self.fail_with.borrow().clone().unwrap_or_default()
+    }
+
+    fn use_program(&self, program: GLuint) {
+        self.record(GlCall::UseProgram(program));
+    }
+
+    fn delete_program(&self, program: GLuint) {
+        self.record(GlCall::DeleteProgram(program));
+    }
+
+    fn get_uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        self.record(GlCall::GetUniformLocation { program, name: name.to_string() });
+        0
+    }
+
+    fn uniform_1i(&self, location: GLint, value: GLint) {
+        self.record(GlCall::Uniform1i { location, value });
+    }
+
+    fn uniform_1f(&self, location: GLint, value: GLfloat) {
+        self.record(GlCall::Uniform1f { location, value });
+    }
+
+    fn uniform_2f(&self, location: GLint, x: GLfloat, y: GLfloat) {
+        self.record(GlCall::Uniform2f { location, value: [x, y] });
+    }
+
+    fn uniform_3f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat) {
+        self.record(GlCall::Uniform3f { location, value: [x, y, z] });
+    }
+
+    fn uniform_4f(&self, location: GLint, x: GLfloat, y: GLfloat, z: GLfloat, w: GLfloat) {
+        self.record(GlCall::Uniform4f { location, value: [x, y, z, w] });
+    }
+
+    fn uniform_matrix_4fv(&self, location: GLint, value: &[GLfloat; 16]) {
+        self.record(GlCall::UniformMatrix4fv { location, value: *value });
+    }
+
+    fn bind_buffer_base(&self, target: GLenum, index: GLuint, buffer: GLuint) {
+        self.record(GlCall::BindBufferBase { target, index, buffer });
+    }
+
+    fn buffer_sub_data(&self, target: GLenum, offset: usize, data: &[u8]) {
+        self.record(GlCall::BufferSubData { target, offset, data: data.to_vec() });
+    }
+
+    fn gen_framebuffer(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::GenFramebuffer(id));
+        id
+    }
+
+    fn bind_framebuffer(&self, target: GLenum, id: GLuint) {
+        self.record(GlCall::BindFramebuffer { target, id });
+    }
+
+    fn framebuffer_texture_2d(&self, target: GLenum, attachment: GLenum, tex_target: GLenum, texture: GLuint, level: GLint) {
+        self.record(GlCall::FramebufferTexture2D { target, attachment, tex_target, texture, level });
+    }
+
+    fn check_framebuffer_status(&self, _target: GLenum) -> GLenum {
+        match self.complete_framebuffers.get() {
+            Some(0) => gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT,
+            Some(left) => {
+                self.complete_framebuffers.set(Some(left - 1));
+                gl::FRAMEBUFFER_COMPLETE
+            }
+            None => gl::FRAMEBUFFER_COMPLETE,
+        }
+    }
+
+    fn delete_framebuffer(&self, id: GLuint) {
+        self.record(GlCall::DeleteFramebuffer(id));
+    }
+
+    fn viewport(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei) {
+        self.record(GlCall::Viewport { x, y, width, height });
+    }
+
+    fn clear_color(&self, r: GLfloat, g: GLfloat, b: GLfloat, a: GLfloat) {
+        self.record(GlCall::ClearColor([r, g, b, a]));
+    }
+
+    fn clear(&self, mask: GLbitfield) {
+        self.record(GlCall::Clear(mask));
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: GLsizei, index_type: GLenum, offset: usize) {
+        self.record(GlCall::DrawElements { mode, count, index_type, offset });
+    }
+
+    fn read_pixels(&self, x: GLint, y: GLint, width: GLsizei, height: GLsizei, format: GLenum, data_type: GLenum, data: &mut [u8]) {
+        // Nothing was rendered, so read back zeroes
+        data.iter_mut().for_each(|b| *b = 0);
+        self.record(GlCall::ReadPixels { x, y, width, height, format, data_type });
+    }
+
+    fn pixel_store_i(&self, pname: GLenum, param: GLint) {
+        self.record(GlCall::PixelStorei { pname, param });
+    }
+
+    // Whatever the last pixel_store_i of `pname` set, else GL's default
+    // alignment of 4 (other parameters default to 0)
+    fn get_integer(&self, pname: GLenum) -> GLint {
+        let calls = self.calls.borrow();
+        calls.iter().rev().find_map(|call| match call {
+            GlCall::PixelStorei { pname: p, param } if *p == pname => Some(*param),
+            _ => None,
+        }).unwrap_or(if pname == gl::PACK_ALIGNMENT || pname == gl::UNPACK_ALIGNMENT { 4 } else { 0 })
+    }
+
+    fn enable(&self, cap: GLenum) {
+        self.record(GlCall::Enable(cap));
+    }
+
+    fn disable(&self, cap: GLenum) {
+        self.record(GlCall::Disable(cap));
+    }
+
+    // Whatever the last enable or disable of `cap` left, off by default
+    fn is_enabled(&self, cap: GLenum) -> bool {
+        let calls = self.calls.borrow();
+        calls.iter().rev().find_map(|call| match call {
+            GlCall::Enable(c) if *c == cap => Some(true),
+            GlCall::Disable(c) if *c == cap => Some(false),
+            _ => None,
+        }) == Some(true)
+    }
+
+    fn blend_func(&self, src: GLenum, dst: GLenum) {
+        self.record(GlCall::BlendFunc { src, dst });
+    }
+
+    fn dispatch_compute(&self, x: GLuint, y: GLuint, z: GLuint) {
+        self.record(GlCall::DispatchCompute { x, y, z });
+    }
+
+    fn bind_image_texture(&self, unit: GLuint, texture: GLuint, access: GLenum, format: GLenum) {
+        self.record(GlCall::BindImageTexture { unit, texture, access, format });
+    }
+
+    fn memory_barrier(&self, barriers: GLbitfield) {
+        self.record(GlCall::MemoryBarrier(barriers));
+    }
+
+    fn gen_query(&self) -> GLuint {
+        let id = self.next_name();
+        self.record(GlCall::GenQuery(id));
+        id
+    }
+
+    fn delete_query(&self, id: GLuint) {
+        self.record(GlCall::DeleteQuery(id));
+    }
+
+    fn query_counter(&self, id: GLuint) {
+        self.record(GlCall::QueryCounter(id));
+    }
+
+    // Results are always available; a timestamp is 1000 ns per call recorded
+    // before its counter, so later counters read later times
+    fn get_query_object_u64(&self, id: GLuint, pname: GLenum) -> u64 {
+        match pname {
+            gl::QUERY_RESULT_AVAILABLE => 1,
+            _ => {
+                let calls = self.calls.borrow();
+                let position = calls.iter().rposition(|c| *c == GlCall::QueryCounter(id)).unwrap_or(0);
+                position as u64 * 1000
+            }
+        }
+    }
+
+    fn object_label(&self, identifier: GLenum, name: GLuint, label: &str) {
+        self.record(GlCall::ObjectLabel { identifier, name, label: label.to_string() });
+    }
+
+    fn push_debug_group(&self, message: &str) {
+        self.record(GlCall::PushDebugGroup(message.to_string()));
+    }
+
+    fn pop_debug_group(&self) {
+        self.record(GlCall::PopDebugGroup);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::check_linked;
+    use crate::shader_pipeline::texture::Texture;
+    use crate::shader_pipeline::EBO::EBO;
+    use crate::shader_pipeline::VAO::VAO;
+    use crate::shader_pipeline::VBO::VBO;
+    use crate::Shader;
+
+    fn recorder() -> (Rc<RecordingGl>, Device) {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        (recording, device)
+    }
+
+    #[test]
+    fn vbo_uploads_vertices_as_bytes() {
+        let (recording, gl) = recorder();
+        let vbo = VBO::with_device(gl, &[1.0, 2.0]);
+        let bytes: Vec<u8> = [1.0f32, 2.0].iter().flat_map(|f| f.to_ne_bytes()).collect();
+        assert_eq!(
+            recording.calls(),
+            vec![
+                GlCall::GenBuffer(vbo.id),
+                GlCall::BindBuffer { target: gl::ARRAY_BUFFER, id: vbo.id },
+                GlCall::BufferData { target: gl::ARRAY_BUFFER, data: bytes, usage: gl::STATIC_DRAW },
+            ]
+        );
+        vbo.delete();
+        assert!(recording.recorded(&GlCall::DeleteBuffer(vbo.id)));
+    }
+
+    #[test]
+    fn ebo_uploads_indices_to_the_element_buffer() {
+        let (recording, gl) = recorder();
+        let ebo = EBO::with_device(gl, &[0, 1, 2]);
+        let bytes: Vec<u8> = [0u32, 1, 2].iter().flat_map(|i| i.to_ne_bytes()).collect();
+        assert!(recording.recorded(&GlCall::BufferData { target: gl::ELEMENT_ARRAY_BUFFER, data: bytes, usage: gl::STATIC_DRAW }));
+        recording.clear();
+        ebo.bind();
+        ebo.unbind();
+        assert_eq!(
+            recording.calls(),
+            vec![
+                GlCall::BindBuffer { target: gl::ELEMENT_ARRAY_BUFFER, id: ebo.id },
+                GlCall::BindBuffer { target: gl::ELEMENT_ARRAY_BUFFER, id: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn link_attrib_sets_stride_and_offset_with_the_vbo_bound() {
+        let (recording, gl) = recorder();
+        let vao = VAO::with_device(gl.clone());
+        let vbo = VBO::with_device(gl, &[0.0; 16]);
+        recording.clear();
+        let stride = 8 * std::mem::size_of::<f32>() as GLsizei;
+        vao.link_attrib(&vbo, 2, 2, gl::FLOAT, stride, (6 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+        assert_eq!(
+            recording.calls(),
+            vec![
+                GlCall::BindBuffer { target: gl::ARRAY_BUFFER, id: vbo.id },
+                GlCall::VertexAttribPointer { index: 2, size: 2, attrib_type: gl::FLOAT, normalized: gl::FALSE, stride: 32, offset: 24 },
+                GlCall::EnableVertexAttribArray(2),
+                GlCall::BindBuffer { target: gl::ARRAY_BUFFER, id: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn vao_binds_unbinds_and_deletes_its_name() {
+        let (recording, gl) = recorder();
+        let vao = VAO::with_device(gl);
+        vao.bind();
+        vao.unbind();
+        vao.delete();
+        assert_eq!(
+            recording.calls(),
+            vec![
+                GlCall::GenVertexArray(vao.id),
+                GlCall::BindVertexArray(vao.id),
+                GlCall::BindVertexArray(0),
+                GlCall::DeleteVertexArray(vao.id),
+            ]
+        );
+    }
+
+    #[test]
+    fn shader_compiles_links_and_deletes_its_stages() {
+        let (recording, gl) = recorder();
+        let shader = Shader::from_source(gl, "vertex code", "fragment code");
+        let calls = recording.calls();
+        let (vertex, fragment) = (1, 2);
+        assert_eq!(
+            calls,
+            vec![
+                GlCall::CreateShader { shader_type: gl::VERTEX_SHADER, id: vertex },
+                GlCall::ShaderSource { shader: vertex, source: "vertex code".to_string() },
+                GlCall::CompileShader(vertex),
+                GlCall::CreateShader { shader_type: gl::FRAGMENT_SHADER, id: fragment },
+                GlCall::ShaderSource { shader: fragment, source: "fragment code".to_string() },
+                GlCall::CompileShader(fragment),
+                GlCall::CreateProgram(shader.id),
+                GlCall::AttachShader { program: shader.id, shader: vertex },
+                GlCall::AttachShader { program: shader.id, shader: fragment },
+                GlCall::LinkProgram(shader.id),
+                GlCall::DeleteShader(vertex),
+                GlCall::DeleteShader(fragment),
+            ]
+        );
+    }
+
+    #[test]
+    fn shader_uniforms_go_to_its_program() {
+        let (recording, gl) = recorder();
+        let shader = Shader::from_source(gl, "", "");
+        recording.clear();
+        shader.activate();
+        shader.uniform_location("scale");
+        assert_eq!(
+            recording.calls(),
+            vec![GlCall::UseProgram(shader.id), GlCall::GetUniformLocation { program: shader.id, name: "scale".to_string() }]
+        );
+    }
+
+    #[test]
+    fn failed_compile_still_cleans_up_the_stages() {
+        let (recording, gl) = recorder();
+        *recording.fail_with.borrow_mut() = Some("0:1: syntax error".to_string());
+        let shader = Shader::compute_from_source(gl, "broken");
+        assert!(recording.recorded(&GlCall::LinkProgram(shader.id)));
+        assert!(recording.recorded(&GlCall::DeleteShader(1)));
+    }
+
+    #[test]
+    fn failed_link_is_an_error_and_frees_the_program() {
+        let (recording, gl) = recorder();
+        *recording.fail_with.borrow_mut() = Some("undefined varying".to_string());
+        let shader = Shader::from_source(gl.clone(), "", "");
+        let id = shader.id;
+        let error = check_linked(&gl, shader, "quad").err().expect("link fails");
+        assert_eq!(error, "quad: failed to link: undefined varying");
+        assert!(recording.recorded(&GlCall::DeleteProgram(id)));
+    }
+
+    #[test]
+    fn successful_link_keeps_the_program() {
+        let (recording, gl) = recorder();
+        let shader = check_linked(&gl, Shader::from_source(gl.clone(), "", ""), "quad").expect("link succeeds");
+        assert!(!recording.recorded(&GlCall::DeleteProgram(shader.id)));
+    }
+
+    #[test]
+    fn rgba_texture_is_clamped_and_uploaded_without_mipmaps() {
+        let (recording, gl) = recorder();
+        let texture = Texture::from_rgba(gl, 2, 1, &[0; 8], gl::NEAREST, gl::TEXTURE3);
+        let calls = recording.calls();
+        assert_eq!(calls[0], GlCall::GenTexture(texture.id));
+        assert_eq!(calls[1], GlCall::ActiveTexture(gl::TEXTURE3));
+        assert!(recording.recorded(&GlCall::TexParameterI { target: gl::TEXTURE_2D, pname: gl::TEXTURE_WRAP_S, param: gl::CLAMP_TO_EDGE as GLint }));
+        assert!(recording.recorded(&GlCall::TexParameterI { target: gl::TEXTURE_2D, pname: gl::TEXTURE_MIN_FILTER, param: gl::NEAREST as GLint }));
+        assert!(recording.recorded(&GlCall::TexImage2D {
+            target: gl::TEXTURE_2D,
+            level: 0,
+            internal_format: gl::RGBA8 as GLint,
+            width: 2,
+            height: 1,
+            format: gl::RGBA,
+            data_type: gl::UNSIGNED_BYTE,
+            len: 8,
+        }));
+        assert!(!calls.iter().any(|c| matches!(c, GlCall::GenerateMipmap(_))));
+        assert_eq!(calls.last(), Some(&GlCall::BindTexture { target: gl::TEXTURE_2D, id: 0 }));
+    }
+
+    #[test]
+    fn blending_is_left_as_it_was() {
+        let (recording, gl) = recorder();
+        blended(&gl, || assert!(gl.is_enabled(gl::BLEND)));
+        assert!(!gl.is_enabled(gl::BLEND));
+        assert!(recording.recorded(&GlCall::BlendFunc { src: gl::SRC_ALPHA, dst: gl::ONE_MINUS_SRC_ALPHA }));
+
+        gl.enable(gl::BLEND);
+        let disables = || recording.calls().iter().filter(|call| **call == GlCall::Disable(gl::BLEND)).count();
+        let before = disables();
+        blended(&gl, || {});
+        assert!(gl.is_enabled(gl::BLEND));
+        assert_eq!(disables(), before);
+    }
+
+    #[test]
+    fn rgb_rows_are_uploaded_byte_aligned() {
+        let (recording, gl) = recorder();
+        gl.pixel_store_i(gl::UNPACK_ALIGNMENT, 8);
+        // 3 RGB pixels make 9 byte rows, which 4 byte alignment would pad
+        let texture = Texture::from_image(gl, &image::DynamicImage::new_rgb8(3, 2), gl::TEXTURE_2D, gl::TEXTURE0);
+        let calls = recording.calls();
+        let upload = calls.iter().position(|c| matches!(c, GlCall::TexImage2D { len: 18, .. })).expect("image uploaded");
+        assert_eq!(calls[upload - 1], GlCall::PixelStorei { pname: gl::UNPACK_ALIGNMENT, param: 1 });
+        assert_eq!(calls[upload + 1], GlCall::PixelStorei { pname: gl::UNPACK_ALIGNMENT, param: 8 });
+        texture.delete();
+    }
+
+    #[test]
+    fn image_texture_is_uploaded_as_rgb_with_mipmaps() {
+        let (recording, gl) = recorder();
+        let image = image::DynamicImage::new_rgba8(3, 2);
+        let texture = Texture::from_image(gl, &image, gl::TEXTURE_2D, gl::TEXTURE0);
+        assert!(recording.recorded(&GlCall::TexImage2D {
+            target: gl::TEXTURE_2D,
+            level: 0,
+            internal_format: gl::RGB as GLint,
+            width: 3,
+            height: 2,
+            format: gl::RGB,
+            data_type: gl::UNSIGNED_BYTE,
+            len: 18,
+        }));
+        assert!(recording.recorded(&GlCall::GenerateMipmap(gl::TEXTURE_2D)));
+        recording.clear();
+        texture.delete();
+        assert_eq!(recording.calls(), vec![GlCall::DeleteTexture(texture.id)]);
+    }
+}
@@ -16,4 +16,11 @@ pub mod VAO;
 pub mod VBO;
 pub mod EBO;
 pub mod Shader;
-pub mod texture;
\ No newline at end of file
+pub mod texture;
+pub mod gl_device;
+pub mod screen_quad;
+pub mod render_target;
+pub mod capture;
+pub mod video;
+pub mod debug;
+pub mod profiler;
@@ -0,0 +1,631 @@
+use gl::types::*;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use super::capture;
+use super::gl_device::{blended, Device};
+use super::screen_quad::ScreenQuad;
+use crate::Shader;
+
+// Frames kept for averages
+const HISTORY: usize = 120;
+
+// Timing of one scope in a frame, relative to the frame's start
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeTiming {
+    pub name: String,
+    // Number of scopes it is nested in
+    pub depth: usize,
+    pub cpu_start_ms: f64,
+    pub cpu_ms: f64,
+    // None when the GPU results weren't ready in time
+    pub gpu_start_ms: Option<f64>,
+    pub gpu_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameProfile {
+    pub frame: u64,
+    // Start of the frame since the profiler was created
+    pub start_ms: f64,
+    pub cpu_ms: f64,
+    pub gpu_ms: Option<f64>,
+    pub scopes: Vec<ScopeTiming>,
+}
+
+// A scope as recorded, with the timestamp queries around it
+struct PendingScope {
+    name: String,
+    depth: usize,
+    cpu_start: f64,
+    cpu_end: f64,
+    queries: (usize, usize),
+}
+
+// Queries of one frame. Their results are read two frames later, when the
+// GPU is normally done with them, so reading doesn't wait on it.
+#[derive(Default)]
+struct FrameQueries {
+    // Names reused from frame to frame, taken in order
+    pool: Vec<GLuint>,
+    used: usize,
+    frame: u64,
+    cpu_start: f64,
+    cpu_end: f64,
+    scopes: Vec<PendingScope>,
+    pending: bool,
+}
+
+impl FrameQueries {
+    fn timestamp(&mut self, gl: &Device) -> usize {
+        if self.used == self.pool.len() {
+            self.pool.push(gl.gen_query());
+        }
+        gl.query_counter(self.pool[self.used]);
+        self.used += 1;
+        self.used - 1
+    }
+
+    fn read(&self, gl: &Device, query: usize) -> f64 {
+        gl.get_query_object_u64(self.pool[query], gl::QUERY_RESULT) as f64 / 1e6
+    }
+
+    // Turns the results into a profile; with `wait` false, GPU times are left
+    // out if the last query isn't done yet
+    fn resolve(&mut self, gl: &Device, wait: bool) -> FrameProfile {
+        self.pending = false;
+        let ready = wait || gl.get_query_object_u64(self.pool[self.used - 1], gl::QUERY_RESULT_AVAILABLE) != 0;
+        let gpu = |query: usize| if ready { Some(self.read(gl, query)) } else { None };
+        // Query 0 marks the start of the frame, the last one its end
+        let gpu_start = gpu(0);
+        let relative = |query: usize| gpu(query).zip(gpu_start).map(|(time, start)| time - start);
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|scope| ScopeTiming {
+                name: scope.name.clone(),
+                depth: scope.depth,
+                cpu_start_ms: scope.cpu_start - self.cpu_start,
+                cpu_ms: scope.cpu_end - scope.cpu_start,
+                gpu_start_ms: relative(scope.queries.0),
+                gpu_ms: relative(scope.queries.1).zip(relative(scope.queries.0)).map(|(end, start)| end - start),
+            })
+            .collect();
+        FrameProfile {
+            frame: self.frame,
+            start_ms: self.cpu_start,
+            cpu_ms: self.cpu_end - self.cpu_start,
+            gpu_ms: relative(self.used - 1),
+            scopes,
+        }
+    }
+}
+
+// Times frames and the scopes inside them on the CPU and, with timestamp
+// queries, on the GPU. Scopes also show up as debug groups.
+pub struct Profiler {
+    gl: Device,
+    frames: [FrameQueries; 2],
+    frame: u64,
+    // Scopes opened and not closed yet, as indices in the current frame
+    open: Vec<usize>,
+    epoch: Instant,
+    history: VecDeque<FrameProfile>,
+    // Frames kept for a trace while one is being recorded
+    trace: Option<Vec<FrameProfile>>,
+}
+
+impl Profiler {
+    pub fn new(gl: Device) -> Self {
+        Profiler {
+            gl,
+            frames: Default::default(),
+            frame: 0,
+            open: Vec::new(),
+            epoch: Instant::now(),
+            history: VecDeque::new(),
+            trace: None,
+        }
+    }
+
+    fn now(&self) -> f64 {
+        self.epoch.elapsed().as_secs_f64() * 1000.0
+    }
+
+    fn current(&mut self) -> &mut FrameQueries {
+        &mut self.frames[(self.frame % 2) as usize]
+    }
+
+    fn push(&mut self, profile: FrameProfile) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(profile.clone());
+        }
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(profile);
+    }
+
+    pub fn begin_frame(&mut self) {
+        let gl = self.gl.clone();
+        let (frame, now) = (self.frame, self.now());
+        let queries = self.current();
+        // This slot was last used two frames ago
+        let profile = queries.pending.then(|| queries.resolve(&gl, false));
+        queries.used = 0;
+        queries.scopes.clear();
+        queries.frame = frame;
+        queries.cpu_start = now;
+        queries.timestamp(&gl);
+        if let Some(profile) = profile {
+            self.push(profile);
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        while !self.open.is_empty() {
+            self.end();
+        }
+        let gl = self.gl.clone();
+        let now = self.now();
+        let queries = self.current();
+        queries.timestamp(&gl);
+        queries.cpu_end = now;
+        queries.pending = true;
+        self.frame += 1;
+    }
+
+    // Opens a scope, closed by the matching `end`
+    pub fn begin(&mut self, name: &str) {
+        let gl = self.gl.clone();
+        gl.push_debug_group(name);
+        let (depth, now) = (self.open.len(), self.now());
+        let queries = self.current();
+        let start = queries.timestamp(&gl);
+        queries.scopes.push(PendingScope { name: name.to_string(), depth, cpu_start: now, cpu_end: now, queries: (start, start) });
+        let index = queries.scopes.len() - 1;
+        self.open.push(index);
+    }
+
+    pub fn end(&mut self) {
+        let Some(index) = self.open.pop() else { return };
+        let gl = self.gl.clone();
+        let now = self.now();
+        let queries = self.current();
+        let end = queries.timestamp(&gl);
+        let scope = &mut queries.scopes[index];
+        scope.cpu_end = now;
+        scope.queries.1 = end;
+        gl.pop_debug_group();
+    }
+
+    // Reads the frames still in flight, waiting for the GPU
+    pub fn flush(&mut self) {
+        let gl = self.gl.clone();
+        // The older frame first
+        for slot in [(self.frame % 2) as usize, ((self.frame + 1) % 2) as usize] {
+            if self.frames[slot].pending {
+                let profile = self.frames[slot].resolve(&gl, true);
+                self.push(profile);
+            }
+        }
+    }
+
+    // Latest frame with its GPU times read back
+    pub fn latest(&self) -> Option<&FrameProfile> {
+        self.history.back()
+    }
+
+    // Average frame over the last `frames` frames, scopes matched by name in
+    // the order of the latest frame
+    pub fn average(&self, frames: usize) -> Option<FrameProfile> {
+        let latest = self.history.back()?;
+        let recent: Vec<&FrameProfile> = self.history.iter().rev().take(frames.max(1)).collect();
+        let mean = |values: Vec<f64>| values.iter().sum::<f64>() / values.len().max(1) as f64;
+        let mean_gpu = |values: Vec<Option<f64>>| {
+            let known: Vec<f64> = values.into_iter().flatten().collect();
+            (!known.is_empty()).then(|| mean(known))
+        };
+        let scopes = latest
+            .scopes
+            .iter()
+            .map(|scope| {
+                let same: Vec<&ScopeTiming> = recent.iter().filter_map(|f| f.scopes.iter().find(|s| s.name == scope.name)).collect();
+                ScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    cpu_start_ms: mean(same.iter().map(|s| s.cpu_start_ms).collect()),
+                    cpu_ms: mean(same.iter().map(|s| s.cpu_ms).collect()),
+                    gpu_start_ms: mean_gpu(same.iter().map(|s| s.gpu_start_ms).collect()),
+                    gpu_ms: mean_gpu(same.iter().map(|s| s.gpu_ms).collect()),
+                }
+            })
+            .collect();
+        Some(FrameProfile {
+            frame: latest.frame,
+            start_ms: latest.start_ms,
+            cpu_ms: mean(recent.iter().map(|f| f.cpu_ms).collect()),
+            gpu_ms: mean_gpu(recent.iter().map(|f| f.gpu_ms).collect()),
+            scopes,
+        })
+    }
+
+    // One line of averages, e.g. for a window title
+    pub fn summary(&self, frames: usize) -> String {
+        let Some(average) = self.average(frames) else { return String::new() };
+        let gpu = |ms: Option<f64>| ms.map_or("?".to_string(), |ms| format!("{:.2}", ms));
+        let mut line = format!("frame cpu {:.2} gpu {} ms", average.cpu_ms, gpu(average.gpu_ms));
+        for scope in &average.scopes {
+            line += &format!(" | {} {:.2}/{}", scope.name, scope.cpu_ms, gpu(scope.gpu_ms));
+        }
+        line
+    }
+
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Vec::new());
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    // Stops tracing and writes the frames in the Chrome trace format, for
+    // chrome://tracing or Perfetto. CPU and GPU get a track each; GPU times
+    // are placed from the start of their frame, their clocks being unrelated.
+    pub fn write_trace(&mut self, path: &Path) -> Result<usize, String> {
+        self.flush();
+        let frames = self.trace.take().unwrap_or_default();
+        let mut events = vec![
+            json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": 1, "args": { "name": "CPU" } }),
+            json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": 2, "args": { "name": "GPU" } }),
+        ];
+        let event = |name: &str, tid: u32, start_ms: f64, duration_ms: f64, frame: u64| -> Value {
+            json!({ "name": name, "ph": "X", "pid": 1, "tid": tid, "ts": start_ms * 1000.0, "dur": duration_ms * 1000.0, "args": { "frame": frame } })
+        };
+        for frame in &frames {
+            events.push(event("frame", 1, frame.start_ms, frame.cpu_ms, frame.frame));
+            if let Some(gpu_ms) = frame.gpu_ms {
+                events.push(event("frame", 2, frame.start_ms, gpu_ms, frame.frame));
+            }
+            for scope in &frame.scopes {
+                events.push(event(&scope.name, 1, frame.start_ms + scope.cpu_start_ms, scope.cpu_ms, frame.frame));
+                if let (Some(start), Some(duration)) = (scope.gpu_start_ms, scope.gpu_ms) {
+                    events.push(event(&scope.name, 2, frame.start_ms + start, duration, frame.frame));
+                }
+            }
+        }
+        let trace = json!({ "traceEvents": events, "displayTimeUnit": "ms" });
+        fs::write(path, trace.to_string()).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(frames.len())
+    }
+
+    // Writes the trace as the next traces/trace_NNNN.json
+    pub fn save_trace(&mut self) -> Result<(PathBuf, usize), String> {
+        let dir = Path::new("traces");
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = capture::next_free_path(dir, "trace", "json");
+        let frames = self.write_trace(&path)?;
+        Ok((path, frames))
+    }
+
+    pub fn delete(&self) {
+        for queries in &self.frames {
+            queries.pool.iter().for_each(|&id| self.gl.delete_query(id));
+        }
+    }
+}
+
+const OVERLAY_VERTEX: &str = r#"#version 330 core
+layout (location = 0) in vec2 aPos;
+// x, y, width, height in pixels from the top left corner
+uniform vec4 rect;
+uniform vec2 resolution;
+out vec2 uv;
+void main()
+{
+	uv = aPos * 0.5 + 0.5;
+	vec2 pixel = rect.xy + uv * rect.zw;
+	gl_Position = vec4(pixel.x / resolution.x * 2.0 - 1.0, 1.0 - pixel.y / resolution.y * 2.0, 0.0, 1.0);
+}
+"#;
+
+const OVERLAY_FRAGMENT: &str = r#"#version 330 core
+out vec4 FragColor;
+in vec2 uv;
+uniform vec4 color;
+// 3x5 glyph bits, see FONT; 0 fills the whole rect
+uniform int glyph;
+void main()
+{
+	if (glyph != 0)
+	{
+		ivec2 cell = ivec2(min(uv * vec2(3.0, 5.0), vec2(2.0, 4.0)));
+		if (((glyph >> (14 - cell.y * 3 - cell.x)) & 1) == 0)
+			discard;
+	}
+	FragColor = color;
+}
+"#;
+
+// Colors of the scopes' bars, in the order of the summary
+const PALETTE: [[f32; 4]; 6] = [
+    [0.95, 0.45, 0.35, 0.9],
+    [0.35, 0.75, 0.95, 0.9],
+    [0.55, 0.9, 0.4, 0.9],
+    [0.95, 0.8, 0.3, 0.9],
+    [0.75, 0.5, 0.95, 0.9],
+    [0.4, 0.9, 0.8, 0.9],
+];
+
+// 3x5 pixel glyphs, rows from the top with the top left pixel in bit 14.
+// Lowercase letters are drawn as capitals, other missing characters as `?`.
+const FONT: [(char, u16); 45] = [
+    (' ', 0),
+    ('A', 0b010_101_111_101_101),
+    ('B', 0b110_101_110_101_110),
+    ('C', 0b011_100_100_100_011),
+    ('D', 0b110_101_101_101_110),
+    ('E', 0b111_100_110_100_111),
+    ('F', 0b111_100_110_100_100),
+    ('G', 0b011_100_101_101_011),
+    ('H', 0b101_101_111_101_101),
+    ('I', 0b111_010_010_010_111),
+    ('J', 0b001_001_001_101_010),
+    ('K', 0b101_101_110_101_101),
+    ('L', 0b100_100_100_100_111),
+    ('M', 0b101_111_111_101_101),
+    ('N', 0b110_101_101_101_101),
+    ('O', 0b010_101_101_101_010),
+    ('P', 0b110_101_110_100_100),
+    ('Q', 0b010_101_101_110_011),
+    ('R', 0b110_101_110_101_101),
+    ('S', 0b011_100_010_001_110),
+    ('T', 0b111_010_010_010_010),
+    ('U', 0b101_101_101_101_111),
+    ('V', 0b101_101_101_101_010),
+    ('W', 0b101_101_111_111_101),
+    ('X', 0b101_101_010_101_101),
+    ('Y', 0b101_101_010_010_010),
+    ('Z', 0b111_001_010_100_111),
+    ('0', 0b111_101_101_101_111),
+    ('1', 0b010_110_010_010_111),
+    ('2', 0b110_001_010_100_111),
+    ('3', 0b110_001_010_001_110),
+    ('4', 0b101_101_111_001_001),
+    ('5', 0b111_100_110_001_110),
+    ('6', 0b011_100_111_101_111),
+    ('7', 0b111_001_010_010_010),
+    ('8', 0b111_101_111_101_111),
+    ('9', 0b111_101_111_001_110),
+    ('.', 0b000_000_000_000_010),
+    ('-', 0b000_000_111_000_000),
+    ('_', 0b000_000_000_000_111),
+    (':', 0b000_010_000_010_000),
+    ('/', 0b001_001_010_100_100),
+    ('(', 0b010_100_100_100_010),
+    (')', 0b010_001_001_001_010),
+    ('?', 0b110_001_010_000_010),
+];
+
+fn glyph(c: char) -> u16 {
+    let c = c.to_ascii_uppercase();
+    let find = |c: char| FONT.iter().find(|(g, _)| *g == c).map(|(_, bits)| *bits);
+    find(c).or_else(|| find('?')).unwrap()
+}
+
+// Name and `gpu/cpu ms` of an overlay row, the GPU time `-` until known
+fn row_text(name: &str, gpu_ms: Option<f64>, cpu_ms: f64) -> String {
+    let gpu = gpu_ms.map_or("-".to_string(), |ms| format!("{:.2}", ms));
+    format!("{} {}/{:.2} ms", name, gpu, cpu_ms)
+}
+
+// Bars in the top left corner: the whole frame first, then every scope, GPU
+// time as a thick bar and CPU time as a thin one below it, with the names
+// and times written after them. The bars' width is one 60 Hz frame, with a
+// mark at half of it.
+pub struct ProfilerOverlay {
+    shader: Shader,
+    quad: ScreenQuad,
+    gl: Device,
+}
+
+impl ProfilerOverlay {
+    pub fn new(gl: Device) -> Result<Self, String> {
+        let shader = crate::assets::check_linked(&gl, Shader::from_source(gl.clone(), OVERLAY_VERTEX, OVERLAY_FRAGMENT), "profiler overlay")?;
+        shader.label("profiler overlay");
+        let quad = ScreenQuad::new(gl.clone());
+        Ok(ProfilerOverlay { shader, quad, gl })
+    }
+
+    fn rect(&self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        self.gl.uniform_4f(self.shader.uniform_location("rect"), x, y, width, height);
+        self.gl.uniform_4f(self.shader.uniform_location("color"), color[0], color[1], color[2], color[3]);
+        self.quad.draw();
+    }
+
+    // One quad per character, glyph pixels `pixel` wide
+    fn text(&self, x: f32, y: f32, pixel: f32, text: &str, color: [f32; 4]) {
+        let location = self.shader.uniform_location("glyph");
+        for (i, c) in text.chars().enumerate() {
+            let bits = glyph(c);
+            if bits != 0 {
+                self.gl.uniform_1i(location, bits as GLint);
+                self.rect(x + i as f32 * 4.0 * pixel, y, 3.0 * pixel, 5.0 * pixel, color);
+            }
+        }
+        self.gl.uniform_1i(location, 0);
+    }
+
+    // Draws averages over the last frames into the bound framebuffer of the
+    // given size in pixels; `scale` is the content scale for HiDPI screens
+    pub fn draw(&self, profiler: &Profiler, size: (u32, u32), scale: f32) {
+        let Some(average) = profiler.average(30) else { return };
+        let (margin, width, row, pixel) = (10.0 * scale, 300.0 * scale, 14.0 * scale, 2.0 * scale);
+        let budget = 1000.0 / 60.0;
+        let bar = |ms: f64| (ms / budget).min(1.0) as f32 * width;
+
+        let lines = std::iter::once((0, row_text("frame", average.gpu_ms, average.cpu_ms), average.gpu_ms, average.cpu_ms, [0.9, 0.9, 0.9, 0.9]))
+            .chain(average.scopes.iter().enumerate().map(|(i, s)| (s.depth, row_text(&s.name, s.gpu_ms, s.cpu_ms), s.gpu_ms, s.cpu_ms, PALETTE[i % PALETTE.len()])))
+            .collect::<Vec<_>>();
+        let indent = |depth: usize| depth as f32 * 4.0 * scale;
+        let text_width = lines.iter().map(|(depth, text, ..)| indent(*depth) + text.chars().count() as f32 * 4.0 * pixel).fold(0.0, f32::max);
+        let text_x = 3.0 * margin + width;
+        let height = lines.len() as f32 * row + 2.0 * margin;
+
+        blended(&self.gl, || {
+            self.shader.activate();
+            self.gl.uniform_2f(self.shader.uniform_location("resolution"), size.0 as f32, size.1 as f32);
+            self.gl.uniform_1i(self.shader.uniform_location("glyph"), 0);
+            self.rect(margin, margin, width + text_width + 3.0 * margin, height, [0.0, 0.0, 0.0, 0.6]);
+            for (i, (depth, text, gpu_ms, cpu_ms, color)) in lines.iter().enumerate() {
+                let y = 2.0 * margin + i as f32 * row;
+                let x = 2.0 * margin + indent(*depth);
+                if let Some(gpu_ms) = gpu_ms {
+                    self.rect(x, y, bar(*gpu_ms), row * 0.5, *color);
+                }
+                let faded = [color[0], color[1], color[2], 0.5];
+                self.rect(x, y + row * 0.5, bar(*cpu_ms), row * 0.2, faded);
+                self.text(text_x + indent(*depth), y, pixel, text, [color[0], color[1], color[2], 1.0]);
+            }
+            // Half a frame
+            self.rect(2.0 * margin + width * 0.5, margin, scale, height, [1.0, 1.0, 1.0, 0.3]);
+        });
+    }
+
+    pub fn delete(&self) {
+        self.shader.delete();
+        self.quad.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+
+    // Frames with a "draw" scope holding a nested "blur" scope
+    fn profile_frames(profiler: &mut Profiler, frames: usize) {
+        for _ in 0..frames {
+            profiler.begin_frame();
+            profiler.begin("draw");
+            profiler.begin("blur");
+            profiler.end();
+            profiler.end();
+            profiler.end_frame();
+        }
+    }
+
+    #[test]
+    fn gpu_times_come_from_the_timestamp_queries() {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut profiler = Profiler::new(device);
+        profile_frames(&mut profiler, 1);
+        assert!(profiler.latest().is_none());
+        profiler.flush();
+
+        let frame = profiler.latest().unwrap();
+        let names: Vec<(&str, usize)> = frame.scopes.iter().map(|s| (s.name.as_str(), s.depth)).collect();
+        assert_eq!(names, [("draw", 0), ("blur", 1)]);
+        // RecordingGl's timestamps are 1 µs per call recorded before them,
+        // so every time is a whole number of calls
+        let (draw, blur) = (&frame.scopes[0], &frame.scopes[1]);
+        let (frame_ms, draw_ms, blur_ms) = (frame.gpu_ms.unwrap(), draw.gpu_ms.unwrap(), blur.gpu_ms.unwrap());
+        assert!(blur_ms > 0.0 && blur_ms < draw_ms && draw_ms < frame_ms);
+        assert!(draw.gpu_start_ms.unwrap() > 0.0);
+        assert!(blur.gpu_start_ms.unwrap() > draw.gpu_start_ms.unwrap());
+        assert!(draw.gpu_start_ms.unwrap() + draw_ms <= frame_ms);
+    }
+
+    #[test]
+    fn frames_are_read_two_frames_later_and_queries_reused() {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut profiler = Profiler::new(device);
+        profile_frames(&mut profiler, 2);
+        assert!(profiler.latest().is_none());
+        profile_frames(&mut profiler, 1);
+        assert_eq!(profiler.latest().unwrap().frame, 0);
+        profile_frames(&mut profiler, 3);
+        assert_eq!(profiler.latest().unwrap().frame, 3);
+        profiler.flush();
+        assert_eq!(profiler.latest().unwrap().frame, 5);
+
+        // Two frames in flight with 6 timestamps each
+        let queries = recording.calls().iter().filter(|call| matches!(call, GlCall::GenQuery(_))).count();
+        assert_eq!(queries, 12);
+        profiler.delete();
+        assert_eq!(recording.calls().iter().filter(|call| matches!(call, GlCall::DeleteQuery(_))).count(), 12);
+    }
+
+    #[test]
+    fn summary_averages_every_scope() {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut profiler = Profiler::new(device);
+        assert_eq!(profiler.summary(30), "");
+        profile_frames(&mut profiler, 4);
+        profiler.flush();
+        let average = profiler.average(30).unwrap();
+        assert_eq!(average.scopes.len(), 2);
+        let summary = profiler.summary(30);
+        assert!(summary.starts_with("frame cpu "), "{}", summary);
+        assert!(summary.contains(" | draw ") && summary.contains(" | blur "), "{}", summary);
+        assert!(!summary.contains('?'), "{}", summary);
+    }
+
+    #[test]
+    fn overlay_leaves_blending_as_it_found_it() {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut profiler = Profiler::new(device.clone());
+        profile_frames(&mut profiler, 1);
+        profiler.flush();
+        let overlay = ProfilerOverlay::new(device.clone()).unwrap();
+
+        overlay.draw(&profiler, (640, 480), 1.0);
+        assert!(!device.is_enabled(gl::BLEND));
+        assert!(recording.recorded(&GlCall::Enable(gl::BLEND)));
+
+        device.enable(gl::BLEND);
+        overlay.draw(&profiler, (640, 480), 1.0);
+        assert!(device.is_enabled(gl::BLEND));
+    }
+
+    #[test]
+    fn glyphs_fall_back_to_capitals_and_question_marks() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_eq!(glyph(' '), 0);
+        assert!(FONT.iter().all(|(_, bits)| *bits < 1 << 15));
+        assert_eq!(row_text("blur", None, 1.5), "blur -/1.50 ms");
+        assert_eq!(row_text("frame", Some(0.25), 16.0), "frame 0.25/16.00 ms");
+    }
+
+    #[test]
+    fn overlay_writes_every_row() {
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let mut profiler = Profiler::new(device.clone());
+        profile_frames(&mut profiler, 1);
+        profiler.flush();
+        let overlay = ProfilerOverlay::new(device.clone()).unwrap();
+        recording.clear();
+        overlay.draw(&profiler, (640, 480), 1.0);
+
+        let glyphs: Vec<GLint> = recording
+            .calls()
+            .iter()
+            .filter_map(|call| match call {
+                GlCall::Uniform1i { value, .. } if *value != 0 => Some(*value),
+                _ => None,
+            })
+            .collect();
+        let average = profiler.average(30).unwrap();
+        let texts = [row_text("frame", average.gpu_ms, average.cpu_ms), row_text("draw", average.scopes[0].gpu_ms, average.scopes[0].cpu_ms), row_text("blur", average.scopes[1].gpu_ms, average.scopes[1].cpu_ms)];
+        let expected: Vec<GLint> = texts.concat().chars().map(glyph).filter(|bits| *bits != 0).map(|bits| bits as GLint).collect();
+        assert_eq!(glyphs, expected);
+    }
+}
@@ -0,0 +1,86 @@
+use gl::types::*;
+use super::capture;
+use super::gl_device::Device;
+use super::texture::Texture;
+
+// A texture with a framebuffer drawing into it, for passes that render
+// offscreen at the window's size and follow it when it changes. RGBA8 from
+// `new`, any color format from `with_format`.
+pub struct RenderTarget {
+    pub texture: Texture,
+    pub framebuffer: GLuint,
+    pub width: u32,
+    pub height: u32,
+    pub internal_format: GLenum,
+    gl: Device,
+}
+
+impl RenderTarget {
+    pub fn new(gl: Device, width: u32, height: u32) -> Result<Self, String> {
+        Self::with_format(gl, width, height, gl::RGBA8)
+    }
+
+    // Same as new with another internal format, like gl::RGBA32F
+    pub fn with_format(gl: Device, width: u32, height: u32, internal_format: GLenum) -> Result<Self, String> {
+        // Zero sized textures make the framebuffer incomplete, e.g. while minimized
+        let (width, height) = (width.max(1), height.max(1));
+        let texture = Texture::empty(gl.clone(), width, height, internal_format, gl::LINEAR, gl::TEXTURE0);
+        let framebuffer = gl.gen_framebuffer();
+        gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture.id, 0);
+        let status = gl.check_framebuffer_status(gl::FRAMEBUFFER);
+        gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            gl.delete_framebuffer(framebuffer);
+            texture.delete();
+            return Err(format!("render target {}x{} is incomplete (status 0x{:x})", width, height, status));
+        }
+        Ok(RenderTarget { texture, framebuffer, width, height, internal_format, gl })
+    }
+
+    // Reallocates the texture at the new size, dropping its contents. The
+    // framebuffer keeps pointing at the same texture name so it stays valid.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.texture.bind();
+        self.gl.tex_image_2d(gl::TEXTURE_2D, 0, self.internal_format as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, &[]);
+        self.texture.unbind();
+        self.width = width;
+        self.height = height;
+    }
+
+    // Draws into the target, with the viewport covering all of it
+    pub fn bind(&self) {
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        self.gl.viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    // Back to the window; the caller restores the window's viewport
+    pub fn unbind(&self) {
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    // Saves the target, as floats for .exr and RGBA8 otherwise
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        capture::save(&self.gl, self.framebuffer, self.width, self.height, path)
+    }
+
+    // Reads the target back as RGBA8, top row first; float targets are clamped
+    pub fn read_rgba(&self) -> image::RgbaImage {
+        capture::read_rgba(&self.gl, self.framebuffer, self.width, self.height)
+    }
+
+    // Names the framebuffer and its texture in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::FRAMEBUFFER, self.framebuffer, name);
+        self.texture.label(&format!("{} color", name));
+    }
+
+    pub fn delete(&self) {
+        self.gl.delete_framebuffer(self.framebuffer);
+        self.texture.delete();
+    }
+}
@@ -0,0 +1,76 @@
+use gl::types::*;
+use super::gl_device::Device;
+use super::VAO::VAO;
+use super::VBO::VBO;
+use super::EBO::EBO;
+
+// Quad covering the whole viewport, with texture coordinates whose v = 0 is
+// the top edge so that images uploaded top row first come out upright.
+// Pairs with assets/shadercode/screen_quad.glsl.
+pub struct ScreenQuad {
+    vao: VAO,
+    vbo: VBO,
+    ebo: EBO,
+    gl: Device,
+}
+
+impl ScreenQuad {
+    pub fn new(gl: Device) -> Self {
+        Self::with_bottom_v(gl, 1.0)
+    }
+
+    // Quad with v = 0 at the bottom edge, GL's own convention. Passes reading
+    // each other's render targets need it to keep images the same way up.
+    pub fn bottom_up(gl: Device) -> Self {
+        Self::with_bottom_v(gl, 0.0)
+    }
+
+    fn with_bottom_v(gl: Device, bottom: GLfloat) -> Self {
+        let top = 1.0 - bottom;
+        let vertices: [GLfloat; 16] = [
+            //  COORDINATES  /  TexCoord   //
+            -1.0, -1.0,     0.0, bottom,  // Lower left corner
+            -1.0,  1.0,     0.0, top,     // Upper left corner
+             1.0,  1.0,     1.0, top,     // Upper right corner
+             1.0, -1.0,     1.0, bottom,  // Lower right corner
+        ];
+        let indices: [GLuint; 6] = [
+            0, 2, 1, // Upper triangle
+            0, 3, 2, // Lower triangle
+        ];
+
+        let vao = VAO::with_device(gl.clone());
+        vao.bind();
+        let vbo = VBO::with_device(gl.clone(), &vertices);
+        let ebo = EBO::with_device(gl.clone(), &indices);
+        let stride = 4 * std::mem::size_of::<f32>() as GLsizei;
+        vao.link_attrib(&vbo, 0, 2, gl::FLOAT, stride, std::ptr::null());
+        vao.link_attrib(&vbo, 1, 2, gl::FLOAT, stride, (2 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+        vao.unbind();
+        ebo.unbind();
+
+        let quad = ScreenQuad { vao, vbo, ebo, gl };
+        quad.label("screen quad");
+        quad
+    }
+
+    // Labels its VAO and buffers after `name`
+    pub fn label(&self, name: &str) {
+        self.vao.label(&format!("{} vao", name));
+        self.vbo.label(&format!("{} vertices", name));
+        self.ebo.label(&format!("{} indices", name));
+    }
+
+    // Draws the quad with whatever program is active
+    pub fn draw(&self) {
+        self.vao.bind();
+        self.gl.draw_elements(gl::TRIANGLES, 6, gl::UNSIGNED_INT, 0);
+        self.vao.unbind();
+    }
+
+    pub fn delete(&self) {
+        self.vao.delete();
+        self.vbo.delete();
+        self.ebo.delete();
+    }
+}
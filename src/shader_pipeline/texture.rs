@@ -3,89 +3,147 @@ use std::ffi::{CString};
 use std::ptr;
 use image::{DynamicImage, GenericImageView};
 use crate::Shader;
+use super::gl_device::{real_device, Device};
 
 pub struct Texture {
     pub id: GLuint,
     pub tex_type: GLenum,
+    // Device the calls are issued on
+    gl: Device,
 }
 
 impl Texture {
     pub fn new(image_path: &str, tex_type: GLenum, slot: GLenum) -> Result<Self, String> {
-        let mut texture = Texture { id: 0, tex_type };
+        // Load the image before touching any GL state
+        let image = image::open(image_path).map_err(|e| e.to_string())?;
+        Ok(Self::from_image(real_device(), &image, tex_type, slot))
+    }
 
+    // Creates the texture from an already decoded image on the given device
+    pub fn from_image(gl: Device, image: &DynamicImage, tex_type: GLenum, slot: GLenum) -> Self {
         // Generate texture ID
-        unsafe {
-            gl::GenTextures(1, &mut texture.id);
-            // Activate the texture unit and bind the texture
-            gl::ActiveTexture(slot);
-            gl::BindTexture(tex_type, texture.id);
-        }
+        let id = gl.gen_texture();
+        let texture = Texture { id, tex_type, gl };
+
+        // Activate the texture unit and bind the texture
+        texture.gl.active_texture(slot);
+        texture.gl.bind_texture(tex_type, texture.id);
 
         // Set the texture wrapping/filtering options (optional)
-        unsafe {
-            // Confige the types of algo that are used to resize the image
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        // Confige the types of algo that are used to resize the image
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
 
-            // Configure the way the texture repeats (if it does at all)
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-        }
+        // Configure the way the texture repeats (if it does at all)
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
 
-        // Load and generate the texture
-        let image = image::open(image_path).map_err(|e| e.to_string())?;
+        // Generate the texture
         let (width, height) = image.dimensions();
-        let data = image.into_bytes();
-
-        unsafe {
-            // Assign the image to a Texture Object
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGB as i32,
-                width as i32,
-                height as i32,
-                0,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                data.as_ptr() as *const std::ffi::c_void,
-            );
-            // Generate MipMaps
-            gl::GenerateMipmap(gl::TEXTURE_2D);
-        }
+        let data = image.to_rgb8().into_raw();
+
+        // RGB rows are tightly packed, so they aren't 4 byte aligned unless
+        // the width happens to be a multiple of 4
+        let alignment = texture.gl.get_integer(gl::UNPACK_ALIGNMENT);
+        texture.gl.pixel_store_i(gl::UNPACK_ALIGNMENT, 1);
+        // Assign the image to a Texture Object
+        texture.gl.tex_image_2d(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGB as i32,
+            width as i32,
+            height as i32,
+            gl::RGB,
+            gl::UNSIGNED_BYTE,
+            &data,
+        );
+        texture.gl.pixel_store_i(gl::UNPACK_ALIGNMENT, alignment);
+        // Generate MipMaps
+        texture.gl.generate_mipmap(gl::TEXTURE_2D);
 
         // Unbind the texture
         texture.unbind();
 
-        Ok(texture)
+        texture
+    }
+
+    // Creates an RGBA8 texture from tightly packed pixels, top row first,
+    // without mipmaps. Use gl::NEAREST for data that must not be blended,
+    // like ID maps and lookup tables.
+    pub fn from_rgba(gl: Device, width: u32, height: u32, pixels: &[u8], filter: GLenum, slot: GLenum) -> Self {
+        let id = gl.gen_texture();
+        let texture = Texture { id, tex_type: gl::TEXTURE_2D, gl };
+
+        texture.gl.active_texture(slot);
+        texture.gl.bind_texture(gl::TEXTURE_2D, texture.id);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+        texture.gl.tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+        texture.unbind();
+
+        texture
+    }
+
+    // Creates an RGBA32F texture from tightly packed floats, without mipmaps
+    pub fn from_rgba_f32(gl: Device, width: u32, height: u32, pixels: &[f32], filter: GLenum, slot: GLenum) -> Self {
+        let texture = Self::empty(gl, width, height, gl::RGBA32F, filter, slot);
+        let bytes = unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const u8, std::mem::size_of_val(pixels)) };
+        texture.bind();
+        texture.gl.tex_sub_image_2d(gl::TEXTURE_2D, 0, 0, 0, width as i32, height as i32, gl::RGBA, gl::FLOAT, bytes);
+        texture.unbind();
+        texture
+    }
+
+    // Allocates an uninitialized texture with the given internal format, e.g.
+    // gl::RGBA32F for render targets that must keep values outside 0..1
+    pub fn empty(gl: Device, width: u32, height: u32, internal_format: GLenum, filter: GLenum, slot: GLenum) -> Self {
+        let id = gl.gen_texture();
+        let texture = Texture { id, tex_type: gl::TEXTURE_2D, gl };
+
+        texture.gl.active_texture(slot);
+        texture.gl.bind_texture(gl::TEXTURE_2D, texture.id);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as i32);
+        texture.gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as i32);
+        texture.gl.tex_image_2d(gl::TEXTURE_2D, 0, internal_format as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, &[]);
+        texture.unbind();
+
+        texture
+    }
+
+    // Overwrites a rectangle of an RGBA8 texture, rows top first
+    pub fn update_rgba(&self, x: u32, y: u32, width: u32, height: u32, pixels: &[u8]) {
+        self.bind();
+        self.gl.tex_sub_image_2d(gl::TEXTURE_2D, 0, x as i32, y as i32, width as i32, height as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+        self.unbind();
     }
 
     pub fn tex_unit(&self, shader: &Shader, uniform: &str, unit: GLuint) {
-        unsafe {
-            // Get location for the uniform
-            let tex_uni = gl::GetUniformLocation(shader.id, CString::new(uniform).unwrap().as_ptr());
-            // Activate Shader
-            shader.activate();
-            // Set the value of the uniform
-            gl::Uniform1i(tex_uni, unit as i32);
-        }
+        // Get location for the uniform
+        let tex_uni = shader.uniform_location(uniform);
+        // Activate Shader
+        shader.activate();
+        // Set the value of the uniform
+        self.gl.uniform_1i(tex_uni, unit as i32);
+    }
+
+    // Names the texture in debug messages and graphics debuggers
+    pub fn label(&self, name: &str) {
+        self.gl.object_label(gl::TEXTURE, self.id, name);
     }
 
     pub fn bind(&self) {
-        unsafe {
-            gl::BindTexture(self.tex_type, self.id);
-        }
+        self.gl.bind_texture(self.tex_type, self.id);
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindTexture(self.tex_type, 0);
-        }
+        self.gl.bind_texture(self.tex_type, 0);
     }
 
     pub fn delete(&self) {
-        unsafe {
-            gl::DeleteTextures(1, &self.id);
-        }
+        self.gl.delete_texture(self.id);
     }
 }
@@ -0,0 +1,274 @@
+use gl::types::*;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use super::capture::{self, FrameSequence};
+use super::gl_device::Device;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    Mp4,
+    WebM,
+    Gif,
+}
+
+impl VideoFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "mp4" => Some(VideoFormat::Mp4),
+            "webm" => Some(VideoFormat::WebM),
+            "gif" => Some(VideoFormat::Gif),
+            _ => None,
+        }
+    }
+
+    // Encoder arguments; yuv420p needs even sizes, so odd ones get a padding row or column
+    fn ffmpeg_args(&self) -> &'static [&'static str] {
+        match self {
+            VideoFormat::Mp4 => &["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-c:v", "libx264", "-pix_fmt", "yuv420p", "-crf", "18"],
+            VideoFormat::WebM => &["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2", "-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p", "-crf", "32", "-b:v", "0"],
+            VideoFormat::Gif => &["-vf", "split[a][b];[a]palettegen[p];[b][p]paletteuse"],
+        }
+    }
+}
+
+// ffmpeg to run, SHADERS_FFMPEG overrides the one on the PATH
+fn ffmpeg_program() -> String {
+    std::env::var("SHADERS_FFMPEG").unwrap_or_else(|_| "ffmpeg".to_string())
+}
+
+pub fn ffmpeg_available() -> bool {
+    Command::new(ffmpeg_program())
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+// Starts recording into the next recordings/recording_NNNN.<extension>,
+// falling back to a GIF when ffmpeg isn't installed
+pub fn start_recording(extension: &str, size: (u32, u32), fps: f32) -> Result<VideoRecorder, String> {
+    let extension = if extension.eq_ignore_ascii_case("gif") || ffmpeg_available() { extension } else { "gif" };
+    let dir = Path::new("recordings");
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    VideoRecorder::new(&capture::next_free_path(dir, "recording", extension), size.0, size.1, fps)
+}
+
+// GIF delays are whole centiseconds and players clamp ones under 2 to about
+// 10, so GIFs run at 50 fps at most. Frame `frame` lasts until the next
+// rounded multiple of 2cs after its end at 1 / fps; a 0 delay means it is
+// dropped, so at 60 fps one frame in six is skipped and the whole animation
+// keeps its length.
+fn gif_delay_cs(frame: u32, fps: f32) -> u32 {
+    let end = |frame: u32| (frame as f64 * 50.0 / fps as f64).round() as u32 * 2;
+    end(frame + 1) - end(frame)
+}
+
+// The file a GifEncoder writes to. The encoder writes the trailer when it is
+// dropped and ignores errors there, so it gets a clone and the recorder
+// keeps the other to flush and to see what failed.
+#[derive(Clone)]
+struct GifFile {
+    writer: Rc<RefCell<BufWriter<File>>>,
+    error: Rc<RefCell<Option<String>>>,
+}
+
+impl Write for GifFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.borrow_mut().write(buf);
+        if let Err(e) = &written {
+            self.error.borrow_mut().get_or_insert_with(|| e.to_string());
+        }
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+enum Encoder {
+    // Raw RGBA frames piped into ffmpeg's stdin
+    Ffmpeg(Child),
+    // Used for .gif when ffmpeg can't be found
+    Gif(GifEncoder<GifFile>, GifFile),
+}
+
+// Encodes frames into a video file as they are pushed. Each frame lasts
+// 1 / fps whatever time it took to render, so recordings are deterministic
+// when the caller also steps its clock by 1 / fps.
+pub struct VideoRecorder {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    pub frames: u32,
+    encoder: Encoder,
+}
+
+impl VideoRecorder {
+    pub fn new(path: &Path, width: u32, height: u32, fps: f32) -> Result<Self, String> {
+        let format = VideoFormat::from_path(path).ok_or_else(|| format!("{}: not a video format, use .mp4, .webm or .gif", path.display()))?;
+        let size = format!("{}x{}", width, height);
+        let rate = fps.to_string();
+        let mut command = Command::new(ffmpeg_program());
+        command
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba", "-s", &size, "-framerate", &rate, "-i", "-"])
+            .args(format.ffmpeg_args())
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null());
+        let encoder = match command.spawn() {
+            Ok(child) => Encoder::Ffmpeg(child),
+            Err(e) if e.kind() == ErrorKind::NotFound && format == VideoFormat::Gif => {
+                let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                let file = GifFile { writer: Rc::new(RefCell::new(BufWriter::new(file))), error: Rc::new(RefCell::new(None)) };
+                let mut gif = GifEncoder::new_with_speed(file.clone(), 10);
+                gif.set_repeat(Repeat::Infinite).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Encoder::Gif(gif, file)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(format!("{}: ffmpeg was not found, install it or record a .gif instead", path.display()));
+            }
+            Err(e) => return Err(format!("{}: {}", ffmpeg_program(), e)),
+        };
+        Ok(VideoRecorder { path: path.to_path_buf(), width, height, fps, frames: 0, encoder })
+    }
+
+    // Appends a frame, top row first
+    pub fn push_frame(&mut self, image: &RgbaImage) -> Result<(), String> {
+        if image.dimensions() != (self.width, self.height) {
+            return Err(format!("{}: frame is {}x{}, the video {}x{}", self.path.display(), image.width(), image.height(), self.width, self.height));
+        }
+        match &mut self.encoder {
+            Encoder::Ffmpeg(child) => {
+                let stdin = child.stdin.as_mut().expect("ffmpeg stdin is piped");
+                stdin.write_all(image.as_raw()).map_err(|e| format!("{}: ffmpeg stopped taking frames: {}", self.path.display(), e))?;
+            }
+            Encoder::Gif(gif, _) => {
+                let delay_cs = gif_delay_cs(self.frames, self.fps);
+                if delay_cs > 0 {
+                    let delay = Delay::from_numer_denom_ms(delay_cs * 10, 1);
+                    gif.encode_frame(Frame::from_parts(image.clone(), 0, 0, delay)).map_err(|e| format!("{}: {}", self.path.display(), e))?;
+                }
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Reads a framebuffer (0 for the window, before swapping) into the video
+    pub fn capture(&mut self, gl: &Device, framebuffer: GLuint) -> Result<(), String> {
+        self.push_frame(&capture::read_rgba(gl, framebuffer, self.width, self.height))
+    }
+
+    // Closes the file, waiting for ffmpeg to finish encoding
+    pub fn finish(self) -> Result<(), String> {
+        match self.encoder {
+            Encoder::Ffmpeg(mut child) => {
+                // Closing stdin tells ffmpeg the last frame came
+                drop(child.stdin.take());
+                let status = child.wait().map_err(|e| format!("{}: {}", ffmpeg_program(), e))?;
+                if !status.success() {
+                    return Err(format!("{}: ffmpeg failed ({})", self.path.display(), status));
+                }
+            }
+            Encoder::Gif(gif, file) => {
+                // Writes the trailer
+                drop(gif);
+                let flushed = file.writer.borrow_mut().flush();
+                if let Some(error) = file.error.borrow_mut().take() {
+                    return Err(format!("{}: {}", self.path.display(), error));
+                }
+                flushed.map_err(|e| format!("{}: {}", self.path.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Where recorded frames go: a video when the path ends in .mp4, .webm or
+// .gif, numbered images in that folder otherwise
+pub enum FrameOutput {
+    Images(FrameSequence),
+    Video(VideoRecorder),
+}
+
+impl FrameOutput {
+    // `image_format` is the extension of numbered images, png or exr
+    pub fn open(path: &Path, image_format: &str, size: (u32, u32), fps: f32) -> Result<Self, String> {
+        match VideoFormat::from_path(path) {
+            Some(_) => Ok(FrameOutput::Video(VideoRecorder::new(path, size.0, size.1, fps)?)),
+            None => Ok(FrameOutput::Images(FrameSequence::new(path, image_format)?)),
+        }
+    }
+
+    pub fn write_frame(&mut self, gl: &Device, framebuffer: GLuint, width: u32, height: u32) -> Result<(), String> {
+        match self {
+            FrameOutput::Images(sequence) => sequence.save_frame(gl, framebuffer, width, height).map(|_| ()),
+            FrameOutput::Video(video) => video.capture(gl, framebuffer),
+        }
+    }
+
+    // Finishes the output, returning a line saying what was written
+    pub fn finish(self) -> Result<String, String> {
+        match self {
+            FrameOutput::Images(sequence) => Ok(format!("wrote {} frames to {}", sequence.next, sequence.dir.display())),
+            FrameOutput::Video(video) => {
+                let summary = format!("wrote {} frames to {}", video.frames, video.path.display());
+                video.finish()?;
+                Ok(summary)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use image::codecs::gif::GifDecoder;
+    use image::{AnimationDecoder, Rgba};
+    use std::io::BufReader;
+
+    #[test]
+    fn gif_delays_add_up_to_the_frame_rate() {
+        let delays: Vec<u32> = (0..6).map(|frame| gif_delay_cs(frame, 60.0)).collect();
+        assert_eq!(delays, [2, 2, 2, 0, 2, 2]);
+        assert_eq!((0..60).map(|frame| gif_delay_cs(frame, 60.0)).sum::<u32>(), 100);
+        assert_eq!((0..60).filter(|&frame| gif_delay_cs(frame, 60.0) > 0).count(), 50);
+        assert!((0..30).all(|frame| gif_delay_cs(frame, 25.0) == 4));
+        assert!((0..30).all(|frame| gif_delay_cs(frame, 30.0) >= 2));
+    }
+
+    #[test]
+    fn gifs_are_written_without_ffmpeg() {
+        let dir = TempDir::new("video-gif", &[]);
+        let path = dir.0.join("out.gif");
+        // No other test runs ffmpeg, so setting this can't race
+        std::env::set_var("SHADERS_FFMPEG", "shaders-test-missing-ffmpeg");
+        let mut recorder = VideoRecorder::new(&path, 3, 2, 25.0).unwrap();
+        let colors = [Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), Rgba([0, 0, 255, 255])];
+        for color in colors {
+            recorder.push_frame(&RgbaImage::from_pixel(3, 2, color)).unwrap();
+        }
+        assert!(recorder.push_frame(&RgbaImage::new(2, 2)).unwrap_err().contains("frame is 2x2, the video 3x2"));
+        assert_eq!(recorder.frames, 3);
+        recorder.finish().unwrap();
+
+        let decoder = GifDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        for (frame, color) in frames.iter().zip(colors) {
+            assert_eq!(frame.buffer().dimensions(), (3, 2));
+            assert_eq!(*frame.buffer().get_pixel(1, 1), color);
+            assert_eq!(frame.delay().numer_denom_ms(), (40, 1));
+        }
+    }
+}
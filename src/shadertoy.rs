@@ -0,0 +1,689 @@
+use gl::types::*;
+use glfw::{Action, Context, Key, MouseButton, WindowEvent};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::assets::{check_linked, read_source, resolve_path, AssetManager, Handle};
+use crate::cli::ShadertoyArgs;
+use crate::shader_pipeline::capture;
+use crate::shader_pipeline::debug;
+use crate::shader_pipeline::gl_device::{real_device, Device};
+use crate::shader_pipeline::profiler::{Profiler, ProfilerOverlay};
+use crate::shader_pipeline::render_target::RenderTarget;
+use crate::shader_pipeline::screen_quad::ScreenQuad;
+use crate::shader_pipeline::texture::Texture;
+use crate::shader_pipeline::video::{start_recording, FrameOutput, VideoFormat, VideoRecorder};
+use crate::surface::Surface;
+use crate::Shader;
+
+pub const CHANNELS: usize = 4;
+pub const BUFFER_NAMES: [&str; 4] = ["A", "B", "C", "D"];
+
+const VERTEX_SOURCE: &str = "#version 330 core
+layout (location = 0) in vec2 aPos;
+void main()
+{
+    gl_Position = vec4(aPos, 0.0, 1.0);
+}
+";
+
+// Declarations Shadertoy puts in front of every pass
+const HEADER: &str = "#version 330 core
+uniform vec3 iResolution;
+uniform float iTime;
+uniform float iTimeDelta;
+uniform float iFrameRate;
+uniform int iFrame;
+uniform vec4 iMouse;
+uniform vec4 iDate;
+uniform float iSampleRate;
+uniform vec3 iChannelResolution[4];
+uniform float iChannelTime[4];
+uniform sampler2D iChannel0;
+uniform sampler2D iChannel1;
+uniform sampler2D iChannel2;
+uniform sampler2D iChannel3;
+out vec4 shadertoyColor;
+";
+
+const FOOTER: &str = "
+void main()
+{
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(color, gl_FragCoord.xy);
+    shadertoyColor = color;
+}
+";
+
+// Turns a Shadertoy pass into a complete fragment shader. `#line` keeps the
+// line numbers of compile errors matching the files: source 1 is the common
+// code, source 2 the pass itself.
+pub fn wrap_pass(common: Option<&str>, code: &str) -> String {
+    let mut source = HEADER.to_string();
+    if let Some(common) = common {
+        source.push_str("#line 1 1\n");
+        source.push_str(common);
+        source.push('\n');
+    }
+    source.push_str("#line 1 2\n");
+    source.push_str(code);
+    source.push_str(FOOTER);
+    source
+}
+
+// Builds the program of a pass, failing when it doesn't link
+pub fn compile_pass(gl: &Device, common: Option<&Path>, code: &Path) -> Result<Shader, String> {
+    let common_code = common.map(read_source).transpose()?;
+    let fragment = wrap_pass(common_code.as_deref(), &read_source(code)?);
+    check_linked(gl, Shader::from_source(gl.clone(), VERTEX_SOURCE, &fragment), &code.display().to_string())
+}
+
+// What a pass samples through one iChannel
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelInput {
+    Empty,
+    // An image file, flipped like Shadertoy does so v = 0 is its bottom row
+    Texture(PathBuf),
+    // Buffer A to D by index: this frame's output when the buffer already
+    // ran, the previous frame's otherwise (including a buffer reading itself)
+    Buffer(usize),
+}
+
+impl ChannelInput {
+    // "A" or "Buffer A" names a buffer, anything else an image file
+    pub fn parse(text: &str, roots: &[&Path]) -> Result<Self, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(ChannelInput::Empty);
+        }
+        let name = text.strip_prefix("Buffer ").or_else(|| text.strip_prefix("buffer ")).unwrap_or(text);
+        if let Some(index) = BUFFER_NAMES.iter().position(|b| b.eq_ignore_ascii_case(name)) {
+            return Ok(ChannelInput::Buffer(index));
+        }
+        Ok(ChannelInput::Texture(resolve_path(Path::new(text), roots)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassSource {
+    pub code: PathBuf,
+    pub channels: [ChannelInput; CHANNELS],
+}
+
+// The passes of a shader: up to four buffers, run in order A to D, then the
+// image shown on screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub common: Option<PathBuf>,
+    pub buffers: [Option<PassSource>; 4],
+    pub image: PassSource,
+}
+
+// A project .json file:
+// { "common": "common.glsl",
+//   "buffers": { "A": { "code": "a.glsl", "channels": ["A", "noise.png"] } },
+//   "image": { "code": "image.glsl", "channels": ["A"] } }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectFile {
+    common: Option<PathBuf>,
+    #[serde(default)]
+    buffers: BTreeMap<String, PassFile>,
+    image: PassFile,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PassFile {
+    code: PathBuf,
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
+fn channels(names: &[String], roots: &[&Path]) -> Result<[ChannelInput; CHANNELS], String> {
+    if names.len() > CHANNELS {
+        return Err(format!("{} channels given, Shadertoy has {}", names.len(), CHANNELS));
+    }
+    let mut channels = [ChannelInput::Empty, ChannelInput::Empty, ChannelInput::Empty, ChannelInput::Empty];
+    for (channel, name) in channels.iter_mut().zip(names) {
+        *channel = ChannelInput::parse(name, roots)?;
+    }
+    Ok(channels)
+}
+
+impl Project {
+    // Reads a project .json, or takes any other file as a lone image pass
+    // with `channels` as its inputs. Paths are looked up next to the file,
+    // then in the working directory and the assets folder.
+    pub fn load(path: &Path, channel_names: &[String]) -> Result<Self, String> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let roots = [dir, Path::new("."), Path::new("assets")];
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            let code = resolve_path(path, &[Path::new(".")])?;
+            return Ok(Project { common: None, buffers: [None, None, None, None], image: PassSource { code, channels: channels(channel_names, &roots)? } });
+        }
+
+        let text = read_source(path)?;
+        let file: ProjectFile = serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let pass = |pass: &PassFile| -> Result<PassSource, String> {
+            Ok(PassSource { code: resolve_path(&pass.code, &roots)?, channels: channels(&pass.channels, &roots)? })
+        };
+        let mut buffers = [None, None, None, None];
+        for (name, buffer) in &file.buffers {
+            let name = name.strip_prefix("Buffer ").unwrap_or(name);
+            let index = BUFFER_NAMES
+                .iter()
+                .position(|b| b.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("{}: unknown buffer '{}', expected A to D", path.display(), name))?;
+            buffers[index] = Some(pass(buffer)?);
+        }
+        let common = file.common.as_deref().map(|c| resolve_path(c, &roots)).transpose()?;
+        Ok(Project { common, buffers, image: pass(&file.image)? })
+    }
+
+    fn passes(&self) -> impl Iterator<Item = &PassSource> {
+        self.buffers.iter().flatten().chain(std::iter::once(&self.image))
+    }
+}
+
+// Mouse state as Shadertoy reports it: xy follows the cursor while a button
+// is held, zw is where it was pressed, z is negative once released and w
+// only positive on the frame of the click. Pixels from the bottom left.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Mouse {
+    pub position: [f32; 2],
+    pub click: [f32; 2],
+    pub down: bool,
+    pub clicked: bool,
+    // Last cursor position, kept while the button is up
+    cursor: [f32; 2],
+}
+
+impl Mouse {
+    // Takes cursor events already in framebuffer pixels, see Surface::cursor_to_pixels
+    pub fn handle_event(&mut self, event: &WindowEvent, framebuffer_height: u32) {
+        match *event {
+            WindowEvent::CursorPos(x, y) => {
+                self.cursor = [x as f32, framebuffer_height as f32 - y as f32];
+                if self.down {
+                    self.position = self.cursor;
+                }
+            }
+            WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => {
+                self.down = true;
+                self.clicked = true;
+                self.position = self.cursor;
+                self.click = self.cursor;
+            }
+            WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _) => self.down = false,
+            _ => {}
+        }
+    }
+
+    pub fn uniform(&self) -> [f32; 4] {
+        let z = if self.down { self.click[0] } else { -self.click[0] };
+        let w = if self.clicked { self.click[1] } else { -self.click[1] };
+        [self.position[0], self.position[1], z, w]
+    }
+}
+
+// Year, month from 0, day and seconds since midnight, in UTC
+pub fn date_now() -> [f32; 4] {
+    date_at(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0))
+}
+
+// iDate of a time in seconds since 1970
+fn date_at(seconds: f64) -> [f32; 4] {
+    let days = (seconds / 86400.0).floor() as i64;
+    // Days since 1970 to the civil calendar, from Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    [year as f32, (month - 1) as f32, day as f32, (seconds - days as f64 * 86400.0) as f32]
+}
+
+// Per frame values, set on every pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInputs {
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub mouse: [f32; 4],
+    pub date: [f32; 4],
+}
+
+impl FrameInputs {
+    fn apply(&self, shader: &Shader, resolution: (u32, u32)) {
+        let gl = shader.device();
+        gl.uniform_3f(shader.uniform_location("iResolution"), resolution.0 as f32, resolution.1 as f32, 1.0);
+        gl.uniform_1f(shader.uniform_location("iTime"), self.time);
+        gl.uniform_1f(shader.uniform_location("iTimeDelta"), self.time_delta);
+        gl.uniform_1f(shader.uniform_location("iFrameRate"), if self.time_delta > 0.0 { 1.0 / self.time_delta } else { 0.0 });
+        gl.uniform_1i(shader.uniform_location("iFrame"), self.frame);
+        let [x, y, z, w] = self.mouse;
+        gl.uniform_4f(shader.uniform_location("iMouse"), x, y, z, w);
+        let [x, y, z, w] = self.date;
+        gl.uniform_4f(shader.uniform_location("iDate"), x, y, z, w);
+        gl.uniform_1f(shader.uniform_location("iSampleRate"), 44100.0);
+    }
+}
+
+struct Pass {
+    source: PassSource,
+    program: Handle<Shader>,
+}
+
+// Runs a Project: buffers ping-pong between two float targets each so they
+// can read their own previous frame, then the image pass draws into the
+// given framebuffer. Pass programs and channel images are assets, so edits
+// to them reload.
+pub struct ShadertoyRunner {
+    assets: AssetManager,
+    buffers: [Option<(Pass, [RenderTarget; 2])>; 4],
+    // Index of the target holding each buffer's latest output
+    current: [usize; 4],
+    image: Pass,
+    textures: HashMap<PathBuf, Handle<Texture>>,
+    quad: ScreenQuad,
+    resolution: (u32, u32),
+    gl: Device,
+}
+
+impl ShadertoyRunner {
+    pub fn new(gl: Device, project: Project, resolution: (u32, u32)) -> Result<Self, String> {
+        // Project paths are resolved already
+        let mut assets = AssetManager::new(gl.clone(), vec![".".into()]);
+        let mut textures = HashMap::new();
+        for pass in project.passes() {
+            for channel in &pass.channels {
+                if let ChannelInput::Texture(path) = channel {
+                    if !textures.contains_key(path) {
+                        textures.insert(path.clone(), assets.flipped_texture(path)?);
+                    }
+                }
+            }
+        }
+
+        let common = project.common.as_deref();
+        let mut compile = |source: &PassSource| -> Result<Pass, String> {
+            Ok(Pass { source: source.clone(), program: assets.shadertoy_pass(common, &source.code)? })
+        };
+        let mut buffers = [None, None, None, None];
+        for (index, source) in project.buffers.iter().enumerate() {
+            if let Some(source) = source {
+                let pass = compile(source)?;
+                let targets = [
+                    RenderTarget::with_format(gl.clone(), resolution.0, resolution.1, gl::RGBA32F)?,
+                    RenderTarget::with_format(gl.clone(), resolution.0, resolution.1, gl::RGBA32F)?,
+                ];
+                for (i, target) in targets.iter().enumerate() {
+                    target.label(&format!("Buffer {} {}", BUFFER_NAMES[index], i));
+                }
+                buffers[index] = Some((pass, targets));
+            }
+        }
+        let image = compile(&project.image)?;
+        let quad = ScreenQuad::new(gl.clone());
+        let mut runner = ShadertoyRunner { assets, buffers, current: [0; 4], image, textures, quad, resolution, gl };
+        runner.restart();
+        Ok(runner)
+    }
+
+    // Reloads the passes and images whose files changed; ones that fail keep
+    // their previous version and the errors are returned
+    pub fn reload_changed(&mut self) -> Vec<String> {
+        self.assets.reload_changed().errors
+    }
+
+    // Clears the buffers, for restarting at frame 0
+    pub fn restart(&mut self) {
+        for (_, targets) in self.buffers.iter().flatten() {
+            for target in targets {
+                target.bind();
+                self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
+                self.gl.clear(gl::COLOR_BUFFER_BIT);
+                target.unbind();
+            }
+        }
+    }
+
+    // Buffers follow the output size and lose their contents, as on Shadertoy
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.resolution = (width.max(1), height.max(1));
+        for (_, targets) in self.buffers.iter_mut().flatten() {
+            for target in targets.iter_mut() {
+                target.resize(width, height);
+            }
+        }
+        self.restart();
+    }
+
+    fn draw_pass(&self, pass: &Pass, inputs: &FrameInputs) {
+        let shader = self.assets.get(&pass.program);
+        shader.activate();
+        inputs.apply(shader, self.resolution);
+        for (unit, channel) in pass.source.channels.iter().enumerate() {
+            // Images don't play, so their channel time stays 0; buffers run
+            // along with the shader
+            let (texture, size, time) = match channel {
+                ChannelInput::Empty => continue,
+                ChannelInput::Texture(path) => {
+                    let texture = &self.textures[path];
+                    (self.assets.get(texture).id, self.assets.texture_size(texture), 0.0)
+                }
+                ChannelInput::Buffer(index) => match &self.buffers[*index] {
+                    Some((_, targets)) => (targets[self.current[*index]].texture.id, self.resolution, inputs.time),
+                    None => continue,
+                },
+            };
+            self.gl.active_texture(gl::TEXTURE0 + unit as GLuint);
+            self.gl.bind_texture(gl::TEXTURE_2D, texture);
+            self.gl.uniform_1i(shader.uniform_location(&format!("iChannel{}", unit)), unit as GLint);
+            self.gl.uniform_3f(shader.uniform_location(&format!("iChannelResolution[{}]", unit)), size.0 as f32, size.1 as f32, 1.0);
+            self.gl.uniform_1f(shader.uniform_location(&format!("iChannelTime[{}]", unit)), time);
+        }
+        self.quad.draw();
+    }
+
+    // Runs the buffers, then draws the image into `framebuffer`, 0 being the
+    // window; every pass is a profiler scope
+    pub fn render(&mut self, inputs: &FrameInputs, framebuffer: GLuint, profiler: &mut Profiler) {
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let Some((pass, targets)) = buffer else { continue };
+            let next = 1 - self.current[index];
+            profiler.begin(&format!("Buffer {}", BUFFER_NAMES[index]));
+            targets[next].bind();
+            self.draw_pass(pass, inputs);
+            targets[next].unbind();
+            profiler.end();
+            self.current[index] = next;
+        }
+        profiler.begin("Image");
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+        self.gl.viewport(0, 0, self.resolution.0 as i32, self.resolution.1 as i32);
+        self.draw_pass(&self.image, inputs);
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+        profiler.end();
+    }
+
+    pub fn delete(&mut self) {
+        for (_, targets) in self.buffers.iter().flatten() {
+            targets.iter().for_each(RenderTarget::delete);
+        }
+        self.assets.delete();
+        self.quad.delete();
+    }
+}
+
+// Renders `frames` frames at a fixed timestep into numbered images or a
+// video, so the result doesn't depend on how fast the machine is
+fn record(gl: &Device, project: Project, size: (u32, u32), frames: u32, args: &ShadertoyArgs) -> Result<(), String> {
+    let video = VideoFormat::from_path(&args.out).is_some();
+    let format = if !video && args.format.eq_ignore_ascii_case("exr") { gl::RGBA32F } else { gl::RGBA8 };
+    let target = RenderTarget::with_format(gl.clone(), size.0, size.1, format)?;
+    let mut runner = ShadertoyRunner::new(gl.clone(), project, size)?;
+    let mut output = FrameOutput::open(&args.out, &args.format, size, args.fps)?;
+    let mut profiler = Profiler::new(gl.clone());
+    if args.trace.is_some() {
+        profiler.start_trace();
+    }
+    let step = 1.0 / args.fps;
+    let start_date = date_now();
+    let mut result = Ok(());
+    for frame in 0..frames {
+        let time = frame as f32 * step;
+        let date = [start_date[0], start_date[1], start_date[2], start_date[3] + time];
+        let inputs = FrameInputs { time, time_delta: step, frame: frame as i32, mouse: [0.0; 4], date };
+        profiler.begin_frame();
+        runner.render(&inputs, target.framebuffer, &mut profiler);
+        profiler.begin("capture");
+        let written = output.write_frame(gl, target.framebuffer, size.0, size.1);
+        profiler.end();
+        profiler.end_frame();
+        if let Err(error) = written {
+            result = Err(error);
+            break;
+        }
+    }
+    if let Some(path) = &args.trace {
+        match profiler.write_trace(path) {
+            Ok(frames) => println!("wrote a trace of {} frames to {}", frames, path.display()),
+            Err(error) => eprintln!("error: {}", error),
+        }
+    }
+    // Still finish on errors so ffmpeg exits and what was encoded stays readable
+    let finished = output.finish();
+    if result.is_ok() {
+        println!("{}", finished?);
+    }
+    runner.delete();
+    profiler.delete();
+    target.delete();
+    result
+}
+
+// `shaders shadertoy`: opens a window running a project or a single
+// mainImage file, or records it with --frames. Space pauses, Backspace
+// restarts, F12 saves a screenshot, F9 starts and stops recording a video,
+// F3 shows frame timings, F4 starts and stops a trace, and edits reload live.
+pub fn run(args: &ShadertoyArgs) -> Result<(), String> {
+    let project = Project::load(&args.path, &args.channels)?;
+    let size = args.size.unwrap_or((800, 450));
+
+    let mut glfw = debug::init_glfw()?;
+    let (mut window, events) = debug::create_window(&mut glfw, (3, 3), size, "Shadertoy", args.frames.is_none())?;
+    window.set_key_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_mouse_button_polling(true);
+    Surface::enable_polling(&mut window);
+    let gl = real_device();
+    if let Some(frames) = args.frames {
+        return record(&gl, project, size, frames, args);
+    }
+
+    let mut surface = Surface::from_window(&window);
+    let mut runner = ShadertoyRunner::new(gl.clone(), project, surface.framebuffer)?;
+    let mut mouse = Mouse::default();
+    let mut inputs = FrameInputs { time: 0.0, time_delta: 0.0, frame: 0, mouse: [0.0; 4], date: date_now() };
+    let mut paused = false;
+    let mut take_screenshot = false;
+    let mut recording: Option<VideoRecorder> = None;
+    let mut profiler = Profiler::new(gl.clone());
+    let overlay = ProfilerOverlay::new(gl.clone())?;
+    let mut show_profiler = false;
+    let mut last_time = glfw.get_time();
+
+    while !window.should_close() {
+        for error in runner.reload_changed() {
+            eprintln!("error: {}", error);
+        }
+        let now = glfw.get_time();
+        if !paused && !surface.is_empty() {
+            // Recordings step time by exactly one video frame, however long drawing takes
+            inputs.time_delta = if recording.is_some() { 1.0 / args.fps } else { (now - last_time) as f32 };
+            inputs.time += inputs.time_delta;
+            inputs.mouse = mouse.uniform();
+            inputs.date = date_now();
+            profiler.begin_frame();
+            runner.render(&inputs, 0, &mut profiler);
+            if take_screenshot {
+                take_screenshot = false;
+                match capture::screenshot(&gl, surface.framebuffer) {
+                    Ok(path) => println!("saved {}", path.display()),
+                    Err(error) => eprintln!("error: {}", error),
+                }
+            }
+            if let Some(video) = &mut recording {
+                if let Err(error) = video.capture(&gl, 0) {
+                    eprintln!("error: {}", error);
+                    stop_recording(&mut recording);
+                }
+            }
+            // After captures, so screenshots and videos don't show it
+            if show_profiler {
+                overlay.draw(&profiler, surface.framebuffer, surface.content_scale.0);
+            }
+            profiler.end_frame();
+            window.swap_buffers();
+            inputs.frame += 1;
+            mouse.clicked = false;
+        }
+        last_time = now;
+
+        if paused {
+            glfw.wait_events_timeout(0.1);
+        } else {
+            glfw.poll_events();
+        }
+        for (_, event) in glfw::flush_messages(&events) {
+            if surface.handle_event(&event) {
+                surface.apply_viewport(&gl);
+                runner.resize(surface.framebuffer.0, surface.framebuffer.1);
+                // Videos keep the size they started with
+                stop_recording(&mut recording);
+            }
+            let event = surface.cursor_to_pixels(event);
+            mouse.handle_event(&event, surface.framebuffer.1);
+            match event {
+                WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
+                WindowEvent::Key(Key::Space, _, Action::Press, _) => paused = !paused,
+                WindowEvent::Key(Key::F12, _, Action::Press, _) => take_screenshot = true,
+                WindowEvent::Key(Key::F3, _, Action::Press, _) => show_profiler = !show_profiler,
+                WindowEvent::Key(Key::F4, _, Action::Press, _) if profiler.is_tracing() => {
+                    match profiler.save_trace() {
+                        Ok((path, frames)) => println!("saved {} frames to {}", frames, path.display()),
+                        Err(error) => eprintln!("error: {}", error),
+                    }
+                }
+                WindowEvent::Key(Key::F4, _, Action::Press, _) => {
+                    profiler.start_trace();
+                    println!("tracing, F4 again to save");
+                }
+                WindowEvent::Key(Key::F9, _, Action::Press, _) if recording.is_some() => stop_recording(&mut recording),
+                WindowEvent::Key(Key::F9, _, Action::Press, _) => match start_recording(&args.video, surface.framebuffer, args.fps) {
+                    Ok(video) => {
+                        println!("recording {}", video.path.display());
+                        recording = Some(video);
+                    }
+                    Err(error) => eprintln!("error: {}", error),
+                },
+                WindowEvent::Key(Key::Backspace, _, Action::Press, _) => {
+                    inputs.time = 0.0;
+                    inputs.frame = 0;
+                    runner.restart();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    stop_recording(&mut recording);
+    runner.delete();
+    profiler.delete();
+    overlay.delete();
+    Ok(())
+}
+
+fn stop_recording(recording: &mut Option<VideoRecorder>) {
+    if let Some(video) = recording.take() {
+        let path = video.path.clone();
+        match video.finish() {
+            Ok(()) => println!("saved {}", path.display()),
+            Err(error) => eprintln!("error: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_pipeline::gl_device::{GlCall, RecordingGl};
+    use crate::test_util::TempDir;
+
+    fn cursor(mouse: &mut Mouse, x: f64, y: f64) {
+        mouse.handle_event(&WindowEvent::CursorPos(x, y), 100);
+    }
+
+    fn button(mouse: &mut Mouse, action: Action) {
+        mouse.handle_event(&WindowEvent::MouseButton(MouseButton::Button1, action, glfw::Modifiers::empty()), 100);
+    }
+
+    #[test]
+    fn mouse_follows_the_cursor_only_while_held() {
+        let mut mouse = Mouse::default();
+        cursor(&mut mouse, 10.0, 30.0);
+        assert_eq!(mouse.uniform(), [0.0, 0.0, -0.0, -0.0]);
+
+        // Pixels from the bottom left; w is positive on the click's frame only
+        button(&mut mouse, Action::Press);
+        assert_eq!(mouse.uniform(), [10.0, 70.0, 10.0, 70.0]);
+        mouse.clicked = false;
+        cursor(&mut mouse, 20.0, 40.0);
+        assert_eq!(mouse.uniform(), [20.0, 60.0, 10.0, -70.0]);
+
+        // Released: z turns negative and xy stays where the drag ended
+        button(&mut mouse, Action::Release);
+        cursor(&mut mouse, 50.0, 50.0);
+        assert_eq!(mouse.uniform(), [20.0, 60.0, -10.0, -70.0]);
+    }
+
+    #[test]
+    fn dates_count_months_from_zero() {
+        assert_eq!(date_at(0.0), [1970.0, 0.0, 1.0, 0.0]);
+        // 2024-02-29 12:30:15, a leap day
+        assert_eq!(date_at(1_709_209_815.0), [2024.0, 1.0, 29.0, 45015.0]);
+        assert_eq!(date_at(1_735_689_599.0), [2024.0, 11.0, 31.0, 86399.0]);
+    }
+
+    #[test]
+    fn passes_keep_their_line_numbers() {
+        let source = wrap_pass(Some("float common;"), "void mainImage(out vec4 c, in vec2 p) {}");
+        let common = source.find("#line 1 1\nfloat common;").unwrap();
+        let code = source.find("#line 1 2\nvoid mainImage").unwrap();
+        assert!(source.starts_with("#version 330 core\n") && common < code);
+        assert!(source.ends_with("shadertoyColor = color;\n}\n"));
+        assert!(!wrap_pass(None, "").contains("#line 1 1"));
+    }
+
+    #[test]
+    fn channels_name_buffers_or_files() {
+        let roots = [Path::new("src")];
+        assert_eq!(ChannelInput::parse(" ", &roots), Ok(ChannelInput::Empty));
+        assert_eq!(ChannelInput::parse("b", &roots), Ok(ChannelInput::Buffer(1)));
+        assert_eq!(ChannelInput::parse("Buffer D", &roots), Ok(ChannelInput::Buffer(3)));
+        assert_eq!(ChannelInput::parse("main.rs", &roots), Ok(ChannelInput::Texture(PathBuf::from("src/main.rs"))));
+        assert!(ChannelInput::parse("E", &roots).is_err());
+    }
+
+    #[test]
+    fn runner_reloads_edited_passes_through_its_assets() {
+        let dir = TempDir::new("shadertoy-reload", &[("image.glsl", "void mainImage(out vec4 c, in vec2 p) { c = vec4(1.0); }")]);
+        let code = dir.0.join("image.glsl");
+        let recording = RecordingGl::new();
+        let device: Device = recording.clone();
+        let project = Project::load(&code, &[]).unwrap();
+        let mut runner = ShadertoyRunner::new(device, project, (4, 4)).unwrap();
+        let first = runner.assets.get(&runner.image.program).id;
+        assert!(runner.reload_changed().is_empty());
+
+        // A failed link keeps the previous program
+        runner.assets.forget_load_times();
+        *recording.fail_with.borrow_mut() = Some("undefined mainImage".to_string());
+        assert_eq!(runner.reload_changed(), vec![format!("{}: failed to link: undefined mainImage", code.display())]);
+        assert_eq!(runner.assets.get(&runner.image.program).id, first);
+
+        runner.assets.forget_load_times();
+        *recording.fail_with.borrow_mut() = None;
+        assert!(runner.reload_changed().is_empty());
+        assert_ne!(runner.assets.get(&runner.image.program).id, first);
+        assert!(recording.recorded(&GlCall::DeleteProgram(first)));
+        runner.delete();
+    }
+}
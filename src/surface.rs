@@ -0,0 +1,97 @@
+use glfw::WindowEvent;
+use crate::shader_pipeline::gl_device::Device;
+use crate::Shader;
+
+// Sizes of a window as they change. The framebuffer is what GL draws into,
+// in pixels; the window size is in screen coordinates, which is what cursor
+// positions use. They differ on HiDPI displays, where the content scale
+// says how much larger text and UI should be drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Surface {
+    pub framebuffer: (u32, u32),
+    pub window: (u32, u32),
+    pub content_scale: (f32, f32),
+}
+
+impl Surface {
+    pub fn from_window(window: &glfw::Window) -> Self {
+        let (fw, fh) = window.get_framebuffer_size();
+        let (ww, wh) = window.get_size();
+        Surface {
+            framebuffer: (fw.max(0) as u32, fh.max(0) as u32),
+            window: (ww.max(0) as u32, wh.max(0) as u32),
+            content_scale: window.get_content_scale(),
+        }
+    }
+
+    // Turns on the events handle_event looks at
+    pub fn enable_polling(window: &mut glfw::Window) {
+        window.set_framebuffer_size_polling(true);
+        window.set_size_polling(true);
+        window.set_content_scale_polling(true);
+    }
+
+    // Returns whether the framebuffer changed size, in which case the
+    // viewport, render targets and cameras need updating
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        match *event {
+            WindowEvent::FramebufferSize(width, height) => {
+                let size = (width.max(0) as u32, height.max(0) as u32);
+                let changed = size != self.framebuffer;
+                self.framebuffer = size;
+                changed
+            }
+            WindowEvent::Size(width, height) => {
+                self.window = (width.max(0) as u32, height.max(0) as u32);
+                false
+            }
+            WindowEvent::ContentScale(x, y) => {
+                self.content_scale = (x, y);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    // Minimized windows have an empty framebuffer, there is nothing to draw
+    pub fn is_empty(&self) -> bool {
+        self.framebuffer.0 == 0 || self.framebuffer.1 == 0
+    }
+
+    // Framebuffer pixels per screen coordinate
+    pub fn pixel_ratio(&self) -> (f32, f32) {
+        if self.window.0 == 0 || self.window.1 == 0 {
+            return (1.0, 1.0);
+        }
+        (self.framebuffer.0 as f32 / self.window.0 as f32, self.framebuffer.1 as f32 / self.window.1 as f32)
+    }
+
+    // Cursor positions in framebuffer pixels, so controllers working in
+    // viewport pixels stay under the cursor on HiDPI displays
+    pub fn cursor_to_pixels(&self, event: WindowEvent) -> WindowEvent {
+        match event {
+            WindowEvent::CursorPos(x, y) => {
+                let (sx, sy) = self.pixel_ratio();
+                WindowEvent::CursorPos(x * sx as f64, y * sy as f64)
+            }
+            event => event,
+        }
+    }
+
+    pub fn apply_viewport(&self, gl: &Device) {
+        gl.viewport(0, 0, self.framebuffer.0 as i32, self.framebuffer.1 as i32);
+    }
+
+    // Viewport size in pixels and pixel aspect ratio, like Shadertoy's iResolution
+    pub fn resolution(&self) -> [f32; 3] {
+        [self.framebuffer.0 as f32, self.framebuffer.1 as f32, 1.0]
+    }
+
+    // Sets `iResolution` (vec3) and `iContentScale` (float); the shader must be active
+    pub fn apply_resolution(&self, shader: &Shader) {
+        let gl = shader.device();
+        let [x, y, z] = self.resolution();
+        gl.uniform_3f(shader.uniform_location("iResolution"), x, y, z);
+        gl.uniform_1f(shader.uniform_location("iContentScale"), self.content_scale.0.max(self.content_scale.1));
+    }
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::path::PathBuf;
+
+// Folder in the system temp dir with the given files, removed when dropped
+// so failing tests do not leave it behind
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    // `name` keeps the folders of different tests apart, e.g. "module-test"; files may be in
+    // subfolders, which are created
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,480 @@
+use ab_glyph::{point, Font, FontVec, GlyphId, PxScale, ScaleFont};
+use gl::types::*;
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fs;
+use crate::camera::CameraUniforms;
+use crate::distance_field::jump_flood_cpu;
+use crate::shader_pipeline::gl_device::{blended, Device};
+use crate::shader_pipeline::texture::Texture;
+use crate::shader_pipeline::EBO::EBO;
+use crate::shader_pipeline::VAO::VAO;
+use crate::shader_pipeline::VBO::VBO;
+use crate::Shader;
+
+// Characters put in the atlas by default: printable ASCII and Latin-1
+pub fn default_charset() -> Vec<char> {
+    (' '..='~').chain('\u{a0}'..='\u{ff}').collect()
+}
+
+// Where a glyph sits in the atlas and how to place it, in atlas pixels at
+// the size the atlas was rasterized with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphInfo {
+    // Top left corner and size of the glyph's cell, padding included
+    pub atlas_pos: [u32; 2],
+    pub atlas_size: [u32; 2],
+    // Offset of the cell's top left corner from the pen position on the baseline
+    pub offset: [f32; 2],
+    pub advance: f32,
+}
+
+// Signed distance field glyphs of one font packed into a single texture.
+// Every texel holds 0.5 on the outline, more inside and less outside, so one
+// rasterization scales to any size and gets outlines and halos for free.
+pub struct GlyphAtlas {
+    font: FontVec,
+    // Pixel height the glyphs were rasterized at
+    pub px_size: f32,
+    // Distance in pixels covered by the 0..1 range of the field, on each side
+    pub spread: f32,
+    pub image: RgbaImage,
+    glyphs: HashMap<char, (GlyphId, GlyphInfo)>,
+}
+
+// Distance field of a coverage bitmap: 0.5 on the edge, +-0.5 at `spread` pixels
+fn glyph_sdf(coverage: &[f32], width: u32, height: u32, spread: f32) -> Vec<u8> {
+    let inside = |x: i64, y: i64| x >= 0 && y >= 0 && x < width as i64 && y < height as i64 && coverage[(y * width as i64 + x) as usize] > 0.5;
+    // Seed every pixel on the outline with the middle of its edge, as for the map borders
+    let mut seeds = vec![[-1.0, -1.0]; coverage.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                if inside(x, y) != inside(x + dx, y + dy) {
+                    seeds[(y * width as i64 + x) as usize] = [x as f32 + 0.5 + dx as f32 * 0.5, y as f32 + 0.5 + dy as f32 * 0.5];
+                    break;
+                }
+            }
+        }
+    }
+    let nearest = jump_flood_cpu(width, height, &seeds);
+    nearest
+        .iter()
+        .enumerate()
+        .map(|(i, seed)| {
+            let (x, y) = ((i as u32 % width) as i64, (i as u32 / width) as i64);
+            let d = if seed[0] < 0.0 { spread } else { ((seed[0] - x as f32 - 0.5).powi(2) + (seed[1] - y as f32 - 0.5).powi(2)).sqrt() };
+            let signed = if inside(x, y) { d } else { -d };
+            ((0.5 + 0.5 * (signed / spread).clamp(-1.0, 1.0)) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+// Width atlases start at; it grows to the widest cell when a huge px_size
+// makes one wider
+const ATLAS_WIDTH: u32 = 1024;
+
+// Shelf packing: cells go left to right, a new shelf starts when a row is
+// full. Returns the top left corner of every cell and the atlas size, at
+// least ATLAS_WIDTH wide and a power of two high.
+fn shelf_pack(sizes: &[[u32; 2]]) -> (Vec<[u32; 2]>, u32, u32) {
+    let width = sizes.iter().map(|s| s[0]).fold(ATLAS_WIDTH, u32::max);
+    let (mut x, mut y, mut shelf_height) = (0u32, 0u32, 0u32);
+    let mut positions = Vec::with_capacity(sizes.len());
+    for &[w, h] in sizes {
+        if x + w > width {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        positions.push([x, y]);
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+    (positions, width, (y + shelf_height).max(1).next_power_of_two())
+}
+
+impl GlyphAtlas {
+    // Loads a TTF/OTF file and rasterizes `charset` at `px_size` pixels
+    pub fn from_file(path: &str, px_size: f32, charset: &[char]) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let font = FontVec::try_from_vec(data).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self::new(font, px_size, charset))
+    }
+
+    pub fn new(font: FontVec, px_size: f32, charset: &[char]) -> Self {
+        let spread = (px_size / 8.0).max(2.0);
+        let padding = spread.ceil() as u32;
+        let scale = PxScale::from(px_size);
+        let scaled = font.as_scaled(scale);
+
+        let mut cells: Vec<(char, GlyphId, GlyphInfo, Vec<u8>)> = Vec::new();
+        for &c in charset {
+            let id = font.glyph_id(c);
+            // Glyph 0 is the font's "missing" box
+            if id.0 == 0 {
+                continue;
+            }
+            let glyph = id.with_scale_and_position(scale, point(0.0, 0.0));
+            let advance = scaled.h_advance(id);
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                // Blank glyphs like the space only advance the pen
+                cells.push((c, id, GlyphInfo { atlas_pos: [0, 0], atlas_size: [0, 0], offset: [0.0, 0.0], advance }, Vec::new()));
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            let (w, h) = (bounds.width() as u32 + 2 * padding, bounds.height() as u32 + 2 * padding);
+            let mut coverage = vec![0.0; (w * h) as usize];
+            outlined.draw(|gx, gy, c| coverage[((gy + padding) * w + gx + padding) as usize] = c);
+            let sdf = glyph_sdf(&coverage, w, h, spread);
+            let info = GlyphInfo {
+                atlas_pos: [0, 0],
+                atlas_size: [w, h],
+                offset: [bounds.min.x - padding as f32, bounds.min.y - padding as f32],
+                advance,
+            };
+            cells.push((c, id, info, sdf));
+        }
+
+        let sizes: Vec<[u32; 2]> = cells.iter().map(|(_, _, info, _)| info.atlas_size).collect();
+        let (positions, width, height) = shelf_pack(&sizes);
+        for (cell, position) in cells.iter_mut().zip(positions) {
+            cell.2.atlas_pos = position;
+        }
+        let mut image = RgbaImage::new(width, height);
+        let mut glyphs = HashMap::new();
+        for (c, id, info, sdf) in cells {
+            for gy in 0..info.atlas_size[1] {
+                for gx in 0..info.atlas_size[0] {
+                    let v = sdf[(gy * info.atlas_size[0] + gx) as usize];
+                    image.put_pixel(info.atlas_pos[0] + gx, info.atlas_pos[1] + gy, Rgba([v, v, v, v]));
+                }
+            }
+            glyphs.insert(c, (id, info));
+        }
+        GlyphAtlas { font, px_size, spread, image, glyphs }
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&GlyphInfo> {
+        self.glyphs.get(&c).map(|(_, info)| info)
+    }
+
+    // Pen positions of every glyph of `text` at `size` pixels, kerning
+    // included; characters missing from the atlas are skipped
+    pub fn layout(&self, text: &str, size: f32) -> Vec<(char, f32)> {
+        let scale = size / self.px_size;
+        let scaled = self.font.as_scaled(PxScale::from(self.px_size));
+        let mut pen = 0.0;
+        let mut previous: Option<GlyphId> = None;
+        let mut placed = Vec::new();
+        for c in text.chars() {
+            let Some((id, info)) = self.glyphs.get(&c) else { continue };
+            if let Some(previous) = previous {
+                pen += scaled.kern(previous, *id) * scale;
+            }
+            placed.push((c, pen));
+            pen += info.advance * scale;
+            previous = Some(*id);
+        }
+        placed
+    }
+
+    // Width of `text` at `size` pixels
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let scale = size / self.px_size;
+        match self.layout(text, size).last() {
+            Some((c, pen)) => pen + self.glyph(*c).map_or(0.0, |g| g.advance * scale),
+            None => 0.0,
+        }
+    }
+
+    // Distance from the baseline to the top and bottom of the tallest glyphs at `size`
+    pub fn ascent_descent(&self, size: f32) -> (f32, f32) {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        (scaled.ascent(), scaled.descent())
+    }
+}
+
+// Position and direction at arc length `s` along a polyline, clamped to its ends
+pub fn point_along(path: &[[f32; 2]], s: f32) -> ([f32; 2], [f32; 2]) {
+    let mut walked = 0.0;
+    let segments = path.len().saturating_sub(1);
+    for (i, segment) in path.windows(2).enumerate() {
+        let (a, b) = (segment[0], segment[1]);
+        let length = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+        // The last segment also takes everything past the end
+        if length > 0.0 && (walked + length >= s || i + 1 == segments) {
+            let t = ((s - walked) / length).clamp(0.0, 1.0);
+            let direction = [(b[0] - a[0]) / length, (b[1] - a[1]) / length];
+            return ([a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t], direction);
+        }
+        walked += length;
+    }
+    (path.first().copied().unwrap_or([0.0, 0.0]), [1.0, 0.0])
+}
+
+pub fn path_length(path: &[[f32; 2]]) -> f32 {
+    path.windows(2).map(|s| ((s[1][0] - s[0][0]).powi(2) + (s[1][1] - s[0][1]).powi(2)).sqrt()).sum()
+}
+
+// Text centered on a path, in map pixels with y down
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathText {
+    pub text: String,
+    // Read from the first point to the last
+    pub path: Vec<[f32; 2]>,
+    // Font size in map pixels
+    pub size: f32,
+}
+
+// Quads of every glyph of the text: 4 vertices of x, y, u, v each. Glyphs are
+// rotated to follow the path, with the baseline through the path so that the
+// text's middle sits on it.
+pub fn path_text_quads(atlas: &GlyphAtlas, text: &PathText) -> Vec<[f32; 16]> {
+    let scale = text.size / atlas.px_size;
+    let width = atlas.text_width(&text.text, text.size);
+    let start = (path_length(&text.path) - width) * 0.5;
+    let (ascent, descent) = atlas.ascent_descent(text.size);
+    // Shift the baseline down so the path runs through the middle of the capitals
+    let baseline = (ascent + descent) * 0.5;
+    let (atlas_w, atlas_h) = (atlas.image.width() as f32, atlas.image.height() as f32);
+
+    let mut quads = Vec::new();
+    for (c, pen) in atlas.layout(&text.text, text.size) {
+        let Some(glyph) = atlas.glyph(c) else { continue };
+        if glyph.atlas_size[0] == 0 {
+            continue;
+        }
+        // Each glyph is placed flat on the tangent at its middle
+        let middle = start + pen + glyph.advance * scale * 0.5;
+        let (center, direction) = point_along(&text.path, middle);
+        let normal = [-direction[1], direction[0]];
+        let corner = |gx: f32, gy: f32| {
+            // Glyph space: x along the baseline from the glyph's middle, y down
+            let x = gx - glyph.advance * scale * 0.5;
+            let y = gy + baseline;
+            [center[0] + direction[0] * x + normal[0] * y, center[1] + direction[1] * x + normal[1] * y]
+        };
+        let (x0, y0) = (glyph.offset[0] * scale, glyph.offset[1] * scale);
+        let (x1, y1) = (x0 + glyph.atlas_size[0] as f32 * scale, y0 + glyph.atlas_size[1] as f32 * scale);
+        let (u0, v0) = (glyph.atlas_pos[0] as f32 / atlas_w, glyph.atlas_pos[1] as f32 / atlas_h);
+        let (u1, v1) = (
+            (glyph.atlas_pos[0] + glyph.atlas_size[0]) as f32 / atlas_w,
+            (glyph.atlas_pos[1] + glyph.atlas_size[1]) as f32 / atlas_h,
+        );
+        let [a, b, c, d] = [corner(x0, y0), corner(x1, y0), corner(x1, y1), corner(x0, y1)];
+        quads.push([a[0], a[1], u0, v0, b[0], b[1], u1, v0, c[0], c[1], u1, v1, d[0], d[1], u0, v1]);
+    }
+    quads
+}
+
+// Look of a label layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub color: [f32; 4],
+    pub outline_color: [f32; 4],
+    // Outline thickness as a share of the atlas spread, 0..1
+    pub outline_width: f32,
+    // Labels smaller or larger than this on screen, in pixels, are hidden
+    pub min_screen_size: f32,
+    pub max_screen_size: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            color: [0.08, 0.06, 0.04, 1.0],
+            outline_color: [1.0, 0.97, 0.9, 0.6],
+            outline_width: 0.4,
+            min_screen_size: 7.0,
+            max_screen_size: 200.0,
+        }
+    }
+}
+
+// Bilinear sample of the atlas field at normalized coordinates
+fn sample_field(image: &RgbaImage, u: f32, v: f32) -> f32 {
+    let (x, y) = (u * image.width() as f32 - 0.5, v * image.height() as f32 - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |x: f32, y: f32| {
+        let x = (x.max(0.0) as u32).min(image.width() - 1);
+        let y = (y.max(0.0) as u32).min(image.height() - 1);
+        image.get_pixel(x, y)[0] as f32 / 255.0
+    };
+    let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+    let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+// Draws texts into an image the way sdf_text.glsl does, at one image pixel
+// per map pixel. Used for previews and exports without a GL context.
+pub fn draw_path_texts(target: &mut RgbaImage, atlas: &GlyphAtlas, texts: &[PathText], style: &TextStyle) {
+    let smoothstep = |e0: f32, e1: f32, x: f32| {
+        let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    for text in texts {
+        // One image pixel in field units
+        let aa = (0.75 * 0.5 * atlas.px_size / (atlas.spread * text.size)).max(1e-4);
+        for q in path_text_quads(atlas, text) {
+            let (a, b, d) = ([q[0], q[1]], [q[4], q[5]], [q[12], q[13]]);
+            let (e1, e2) = ([b[0] - a[0], b[1] - a[1]], [d[0] - a[0], d[1] - a[1]]);
+            let det = e1[0] * e2[1] - e1[1] * e2[0];
+            if det.abs() < 1e-6 {
+                continue;
+            }
+            let xs = [q[0], q[4], q[8], q[12]];
+            let ys = [q[1], q[5], q[9], q[13]];
+            let min_x = xs.iter().cloned().fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+            let min_y = ys.iter().cloned().fold(f32::MAX, f32::min).floor().max(0.0) as u32;
+            let max_x = (xs.iter().cloned().fold(f32::MIN, f32::max).ceil().max(0.0) as u32).min(target.width());
+            let max_y = (ys.iter().cloned().fold(f32::MIN, f32::max).ceil().max(0.0) as u32).min(target.height());
+            for y in min_y..max_y {
+                for x in min_x..max_x {
+                    // Position in the quad, 0..1 along each edge
+                    let (px, py) = (x as f32 + 0.5 - a[0], y as f32 + 0.5 - a[1]);
+                    let s = (px * e2[1] - py * e2[0]) / det;
+                    let t = (e1[0] * py - e1[1] * px) / det;
+                    if !(0.0..=1.0).contains(&s) || !(0.0..=1.0).contains(&t) {
+                        continue;
+                    }
+                    let field = sample_field(&atlas.image, q[2] + (q[6] - q[2]) * s, q[3] + (q[15] - q[3]) * t);
+                    let fill = smoothstep(0.5 - aa, 0.5 + aa, field);
+                    let edge = 0.5 - style.outline_width * 0.5;
+                    let outline = smoothstep(edge - aa, edge + aa, field) * style.outline_color[3];
+                    let pixel = target.get_pixel_mut(x, y);
+                    for i in 0..3 {
+                        let under = pixel[i] as f32 / 255.0;
+                        let halo = under + (style.outline_color[i] - under) * outline;
+                        let color = halo + (style.color[i] - halo) * fill * style.color[3];
+                        pixel[i] = (color.clamp(0.0, 1.0) * 255.0).round() as u8;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A set of path texts drawn with SDF glyphs. Geometry is built once in map
+// pixels, so zooming only changes the matrix and which labels are visible.
+pub struct LabelLayer {
+    shader: Shader,
+    atlas: Texture,
+    vao: VAO,
+    buffers: Option<(VBO, EBO)>,
+    // First index and index count of every text, with its size
+    ranges: Vec<(usize, i32, f32)>,
+    pub style: TextStyle,
+}
+
+impl LabelLayer {
+    // Builds the layer from sdf_text_vertex.glsl and sdf_text.glsl in `shader_dir`
+    pub fn new(gl: Device, shader_dir: &str, atlas: &GlyphAtlas, style: TextStyle) -> Result<Self, String> {
+        let read = |name: &str| {
+            let path = format!("{}/{}", shader_dir, name);
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))
+        };
+        let shader = Shader::from_source(gl.clone(), &read("sdf_text_vertex.glsl")?, &read("sdf_text.glsl")?);
+        let texture = Texture::from_rgba(gl.clone(), atlas.image.width(), atlas.image.height(), atlas.image.as_raw(), gl::LINEAR, gl::TEXTURE0);
+        Ok(LabelLayer { shader, atlas: texture, vao: VAO::with_device(gl), buffers: None, ranges: Vec::new(), style })
+    }
+
+    // Replaces the texts of the layer
+    pub fn set_texts(&mut self, atlas: &GlyphAtlas, texts: &[PathText]) {
+        if let Some((vbo, ebo)) = self.buffers.take() {
+            vbo.delete();
+            ebo.delete();
+        }
+        let gl = self.shader.device().clone();
+        let mut vertices: Vec<GLfloat> = Vec::new();
+        let mut indices: Vec<GLuint> = Vec::new();
+        self.ranges.clear();
+        for text in texts {
+            let first = indices.len();
+            for quad in path_text_quads(atlas, text) {
+                let base = (vertices.len() / 4) as GLuint;
+                vertices.extend_from_slice(&quad);
+                indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+            }
+            self.ranges.push((first, (indices.len() - first) as i32, text.size));
+        }
+
+        self.vao.bind();
+        let vbo = VBO::with_device(gl.clone(), &vertices);
+        let ebo = EBO::with_device(gl, &indices);
+        let stride = 4 * std::mem::size_of::<f32>() as GLsizei;
+        self.vao.link_attrib(&vbo, 0, 2, gl::FLOAT, stride, std::ptr::null());
+        self.vao.link_attrib(&vbo, 1, 2, gl::FLOAT, stride, (2 * std::mem::size_of::<f32>()) as *const std::ffi::c_void);
+        self.vao.unbind();
+        ebo.unbind();
+        self.buffers = Some((vbo, ebo));
+    }
+
+    // Draws the texts whose size on screen is within the style's limits
+    pub fn draw(&self, camera: &CameraUniforms) {
+        if self.buffers.is_none() {
+            return;
+        }
+        let gl = self.shader.device();
+        self.shader.activate();
+        camera.apply(&self.shader);
+        let [r, g, b, a] = self.style.color;
+        gl.uniform_4f(self.shader.uniform_location("textColor"), r, g, b, a);
+        let [r, g, b, a] = self.style.outline_color;
+        gl.uniform_4f(self.shader.uniform_location("outlineColor"), r, g, b, a);
+        gl.uniform_1f(self.shader.uniform_location("outlineWidth"), self.style.outline_width);
+        self.atlas.tex_unit(&self.shader, "glyphAtlas", 0);
+        gl.active_texture(gl::TEXTURE0);
+        self.atlas.bind();
+
+        self.vao.bind();
+        blended(gl, || {
+            camera.draw_copies(&self.shader, || {
+                for (first, count, size) in &self.ranges {
+                    let screen_size = size * camera.zoom;
+                    if screen_size < self.style.min_screen_size || screen_size > self.style.max_screen_size {
+                        continue;
+                    }
+                    gl.draw_elements(gl::TRIANGLES, *count, gl::UNSIGNED_INT, first * std::mem::size_of::<GLuint>());
+                }
+            })
+        });
+        self.vao.unbind();
+    }
+
+    pub fn delete(&self) {
+        self.shader.delete();
+        self.atlas.delete();
+        self.vao.delete();
+        if let Some((vbo, ebo)) = &self.buffers {
+            vbo.delete();
+            ebo.delete();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shelves_wrap_when_a_row_is_full() {
+        let (positions, width, height) = shelf_pack(&[[600, 40], [400, 60], [100, 30], [24, 24]]);
+        assert_eq!(positions, vec![[0, 0], [600, 0], [0, 60], [100, 60]]);
+        assert_eq!((width, height), (ATLAS_WIDTH, 128));
+    }
+
+    #[test]
+    fn atlas_widens_for_cells_wider_than_it() {
+        let (positions, width, height) = shelf_pack(&[[100, 10], [1500, 900], [1500, 20]]);
+        assert_eq!(width, 1500);
+        assert_eq!(positions, vec![[0, 0], [0, 10], [0, 910]]);
+        assert_eq!(height, 1024);
+    }
+
+    #[test]
+    fn empty_charsets_pack_into_one_row() {
+        assert_eq!(shelf_pack(&[]), (Vec::new(), ATLAS_WIDTH, 1));
+    }
+}
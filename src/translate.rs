@@ -0,0 +1,350 @@
+use naga::front::glsl::{Frontend, Options};
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+use naga::{AddressSpace, ImageClass, ShaderStage, TypeInner};
+use regex::{Captures, Regex};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+
+// A problem found in a shader, pointing at the line in the original file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    // 1-based, 0 when the problem has no location
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+        }
+    }
+}
+
+// What kind of resource sits at a binding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    UniformBlock,
+    StorageBlock,
+    Texture,
+    StorageImage,
+    Sampler,
+}
+
+// Where a resource of the GLSL source ended up in the translated module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingInfo {
+    pub name: String,
+    pub group: u32,
+    pub binding: u32,
+    pub kind: BindingKind,
+}
+
+// A parsed and validated shader, ready to be written out
+pub struct Translated {
+    pub stage: ShaderStage,
+    pub module: naga::Module,
+    pub info: ModuleInfo,
+    // Resources sorted by group and binding
+    pub bindings: Vec<BindingInfo>,
+}
+
+impl Translated {
+    // Moves every resource into one bind group, keeping the binding numbers.
+    // Engines like Bevy reserve the other groups for their own data.
+    pub fn move_to_group(&mut self, group: u32) {
+        for (_, var) in self.module.global_variables.iter_mut() {
+            if let Some(binding) = var.binding.as_mut() {
+                binding.group = group;
+            }
+        }
+        for binding in &mut self.bindings {
+            binding.group = group;
+        }
+    }
+
+    pub fn to_wgsl(&self) -> Result<String, String> {
+        naga::back::wgsl::write_string(&self.module, &self.info, naga::back::wgsl::WriterFlags::EXPLICIT_TYPES)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn to_spirv(&self) -> Result<Vec<u32>, String> {
+        let options = naga::back::spv::Options::default();
+        let pipeline_options = naga::back::spv::PipelineOptions {
+            shader_stage: self.stage,
+            entry_point: "main".to_string(),
+        };
+        naga::back::spv::write_vec(&self.module, &self.info, &options, Some(&pipeline_options))
+            .map_err(|e| e.to_string())
+    }
+}
+
+// Opaque GLSL sampler types and the separate texture type Vulkan GLSL uses for them
+const COMBINED_SAMPLERS: &[(&str, &str)] = &[
+    ("sampler1D", "texture1D"),
+    ("sampler2D", "texture2D"),
+    ("sampler3D", "texture3D"),
+    ("samplerCube", "textureCube"),
+    ("sampler2DArray", "texture2DArray"),
+    ("isampler2D", "itexture2D"),
+    ("usampler2D", "utexture2D"),
+];
+
+// Rewrites desktop GLSL into the Vulkan flavour naga understands. Every
+// change stays on the line it came from so that diagnostics still point at
+// the original source:
+// - loose `uniform T name;` become one-member uniform blocks
+// - combined samplers are split into a texture and a sampler, and the
+//   lookups reading them (`texture(name, ...)`, `texelFetch`, ...) get a
+//   `sampler2D(tex, smp)` constructor instead; sampler function parameters
+//   can't be split and are reported
+// - uniforms, blocks and images without `binding` get the next free one
+// - stage inputs and outputs without `location` get the next free one
+// - the legacy `texture2D()` lookup becomes `texture()`
+fn rewrite_for_naga(file: &str, source: &str) -> Result<String, Diagnostic> {
+    let uniform_re = Regex::new(r"^(\s*)(?:layout\s*\(([^)]*)\)\s*)?uniform\s+((?:(?:readonly|writeonly|coherent|volatile|restrict|highp|mediump|lowp)\s+)*)(\w+)\s+(\w+)\s*;").unwrap();
+    let block_re = Regex::new(r"^(\s*)(?:layout\s*\(([^)]*)\)\s*)?(uniform|buffer)\s+(\w+)\s*(\{|$)").unwrap();
+    let io_re = Regex::new(r"^(\s*)(?:layout\s*\(([^)]*)\)\s*)?((?:(?:flat|smooth|noperspective)\s+)?)(in|out)\s+(\w+)\s+(\w+)\s*;").unwrap();
+    let binding_re = Regex::new(r"\bbinding\s*=\s*(\d+)").unwrap();
+    let location_re = Regex::new(r"\blocation\s*=\s*(\d+)").unwrap();
+    let sampler_param_re = Regex::new(r"[(,]\s*(?:(?:in|const|highp|mediump|lowp)\s+)*([iu]?sampler\w+)\s+\w+\s*[,)\[]").unwrap();
+
+    // Reserve every binding and location that is already spelled out
+    let mut used_bindings = HashSet::new();
+    let mut used_in = HashSet::new();
+    let mut used_out = HashSet::new();
+    for (number, line) in source.lines().enumerate() {
+        if let Some(caps) = sampler_param_re.captures(line) {
+            let column = caps.get(1).unwrap().start();
+            return Err(Diagnostic {
+                file: file.to_string(),
+                line: number as u32 + 1,
+                column: column as u32 + 1,
+                message: format!("{} parameters can't be translated, naga needs the texture and sampler passed separately; read the uniform directly", &caps[1]),
+            });
+        }
+        for caps in binding_re.captures_iter(line) {
+            used_bindings.insert(caps[1].parse::<u32>().unwrap_or(0));
+        }
+        if let Some(caps) = io_re.captures(line) {
+            if let Some(location) = caps.get(2).and_then(|l| location_re.captures(l.as_str())) {
+                let location = location[1].parse::<u32>().unwrap_or(0);
+                if &caps[4] == "in" { used_in.insert(location) } else { used_out.insert(location) };
+            }
+        }
+    }
+    let next_free = |used: &mut HashSet<u32>| {
+        let mut n = 0;
+        while used.contains(&n) {
+            n += 1;
+        }
+        used.insert(n);
+        n
+    };
+
+    // Qualifier list with a binding added if it had none
+    let with_binding = |layout: Option<&str>, used: &mut HashSet<u32>| match layout {
+        Some(l) if binding_re.is_match(l) => l.to_string(),
+        Some(l) if !l.trim().is_empty() => format!("{}, binding = {}", l, next_free(used)),
+        _ => format!("binding = {}", next_free(used)),
+    };
+
+    // Names of the split samplers with their type
+    let mut split_samplers: Vec<(String, String)> = Vec::new();
+    let mut lines: Vec<String> = Vec::new();
+    for line in source.lines() {
+        if let Some(caps) = uniform_re.captures(line) {
+            let indent = &caps[1];
+            let layout = caps.get(2).map(|m| m.as_str());
+            let qualifiers = &caps[3];
+            let ty = &caps[4];
+            let name = &caps[5];
+            let rest = &line[caps.get(0).unwrap().end()..];
+            if let Some((_, tex_ty)) = COMBINED_SAMPLERS.iter().find(|(s, _)| *s == ty) {
+                let tex_layout = with_binding(layout, &mut used_bindings);
+                let smp_binding = next_free(&mut used_bindings);
+                lines.push(format!(
+                    "{}layout({}) uniform {} {}_tex; layout(binding = {}) uniform sampler {}_smp;{}",
+                    indent, tex_layout, tex_ty, name, smp_binding, name, rest
+                ));
+                split_samplers.push((name.to_string(), ty.to_string()));
+            } else if ty.starts_with("image") || ty.starts_with("iimage") || ty.starts_with("uimage") {
+                let layout = with_binding(layout, &mut used_bindings);
+                lines.push(format!("{}layout({}) uniform {}{} {};{}", indent, layout, qualifiers, ty, name, rest));
+            } else {
+                let layout = with_binding(layout, &mut used_bindings);
+                lines.push(format!("{}layout(std140, {}) uniform {}_block {{ {} {}; }};{}", indent, layout, name, ty, name, rest));
+            }
+        } else if let Some(caps) = block_re.captures(line) {
+            let layout = with_binding(caps.get(2).map(|m| m.as_str()), &mut used_bindings);
+            let rest = &line[caps.get(3).unwrap().start()..];
+            lines.push(format!("{}layout({}) {}", &caps[1], layout, rest));
+        } else if let Some(caps) = io_re.captures(line).filter(|c| c.get(2).is_none_or(|l| !location_re.is_match(l.as_str()))) {
+            let used = if &caps[4] == "in" { &mut used_in } else { &mut used_out };
+            let location = format!("location = {}", next_free(used));
+            let layout = match caps.get(2) {
+                Some(l) if !l.as_str().trim().is_empty() => format!("{}, {}", l.as_str(), location),
+                _ => location,
+            };
+            let rest = &line[caps.get(3).unwrap().start()..];
+            lines.push(format!("{}layout({}) {}", &caps[1], layout, rest));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    let mut rewritten = lines.join("\n");
+    rewritten = Regex::new(r"\btexture2D\s*\(").unwrap().replace_all(&rewritten, "texture(").into_owned();
+    for (name, ty) in split_samplers {
+        // Only the sampler argument of lookups; comments, fields and locals
+        // of the same name are left alone
+        let lookup_re = Regex::new(&format!(r"\b((?:texture|texel)\w*\s*\(\s*){}\b", regex::escape(&name))).unwrap();
+        rewritten = lookup_re
+            .replace_all(&rewritten, |caps: &Captures| format!("{}{}({2}_tex, {2}_smp)", &caps[1], ty, name))
+            .into_owned();
+    }
+    Ok(rewritten)
+}
+
+// Collects the resource bindings of a module
+fn collect_bindings(module: &naga::Module) -> Vec<BindingInfo> {
+    let mut bindings: Vec<BindingInfo> = module
+        .global_variables
+        .iter()
+        .filter_map(|(_, var)| {
+            let binding = var.binding.as_ref()?;
+            let kind = match (&module.types[var.ty].inner, var.space) {
+                (TypeInner::Image { class: ImageClass::Storage { .. }, .. }, _) => BindingKind::StorageImage,
+                (TypeInner::Image { .. }, _) => BindingKind::Texture,
+                (TypeInner::Sampler { .. }, _) => BindingKind::Sampler,
+                (_, AddressSpace::Storage { .. }) => BindingKind::StorageBlock,
+                _ => BindingKind::UniformBlock,
+            };
+            // Blocks without an instance name are named after their type;
+            // rewritten loose uniforms drop the `_block` suffix again
+            let name = var.name.clone().or_else(|| {
+                module.types[var.ty].name.as_ref().map(|n| n.trim_end_matches("_block").to_string())
+            });
+            Some(BindingInfo {
+                name: name.unwrap_or_default(),
+                group: binding.group,
+                binding: binding.binding,
+                kind,
+            })
+        })
+        .collect();
+    bindings.sort_by_key(|b| (b.group, b.binding));
+    bindings
+}
+
+// Builds a diagnostic from a byte span of the rewritten source. Lines are
+// preserved by the rewrite, columns may be off on rewritten declarations.
+fn diagnostic_at(file: &str, source: &str, span: naga::Span, message: String) -> Diagnostic {
+    let (line, column) = if span.is_defined() {
+        let location = span.location(source);
+        (location.line_number, location.line_position)
+    } else {
+        (0, 0)
+    };
+    Diagnostic { file: file.to_string(), line, column, message }
+}
+
+// Parses and validates GLSL source for the given stage. `file` is only used
+// to label diagnostics.
+pub fn translate(file: &str, source: &str, stage: ShaderStage) -> Result<Translated, Vec<Diagnostic>> {
+    let rewritten = rewrite_for_naga(file, source).map_err(|diagnostic| vec![diagnostic])?;
+
+    let module = Frontend::default()
+        .parse(&Options::from(stage), &rewritten)
+        .map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|e| diagnostic_at(file, &rewritten, e.meta, e.kind.to_string()))
+                .collect::<Vec<_>>()
+        })?;
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| {
+            // Spell out the whole chain of causes, the outer error is usually vague
+            let mut message = e.as_inner().to_string();
+            let mut source = std::error::Error::source(e.as_inner());
+            while let Some(cause) = source {
+                message.push_str(": ");
+                message.push_str(&cause.to_string());
+                source = cause.source();
+            }
+            let span = e.spans().next().map(|(span, _)| *span).unwrap_or_default();
+            vec![diagnostic_at(file, &rewritten, span, message)]
+        })?;
+
+    let bindings = collect_bindings(&module);
+    Ok(Translated { stage, module, info, bindings })
+}
+
+// Reads and translates a shader file
+pub fn translate_file(path: &str, stage: ShaderStage) -> Result<Translated, Vec<Diagnostic>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        vec![Diagnostic { file: path.to_string(), line: 0, column: 0, message: e.to_string() }]
+    })?;
+    translate(path, &source, stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENT: &str = "#version 450
+in vec2 uv;
+out vec4 color;
+// inputTexture holds the image
+uniform sampler2D inputTexture;
+struct Sample { float inputTexture; };
+void main() {
+    vec4 a = texture(inputTexture, uv);
+    vec4 b = texelFetch(inputTexture, ivec2(0), 0);
+    ivec2 size = textureSize( inputTexture, 0);
+    color = a + b + vec4(size, 0.0, 1.0);
+}
+";
+
+    #[test]
+    fn only_lookups_get_the_split_sampler() {
+        let rewritten = rewrite_for_naga("test.glsl", FRAGMENT).unwrap();
+        let lines: Vec<&str> = rewritten.lines().collect();
+        assert_eq!(lines.len(), FRAGMENT.lines().count());
+        assert_eq!(lines[3], "// inputTexture holds the image");
+        assert_eq!(lines[5], "struct Sample { float inputTexture; };");
+        assert_eq!(lines[7], "    vec4 a = texture(sampler2D(inputTexture_tex, inputTexture_smp), uv);");
+        assert_eq!(lines[8], "    vec4 b = texelFetch(sampler2D(inputTexture_tex, inputTexture_smp), ivec2(0), 0);");
+        assert_eq!(lines[9], "    ivec2 size = textureSize( sampler2D(inputTexture_tex, inputTexture_smp), 0);");
+    }
+
+    #[test]
+    fn lookups_keep_the_sampler_type() {
+        let source = "uniform usampler2D ids;\nvoid main() { uvec4 id = texelFetch(ids, ivec2(0), 0); }";
+        let rewritten = rewrite_for_naga("test.glsl", source).unwrap();
+        assert!(rewritten.contains("texelFetch(usampler2D(ids_tex, ids_smp), ivec2(0), 0)"));
+    }
+
+    #[test]
+    fn rewritten_lookups_validate() {
+        let translated = translate("test.glsl", FRAGMENT, ShaderStage::Fragment).unwrap_or_else(|d| panic!("{:?}", d));
+        let kinds: Vec<BindingKind> = translated.bindings.iter().map(|b| b.kind).collect();
+        assert_eq!(kinds, vec![BindingKind::Texture, BindingKind::Sampler]);
+    }
+
+    #[test]
+    fn sampler_parameters_are_reported_where_declared() {
+        let source = "#version 450\nuniform sampler2D image;\nvec4 blur(in sampler2D source, vec2 uv) { return texture(source, uv); }\nvoid main() {}\n";
+        let diagnostics = translate("blur.glsl", source, ShaderStage::Fragment).err().expect("sampler parameter is refused");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, 14));
+        assert!(diagnostics[0].message.starts_with("sampler2D parameters can't be translated"));
+    }
+}